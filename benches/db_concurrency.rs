@@ -0,0 +1,171 @@
+//! Message ingestion latency while semantic searches run concurrently.
+//!
+//! Compares routing reads through the writer (`DATABASE_READ_CONNECTIONS=0`, equivalent to the
+//! old single-connection design) against the reader pool.
+//!
+//! Run with `cargo bench --bench db_concurrency`.
+
+use mascord::config::Config;
+use mascord::db::Database;
+use mascord::rag::SearchFilter;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const SEED_MESSAGES: usize = 20_000;
+const EMBEDDING_DIM: usize = 384;
+const INSERTS: usize = 300;
+const SEARCH_THREADS: usize = 2;
+
+fn embedding(seed: usize) -> Vec<f32> {
+    // Cheap deterministic pseudo-random vector (xorshift).
+    let mut x = (seed as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..EMBEDDING_DIM)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            (x % 2000) as f32 / 1000.0 - 1.0
+        })
+        .collect()
+}
+
+fn config(database_url: &str, read_connections: usize) -> Config {
+    std::env::set_var("DISCORD_TOKEN", "bench");
+    std::env::set_var("APPLICATION_ID", "0");
+    std::env::set_var("DATABASE_URL", database_url);
+    std::env::set_var("DATABASE_READ_CONNECTIONS", read_connections.to_string());
+    std::env::set_var("VECTOR_INDEX_ENABLED", "false");
+    Config::from_env().expect("bench config")
+}
+
+fn seed(database_url: &str) {
+    let db = Database::new(&config(database_url, 0)).unwrap();
+    db.execute_init().unwrap();
+    let base = chrono::Utc::now().timestamp() - SEED_MESSAGES as i64;
+    for i in 0..SEED_MESSAGES {
+        db.save_message(
+            &format!("seed-{}", i),
+            "bench-guild",
+            &format!("channel-{}", i % 8),
+            &format!("user-{}", i % 50),
+            &format!("seed message number {} about topic {}", i, i % 97),
+            base + i as i64,
+        )
+        .unwrap();
+    }
+    for (id, _) in db.get_messages_missing_embeddings(SEED_MESSAGES).unwrap() {
+        db.set_message_embedding(id, &embedding(id as usize))
+            .unwrap();
+    }
+}
+
+struct Report {
+    latencies: Vec<Duration>,
+    searches: usize,
+}
+
+fn run(database_url: &str, read_connections: usize, with_search: bool) -> Report {
+    let db = Database::new(&config(database_url, read_connections)).unwrap();
+    db.execute_init().unwrap();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let searches = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..if with_search { SEARCH_THREADS } else { 0 })
+        .map(|t| {
+            let db = db.clone();
+            let stop = stop.clone();
+            let searches = searches.clone();
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .build()
+                    .unwrap();
+                let mut n = t;
+                while !stop.load(Ordering::Relaxed) {
+                    let filter = SearchFilter::new("bench-guild").with_limit(10);
+                    rt.block_on(db.search_messages("topic", embedding(n), filter))
+                        .unwrap();
+                    searches.fetch_add(1, Ordering::Relaxed);
+                    n += SEARCH_THREADS;
+                }
+            })
+        })
+        .collect();
+
+    // Let searches get going before measuring.
+    if with_search {
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    let mut latencies = Vec::with_capacity(INSERTS);
+    for i in 0..INSERTS {
+        let start = Instant::now();
+        db.save_message(
+            &format!("bench-{}-{}-{}", read_connections, with_search, i),
+            "bench-guild",
+            "channel-live",
+            "user-live",
+            "a freshly ingested message",
+            chrono::Utc::now().timestamp(),
+        )
+        .unwrap();
+        latencies.push(start.elapsed());
+        std::thread::sleep(Duration::from_millis(2));
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    Report {
+        latencies,
+        searches: searches.load(Ordering::Relaxed),
+    }
+}
+
+fn print(label: &str, mut report: Report) {
+    report.latencies.sort();
+    let pct = |p: f64| {
+        let idx = ((report.latencies.len() as f64 * p) as usize).min(report.latencies.len() - 1);
+        report.latencies[idx]
+    };
+    println!(
+        "{:<36} p50 {:>10.2?}  p95 {:>10.2?}  p99 {:>10.2?}  max {:>10.2?}  ({} searches)",
+        label,
+        pct(0.50),
+        pct(0.95),
+        pct(0.99),
+        report.latencies.last().copied().unwrap_or_default(),
+        report.searches
+    );
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; ignore arguments.
+    let database_url = std::env::temp_dir()
+        .join(format!("mascord-bench-{}.db", std::process::id()))
+        .display()
+        .to_string();
+
+    println!(
+        "Seeding {} messages with {}-dim embeddings...",
+        SEED_MESSAGES, EMBEDDING_DIM
+    );
+    seed(&database_url);
+
+    println!("Ingestion latency over {} inserts:", INSERTS);
+    print("idle", run(&database_url, 0, false));
+    print(
+        "search running, reads via writer",
+        run(&database_url, 0, true),
+    );
+    print(
+        "search running, 4 readers (WAL)",
+        run(&database_url, 4, true),
+    );
+
+    for suffix in ["", "-wal", "-shm"] {
+        std::fs::remove_file(format!("{}{}", database_url, suffix)).ok();
+    }
+}
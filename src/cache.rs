@@ -75,6 +75,21 @@ impl MessageCache {
         cache.get(message_id).cloned()
     }
 
+    /// Evict a single message (e.g. after it was edited or deleted on Discord).
+    /// Returns whether it was cached.
+    pub fn remove(&self, message_id: &str) -> bool {
+        let removed = self.lock_cache().pop(message_id);
+        let Some(message) = removed else {
+            return false;
+        };
+
+        let mut index = self.lock_index();
+        if let Some(queue) = index.get_mut(&message.channel_id) {
+            queue.retain(|id| id != message_id);
+        }
+        true
+    }
+
     /// Retrieve recent messages from a specific channel, ordered oldest to newest
     pub fn get_channel_history(&self, channel_id: ChannelId, limit: usize) -> Vec<Message> {
         let index = self.lock_index();
@@ -248,6 +263,21 @@ mod tests {
         assert!(empty.is_empty());
     }
 
+    #[test]
+    fn test_cache_remove() {
+        let cache = MessageCache::new(10);
+        cache.insert(mock_message(1, 100));
+        cache.insert(mock_message(2, 100));
+
+        assert!(cache.remove("1"));
+        assert!(!cache.remove("1"));
+        assert!(cache.get("1").is_none());
+
+        let history = cache.get_channel_history(ChannelId::new(100), 10);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].content, "Message 2");
+    }
+
     #[test]
     fn test_cache_cleanup_old_messages() {
         let cache = MessageCache::new(10);
@@ -1,4 +1,5 @@
 use crate::{Context, Error};
+use poise::serenity_prelude as serenity;
 use tracing::info;
 
 /// Shut down the bot (Owner only)
@@ -26,3 +27,62 @@ pub async fn restart(ctx: Context<'_>) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Show embedding coverage and re-index progress for the active model (Owner only)
+#[poise::command(slash_command, owners_only, hide_in_help)]
+pub async fn embedding_status(ctx: Context<'_>) -> Result<(), Error> {
+    let status = ctx
+        .data()
+        .db
+        .run_blocking(|db| db.get_embedding_status())
+        .await?;
+
+    let dims = if status.active_dims.is_empty() {
+        "unknown".to_string()
+    } else {
+        status
+            .active_dims
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut description = format!(
+        "**Active model:** `{}` ({} dims)\n\
+         **Progress:** {:.1}%\n\
+         **Embedded (active model):** {}\n\
+         **Awaiting re-embedding:** {}\n\
+         **Never embedded:** {}\n\
+         **Skipped (too short):** {}\n\
+         **Failed (gave up):** {}",
+        status.active_model,
+        dims,
+        status.progress() * 100.0,
+        status.current,
+        status.stale(),
+        status.pending,
+        status.skipped,
+        status.failed,
+    );
+
+    if !status.stale_by_model.is_empty() {
+        description.push_str("\n\n**Stale embeddings by model:**\n");
+        for (model, count) in &status.stale_by_model {
+            description.push_str(&format!(
+                "• `{}`: {}\n",
+                model.as_deref().unwrap_or("unknown"),
+                count
+            ));
+        }
+    }
+
+    let embed = serenity::CreateEmbed::new()
+        .title("🧮 Embedding Index Status")
+        .description(description)
+        .color(0x5865F2);
+
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+    Ok(())
+}
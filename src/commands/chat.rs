@@ -1,14 +1,23 @@
-use crate::config::DISCORD_EMBED_LIMIT;
-use crate::context::ConversationContext;
+use crate::config::{Config, DISCORD_EMBED_LIMIT};
+use crate::context::{ConversationContext, PromptSections};
+use crate::llm::agent::{Agent, AgentRunOutput};
 use crate::llm::confirm::ToolConfirmationContext;
+use crate::llm::vision::{self, ImageCollector};
 use crate::services::user_memory::UserMemoryService;
 use crate::system_prompt;
+use crate::tools::ToolContext;
 use crate::{Context, Error};
 use async_openai::types::{
     ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
     ChatCompletionRequestUserMessageArgs,
 };
-use poise::serenity_prelude::{CreateEmbed, CreateEmbedFooter};
+use poise::serenity_prelude::{
+    Attachment, CacheHttp, ChannelId, CreateEmbed, CreateEmbedFooter, CreateMessage, EditMessage,
+    Http, Message, MessageId,
+};
+use std::future::Future;
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
 use tracing::{error, info, warn};
 
 /// Chat with the all-in-one assistant
@@ -16,6 +25,7 @@ use tracing::{error, info, warn};
 pub async fn chat(
     ctx: Context<'_>,
     #[description = "Your message to the assistant"] message: String,
+    #[description = "Image to look at (vision models)"] image: Option<Attachment>,
 ) -> Result<(), Error> {
     info!(
         "Chat command received from {} in channel {}: {}",
@@ -46,18 +56,20 @@ pub async fn chat(
     };
 
     // Build messages with configurable system prompt
-    let mut messages: Vec<ChatCompletionRequestMessage> =
-        vec![ChatCompletionRequestSystemMessageArgs::default()
+    let mut sections = PromptSections::default();
+    sections.instructions.push(
+        ChatCompletionRequestSystemMessageArgs::default()
             .content(system_prompt)
             .build()?
-            .into()];
+            .into(),
+    );
 
     // Inject current date/time context
     if let Ok(datetime_msg) = ChatCompletionRequestSystemMessageArgs::default()
         .content(system_prompt::build_datetime_system_message())
         .build()
     {
-        messages.push(datetime_msg.into());
+        sections.instructions.push(datetime_msg.into());
     }
 
     let memory_service = UserMemoryService::new(ctx.data().db.clone(), ctx.data().cache.clone());
@@ -69,7 +81,7 @@ pub async fn chat(
             .content("Temporary no-memory request: do not use or update user memory. Do not call get_user_memory.")
             .build()
         {
-            messages.push(msg.into());
+            sections.instructions.push(msg.into());
         }
     }
 
@@ -81,7 +93,7 @@ pub async fn chat(
         ))
         .build()
     {
-        messages.push(meta_msg.into());
+        sections.instructions.push(meta_msg.into());
     }
 
     let memory_record = if skip_memory {
@@ -98,7 +110,7 @@ pub async fn chat(
             )
             .build()
         {
-            messages.push(help_msg.into());
+            sections.instructions.push(help_msg.into());
         }
     }
 
@@ -109,7 +121,7 @@ pub async fn chat(
                 .content(snippet)
                 .build()
             {
-                messages.push(msg.into());
+                sections.user_memory.push(msg.into());
             }
         }
     }
@@ -124,27 +136,58 @@ pub async fn chat(
         Some(ctx.data().bot_id),
         None,
     );
-    messages.extend(context_messages.await);
+    sections.add_channel_context(context_messages.await);
 
-    // Add the current user message
-    messages.push(
-        ChatCompletionRequestUserMessageArgs::default()
-            .content(format!("[{}]: {}", ctx.author().name, message.clone()))
-            .build()?
-            .into(),
-    );
+    // Add the current user message, with the image when vision is enabled
+    let images = match ImageCollector::new(ctx.data().http_client.clone(), &ctx.data().config) {
+        Some(collector) => collector.collect(&image.iter().collect::<Vec<_>>()).await,
+        None => Vec::new(),
+    };
+    sections.current.push(vision::user_message(
+        format!("[{}]: {}", ctx.author().name, message.clone()),
+        &images,
+    )?);
+
+    let messages = sections
+        .fit(
+            ctx.data().tokenizer.as_ref(),
+            ctx.data().config.context_max_tokens,
+        )
+        .await;
 
     let query_msg = ctx.say("Thinking...").await?;
 
-    let agent = crate::llm::agent::Agent::new(ctx.data());
+    let agent = Agent::new(ctx.data());
     let confirm_ctx = ToolConfirmationContext::new(
         ctx.serenity_context(),
         ctx.channel_id(),
         ctx.author().id,
         std::time::Duration::from_secs(confirm_timeout_secs),
     );
-    let response = match agent.run_with_confirmation(confirm_ctx, messages, 10).await {
-        Ok(r) => r,
+    let member = ctx.author_member().await;
+    let interaction_permissions = member.as_ref().and_then(|m| m.permissions);
+    let member_roles = member.as_ref().map(|m| m.roles.clone());
+    let tool_ctx = ToolContext::for_invocation(
+        ctx.data(),
+        ctx.serenity_context(),
+        ctx.guild_id(),
+        ctx.channel_id(),
+        ctx.author().id,
+    )
+    .with_member_permissions(interaction_permissions)
+    .with_member_roles(member_roles);
+    let mut reply = StreamingEmbedReply::for_command(ctx);
+    let output = run_agent_with_reply(
+        &agent,
+        &ctx.data().config,
+        confirm_ctx,
+        &tool_ctx,
+        messages,
+        &mut reply,
+    )
+    .await;
+    let response = match &output.result {
+        Ok(r) => r.clone(),
         Err(e) => {
             error!(
                 "Assistant error in /chat for channel {}: {}",
@@ -155,8 +198,11 @@ pub async fn chat(
         }
     };
 
-    // Handle long responses with embeds
-    send_response(&ctx, &response).await?;
+    // Render the final text; long responses are split across several embeds
+    let sent_ids = reply.finish(&response).await?;
+    output
+        .save(&ctx.data().db, &tool_ctx, None, &sent_ids)
+        .await;
     info!(
         "Assistant response sent to {} in channel {}",
         ctx.author().name,
@@ -187,78 +233,342 @@ pub async fn chat(
     Ok(())
 }
 
-/// Send response, always using embeds to avoid plain text limits
-pub async fn send_response(ctx: &Context<'_>, content: &str) -> Result<(), Error> {
-    if content.len() <= DISCORD_EMBED_LIMIT {
-        let embed = CreateEmbed::new()
-            .title("🤖 Mascord Response")
-            .description(content)
-            .color(0x5865F2)
-            .footer(CreateEmbedFooter::new("Powered by llama.cpp"));
-
-        ctx.send(poise::CreateReply::default().embed(embed)).await?;
-    } else {
-        // Split into multiple embeds if extremely long
-        let chunks: Vec<&str> = content
-            .as_bytes()
-            .chunks(DISCORD_EMBED_LIMIT - 100)
-            .map(|c| std::str::from_utf8(c).unwrap_or("..."))
-            .collect();
-
-        for (i, chunk) in chunks.iter().enumerate() {
-            let embed = CreateEmbed::new()
-                .title(format!("🤖 Response (Part {}/{})", i + 1, chunks.len()))
-                .description(*chunk)
-                .color(0x5865F2);
-
-            ctx.send(poise::CreateReply::default().embed(embed)).await?;
+const EMBED_CHUNK_LIMIT: usize = DISCORD_EMBED_LIMIT - 100;
+const RESPONSE_COLOR: u32 = 0x5865F2;
+const FOOTER_DONE: &str = "Powered by llama.cpp";
+const FOOTER_STREAMING: &str = "Generating…";
+
+/// Split `content` into embed-sized pieces without cutting through a UTF-8 character.
+/// Prefers breaking after a newline when one is available in the second half of a chunk.
+fn split_embed_chunks(content: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = content;
+    while rest.len() > EMBED_CHUNK_LIMIT {
+        let mut end = EMBED_CHUNK_LIMIT;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if let Some(newline) = rest[..end].rfind('\n') {
+            if newline >= end / 2 {
+                end = newline + 1;
+            }
         }
+        let (head, tail) = rest.split_at(end);
+        chunks.push(head);
+        rest = tail;
+    }
+    if !rest.is_empty() || chunks.is_empty() {
+        chunks.push(rest);
     }
+    chunks
+}
+
+/// What a single response message currently shows, so unchanged parts are not re-edited.
+#[derive(Clone, Debug, PartialEq)]
+struct ResponseEmbed {
+    title: String,
+    body: String,
+    footer: Option<&'static str>,
+}
+
+impl ResponseEmbed {
+    fn build(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::new()
+            .title(&self.title)
+            .description(&self.body)
+            .color(RESPONSE_COLOR);
+        if let Some(footer) = self.footer {
+            embed = embed.footer(CreateEmbedFooter::new(footer));
+        }
+        embed
+    }
+}
+
+fn render_response_embeds(content: &str, streaming: bool) -> Vec<ResponseEmbed> {
+    let chunks = split_embed_chunks(content);
+    let total = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let last = i + 1 == total;
+            ResponseEmbed {
+                title: if total == 1 {
+                    "🤖 Mascord Response".to_string()
+                } else {
+                    format!("🤖 Response (Part {}/{})", i + 1, total)
+                },
+                body: if chunk.is_empty() {
+                    "…".to_string()
+                } else {
+                    chunk.to_string()
+                },
+                footer: match (streaming, last, total) {
+                    (true, true, _) => Some(FOOTER_STREAMING),
+                    (false, _, 1) => Some(FOOTER_DONE),
+                    _ => None,
+                },
+            }
+        })
+        .collect()
+}
+
+enum ReplyTarget<'a> {
+    Channel {
+        http: &'a Http,
+        channel_id: ChannelId,
+        reply_to: Option<MessageId>,
+        sent: Vec<Message>,
+    },
+    Command {
+        ctx: Context<'a>,
+        sent: Vec<poise::ReplyHandle<'a>>,
+    },
+}
+
+/// A response that is rendered as one or more embeds and can be re-rendered as text grows.
+///
+/// Parts are edited in place; once the text crosses `DISCORD_EMBED_LIMIT` a new message is
+/// sent for the overflow.
+pub struct StreamingEmbedReply<'a> {
+    target: ReplyTarget<'a>,
+    rendered: Vec<ResponseEmbed>,
+}
+
+impl<'a> StreamingEmbedReply<'a> {
+    /// Send as regular channel messages, optionally replying to `reply_to`.
+    pub fn for_channel(http: &'a Http, channel_id: ChannelId, reply_to: Option<MessageId>) -> Self {
+        Self {
+            target: ReplyTarget::Channel {
+                http,
+                channel_id,
+                reply_to,
+                sent: Vec::new(),
+            },
+            rendered: Vec::new(),
+        }
+    }
+
+    /// Send as replies to a slash command invocation.
+    pub fn for_command(ctx: Context<'a>) -> Self {
+        Self {
+            target: ReplyTarget::Command {
+                ctx,
+                sent: Vec::new(),
+            },
+            rendered: Vec::new(),
+        }
+    }
+
+    /// Show partial text with a "generating" footer.
+    pub async fn update(&mut self, content: &str) -> Result<(), Error> {
+        self.render(content, true).await
+    }
+
+    /// Show the final text and return the ids of all messages that make up the response.
+    pub async fn finish(mut self, content: &str) -> Result<Vec<MessageId>, Error> {
+        self.render(content, false).await?;
+        match self.target {
+            ReplyTarget::Channel { sent, .. } => Ok(sent.iter().map(|m| m.id).collect()),
+            ReplyTarget::Command { sent, .. } => {
+                let mut ids = Vec::with_capacity(sent.len());
+                for handle in &sent {
+                    ids.push(handle.message().await?.id);
+                }
+                Ok(ids)
+            }
+        }
+    }
+
+    async fn render(&mut self, content: &str, streaming: bool) -> Result<(), Error> {
+        let embeds = render_response_embeds(content, streaming);
+
+        for (i, embed) in embeds.iter().enumerate() {
+            if self.rendered.get(i) == Some(embed) {
+                continue;
+            }
+            match &mut self.target {
+                ReplyTarget::Channel {
+                    http,
+                    channel_id,
+                    reply_to,
+                    sent,
+                } => {
+                    if let Some(message) = sent.get_mut(i) {
+                        message
+                            .edit(*http, EditMessage::new().embed(embed.build()))
+                            .await?;
+                    } else {
+                        let mut message = CreateMessage::new().embed(embed.build());
+                        if let Some(id) = reply_to {
+                            message = message.reference_message((*channel_id, *id));
+                        }
+                        sent.push(channel_id.send_message(*http, message).await?);
+                    }
+                }
+                ReplyTarget::Command { ctx, sent } => {
+                    let reply = poise::CreateReply::default().embed(embed.build());
+                    if let Some(handle) = sent.get(i) {
+                        handle.edit(*ctx, reply).await?;
+                    } else {
+                        sent.push(ctx.send(reply).await?);
+                    }
+                }
+            }
+        }
+
+        // The text can shrink when the agent starts a new turn after a tool call.
+        match &mut self.target {
+            ReplyTarget::Channel { http, sent, .. } => {
+                for message in sent.drain(embeds.len().min(sent.len())..) {
+                    let _ = message.delete(*http).await;
+                }
+            }
+            ReplyTarget::Command { ctx, sent } => {
+                for handle in sent.drain(embeds.len().min(sent.len())..) {
+                    let _ = handle.delete(*ctx).await;
+                }
+            }
+        }
+
+        self.rendered = embeds;
+        Ok(())
+    }
+}
+
+/// Drive `run` to completion while mirroring streamed progress into `reply`.
+///
+/// Edits are throttled to one per `interval` to stay clear of Discord rate limits.
+/// Failed edits are logged and skipped; the final render happens in `StreamingEmbedReply::finish`.
+pub async fn stream_agent_reply<F, T>(
+    reply: &mut StreamingEmbedReply<'_>,
+    mut progress: watch::Receiver<String>,
+    interval: Duration,
+    run: F,
+) -> T
+where
+    F: Future<Output = T>,
+{
+    tokio::pin!(run);
+    let mut dirty = false;
+    let mut open = true;
+    let mut next_flush = Instant::now();
+
+    loop {
+        tokio::select! {
+            result = &mut run => return result,
+            changed = progress.changed(), if open && !dirty => {
+                match changed {
+                    Ok(()) => dirty = true,
+                    Err(_) => open = false,
+                }
+            }
+            _ = tokio::time::sleep_until(next_flush), if dirty => {
+                let text = progress.borrow_and_update().clone();
+                if !text.trim().is_empty() {
+                    if let Err(e) = reply.update(&text).await {
+                        warn!("Failed to update streaming response: {}", e);
+                    }
+                }
+                dirty = false;
+                next_flush = Instant::now() + interval;
+            }
+        }
+    }
+}
+
+/// Run the agent and render its answer into `reply`, streaming when enabled in the config.
+pub async fn run_agent_with_reply(
+    agent: &Agent,
+    config: &Config,
+    confirmation: ToolConfirmationContext<'_>,
+    tool_ctx: &ToolContext,
+    messages: Vec<ChatCompletionRequestMessage>,
+    reply: &mut StreamingEmbedReply<'_>,
+) -> AgentRunOutput {
+    if !config.llm_streaming_enabled {
+        return agent
+            .run_with_confirmation(confirmation, tool_ctx, messages, 10)
+            .await;
+    }
+
+    let (progress_tx, progress_rx) = watch::channel(String::new());
+    stream_agent_reply(
+        reply,
+        progress_rx,
+        Duration::from_millis(config.stream_edit_interval_ms),
+        agent.run_with_confirmation_streaming(confirmation, tool_ctx, messages, 10, &progress_tx),
+    )
+    .await
+}
+
+/// Send response, always using embeds to avoid plain text limits
+pub async fn send_response(ctx: &Context<'_>, content: &str) -> Result<(), Error> {
+    StreamingEmbedReply::for_command(*ctx)
+        .finish(content)
+        .await?;
     Ok(())
 }
 
 /// Generic helper to send an embed response to a specific channel
 pub async fn send_embed_reply(
-    http: impl poise::serenity_prelude::CacheHttp,
-    channel_id: poise::serenity_prelude::ChannelId,
+    http: impl CacheHttp,
+    channel_id: ChannelId,
     content: &str,
-    reply_to: Option<poise::serenity_prelude::MessageId>,
-) -> Result<Vec<poise::serenity_prelude::MessageId>, Error> {
-    use poise::serenity_prelude::CreateMessage;
-
-    let mut sent_ids = Vec::new();
-    let mut message = CreateMessage::new();
-    if let Some(id) = reply_to {
-        message = message.reference_message((channel_id, id));
+    reply_to: Option<MessageId>,
+) -> Result<Vec<MessageId>, Error> {
+    StreamingEmbedReply::for_channel(http.http(), channel_id, reply_to)
+        .finish(content)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_embed_chunks_respects_char_boundaries() {
+        let content = "é".repeat(EMBED_CHUNK_LIMIT);
+        let chunks = split_embed_chunks(&content);
+        assert_eq!(chunks.concat(), content);
+        assert!(chunks.iter().all(|c| c.len() <= EMBED_CHUNK_LIMIT));
+        assert_eq!(chunks.len(), 2);
     }
 
-    if content.len() <= DISCORD_EMBED_LIMIT {
-        let embed = CreateEmbed::new()
-            .title("🤖 Mascord Response")
-            .description(content)
-            .color(0x5865F2)
-            .footer(CreateEmbedFooter::new("Powered by llama.cpp"));
+    #[test]
+    fn test_split_embed_chunks_prefers_newlines() {
+        let first_line = "a".repeat(EMBED_CHUNK_LIMIT - 10);
+        let content = format!("{}\n{}", first_line, "b".repeat(50));
+        let chunks = split_embed_chunks(&content);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0], format!("{}\n", first_line));
+        assert_eq!(chunks[1], "b".repeat(50));
+    }
 
-        let sent = channel_id.send_message(http, message.embed(embed)).await?;
-        sent_ids.push(sent.id);
-    } else {
-        let chunks: Vec<&str> = content
-            .as_bytes()
-            .chunks(DISCORD_EMBED_LIMIT - 100)
-            .map(|c| std::str::from_utf8(c).unwrap_or("..."))
-            .collect();
-
-        for (i, chunk) in chunks.iter().enumerate() {
-            let embed = CreateEmbed::new()
-                .title(format!("🤖 Response (Part {}/{})", i + 1, chunks.len()))
-                .description(*chunk)
-                .color(0x5865F2);
-
-            let sent = channel_id
-                .send_message(&http, message.clone().embed(embed))
-                .await?;
-            sent_ids.push(sent.id);
-        }
+    #[test]
+    fn test_split_embed_chunks_short_and_empty() {
+        assert_eq!(split_embed_chunks("hello"), vec!["hello"]);
+        assert_eq!(split_embed_chunks(""), vec![""]);
+    }
+
+    #[test]
+    fn test_render_response_embeds_footers() {
+        let single = render_response_embeds("hi", false);
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].title, "🤖 Mascord Response");
+        assert_eq!(single[0].footer, Some(FOOTER_DONE));
+
+        let streaming = render_response_embeds("hi", true);
+        assert_eq!(streaming[0].footer, Some(FOOTER_STREAMING));
+
+        let long = "x".repeat(EMBED_CHUNK_LIMIT + 1);
+        let parts = render_response_embeds(&long, true);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].title, "🤖 Response (Part 1/2)");
+        assert_eq!(parts[0].footer, None);
+        assert_eq!(parts[1].footer, Some(FOOTER_STREAMING));
+
+        let done = render_response_embeds(&long, false);
+        assert!(done.iter().all(|p| p.footer.is_none()));
     }
-    Ok(sent_ids)
 }
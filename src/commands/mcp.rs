@@ -1,13 +1,33 @@
+use crate::commands::chat::{run_agent_with_reply, StreamingEmbedReply};
 use crate::config::Config;
+use crate::context::{ConversationContext, PromptSections};
+use crate::llm::agent::Agent;
+use crate::llm::confirm::ToolConfirmationContext;
+use crate::mcp::client::ServerHealth;
 use crate::mcp::config::{McpServerConfig, McpTransport};
+use crate::system_prompt;
+use crate::tools::ToolContext;
 use crate::{Context, Error};
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+};
 use poise::serenity_prelude as serenity;
-use tracing::{info, warn};
+use rmcp::model::{GetPromptResult, PromptMessageContent, PromptMessageRole, ResourceContents};
+use serde_json::{Map, Value};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Autocomplete must answer within Discord's 3 second window.
+const AUTOCOMPLETE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Discord's limit on autocomplete choices and on their length.
+const AUTOCOMPLETE_MAX_CHOICES: usize = 25;
+const AUTOCOMPLETE_MAX_LEN: usize = 100;
 
 /// Manage MCP servers
 #[poise::command(
     slash_command,
-    subcommands("list", "add", "remove"),
+    subcommands("list", "add", "remove", "refresh", "prompt"),
     check = "is_owner"
 )]
 pub async fn mcp(_ctx: Context<'_>) -> Result<(), Error> {
@@ -22,6 +42,13 @@ async fn is_owner(ctx: Context<'_>) -> Result<bool, Error> {
         }
     }
 
+    // Autocomplete requests cannot be answered with a message.
+    if let poise::Context::Application(actx) = ctx {
+        if actx.interaction_type == poise::CommandInteractionType::Autocomplete {
+            return Ok(false);
+        }
+    }
+
     ctx.say("❌ Only the bot owner can manage MCP servers.")
         .await?;
     Ok(false)
@@ -32,6 +59,7 @@ async fn is_owner(ctx: Context<'_>) -> Result<bool, Error> {
 pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
     info!("MCP list command received from {}", ctx.author().name);
     let active_servers = ctx.data().mcp_manager.list_active_servers().await;
+    let health = ctx.data().mcp_manager.server_health();
     let all_configured = &ctx.data().config.mcp_servers;
 
     let mut response = String::from("## Configured MCP Servers\n");
@@ -39,8 +67,12 @@ pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
         response.push_str("_No servers configured._");
     } else {
         for server in all_configured {
+            let server_health = health.get(&server.name);
             let status = if active_servers.contains(&server.name) {
                 "🟢 Active"
+            } else if server_health.is_some() {
+                // Connected before; the supervisor is reconnecting it.
+                "🟡 Reconnecting"
             } else {
                 "🔴 Offline"
             };
@@ -48,6 +80,9 @@ pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
                 "- **{}**: {} ({:?})\n",
                 server.name, status, server.transport
             ));
+            if let Some(server_health) = server_health {
+                response.push_str(&format_health(server_health));
+            }
         }
     }
 
@@ -55,6 +90,28 @@ pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Health details shown under a server in `/mcp list`.
+fn format_health(health: &ServerHealth) -> String {
+    let mut details = Vec::new();
+    if let Some(latency) = health.latency {
+        details.push(format!("ping {} ms", latency.as_millis()));
+    }
+    if let Some(checked) = health.last_checked {
+        details.push(format!("checked <t:{}:R>", checked.timestamp()));
+    }
+    details.push(format!("restarts: {}", health.restart_count));
+    let mut text = format!("  - {}\n", details.join(" · "));
+    if let (Some(error), Some(at)) = (&health.last_error, health.last_error_at) {
+        let error: String = error.chars().take(200).collect();
+        text.push_str(&format!(
+            "  - Last error <t:{}:R>: `{}`\n",
+            at.timestamp(),
+            error.replace('`', "'")
+        ));
+    }
+    text
+}
+
 /// Add a new stdio-based MCP server
 #[poise::command(slash_command)]
 pub async fn add(
@@ -72,10 +129,15 @@ pub async fn add(
         args: args_vec,
         url: None,
         env: None,
+        headers: None,
+        bearer_token: None,
     };
 
-    // 1. Connect to the new server
-    ctx.data().mcp_manager.connect(&new_server).await?;
+    // 1. Connect to the new server. A failed add is not saved, so it is not retried either.
+    if let Err(e) = ctx.data().mcp_manager.connect(&new_server).await {
+        let _ = ctx.data().mcp_manager.disconnect(&name).await;
+        return Err(e.into());
+    }
 
     // 2. Update and persist configuration (This part is tricky because Data.config is not mutable)
     // We should probably have a way to update the global config or just rely on the TOML file.
@@ -148,3 +210,417 @@ pub async fn remove(
 
     Ok(())
 }
+
+/// Re-fetch the tool list of connected MCP servers
+#[poise::command(slash_command)]
+pub async fn refresh(
+    ctx: Context<'_>,
+    #[description = "Server to refresh (default: all connected servers)"] name: Option<String>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let results = ctx.data().mcp_manager.refresh_tools(name.as_deref()).await;
+
+    if results.is_empty() {
+        let message = match name {
+            Some(name) => format!("❌ MCP server **{}** is not connected.", name),
+            None => "No MCP servers are connected.".to_string(),
+        };
+        ctx.say(message).await?;
+        return Ok(());
+    }
+
+    let mut response = String::from("**Refreshed MCP tools:**\n");
+    for (server, result) in results {
+        match result {
+            Ok(count) => response.push_str(&format!("✅ **{}**: {} tools\n", server, count)),
+            Err(e) => response.push_str(&format!("❌ **{}**: {:#}\n", server, e)),
+        }
+    }
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// Run an MCP prompt and answer it with the assistant
+#[poise::command(slash_command)]
+pub async fn prompt(
+    ctx: Context<'_>,
+    #[description = "MCP server providing the prompt"]
+    #[autocomplete = "autocomplete_prompt_server"]
+    server: String,
+    #[description = "Prompt name"]
+    #[autocomplete = "autocomplete_prompt_name"]
+    name: String,
+    #[description = "Prompt arguments as key=value; key=value"]
+    #[autocomplete = "autocomplete_prompt_arguments"]
+    arguments: Option<String>,
+) -> Result<(), Error> {
+    info!(
+        "MCP prompt command: Running prompt '{}' from server '{}'",
+        name, server
+    );
+    ctx.defer().await?;
+    let manager = &ctx.data().mcp_manager;
+
+    let arguments = match parse_prompt_arguments(arguments.as_deref().unwrap_or_default()) {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            ctx.say(format!("❌ {}", e)).await?;
+            return Ok(());
+        }
+    };
+    let prompts = match manager.list_prompts(&server).await {
+        Ok(prompts) => prompts,
+        Err(e) => {
+            ctx.say(format!("❌ {:#}", e)).await?;
+            return Ok(());
+        }
+    };
+    let Some(definition) = prompts.iter().find(|p| p.name == name) else {
+        ctx.say(format!(
+            "❌ MCP server **{}** has no prompt named **{}**.",
+            server, name
+        ))
+        .await?;
+        return Ok(());
+    };
+    let missing: Vec<&str> = definition
+        .arguments
+        .iter()
+        .flatten()
+        .filter(|a| a.required == Some(true) && !arguments.contains_key(&a.name))
+        .map(|a| a.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        ctx.say(format!(
+            "❌ Missing required arguments: {}",
+            missing.join(", ")
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let result = match manager.get_prompt(&server, &name, arguments).await {
+        Ok(result) => result,
+        Err(e) => {
+            ctx.say(format!("❌ {:#}", e)).await?;
+            return Ok(());
+        }
+    };
+    let prompt_messages = prompt_messages(&result)?;
+    if prompt_messages.is_empty() {
+        ctx.say(format!("❌ Prompt **{}** returned no messages.", name))
+            .await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().map(|id| id.get());
+    let (system_prompt, confirm_timeout_secs) = if let Some(gid) = guild_id {
+        ctx.data()
+            .db
+            .run_blocking(move |db| {
+                Ok((
+                    db.get_guild_system_prompt(gid)?,
+                    db.get_guild_agent_confirm_timeout(gid)?,
+                ))
+            })
+            .await?
+    } else {
+        (None, None)
+    };
+    let config = &ctx.data().config;
+
+    let mut sections = PromptSections::default();
+    sections.instructions.push(
+        ChatCompletionRequestSystemMessageArgs::default()
+            .content(system_prompt.unwrap_or_else(|| config.system_prompt.clone()))
+            .build()?
+            .into(),
+    );
+    sections.instructions.push(
+        ChatCompletionRequestSystemMessageArgs::default()
+            .content(system_prompt::build_datetime_system_message())
+            .build()?
+            .into(),
+    );
+    let context_messages = ConversationContext::get_context_for_channel_async(
+        ctx.data().cache.clone(),
+        ctx.data().db.clone(),
+        config.clone(),
+        ctx.channel_id(),
+        guild_id,
+        Some(ctx.data().bot_id),
+        None,
+    );
+    sections.add_channel_context(context_messages.await);
+    sections.current = prompt_messages;
+    let messages = sections
+        .fit(ctx.data().tokenizer.as_ref(), config.context_max_tokens)
+        .await;
+
+    let status_msg = ctx
+        .say(format!(
+            "Running prompt **{}** from **{}**...",
+            name, server
+        ))
+        .await?;
+
+    let agent = Agent::new(ctx.data());
+    let confirm_ctx = ToolConfirmationContext::new(
+        ctx.serenity_context(),
+        ctx.channel_id(),
+        ctx.author().id,
+        Duration::from_secs(confirm_timeout_secs.unwrap_or(config.agent_confirm_timeout_secs)),
+    );
+    let member = ctx.author_member().await;
+    let tool_ctx = ToolContext::for_invocation(
+        ctx.data(),
+        ctx.serenity_context(),
+        ctx.guild_id(),
+        ctx.channel_id(),
+        ctx.author().id,
+    )
+    .with_member_permissions(member.as_ref().and_then(|m| m.permissions))
+    .with_member_roles(member.as_ref().map(|m| m.roles.clone()));
+    let mut reply = StreamingEmbedReply::for_command(ctx);
+    let output =
+        run_agent_with_reply(&agent, config, confirm_ctx, &tool_ctx, messages, &mut reply).await;
+    let response = match &output.result {
+        Ok(r) => r.clone(),
+        Err(e) => {
+            error!(
+                "Assistant error in /mcp prompt for channel {}: {}",
+                ctx.channel_id(),
+                e
+            );
+            format!("❌ Assistant Error: {}", e)
+        }
+    };
+
+    let sent_ids = reply.finish(&response).await?;
+    output
+        .save(&ctx.data().db, &tool_ctx, None, &sent_ids)
+        .await;
+    if let Ok(m) = status_msg.into_message().await {
+        let _ = m.delete(ctx).await;
+    }
+    Ok(())
+}
+
+/// Parse `key=value; key=value` prompt arguments.
+fn parse_prompt_arguments(input: &str) -> Result<Map<String, Value>, String> {
+    let mut arguments = Map::new();
+    for pair in input.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("Expected key=value, got '{}'", pair))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(format!("Missing argument name in '{}'", pair));
+        }
+        arguments.insert(key.to_string(), Value::String(value.trim().to_string()));
+    }
+    Ok(arguments)
+}
+
+/// The prompt's messages as chat messages. Embedded text resources are inlined; images and
+/// binary resources are replaced by a placeholder.
+fn prompt_messages(result: &GetPromptResult) -> anyhow::Result<Vec<ChatCompletionRequestMessage>> {
+    result
+        .messages
+        .iter()
+        .map(|message| {
+            let text = match &message.content {
+                PromptMessageContent::Text { text } => text.clone(),
+                PromptMessageContent::Image { .. } => "[image omitted]".to_string(),
+                PromptMessageContent::Resource { resource } => match &resource.resource {
+                    ResourceContents::TextResourceContents { text, .. } => text.clone(),
+                    ResourceContents::BlobResourceContents { uri, .. } => {
+                        format!("[binary resource omitted: {}]", uri)
+                    }
+                },
+                PromptMessageContent::ResourceLink { link } => {
+                    format!("[resource: {}]", link.uri)
+                }
+            };
+            Ok(match message.role {
+                PromptMessageRole::User => ChatCompletionRequestUserMessageArgs::default()
+                    .content(text)
+                    .build()?
+                    .into(),
+                PromptMessageRole::Assistant => {
+                    ChatCompletionRequestAssistantMessageArgs::default()
+                        .content(text)
+                        .build()?
+                        .into()
+                }
+            })
+        })
+        .collect()
+}
+
+/// The value of another option of the command being autocompleted.
+fn option_value<'a>(ctx: Context<'a>, name: &str) -> Option<&'a str> {
+    let poise::Context::Application(actx) = ctx else {
+        return None;
+    };
+    actx.args
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| match &o.value {
+            serenity::ResolvedValue::String(value) => Some(*value),
+            serenity::ResolvedValue::Autocomplete { value, .. } => Some(*value),
+            _ => None,
+        })
+}
+
+async fn autocomplete_prompt_server(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let mut servers = ctx.data().mcp_manager.prompt_servers().await;
+    servers.retain(|s| s.to_lowercase().contains(&partial.to_lowercase()));
+    servers.sort();
+    servers.truncate(AUTOCOMPLETE_MAX_CHOICES);
+    servers
+}
+
+async fn autocomplete_prompt_name(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    let Some(server) = option_value(ctx, "server") else {
+        return Vec::new();
+    };
+    let prompts = tokio::time::timeout(
+        AUTOCOMPLETE_TIMEOUT,
+        ctx.data().mcp_manager.list_prompts(server),
+    )
+    .await;
+    let Ok(Ok(prompts)) = prompts else {
+        return Vec::new();
+    };
+    prompts
+        .into_iter()
+        .filter(|p| p.name.to_lowercase().contains(&partial.to_lowercase()))
+        .filter(|p| p.name.len() <= AUTOCOMPLETE_MAX_LEN)
+        .take(AUTOCOMPLETE_MAX_CHOICES)
+        .map(|p| {
+            let label = match p.description.as_deref().filter(|d| !d.is_empty()) {
+                Some(description) => format!("{} - {}", p.name, description),
+                None => p.name.clone(),
+            };
+            let label: String = label.chars().take(AUTOCOMPLETE_MAX_LEN).collect();
+            serenity::AutocompleteChoice::new(label, p.name)
+        })
+        .collect()
+}
+
+/// Suggest the prompt's remaining argument names, or the server's completions for the value
+/// being typed.
+async fn autocomplete_prompt_arguments(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let (Some(server), Some(name)) = (option_value(ctx, "server"), option_value(ctx, "name"))
+    else {
+        return Vec::new();
+    };
+    let manager = &ctx.data().mcp_manager;
+    let (prefix, current) = split_arguments_input(partial);
+
+    let suggestions = if let Some((key, value)) = current.split_once('=') {
+        let completions = tokio::time::timeout(
+            AUTOCOMPLETE_TIMEOUT,
+            manager.complete_prompt_argument(server, name, key.trim(), value),
+        )
+        .await;
+        match completions {
+            Ok(Ok(values)) => values
+                .into_iter()
+                .map(|v| format!("{}{}={}", prefix, key.trim(), v))
+                .collect(),
+            _ => Vec::new(),
+        }
+    } else {
+        let prompts =
+            tokio::time::timeout(AUTOCOMPLETE_TIMEOUT, manager.list_prompts(server)).await;
+        let Ok(Ok(prompts)) = prompts else {
+            return Vec::new();
+        };
+        let given = parse_prompt_arguments(&prefix).unwrap_or_default();
+        let mut remaining: Vec<_> = prompts
+            .into_iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.arguments)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|a| !given.contains_key(&a.name) && a.name.starts_with(current.trim()))
+            .collect();
+        // Required arguments first.
+        remaining.sort_by_key(|a| a.required != Some(true));
+        remaining
+            .into_iter()
+            .map(|a| format!("{}{}=", prefix, a.name))
+            .collect()
+    };
+
+    let mut choices: Vec<String> = suggestions
+        .into_iter()
+        .filter(|s| s.len() <= AUTOCOMPLETE_MAX_LEN)
+        .take(AUTOCOMPLETE_MAX_CHOICES)
+        .collect();
+    // Keep what was typed selectable.
+    if choices.is_empty() && !partial.trim().is_empty() && partial.len() <= AUTOCOMPLETE_MAX_LEN {
+        choices.push(partial.to_string());
+    }
+    choices
+}
+
+/// Split `a=1; b` into the finished pairs (`"a=1; "`) and the pair being typed (`"b"`).
+fn split_arguments_input(input: &str) -> (String, &str) {
+    match input.rsplit_once(';') {
+        Some((done, current)) => {
+            let prefix: String = done
+                .split(';')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(|p| format!("{}; ", p))
+                .collect();
+            (prefix, current.trim_start())
+        }
+        None => (String::new(), input.trim_start()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::PromptMessage;
+
+    #[test]
+    fn test_parse_prompt_arguments() {
+        let arguments = parse_prompt_arguments(" topic = rust; style=short ;").unwrap();
+        assert_eq!(arguments["topic"], "rust");
+        assert_eq!(arguments["style"], "short");
+        assert!(parse_prompt_arguments("").unwrap().is_empty());
+        assert!(parse_prompt_arguments("topic").is_err());
+        assert!(parse_prompt_arguments("=rust").is_err());
+
+        assert_eq!(
+            split_arguments_input("topic=rust;sty"),
+            ("topic=rust; ".to_string(), "sty")
+        );
+        assert_eq!(split_arguments_input("to"), (String::new(), "to"));
+    }
+
+    #[test]
+    fn test_prompt_messages_keep_roles() {
+        let result = GetPromptResult {
+            description: None,
+            messages: vec![
+                PromptMessage::new_text(PromptMessageRole::User, "Review this"),
+                PromptMessage::new_text(PromptMessageRole::Assistant, "Sure"),
+            ],
+        };
+        let messages = prompt_messages(&result).unwrap();
+        assert!(matches!(messages[0], ChatCompletionRequestMessage::User(_)));
+        assert!(matches!(
+            messages[1],
+            ChatCompletionRequestMessage::Assistant(_)
+        ));
+    }
+}
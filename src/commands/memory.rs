@@ -168,12 +168,13 @@ pub async fn delete_data(ctx: Context<'_>) -> Result<(), Error> {
                         serenity::CreateInteractionResponseMessage::new()
                             .content(format!(
                                 "🗑️ Deleted **{}** messages and **{}** memory profile(s). Removed **{}** cached items. \
-Cleared **{}** channel summary snapshots and **{}** milestone sets for affected channels.",
+Cleared **{}** channel summary snapshots and **{}** milestone sets for affected channels, and **{}** assistant run transcripts.",
                                 result.messages_deleted,
                                 result.memory_deleted,
                                 result.cache_deleted,
                                 result.summaries_deleted,
-                                result.milestones_deleted
+                                result.milestones_deleted,
+                                result.agent_runs_deleted
                             ))
                             .components(vec![]),
                     ),
@@ -1,10 +1,10 @@
-use crate::{Context, Error};
+use crate::services::music::MusicService;
+use crate::{Context, Data, Error};
 use poise::serenity_prelude::{
     ButtonStyle, CreateActionRow, CreateButton, CreateEmbed, CreateInteractionResponse,
     CreateInteractionResponseMessage,
 };
-use songbird::input::{Compose, YoutubeDl};
-use tracing::{info, warn};
+use tracing::info;
 // use poise::serenity_prelude as serenity;
 
 /// Join a voice channel
@@ -14,65 +14,23 @@ use tracing::{info, warn};
     required_bot_permissions = "CONNECT | SPEAK"
 )]
 pub async fn join(ctx: Context<'_>) -> Result<(), Error> {
-    let channel_id = join_voice_channel_internal(ctx).await?;
-    ctx.say(format!("🔊 Joined <#{}>", channel_id)).await?;
-    Ok(())
-}
-
-/// Helper function to join the user's voice channel
-async fn join_voice_channel_internal(
-    ctx: Context<'_>,
-) -> Result<serenity::model::id::ChannelId, Error> {
     let guild_id = ctx
         .guild_id()
         .ok_or("This command must be used in a server")?;
-    let channel_id = {
-        let guild = ctx.guild().ok_or("Could not access guild")?;
-        guild
-            .voice_states
-            .get(&ctx.author().id)
-            .and_then(|vs| vs.channel_id)
-            .ok_or("You must be in a voice channel to use this command")?
-    };
-
-    info!(
-        "Attempting to join voice channel {} for guild {}",
-        channel_id, guild_id
-    );
+    let channel_id = music_service(ctx.data())
+        .join(ctx.serenity_context(), guild_id, ctx.author().id)
+        .await?;
+    ctx.say(format!("🔊 Joined <#{}>", channel_id)).await?;
+    Ok(())
+}
 
-    let manager = songbird::get(ctx.serenity_context())
-        .await
-        .ok_or("Songbird Voice client not initialized")?
-        .clone();
-
-    match manager.join(guild_id, channel_id).await {
-        Ok(handler_lock) => {
-            info!(
-                "Successfully joined voice channel {} for guild {}",
-                channel_id, guild_id
-            );
-            let mut handler = handler_lock.lock().await;
-
-            let idle_timeout_secs = ctx
-                .data()
-                .db
-                .get_guild_voice_idle_timeout(guild_id.get())?
-                .unwrap_or(ctx.data().config.voice_idle_timeout_secs);
-
-            // Add idle handler to leave after a period of no tracks
-            handler.add_global_event(
-                songbird::Event::Track(songbird::TrackEvent::End),
-                crate::voice::events::IdleHandler {
-                    guild_id,
-                    manager: manager.clone(),
-                    idle_timeout_secs,
-                },
-            );
-
-            Ok(channel_id)
-        }
-        Err(e) => Err(format!("❌ Failed to join voice channel: {}", e).into()),
-    }
+fn music_service(data: &Data) -> MusicService {
+    MusicService::new(
+        data.db.clone(),
+        data.http_client.clone(),
+        data.config.youtube_cookies.clone(),
+        data.config.voice_idle_timeout_secs,
+    )
 }
 
 /// Play audio from YouTube
@@ -90,74 +48,14 @@ pub async fn play(
     let guild_id = ctx
         .guild_id()
         .ok_or("This command must be used in a server")?;
-    let manager = songbird::get(ctx.serenity_context())
-        .await
-        .ok_or("Songbird Voice client not initialized")?
-        .clone();
-
-    let handler_lock = if let Some(hl) = manager.get(guild_id) {
-        hl
-    } else {
-        info!(
-            "Not in a voice channel, attempting auto-join for guild {}",
-            guild_id
-        );
-        join_voice_channel_internal(ctx).await?;
-        manager
-            .get(guild_id)
-            .ok_or("Failed to retrieve handler after join")?
-    };
-
-    let mut handler = handler_lock.lock().await;
-
-    let cookies_path = ctx.data().config.youtube_cookies.clone();
-    let cookies_ok = cookies_path
-        .as_deref()
-        .is_some_and(|p| std::path::Path::new(p).exists());
-
-    if cookies_path.is_some() && !cookies_ok {
-        warn!(
-            "YOUTUBE_COOKIES set but file not found at '{:?}'; proceeding without cookies",
-            cookies_path
-        );
-    }
-
-    let is_url = url.starts_with("http://") || url.starts_with("https://");
-    let mut source = if is_url {
-        YoutubeDl::new(ctx.data().http_client.clone(), url.clone())
-    } else {
-        YoutubeDl::new_search(ctx.data().http_client.clone(), url.clone())
-    };
-
-    // Pass args directly to yt-dlp via Songbird.
-    let mut args = vec!["--no-playlist".to_string()];
-    if let (Some(path), true) = (cookies_path.as_ref(), cookies_ok) {
-        args.push("--cookies".to_string());
-        args.push(path.clone());
-    }
-    source = source.user_args(args);
-
-    // Preflight to surface age restriction errors as a user-visible command failure.
-    if let Err(e) = source.aux_metadata().await {
-        let msg = e.to_string().to_lowercase();
-        let looks_age_restricted = msg.contains("confirm your age")
-            || msg.contains("age-restricted")
-            || msg.contains("sign in to confirm your age")
-            || msg.contains("age restricted");
-
-        if looks_age_restricted && !cookies_ok {
-            return Err("❌ This video appears to be age-restricted. Configure `YOUTUBE_COOKIES` with a valid cookies file to play age-restricted videos.".into());
-        }
-
-        return Err(format!("❌ Failed to fetch audio metadata: {}", e).into());
-    }
-
-    info!("Queueing audio for guild {}: {}", guild_id, url);
-    handler.enqueue_input(source.into()).await;
+    let track = music_service(ctx.data())
+        .play(ctx.serenity_context(), guild_id, ctx.author().id, &url)
+        .await?;
 
+    let title = track.title.as_deref().unwrap_or(&url);
     let embed = CreateEmbed::new()
         .title("🎵 Added to Queue")
-        .description(format!("```{}```", truncate(&url, 100)))
+        .description(format!("```{}```", truncate(title, 100)))
         .color(0x57F287);
 
     ctx.send(poise::CreateReply::default().embed(embed)).await?;
@@ -473,9 +371,8 @@ pub async fn queue(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 fn truncate(s: &str, max_len: usize) -> String {
-    if s.len() > max_len {
-        format!("{}...", &s[..max_len])
-    } else {
-        s.to_string()
+    match s.char_indices().nth(max_len) {
+        Some((idx, _)) => format!("{}...", &s[..idx]),
+        None => s.to_string(),
     }
 }
@@ -1,18 +1,45 @@
-use crate::rag::SearchFilter;
+use crate::rag::{SearchFilter, ThreadFilter};
 use crate::{Context, Error};
 use chrono::{Duration, Utc};
+use poise::serenity_prelude as serenity;
 use tracing::{info, warn};
 
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum ThreadScope {
+    #[name = "Include threads"]
+    Include,
+    #[name = "Exclude threads"]
+    Exclude,
+    #[name = "Only threads"]
+    Only,
+}
+
+impl From<ThreadScope> for ThreadFilter {
+    fn from(scope: ThreadScope) -> Self {
+        match scope {
+            ThreadScope::Include => ThreadFilter::Include,
+            ThreadScope::Exclude => ThreadFilter::Exclude,
+            ThreadScope::Only => ThreadFilter::Only,
+        }
+    }
+}
+
 /// Search for messages in history
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 pub async fn search(
     ctx: Context<'_>,
     #[description = "Search query"] query: String,
     #[description = "Limit results to latest XX days"] days: Option<i64>,
     #[description = "Limit results to specific channel"] channel_id: Option<String>,
+    #[description = "Only messages written by this user"] author: Option<serenity::User>,
+    #[description = "Threads and forum posts"] threads: Option<ThreadScope>,
 ) -> Result<(), Error> {
     ctx.defer().await?;
 
+    let guild_id = ctx
+        .guild_id()
+        .ok_or("This command must be used in a server")?;
+
     let db = &ctx.data().db;
     let llm_client = &ctx.data().llm_client;
 
@@ -28,7 +55,16 @@ pub async fn search(
         }
     };
 
-    let mut filter = SearchFilter::default().with_limit(5);
+    // Results are always limited to the invoking guild, even if another guild's channel id is given.
+    let mut filter = SearchFilter::new(guild_id.to_string()).with_limit(5);
+
+    if let Some(user) = author {
+        filter = filter.with_author(user.id.to_string());
+    }
+
+    if let Some(scope) = threads {
+        filter = filter.with_threads(scope.into());
+    }
 
     if let Some(d) = days {
         let from_date = Utc::now() - Duration::days(d);
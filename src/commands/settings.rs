@@ -1,11 +1,23 @@
-use crate::{Context, Error};
+use crate::config::Config;
+use crate::message_events;
+use crate::services::attachments::AttachmentService;
+use crate::services::backfill::{self, BackfillService};
+use crate::tools::policy::{PolicyAction, PolicyScope, PolicyTarget, ToolPolicy};
+use crate::{Context, Data, Error};
 use poise::serenity_prelude as serenity;
-use tracing::info;
+use tracing::{info, warn};
 
 /// Manage bot settings
 #[poise::command(
     slash_command,
-    subcommands("context", "memory", "system_prompt", "agent_timeout", "voice_timeout"),
+    subcommands(
+        "context",
+        "memory",
+        "system_prompt",
+        "agent_timeout",
+        "voice_timeout",
+        "tools"
+    ),
     required_permissions = "MANAGE_GUILD",
     guild_only
 )]
@@ -19,15 +31,41 @@ pub async fn context(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-/// Manage per-channel memory settings (list, enable, disable, scope, purge)
+/// Manage per-channel memory settings (list, enable, disable, scope, purge, backfill)
 #[poise::command(
     slash_command,
-    subcommands("list", "enable", "disable", "scope", "purge")
+    subcommands("list", "enable", "disable", "scope", "purge", "backfill")
 )]
 pub async fn memory(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Allow, deny or require confirmation for tools and MCP servers
+#[poise::command(slash_command, subcommands("tools_set", "tools_remove", "tools_list"))]
+pub async fn tools(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum ToolPolicyChoice {
+    #[name = "Allow"]
+    Allow,
+    #[name = "Require confirmation"]
+    Confirm,
+    #[name = "Deny"]
+    Deny,
+}
+
+impl From<ToolPolicyChoice> for PolicyAction {
+    fn from(choice: ToolPolicyChoice) -> Self {
+        match choice {
+            ToolPolicyChoice::Allow => PolicyAction::Allow,
+            ToolPolicyChoice::Confirm => PolicyAction::Confirm,
+            ToolPolicyChoice::Deny => PolicyAction::Deny,
+        }
+    }
+}
+
 /// View or update the server system prompt
 #[poise::command(slash_command)]
 pub async fn system_prompt(
@@ -403,6 +441,129 @@ pub async fn purge(
     Ok(())
 }
 
+/// Import earlier channel history into memory (resumes an interrupted run)
+#[poise::command(slash_command)]
+pub async fn backfill(
+    ctx: Context<'_>,
+    #[description = "The channel to backfill"] target_channel: serenity::Channel,
+    #[description = "Optional: Only import messages from this date on (YYYY-MM-DD [HH:MM:SS], UTC)"]
+    since: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Must be run in a guild")?;
+    let channel_id = target_channel.id();
+    let Some(channel) = target_channel.guild().filter(|c| c.guild_id == guild_id) else {
+        ctx.say("❌ That channel is not in this server.").await?;
+        return Ok(());
+    };
+    let since = match since {
+        Some(date) => Some(
+            backfill::parse_since(&date)
+                .ok_or("Invalid date. Use YYYY-MM-DD or YYYY-MM-DD HH:MM:SS (UTC).")?,
+        ),
+        None => None,
+    };
+
+    let channel_id_str = channel_id.to_string();
+    let enabled = ctx
+        .data()
+        .db
+        .run_blocking(move |db| db.is_channel_tracking_enabled(&channel_id_str))
+        .await?;
+    if !enabled {
+        ctx.say(format!(
+            "❌ Memory tracking is disabled for <#{}>. Enable it with `/settings memory enable` first.",
+            channel_id
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+    info!(
+        "Backfill of channel {} requested by {} (since: {:?})",
+        channel_id,
+        ctx.author().name,
+        since
+    );
+
+    let data = ctx.data();
+    let mut service = BackfillService::new(data.db.clone());
+    if data.config.attachment_ingest_enabled {
+        service = service.with_attachments(AttachmentService::new(
+            data.db.clone(),
+            data.http_client.clone(),
+            &data.config,
+        ));
+    }
+    // Thread and forum post history is filtered by the parent channel, like live messages.
+    if let Some(thread) = message_events::channel_thread(ctx.serenity_context(), &channel) {
+        service.link_thread(thread).await?;
+    }
+    let mut checkpoint = service
+        .start(guild_id.get(), channel_id.get(), since)
+        .await?;
+    let action = if checkpoint.before_message_id.is_some() {
+        "Resuming backfill of"
+    } else {
+        "Backfilling"
+    };
+    let progress = |fetched: i64, saved: i64| {
+        format!(
+            "⏳ {} <#{}>: read **{}** messages, **{}** new so far...",
+            action, channel_id, fetched, saved
+        )
+    };
+    let reply = ctx
+        .say(progress(checkpoint.fetched, checkpoint.saved))
+        .await?;
+
+    loop {
+        let mut request = serenity::GetMessages::new().limit(backfill::PAGE_SIZE);
+        if let Some(before) = checkpoint
+            .before_message_id
+            .as_deref()
+            .and_then(|id| id.parse::<u64>().ok())
+        {
+            request = request.before(serenity::MessageId::new(before));
+        }
+        // Serenity's HTTP client waits out Discord's rate limits for this route.
+        let page = channel_id.messages(ctx.http(), request).await?;
+        let outcome = service.ingest_page(&mut checkpoint, page).await?;
+        if outcome.done {
+            break;
+        }
+
+        if let Err(e) = reply
+            .edit(
+                ctx,
+                poise::CreateReply::default()
+                    .content(progress(checkpoint.fetched, checkpoint.saved)),
+            )
+            .await
+        {
+            warn!("Failed to update backfill progress: {}", e);
+        }
+        tokio::time::sleep(backfill::PAGE_DELAY).await;
+    }
+    service.complete(&checkpoint).await?;
+
+    info!(
+        "Backfill of channel {} complete: {} read, {} saved",
+        channel_id, checkpoint.fetched, checkpoint.saved
+    );
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default().content(format!(
+                "✅ Backfill of <#{}> complete: read **{}** messages, saved **{}** new. \
+They are searchable by keyword now and semantically once the embedding indexer catches up.",
+                channel_id, checkpoint.fetched, checkpoint.saved
+            )),
+        )
+        .await?;
+    Ok(())
+}
+
 /// Get current context settings
 #[poise::command(slash_command)]
 pub async fn get(ctx: Context<'_>) -> Result<(), Error> {
@@ -512,6 +673,7 @@ pub async fn summarize(ctx: Context<'_>) -> Result<(), Error> {
     let manager = crate::summarize::SummarizationManager::new(
         ctx.data().db.clone(),
         ctx.data().llm_client.clone(),
+        ctx.data().tokenizer.clone(),
         &ctx.data().config,
     );
 
@@ -526,3 +688,275 @@ pub async fn summarize(ctx: Context<'_>) -> Result<(), Error> {
     ctx.say("✅ Channel summarization complete.").await?;
     Ok(())
 }
+
+/// Resolve the `tool`/`server` and `channel`/`role` options of `/settings tools`.
+fn tool_policy_key(
+    guild_id: serenity::GuildId,
+    tool: Option<String>,
+    server: Option<String>,
+    channel: Option<&serenity::Channel>,
+    role: Option<&serenity::Role>,
+) -> Result<(PolicyScope, String, PolicyTarget, String), Error> {
+    let (target, target_name) = match (tool, server) {
+        (Some(tool), None) => (PolicyTarget::Tool, tool),
+        (None, Some(server)) => (PolicyTarget::Server, server),
+        _ => return Err("Specify exactly one of `tool` or `server`.".into()),
+    };
+    let target_name = target_name.trim().to_string();
+    if target_name.is_empty() {
+        return Err("The tool or server name cannot be empty.".into());
+    }
+    let (scope, scope_id) = match (channel, role) {
+        (None, None) => (PolicyScope::Guild, guild_id.to_string()),
+        (Some(channel), None) => (PolicyScope::Channel, channel.id().to_string()),
+        (None, Some(role)) => (PolicyScope::Role, role.id.to_string()),
+        (Some(_), Some(_)) => return Err("Specify at most one of `channel` or `role`.".into()),
+    };
+    Ok((scope, scope_id, target, target_name))
+}
+
+/// Names of the built-in tools and of the tools of connected MCP servers.
+async fn known_tool_names(data: &Data) -> Vec<String> {
+    let mut names: Vec<String> = data
+        .tools
+        .list_tools()
+        .iter()
+        .map(|t| t.name().to_string())
+        .collect();
+    names.extend(
+        data.mcp_manager
+            .list_all_tools()
+            .await
+            .iter()
+            .map(|t| t.name().to_string()),
+    );
+    names.sort();
+    names
+}
+
+/// Names of the connected and configured MCP servers.
+async fn known_server_names(data: &Data) -> Vec<String> {
+    let mut names = data.mcp_manager.list_active_servers().await;
+    names.extend(
+        Config::load_mcp_servers()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|s| s.name),
+    );
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Why a policy for `target_name` could never match, if it could not.
+async fn unknown_policy_target(
+    data: &Data,
+    target: PolicyTarget,
+    target_name: &str,
+) -> Option<String> {
+    match target {
+        PolicyTarget::Server => {
+            if known_server_names(data)
+                .await
+                .iter()
+                .any(|s| s == target_name)
+            {
+                return None;
+            }
+            Some(format!("Unknown MCP server `{}`.", target_name))
+        }
+        PolicyTarget::Tool => {
+            let names = known_tool_names(data).await;
+            if names.iter().any(|n| n == target_name) {
+                return None;
+            }
+            // A raw MCP tool name: the tool is exposed as `server__tool`.
+            let suffix = format!("{}{}", crate::mcp::naming::SEPARATOR, target_name);
+            let candidates: Vec<String> = names
+                .iter()
+                .filter(|n| n.ends_with(&suffix))
+                .map(|n| format!("`{}`", n))
+                .collect();
+            if candidates.is_empty() {
+                Some(format!(
+                    "Unknown tool `{}`. MCP tools are named `server__tool` and are only known while their server is connected.",
+                    target_name
+                ))
+            } else {
+                Some(format!(
+                    "Unknown tool `{}`. Did you mean {}?",
+                    target_name,
+                    candidates.join(" or ")
+                ))
+            }
+        }
+    }
+}
+
+async fn autocomplete_policy_tool(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    known_tool_names(ctx.data())
+        .await
+        .into_iter()
+        .filter(|n| n.contains(partial))
+        .take(25)
+        .collect()
+}
+
+async fn autocomplete_policy_server(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    known_server_names(ctx.data())
+        .await
+        .into_iter()
+        .filter(|n| n.contains(partial))
+        .take(25)
+        .collect()
+}
+
+fn describe_tool_policy(
+    scope: PolicyScope,
+    scope_id: &str,
+    target: PolicyTarget,
+    target_name: &str,
+) -> String {
+    let target = match target {
+        PolicyTarget::Tool => format!("tool `{}`", target_name),
+        PolicyTarget::Server => format!("MCP server `{}`", target_name),
+    };
+    let scope = match scope {
+        PolicyScope::Guild => "this server".to_string(),
+        PolicyScope::Channel => format!("<#{}>", scope_id),
+        PolicyScope::Role => format!("<@&{}>", scope_id),
+    };
+    format!("{} in {}", target, scope)
+}
+
+/// Allow, deny or require confirmation for a tool or MCP server
+#[poise::command(slash_command, rename = "set")]
+pub async fn tools_set(
+    ctx: Context<'_>,
+    #[description = "What to do with the tool"] action: ToolPolicyChoice,
+    #[description = "Tool name (MCP tools: server__tool)"]
+    #[autocomplete = "autocomplete_policy_tool"]
+    tool: Option<String>,
+    #[description = "MCP server name (applies to all of its tools)"]
+    #[autocomplete = "autocomplete_policy_server"]
+    server: Option<String>,
+    #[description = "Only in this channel (and its threads)"] channel: Option<serenity::Channel>,
+    #[description = "Only for members with this role"] role: Option<serenity::Role>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Must be run in a guild")?;
+    let (scope, scope_id, target, target_name) =
+        match tool_policy_key(guild_id, tool, server, channel.as_ref(), role.as_ref()) {
+            Ok(key) => key,
+            Err(e) => {
+                ctx.say(format!("❌ {}", e)).await?;
+                return Ok(());
+            }
+        };
+    if let Some(problem) = unknown_policy_target(ctx.data(), target, &target_name).await {
+        ctx.say(format!("❌ {}", problem)).await?;
+        return Ok(());
+    }
+    let action = PolicyAction::from(action);
+    let described = describe_tool_policy(scope, &scope_id, target, &target_name);
+    let policy = ToolPolicy {
+        guild_id: guild_id.to_string(),
+        scope,
+        scope_id,
+        target,
+        target_name,
+        action,
+    };
+    ctx.data()
+        .db
+        .run_blocking(move |db| db.set_tool_policy(&policy))
+        .await?;
+    ctx.say(format!("✅ **{}** {}.", action.as_str(), described))
+        .await?;
+    Ok(())
+}
+
+/// Remove a tool policy
+#[poise::command(slash_command, rename = "remove")]
+pub async fn tools_remove(
+    ctx: Context<'_>,
+    #[description = "Tool name (MCP tools: server__tool)"] tool: Option<String>,
+    #[description = "MCP server name"] server: Option<String>,
+    #[description = "Channel the policy applies to"] channel: Option<serenity::Channel>,
+    #[description = "Role the policy applies to"] role: Option<serenity::Role>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Must be run in a guild")?;
+    let (scope, scope_id, target, target_name) =
+        match tool_policy_key(guild_id, tool, server, channel.as_ref(), role.as_ref()) {
+            Ok(key) => key,
+            Err(e) => {
+                ctx.say(format!("❌ {}", e)).await?;
+                return Ok(());
+            }
+        };
+    let described = describe_tool_policy(scope, &scope_id, target, &target_name);
+    let guild_id_str = guild_id.to_string();
+    let removed = ctx
+        .data()
+        .db
+        .run_blocking(move |db| {
+            db.delete_tool_policy(&guild_id_str, scope, &scope_id, target, &target_name)
+        })
+        .await?;
+    if removed == 0 {
+        ctx.say(format!("📭 No policy found for {}.", described))
+            .await?;
+    } else {
+        ctx.say(format!("✅ Removed the policy for {}.", described))
+            .await?;
+    }
+    Ok(())
+}
+
+/// List the tool policies of this server
+#[poise::command(slash_command, rename = "list")]
+pub async fn tools_list(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Must be run in a guild")?;
+    let guild_id_str = guild_id.to_string();
+    let policies = ctx
+        .data()
+        .db
+        .run_blocking(move |db| db.list_tool_policies(&guild_id_str))
+        .await?;
+
+    if policies.is_empty() {
+        ctx.say("📭 No tool policies. All tools use their default behavior.")
+            .await?;
+        return Ok(());
+    }
+
+    let mut description = String::new();
+    for policy in &policies {
+        let icon = match policy.action {
+            PolicyAction::Allow => "✅",
+            PolicyAction::Confirm => "⚠️",
+            PolicyAction::Deny => "⛔",
+        };
+        description.push_str(&format!(
+            "{} **{}** {}\n",
+            icon,
+            policy.action.as_str(),
+            describe_tool_policy(
+                policy.scope,
+                &policy.scope_id,
+                policy.target,
+                &policy.target_name
+            )
+        ));
+    }
+
+    let embed = serenity::CreateEmbed::new()
+        .title("🛠️ Tool Policies")
+        .description(description)
+        .footer(serenity::CreateEmbedFooter::new(
+            "Tool rules beat server rules; channel > role > server-wide; ties: most restrictive",
+        ))
+        .color(0x5865F2);
+
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
@@ -12,10 +12,17 @@ pub struct Config {
     pub llama_url: String,
     pub llama_model: String,
     pub llama_api_key: Option<String>,
+    // Vision (image input) settings
+    pub llama_vision: bool,
+    pub vision_max_images: usize,
+    pub vision_max_image_bytes: u64,
+    pub vision_inline_images: bool,
     pub embedding_url: String,
     pub embedding_model: String,
     pub embedding_api_key: Option<String>,
     pub database_url: String,
+    pub database_read_connections: usize,
+    pub database_busy_timeout_ms: u64,
     pub system_prompt: String,
     pub max_context_messages: usize,
     pub status_message: String,
@@ -26,10 +33,14 @@ pub struct Config {
     // Context persistence settings
     pub context_message_limit: usize,
     pub context_retention_hours: u64,
+    pub context_max_tokens: usize,
+    pub tokenizer: String,
     // Timeout & Maintenance settings
     pub llm_timeout_secs: u64,
     pub embedding_timeout_secs: u64,
     pub mcp_timeout_secs: u64,
+    /// Seconds between MCP server health checks (0 disables the supervisor).
+    pub mcp_health_check_interval_secs: u64,
     pub voice_idle_timeout_secs: u64,
     pub dev_guild_id: Option<u64>,
     pub register_commands: bool,
@@ -37,11 +48,26 @@ pub struct Config {
 
     // Agent confirmation settings
     pub agent_confirm_timeout_secs: u64,
+    pub agent_tool_concurrency: usize,
+    pub agent_tool_timeout_secs: u64,
+
+    // Streaming response settings
+    pub llm_streaming_enabled: bool,
+    pub stream_edit_interval_ms: u64,
 
     // Background embedding indexer settings
     pub embedding_indexer_enabled: bool,
     pub embedding_indexer_batch_size: usize,
     pub embedding_indexer_interval_secs: u64,
+    pub embedding_batch_max_inputs: usize,
+    pub embedding_batch_max_tokens: usize,
+    pub embedding_max_attempts: u32,
+    pub vector_index_enabled: bool,
+
+    // Attachment ingestion settings
+    pub attachment_ingest_enabled: bool,
+    pub attachment_max_bytes: u64,
+    pub attachment_chunk_chars: usize,
 
     // Background summarization settings
     pub summarization_enabled: bool,
@@ -87,6 +113,22 @@ impl Config {
                 .unwrap_or_else(|_| "http://localhost:8080/v1".to_string()),
             llama_model: env::var("LLAMA_MODEL").unwrap_or_else(|_| "local-model".to_string()),
             llama_api_key: env::var("LLAMA_API_KEY").ok(),
+            llama_vision: env::var("LLAMA_VISION")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            vision_max_images: env::var("VISION_MAX_IMAGES")
+                .unwrap_or_else(|_| "4".to_string())
+                .parse()
+                .unwrap_or(4),
+            vision_max_image_bytes: env::var("VISION_MAX_IMAGE_BYTES")
+                .unwrap_or_else(|_| "5242880".to_string())
+                .parse()
+                .unwrap_or(5_242_880),
+            vision_inline_images: env::var("VISION_INLINE_IMAGES")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .unwrap_or(true),
             embedding_url: env::var("EMBEDDING_URL").unwrap_or_else(|_| {
                 env::var("LLAMA_URL").unwrap_or_else(|_| "http://localhost:8080/v1".to_string())
             }),
@@ -95,6 +137,14 @@ impl Config {
             embedding_api_key: env::var("EMBEDDING_API_KEY").ok(),
             database_url: env::var("DATABASE_URL")
                 .unwrap_or_else(|_| "data/mascord.db".to_string()),
+            database_read_connections: env::var("DATABASE_READ_CONNECTIONS")
+                .unwrap_or_else(|_| "4".to_string())
+                .parse()
+                .unwrap_or(4),
+            database_busy_timeout_ms: env::var("DATABASE_BUSY_TIMEOUT_MS")
+                .unwrap_or_else(|_| "5000".to_string())
+                .parse()
+                .unwrap_or(5000),
             system_prompt: env::var("SYSTEM_PROMPT")
                 .unwrap_or_else(|_| DEFAULT_SYSTEM_PROMPT.to_string()),
             max_context_messages: env::var("MAX_CONTEXT_MESSAGES")
@@ -119,6 +169,11 @@ impl Config {
                 .unwrap_or_else(|_| "24".to_string())
                 .parse()
                 .unwrap_or(24),
+            context_max_tokens: env::var("CONTEXT_MAX_TOKENS")
+                .unwrap_or_else(|_| "8192".to_string())
+                .parse()
+                .unwrap_or(8192),
+            tokenizer: env::var("TOKENIZER").unwrap_or_else(|_| "cl100k".to_string()),
             llm_timeout_secs: env::var("LLM_TIMEOUT_SECS")
                 .unwrap_or_else(|_| "120".to_string())
                 .parse()
@@ -131,6 +186,10 @@ impl Config {
                 .unwrap_or_else(|_| "60".to_string())
                 .parse()
                 .unwrap_or(60),
+            mcp_health_check_interval_secs: env::var("MCP_HEALTH_CHECK_INTERVAL_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
             voice_idle_timeout_secs: env::var("VOICE_IDLE_TIMEOUT_SECS")
                 .unwrap_or_else(|_| "300".to_string())
                 .parse()
@@ -149,6 +208,23 @@ impl Config {
                 .unwrap_or_else(|_| "300".to_string())
                 .parse()
                 .unwrap_or(300),
+            agent_tool_concurrency: env::var("AGENT_TOOL_CONCURRENCY")
+                .unwrap_or_else(|_| "4".to_string())
+                .parse()
+                .unwrap_or(4),
+            agent_tool_timeout_secs: env::var("AGENT_TOOL_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "120".to_string())
+                .parse()
+                .unwrap_or(120),
+
+            llm_streaming_enabled: env::var("LLM_STREAMING_ENABLED")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .unwrap_or(true),
+            stream_edit_interval_ms: env::var("STREAM_EDIT_INTERVAL_MS")
+                .unwrap_or_else(|_| "1500".to_string())
+                .parse()
+                .unwrap_or(1500),
 
             embedding_indexer_enabled: env::var("EMBEDDING_INDEXER_ENABLED")
                 .unwrap_or_else(|_| "true".to_string())
@@ -162,6 +238,35 @@ impl Config {
                 .unwrap_or_else(|_| "300".to_string())
                 .parse()
                 .unwrap_or(300),
+            embedding_batch_max_inputs: env::var("EMBEDDING_BATCH_MAX_INPUTS")
+                .unwrap_or_else(|_| "32".to_string())
+                .parse()
+                .unwrap_or(32),
+            embedding_batch_max_tokens: env::var("EMBEDDING_BATCH_MAX_TOKENS")
+                .unwrap_or_else(|_| "8192".to_string())
+                .parse()
+                .unwrap_or(8192),
+            embedding_max_attempts: env::var("EMBEDDING_MAX_ATTEMPTS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
+            vector_index_enabled: env::var("VECTOR_INDEX_ENABLED")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .unwrap_or(true),
+
+            attachment_ingest_enabled: env::var("ATTACHMENT_INGEST_ENABLED")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .unwrap_or(true),
+            attachment_max_bytes: env::var("ATTACHMENT_MAX_BYTES")
+                .unwrap_or_else(|_| "1048576".to_string())
+                .parse()
+                .unwrap_or(1_048_576),
+            attachment_chunk_chars: env::var("ATTACHMENT_CHUNK_CHARS")
+                .unwrap_or_else(|_| "1500".to_string())
+                .parse()
+                .unwrap_or(1500),
 
             summarization_enabled: env::var("SUMMARIZATION_ENABLED")
                 .unwrap_or_else(|_| "true".to_string())
@@ -267,6 +372,10 @@ impl std::fmt::Debug for Config {
                 "llama_api_key",
                 &self.llama_api_key.as_ref().map(|_| "[REDACTED]"),
             )
+            .field("llama_vision", &self.llama_vision)
+            .field("vision_max_images", &self.vision_max_images)
+            .field("vision_max_image_bytes", &self.vision_max_image_bytes)
+            .field("vision_inline_images", &self.vision_inline_images)
             .field("embedding_url", &self.embedding_url)
             .field("embedding_model", &self.embedding_model)
             .field(
@@ -274,6 +383,8 @@ impl std::fmt::Debug for Config {
                 &self.embedding_api_key.as_ref().map(|_| "[REDACTED]"),
             )
             .field("database_url", &self.database_url)
+            .field("database_read_connections", &self.database_read_connections)
+            .field("database_busy_timeout_ms", &self.database_busy_timeout_ms)
             .field("system_prompt", &self.system_prompt)
             .field("max_context_messages", &self.max_context_messages)
             .field("status_message", &self.status_message)
@@ -284,9 +395,15 @@ impl std::fmt::Debug for Config {
             .field("mcp_servers", &self.mcp_servers)
             .field("context_message_limit", &self.context_message_limit)
             .field("context_retention_hours", &self.context_retention_hours)
+            .field("context_max_tokens", &self.context_max_tokens)
+            .field("tokenizer", &self.tokenizer)
             .field("llm_timeout_secs", &self.llm_timeout_secs)
             .field("embedding_timeout_secs", &self.embedding_timeout_secs)
             .field("mcp_timeout_secs", &self.mcp_timeout_secs)
+            .field(
+                "mcp_health_check_interval_secs",
+                &self.mcp_health_check_interval_secs,
+            )
             .field("voice_idle_timeout_secs", &self.voice_idle_timeout_secs)
             .field("dev_guild_id", &self.dev_guild_id)
             .field("register_commands", &self.register_commands)
@@ -298,6 +415,10 @@ impl std::fmt::Debug for Config {
                 "agent_confirm_timeout_secs",
                 &self.agent_confirm_timeout_secs,
             )
+            .field("agent_tool_concurrency", &self.agent_tool_concurrency)
+            .field("agent_tool_timeout_secs", &self.agent_tool_timeout_secs)
+            .field("llm_streaming_enabled", &self.llm_streaming_enabled)
+            .field("stream_edit_interval_ms", &self.stream_edit_interval_ms)
             .field("embedding_indexer_enabled", &self.embedding_indexer_enabled)
             .field(
                 "embedding_indexer_batch_size",
@@ -307,6 +428,19 @@ impl std::fmt::Debug for Config {
                 "embedding_indexer_interval_secs",
                 &self.embedding_indexer_interval_secs,
             )
+            .field(
+                "embedding_batch_max_inputs",
+                &self.embedding_batch_max_inputs,
+            )
+            .field(
+                "embedding_batch_max_tokens",
+                &self.embedding_batch_max_tokens,
+            )
+            .field("embedding_max_attempts", &self.embedding_max_attempts)
+            .field("vector_index_enabled", &self.vector_index_enabled)
+            .field("attachment_ingest_enabled", &self.attachment_ingest_enabled)
+            .field("attachment_max_bytes", &self.attachment_max_bytes)
+            .field("attachment_chunk_chars", &self.attachment_chunk_chars)
             .field("summarization_enabled", &self.summarization_enabled)
             .field(
                 "summarization_interval_secs",
@@ -1,21 +1,264 @@
 //! Conversation context management for persistent LLM memory
 //!
 //! Provides per-channel context retrieval for injecting recent message history
-//! into LLM conversations.
+//! into LLM conversations, and fits assembled prompts into a token budget.
 
 use async_openai::types::{
     ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
-    ChatCompletionRequestUserMessageArgs,
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
 };
 use chrono::{Duration, Utc};
 use serenity::model::channel::Message;
 use serenity::model::id::ChannelId;
 
+use std::collections::HashMap;
+
 use crate::cache::MessageCache;
 use crate::config::Config;
 use crate::db::Database;
+use crate::llm::tokenizer::{HeuristicTokenizer, Tokenizer};
 use tracing::{debug, warn};
 
+/// Characters of attachment text injected into a prompt, spent on the newest messages first.
+pub const ATTACHMENT_CONTEXT_CHARS: usize = 6000;
+/// Most recent channel milestones injected after the summary.
+const CONTEXT_MILESTONES: usize = 10;
+/// Shares of the prompt budget (in percent) that each optional section may use at most.
+/// Recent messages get whatever is left.
+const USER_MEMORY_BUDGET_PERCENT: usize = 10;
+const SUMMARY_BUDGET_PERCENT: usize = 25;
+const MILESTONE_BUDGET_PERCENT: usize = 5;
+/// Per-message framing tokens (role, separators) added by chat templates.
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+/// Flat cost charged per image content part.
+const IMAGE_TOKENS: usize = 768;
+
+/// Stored channel memory for a prompt, grouped by how it is budgeted.
+#[derive(Default)]
+pub struct ChannelContext {
+    /// Parent channel summary, channel summary and thread starter, in that order.
+    pub summaries: Vec<ChatCompletionRequestMessage>,
+    /// Durable milestones extracted from the channel summary.
+    pub milestones: Vec<ChatCompletionRequestMessage>,
+    /// Recent messages, oldest first.
+    pub history: Vec<ChatCompletionRequestMessage>,
+}
+
+impl ChannelContext {
+    pub fn len(&self) -> usize {
+        self.summaries.len() + self.milestones.len() + self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn into_messages(self) -> Vec<ChatCompletionRequestMessage> {
+        let mut messages = self.summaries;
+        messages.extend(self.milestones);
+        messages.extend(self.history);
+        messages
+    }
+}
+
+/// A prompt assembled by section, trimmed to a token budget by [`PromptSections::fit`].
+#[derive(Default)]
+pub struct PromptSections {
+    /// System prompt, date/time and other instructions. Never dropped.
+    pub instructions: Vec<ChatCompletionRequestMessage>,
+    /// User memory snippet.
+    pub user_memory: Vec<ChatCompletionRequestMessage>,
+    pub summaries: Vec<ChatCompletionRequestMessage>,
+    pub milestones: Vec<ChatCompletionRequestMessage>,
+    /// Recent messages, oldest first.
+    pub history: Vec<ChatCompletionRequestMessage>,
+    /// The message being answered and what it replies to. Never dropped.
+    pub current: Vec<ChatCompletionRequestMessage>,
+}
+
+impl PromptSections {
+    pub fn add_channel_context(&mut self, context: ChannelContext) {
+        self.summaries.extend(context.summaries);
+        self.milestones.extend(context.milestones);
+        self.history.extend(context.history);
+    }
+
+    /// The prompt messages, fitted into `max_tokens`. User memory, summaries and milestones
+    /// are each capped at a share of the budget; recent messages fill the rest. Within a
+    /// section the oldest content is dropped first, and a single entry that is too long on its
+    /// own is truncated.
+    pub async fn fit(
+        self,
+        tokenizer: &dyn Tokenizer,
+        max_tokens: usize,
+    ) -> Vec<ChatCompletionRequestMessage> {
+        let sections = [
+            self.instructions,
+            self.user_memory,
+            self.summaries,
+            self.milestones,
+            self.history,
+            self.current,
+        ];
+        let texts: Vec<String> = sections
+            .iter()
+            .flatten()
+            .map(|m| message_text(m).0)
+            .collect();
+        let counts = match tokenizer.count_tokens(&texts).await {
+            Ok(counts) if counts.len() == texts.len() => counts,
+            Ok(_) | Err(_) => {
+                warn!("Context: Token counting failed, estimating instead");
+                texts
+                    .iter()
+                    .map(|t| HeuristicTokenizer::estimate(t))
+                    .collect()
+            }
+        };
+        let mut counts = counts.into_iter();
+        let [instructions, user_memory, summaries, milestones, history, current] =
+            sections.map(|section| {
+                section
+                    .into_iter()
+                    .map(|m| {
+                        let tokens = counts.next().unwrap_or_default()
+                            + MESSAGE_OVERHEAD_TOKENS
+                            + message_text(&m).1 * IMAGE_TOKENS;
+                        (m, tokens)
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+        let fixed: usize = instructions.iter().chain(&current).map(|(_, t)| t).sum();
+        let mut available = max_tokens.saturating_sub(fixed);
+        if fixed > max_tokens {
+            warn!(
+                "Context: Instructions and the current message alone use {} tokens (budget {}); dropping all context",
+                fixed, max_tokens
+            );
+        }
+
+        let mut fit_section = |section: Vec<(ChatCompletionRequestMessage, usize)>,
+                               percent: Option<usize>| {
+            let cap = percent.map_or(available, |p| (max_tokens * p / 100).min(available));
+            let (kept, used) = keep_newest(section, cap);
+            available -= used;
+            kept
+        };
+        let user_memory = fit_section(user_memory, Some(USER_MEMORY_BUDGET_PERCENT));
+        let summaries = fit_section(summaries, Some(SUMMARY_BUDGET_PERCENT));
+        let milestones = fit_section(milestones, Some(MILESTONE_BUDGET_PERCENT));
+        let history_len = history.len();
+        let history = fit_section(history, None);
+        if history.len() < history_len {
+            debug!(
+                "Context: Dropped {} oldest messages to fit {} tokens",
+                history_len - history.len(),
+                max_tokens
+            );
+        }
+
+        [
+            instructions.into_iter().map(|(m, _)| m).collect(),
+            user_memory,
+            summaries,
+            milestones,
+            history,
+            current.into_iter().map(|(m, _)| m).collect(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// Keep the newest entries of `section` that fit in `cap` tokens. When even the newest entry
+/// does not fit, a truncated copy of it is kept instead. Returns the kept entries (oldest
+/// first) and the tokens they use.
+fn keep_newest(
+    section: Vec<(ChatCompletionRequestMessage, usize)>,
+    cap: usize,
+) -> (Vec<ChatCompletionRequestMessage>, usize) {
+    let mut kept = Vec::new();
+    let mut used = 0;
+    for (message, tokens) in section.into_iter().rev() {
+        if used + tokens <= cap {
+            used += tokens;
+            kept.push(message);
+        } else if kept.is_empty() {
+            let budget = cap.saturating_sub(MESSAGE_OVERHEAD_TOKENS);
+            if let Some(truncated) = truncate_message(&message, tokens, budget) {
+                used += cap;
+                kept.push(truncated);
+            }
+            break;
+        } else {
+            break;
+        }
+    }
+    kept.reverse();
+    (kept, used)
+}
+
+/// Text of a message as the model sees it, and the number of images it carries.
+fn message_text(message: &ChatCompletionRequestMessage) -> (String, usize) {
+    let value = serde_json::to_value(message).unwrap_or_default();
+    let mut text = String::new();
+    let mut images = 0;
+    match &value["content"] {
+        serde_json::Value::String(content) => text.push_str(content),
+        serde_json::Value::Array(parts) => {
+            for part in parts {
+                if let Some(part_text) = part["text"].as_str() {
+                    text.push_str(part_text);
+                } else if part.get("image_url").is_some() {
+                    images += 1;
+                }
+            }
+        }
+        _ => {}
+    }
+    if let Some(tool_calls) = value.get("tool_calls") {
+        text.push_str(&tool_calls.to_string());
+    }
+    (text, images)
+}
+
+/// A copy of a text message cut down from about `tokens` to about `budget` tokens, or `None`
+/// when it cannot be shortened meaningfully.
+fn truncate_message(
+    message: &ChatCompletionRequestMessage,
+    tokens: usize,
+    budget: usize,
+) -> Option<ChatCompletionRequestMessage> {
+    if budget == 0 {
+        return None;
+    }
+    let (text, _) = message_text(message);
+    let chars = text.chars().count();
+    let keep = chars * budget / tokens.max(1);
+    if keep < 16 {
+        return None;
+    }
+    let truncated = format!("{}…", text.chars().take(keep).collect::<String>());
+    match message {
+        ChatCompletionRequestMessage::System(_) => ConversationContext::system_message(truncated),
+        ChatCompletionRequestMessage::User(_) => ChatCompletionRequestUserMessageArgs::default()
+            .content(truncated)
+            .build()
+            .ok()
+            .map(Into::into),
+        ChatCompletionRequestMessage::Assistant(_) => {
+            ChatCompletionRequestAssistantMessageArgs::default()
+                .content(truncated)
+                .build()
+                .ok()
+                .map(Into::into)
+        }
+        _ => None,
+    }
+}
+
 /// Formats cached messages into LLM-compatible context messages
 pub struct ConversationContext;
 
@@ -29,7 +272,7 @@ impl ConversationContext {
         guild_id: Option<u64>,
         bot_id: Option<u64>,
         exclude_message_id: Option<u64>,
-    ) -> Vec<ChatCompletionRequestMessage> {
+    ) -> ChannelContext {
         tokio::task::spawn_blocking(move || {
             Self::get_context_for_channel(
                 &cache,
@@ -47,7 +290,7 @@ impl ConversationContext {
                 "Context: Failed to fetch context for channel {}: {}",
                 channel_id, e
             );
-            Vec::new()
+            ChannelContext::default()
         })
     }
 
@@ -58,7 +301,8 @@ impl ConversationContext {
     /// - Retention period (config.context_retention_hours)
     /// - Limit (config.context_message_limit)
     ///
-    /// Returns messages oldest-first, formatted as user/assistant messages
+    /// Returns the channel's summaries and milestones, and its recent messages oldest-first,
+    /// formatted as user/assistant messages
     pub fn get_context_for_channel(
         cache: &MessageCache,
         db: &Database,
@@ -67,7 +311,7 @@ impl ConversationContext {
         guild_id: Option<u64>,
         bot_id: Option<u64>,
         exclude_message_id: Option<u64>,
-    ) -> Vec<ChatCompletionRequestMessage> {
+    ) -> ChannelContext {
         // Resolve settings: Check DB -> Fallback to Config
         let (limit, retention) = if let Some(gid) = guild_id {
             match db.get_guild_settings(gid) {
@@ -98,7 +342,7 @@ impl ConversationContext {
                         "Context: Channel {} memory disabled; skipping context",
                         channel_id
                     );
-                    return Vec::new();
+                    return ChannelContext::default();
                 }
 
                 if let Some(scope_date) = scope_date {
@@ -132,7 +376,45 @@ impl ConversationContext {
             ),
         }
 
-        let mut messages = Vec::new();
+        let mut context = ChannelContext::default();
+
+        // 0b. Threads and forum posts: inject the parent channel's working memory first.
+        let thread = match db.get_channel_thread(&channel_id.to_string()) {
+            Ok(thread) => thread,
+            Err(e) => {
+                warn!(
+                    "Context: Failed to load thread info for channel {}: {}",
+                    channel_id, e
+                );
+                None
+            }
+        };
+        // The parent's memory settings also apply to what it shares with its threads.
+        let thread = thread.filter(|t| {
+            db.is_channel_tracking_enabled(&t.parent_channel_id)
+                .unwrap_or(true)
+        });
+        if let Some(thread) = &thread {
+            match db.get_latest_summary(&thread.parent_channel_id) {
+                Ok(Some(summary)) => {
+                    debug!(
+                        "Context: Injecting parent channel {} summary for thread {}",
+                        thread.parent_channel_id, channel_id
+                    );
+                    if let Some(msg) = Self::system_message(format!(
+                        "Earlier conversation summary for the parent channel <#{}>:\n{}",
+                        thread.parent_channel_id, summary
+                    )) {
+                        context.summaries.push(msg);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!(
+                    "Context: Failed to load summary for parent channel {}: {}",
+                    thread.parent_channel_id, e
+                ),
+            }
+        }
 
         // 1. Inject Working Memory (Latest Summary) if available
         match db.get_latest_summary(&channel_id.to_string()) {
@@ -141,15 +423,11 @@ impl ConversationContext {
                     "Context: Injecting working memory (summary) for channel {}",
                     channel_id
                 );
-                use async_openai::types::ChatCompletionRequestSystemMessageArgs;
-                if let Ok(msg) = ChatCompletionRequestSystemMessageArgs::default()
-                    .content(format!(
-                        "Earlier conversation summary for this channel:\n{}",
-                        summary
-                    ))
-                    .build()
-                {
-                    messages.push(msg.into());
+                if let Some(msg) = Self::system_message(format!(
+                    "Earlier conversation summary for this channel:\n{}",
+                    summary
+                )) {
+                    context.summaries.push(msg);
                 }
             }
             Ok(None) => {}
@@ -159,6 +437,29 @@ impl ConversationContext {
             ),
         }
 
+        // 1a. Durable milestones (decisions, commitments) extracted with the summary
+        match db.get_channel_milestones(&channel_id.to_string(), CONTEXT_MILESTONES) {
+            Ok(milestones) if !milestones.is_empty() => {
+                let list = milestones
+                    .iter()
+                    .rev()
+                    .map(|m| format!("- {}", m))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if let Some(msg) = Self::system_message(format!(
+                    "Key milestones for this channel (oldest first):\n{}",
+                    list
+                )) {
+                    context.milestones.push(msg);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!(
+                "Context: Failed to load milestones for channel {}: {}",
+                channel_id, e
+            ),
+        }
+
         // 2. Fetch Short-Term context (verbatim messages)
         if retention == 0 {
             debug!(
@@ -173,29 +474,166 @@ impl ConversationContext {
         }
         let entries = cache.get_channel_history(channel_id, limit);
 
-        let mut short_term_messages: Vec<ChatCompletionRequestMessage> = entries
+        // 1b. The message that started a thread or forum post anchors its topic; inject it when
+        // it is not part of the short-term history anyway.
+        if let Some(thread) = &thread {
+            let in_history = entries
+                .iter()
+                .any(|msg| msg.id.to_string() == thread.channel_id);
+            if !in_history {
+                match db.get_message_by_discord_id(&thread.channel_id) {
+                    Ok(Some(starter)) => {
+                        let kind = if thread.is_forum_post {
+                            "forum post"
+                        } else {
+                            "thread"
+                        };
+                        if let Some(msg) = Self::system_message(format!(
+                            "This conversation is the {} \"{}\" in <#{}>. It was started by <@{}>:\n{}",
+                            kind,
+                            thread.name,
+                            thread.parent_channel_id,
+                            starter.user_id,
+                            starter.content
+                        )) {
+                            context.summaries.push(msg);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!(
+                        "Context: Failed to load starter message for thread {}: {}",
+                        channel_id, e
+                    ),
+                }
+            }
+        }
+
+        let entries: Vec<Message> = entries
             .into_iter()
             .filter(|msg| exclude_message_id.is_none_or(|exclude| msg.id.get() != exclude))
             .filter(|msg| {
                 // Filter by retention period using unix timestamps (unless disabled)
                 cutoff_unix.is_none_or(|cutoff| msg.timestamp.unix_timestamp() > cutoff)
             })
-            .filter_map(|msg| Self::format_message(&msg, bot_id))
+            .collect();
+
+        // Text of attached files, newest messages first within the budget.
+        let with_attachments: Vec<String> = entries
+            .iter()
+            .rev()
+            .filter(|msg| !msg.attachments.is_empty())
+            .map(|msg| msg.id.to_string())
+            .collect();
+        let attachment_texts =
+            Self::attachment_texts(db, &with_attachments, ATTACHMENT_CONTEXT_CHARS);
+
+        context.history = entries
+            .iter()
+            .filter_map(|msg| {
+                let attachments = attachment_texts.get(&msg.id.to_string());
+                Self::format_message(msg, bot_id, attachments.map(String::as_str))
+            })
             .collect();
 
         debug!(
             "Context: Retrieved {} short-term messages for channel {}",
-            short_term_messages.len(),
+            context.history.len(),
             channel_id
         );
-        messages.append(&mut short_term_messages);
-        messages
+        context
+    }
+
+    /// Stored attachment text of a single message (empty when it has none), for appending to
+    /// the message that triggered a response.
+    pub async fn attachment_text_for_message(db: Database, message: &Message) -> String {
+        if message.attachments.is_empty() {
+            return String::new();
+        }
+        let message_id = message.id.to_string();
+        db.run_blocking(move |db| {
+            let ids = [message_id];
+            let mut texts = Self::attachment_texts(db, &ids, ATTACHMENT_CONTEXT_CHARS);
+            Ok(texts.remove(&ids[0]).unwrap_or_default())
+        })
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Context: Failed to load attachment text: {}", e);
+            String::new()
+        })
+    }
+
+    /// Stored attachment text for `message_ids`, formatted per message for a prompt. Messages
+    /// are served in the given order until `budget` characters are used.
+    pub fn attachment_texts(
+        db: &Database,
+        message_ids: &[String],
+        budget: usize,
+    ) -> HashMap<String, String> {
+        let chunks = match db.get_message_attachment_chunks(message_ids) {
+            Ok(chunks) => chunks,
+            Err(e) => {
+                warn!("Context: Failed to load attachment text: {}", e);
+                return HashMap::new();
+            }
+        };
+
+        let mut texts = HashMap::new();
+        let mut remaining = budget;
+        for message_id in message_ids {
+            if remaining == 0 {
+                break;
+            }
+            let mut text = String::new();
+            let mut filename: Option<&str> = None;
+            for chunk in chunks
+                .iter()
+                .filter(|c| &c.message_discord_id == message_id)
+            {
+                if filename != Some(chunk.filename.as_str()) {
+                    filename = Some(&chunk.filename);
+                    if !text.is_empty() {
+                        text.push('\n');
+                    }
+                    text.push_str(&format!("[attachment {}]\n", chunk.filename));
+                }
+                let chunk_chars = chunk.content.chars().count();
+                if chunk_chars > remaining {
+                    text.extend(chunk.content.chars().take(remaining));
+                    text.push_str("\n[attachment truncated]");
+                    remaining = 0;
+                    break;
+                }
+                text.push_str(&chunk.content);
+                text.push('\n');
+                remaining -= chunk_chars;
+            }
+            if !text.is_empty() {
+                texts.insert(message_id.clone(), text.trim_end().to_string());
+            }
+        }
+        texts
     }
 
-    /// Formats a Discord message into an LLM message
-    fn format_message(msg: &Message, bot_id: Option<u64>) -> Option<ChatCompletionRequestMessage> {
+    fn system_message(content: String) -> Option<ChatCompletionRequestMessage> {
+        ChatCompletionRequestSystemMessageArgs::default()
+            .content(content)
+            .build()
+            .ok()
+            .map(|m| m.into())
+    }
+
+    /// Formats a Discord message (plus the text of its attachments) into an LLM message
+    fn format_message(
+        msg: &Message,
+        bot_id: Option<u64>,
+        attachments: Option<&str>,
+    ) -> Option<ChatCompletionRequestMessage> {
+        let content = match attachments {
+            Some(attachments) => format!("{}\n{}", msg.content, attachments),
+            None => msg.content.clone(),
+        };
         // Skip empty messages
-        if msg.content.trim().is_empty() {
+        if content.trim().is_empty() {
             return None;
         }
 
@@ -204,13 +642,13 @@ impl ConversationContext {
         if is_bot {
             // Bot's own messages become assistant messages
             ChatCompletionRequestAssistantMessageArgs::default()
-                .content(msg.content.clone())
+                .content(content)
                 .build()
                 .ok()
                 .map(|m| m.into())
         } else {
             // Other users' messages become user messages with attribution
-            let formatted = format!("[{}]: {}", msg.author.name, msg.content);
+            let formatted = format!("[{}]: {}", msg.author.name, content);
             ChatCompletionRequestUserMessageArgs::default()
                 .content(formatted)
                 .build()
@@ -221,14 +659,14 @@ impl ConversationContext {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use serenity::model::id::MessageId;
     use serenity::model::id::UserId;
     use serenity::model::timestamp::Timestamp;
     use serenity::model::user::User;
 
-    fn mock_config() -> Config {
+    pub(crate) fn mock_config() -> Config {
         Config {
             discord_token: "test".to_string(),
             application_id: 0,
@@ -236,10 +674,16 @@ mod tests {
             llama_url: "test".to_string(),
             llama_model: "test".to_string(),
             llama_api_key: None,
+            llama_vision: false,
+            vision_max_images: 4,
+            vision_max_image_bytes: 5_242_880,
+            vision_inline_images: true,
             embedding_url: "test".to_string(),
             embedding_model: "test".to_string(),
             embedding_api_key: None,
             database_url: ":memory:".to_string(),
+            database_read_connections: 4,
+            database_busy_timeout_ms: 5000,
             system_prompt: "test".to_string(),
             max_context_messages: 10,
             status_message: "test".to_string(),
@@ -249,17 +693,31 @@ mod tests {
             mcp_servers: Vec::new(),
             context_message_limit: 5,
             context_retention_hours: 24,
+            context_max_tokens: 8192,
+            tokenizer: "heuristic".to_string(),
             llm_timeout_secs: 120,
             embedding_timeout_secs: 30,
             mcp_timeout_secs: 60,
+            mcp_health_check_interval_secs: 30,
             voice_idle_timeout_secs: 300,
             dev_guild_id: None,
             register_commands: false,
             mcp_tools_require_confirmation: true,
             agent_confirm_timeout_secs: 300,
+            agent_tool_concurrency: 4,
+            agent_tool_timeout_secs: 120,
+            llm_streaming_enabled: true,
+            stream_edit_interval_ms: 1500,
             embedding_indexer_enabled: true,
             embedding_indexer_batch_size: 25,
             embedding_indexer_interval_secs: 30,
+            embedding_batch_max_inputs: 32,
+            embedding_batch_max_tokens: 8192,
+            embedding_max_attempts: 5,
+            vector_index_enabled: true,
+            attachment_ingest_enabled: true,
+            attachment_max_bytes: 1_048_576,
+            attachment_chunk_chars: 1500,
             summarization_enabled: true,
             summarization_interval_secs: 3600,
             summarization_active_channels_lookback_days: 7,
@@ -352,6 +810,57 @@ mod tests {
         assert_eq!(context.len(), 5);
     }
 
+    #[test]
+    fn test_thread_context_includes_parent_summary_and_starter() {
+        let cache = MessageCache::new(100);
+        let config = mock_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_channel_thread(&crate::db::ChannelThread {
+            channel_id: "200".to_string(),
+            guild_id: "123".to_string(),
+            parent_channel_id: "100".to_string(),
+            name: "deploy issues".to_string(),
+            is_forum_post: false,
+        })
+        .unwrap();
+        db.save_summary("100", "The team is migrating to the new cluster.")
+            .unwrap();
+        // The thread was started from message 200 in the parent channel.
+        db.save_message(
+            "200",
+            "123",
+            "100",
+            "1",
+            "Deploys keep failing",
+            Utc::now().timestamp(),
+        )
+        .unwrap();
+        cache.insert(mock_message(201, 200, 2, "Which step fails?", "Bob"));
+
+        let context = ConversationContext::get_context_for_channel(
+            &cache,
+            &db,
+            &config,
+            ChannelId::new(200),
+            Some(123),
+            None,
+            None,
+        );
+
+        assert_eq!(context.len(), 3);
+        assert_eq!(context.history.len(), 1);
+        let ChatCompletionRequestMessage::System(parent_summary) = &context.summaries[0] else {
+            panic!("expected the parent summary first");
+        };
+        let ChatCompletionRequestMessage::System(starter) = &context.summaries[1] else {
+            panic!("expected the starter message second");
+        };
+        assert!(format!("{:?}", parent_summary.content).contains("new cluster"));
+        assert!(format!("{:?}", starter.content).contains("Deploys keep failing"));
+    }
+
     #[test]
     fn test_context_retention_disabled_includes_old_messages() {
         let cache = MessageCache::new(100);
@@ -380,4 +889,120 @@ mod tests {
 
         assert_eq!(context.len(), 2);
     }
+
+    #[test]
+    fn test_attachment_texts_spend_budget_on_first_messages() {
+        let config = mock_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message("1", "g1", "100", "1", "older", 1700000000)
+            .unwrap();
+        db.save_message("2", "g1", "100", "1", "newer", 1700000001)
+            .unwrap();
+        db.save_attachment_chunks("1", "a1", "old.txt", &["0123456789".to_string()])
+            .unwrap();
+        db.save_attachment_chunks(
+            "2",
+            "a2",
+            "new.md",
+            &["abcdef".to_string(), "ghij".to_string()],
+        )
+        .unwrap();
+
+        // Newest first: "new.md" fits, "old.txt" gets the remaining 5 characters.
+        let ids = vec!["2".to_string(), "1".to_string()];
+        let texts = ConversationContext::attachment_texts(&db, &ids, 15);
+        assert_eq!(texts["2"], "[attachment new.md]\nabcdef\nghij");
+        assert_eq!(
+            texts["1"],
+            "[attachment old.txt]\n01234\n[attachment truncated]"
+        );
+
+        let texts = ConversationContext::attachment_texts(&db, &ids, 10);
+        assert!(!texts.contains_key("1"));
+    }
+
+    fn text_of(message: &ChatCompletionRequestMessage) -> String {
+        message_text(message).0
+    }
+
+    fn user(text: String) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestUserMessageArgs::default()
+            .content(text)
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_fit_caps_sections_and_drops_oldest_history() {
+        // Heuristic counts: ceil(chars / 4) + 4 framing tokens per message.
+        let mut sections = PromptSections::default();
+        sections
+            .instructions
+            .push(ConversationContext::system_message("i".repeat(40)).unwrap());
+        sections.user_memory.push(user("m".repeat(20)));
+        sections
+            .summaries
+            .push(ConversationContext::system_message("s".repeat(400)).unwrap());
+        sections
+            .milestones
+            .push(ConversationContext::system_message("k".repeat(40)).unwrap());
+        sections.history = (0..6).map(|i| user(format!("{i}").repeat(40))).collect();
+        sections.current.push(user("c".repeat(40)));
+
+        let messages = sections.fit(&HeuristicTokenizer, 100).await;
+        let texts: Vec<String> = messages.iter().map(text_of).collect();
+
+        // 28 tokens are fixed. Memory (9) fits its 10-token share; the summary is cut to its
+        // 25-token share; the milestone cannot be shortened into 5 tokens and is dropped. The
+        // remaining 38 tokens hold the two newest messages (14 each).
+        assert_eq!(texts.len(), 6);
+        assert_eq!(texts[0], "i".repeat(40));
+        assert_eq!(texts[1], "m".repeat(20));
+        assert_eq!(texts[2], format!("{}…", "s".repeat(80)));
+        assert_eq!(texts[3], "4".repeat(40));
+        assert_eq!(texts[4], "5".repeat(40));
+        assert_eq!(texts[5], "c".repeat(40));
+    }
+
+    #[tokio::test]
+    async fn test_fit_keeps_instructions_and_current_over_budget() {
+        let mut sections = PromptSections::default();
+        sections
+            .instructions
+            .push(ConversationContext::system_message("i".repeat(400)).unwrap());
+        sections.history.push(user("old".to_string()));
+        sections.current.push(user("now".to_string()));
+
+        let messages = sections.fit(&HeuristicTokenizer, 50).await;
+        assert_eq!(
+            messages.iter().map(text_of).collect::<Vec<_>>(),
+            vec!["i".repeat(400), "now".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_context_includes_milestones_oldest_first() {
+        let cache = MessageCache::new(100);
+        let config = mock_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+        db.replace_channel_milestones("100", &["Chose Postgres".to_string()])
+            .unwrap();
+
+        let context = ConversationContext::get_context_for_channel(
+            &cache,
+            &db,
+            &config,
+            ChannelId::new(100),
+            Some(123),
+            None,
+            None,
+        );
+
+        assert_eq!(context.milestones.len(), 1);
+        assert!(text_of(&context.milestones[0]).ends_with("- Chose Postgres"));
+    }
 }
@@ -0,0 +1,93 @@
+-- Database as created by `Database::execute_init` before versioned migrations
+-- (no schema_version table). Used by the migration upgrade tests.
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    discord_id TEXT NOT NULL UNIQUE,
+    guild_id TEXT NOT NULL,
+    channel_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    timestamp DATETIME NOT NULL,
+    is_indexed BOOLEAN DEFAULT FALSE,
+    embedding BLOB NULL
+);
+CREATE INDEX idx_messages_channel_date ON messages (channel_id, timestamp);
+CREATE INDEX idx_messages_guild_date ON messages (guild_id, timestamp);
+
+CREATE TABLE settings (
+    guild_id TEXT PRIMARY KEY,
+    context_limit INTEGER,
+    context_retention INTEGER,
+    system_prompt TEXT,
+    agent_confirm_timeout_secs INTEGER,
+    voice_idle_timeout_secs INTEGER
+);
+
+CREATE TABLE channel_summaries (
+    channel_id TEXT PRIMARY KEY,
+    summary TEXT NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    refreshed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE channel_settings (
+    guild_id TEXT NOT NULL,
+    channel_id TEXT PRIMARY KEY,
+    enabled BOOLEAN DEFAULT TRUE,
+    memory_start_date DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_channel_guild ON channel_settings (guild_id);
+
+CREATE TABLE channel_milestones (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    channel_id TEXT NOT NULL,
+    milestone TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_milestones_channel_created ON channel_milestones (channel_id, created_at);
+
+CREATE TABLE user_memory (
+    user_id TEXT PRIMARY KEY,
+    summary TEXT NOT NULL,
+    enabled BOOLEAN DEFAULT TRUE,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    expires_at DATETIME
+);
+
+CREATE TABLE reminders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id TEXT NOT NULL,
+    channel_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    message TEXT NOT NULL,
+    remind_at DATETIME NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    delivered_at DATETIME
+);
+CREATE INDEX idx_reminders_due ON reminders (remind_at, delivered_at);
+CREATE INDEX idx_reminders_user ON reminders (user_id, delivered_at);
+
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+    content,
+    content = 'messages',
+    content_rowid = 'id',
+    tokenize = 'porter unicode61'
+);
+CREATE TRIGGER messages_fts_ai AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_fts(rowid, content) VALUES (new.id, new.content);
+END;
+CREATE TRIGGER messages_fts_ad AFTER DELETE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+END;
+CREATE TRIGGER messages_fts_au AFTER UPDATE OF content ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+    INSERT INTO messages_fts(rowid, content) VALUES (new.id, new.content);
+END;
+
+INSERT INTO messages (discord_id, guild_id, channel_id, user_id, content, timestamp)
+VALUES ('100', 'g1', 'c1', 'u1', 'deploying the new build tonight', '2024-01-01 10:00:00'),
+       ('101', 'g1', 'c1', 'u2', 'sounds good', '2024-01-01 10:01:00');
+INSERT INTO settings (guild_id, context_limit, system_prompt) VALUES ('g1', 25, 'be brief');
+INSERT INTO user_memory (user_id, summary) VALUES ('u1', 'likes rust');
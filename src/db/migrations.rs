@@ -0,0 +1,592 @@
+//! Versioned schema migrations for the SQLite store.
+//!
+//! Each [`Migration`] runs once, in order, inside its own transaction together with the
+//! `schema_version` row that records it. Steps must also be idempotent: databases created
+//! before versioning existed carry no `schema_version` rows but may already contain some or
+//! all of the objects a step creates.
+
+use anyhow::Context as AnyhowContext;
+use rusqlite::{Connection, Transaction};
+use tracing::info;
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    apply: fn(&Transaction<'_>) -> anyhow::Result<()>,
+}
+
+/// All migrations, ordered by version. Append new steps; never edit or reorder applied ones.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "baseline schema",
+        apply: baseline_schema,
+    },
+    Migration {
+        version: 2,
+        description: "messages_fts keyword index",
+        apply: messages_fts,
+    },
+    Migration {
+        version: 3,
+        description: "embedding model metadata",
+        apply: embedding_metadata,
+    },
+    Migration {
+        version: 4,
+        description: "embedding retry tracking",
+        apply: embedding_retries,
+    },
+    Migration {
+        version: 5,
+        description: "channel summary dirty flag",
+        apply: summary_dirty_flag,
+    },
+    Migration {
+        version: 6,
+        description: "channel backfill checkpoints",
+        apply: backfill_checkpoints,
+    },
+    Migration {
+        version: 7,
+        description: "thread parent links",
+        apply: channel_threads,
+    },
+    Migration {
+        version: 8,
+        description: "attachment text chunks",
+        apply: attachment_chunks,
+    },
+    Migration {
+        version: 9,
+        description: "agent run transcripts",
+        apply: agent_runs,
+    },
+    Migration {
+        version: 10,
+        description: "per-guild tool policies",
+        apply: tool_policies,
+    },
+];
+
+/// Highest schema version this build knows about.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Version recorded in `schema_version` (0 for unversioned or empty databases).
+pub fn current_version(conn: &Connection) -> anyhow::Result<u32> {
+    let has_table: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+            [],
+            |row| row.get(0),
+        )
+        .context("Failed to check for schema_version")?;
+    if !has_table {
+        return Ok(0);
+    }
+    let version: Option<u32> = conn
+        .query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+            row.get(0)
+        })
+        .context("Failed to read schema version")?;
+    Ok(version.unwrap_or(0))
+}
+
+/// Apply all pending migrations and return the versions that were applied.
+pub fn run(conn: &mut Connection) -> anyhow::Result<Vec<u32>> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        ",
+    )
+    .context("Failed to create schema_version table")?;
+
+    let current = current_version(conn)?;
+    let latest = latest_version();
+    if current > latest {
+        anyhow::bail!(
+            "Database schema version {} is newer than this build supports ({}); refusing to start",
+            current,
+            latest
+        );
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        info!(
+            "Database: Applying migration {} ({})",
+            migration.version, migration.description
+        );
+        let tx = conn
+            .transaction()
+            .context("Failed to start migration transaction")?;
+        (migration.apply)(&tx).with_context(|| {
+            format!(
+                "Migration {} ({}) failed",
+                migration.version, migration.description
+            )
+        })?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?1, ?2)",
+            (migration.version, migration.description),
+        )
+        .context("Failed to record schema version")?;
+        tx.commit()
+            .with_context(|| format!("Failed to commit migration {}", migration.version))?;
+        applied.push(migration.version);
+    }
+
+    Ok(applied)
+}
+
+/// Add a column unless it already exists.
+/// SQLite doesn't support `ADD COLUMN IF NOT EXISTS`, so we ignore "duplicate column name".
+fn add_column(tx: &Transaction<'_>, table: &str, column_def: &str) -> anyhow::Result<()> {
+    if let Err(e) = tx.execute(
+        &format!("ALTER TABLE {} ADD COLUMN {}", table, column_def),
+        [],
+    ) {
+        if !e.to_string().contains("duplicate column name") {
+            return Err(e).with_context(|| format!("Failed to add {}.{}", table, column_def));
+        }
+    }
+    Ok(())
+}
+
+/// v1: the schema as it stood before versioning. Columns that used to be patched in on every
+/// start are still added for databases created before they existed.
+fn baseline_schema(tx: &Transaction<'_>) -> anyhow::Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            discord_id TEXT NOT NULL UNIQUE,
+            guild_id TEXT NOT NULL,
+            channel_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            content TEXT NOT NULL,
+            timestamp DATETIME NOT NULL,
+            is_indexed BOOLEAN DEFAULT FALSE
+        );
+        CREATE INDEX IF NOT EXISTS idx_messages_channel_date ON messages (channel_id, timestamp);
+        CREATE INDEX IF NOT EXISTS idx_messages_guild_date ON messages (guild_id, timestamp);
+
+        CREATE TABLE IF NOT EXISTS settings (
+            guild_id TEXT PRIMARY KEY,
+            context_limit INTEGER,
+            context_retention INTEGER,
+            system_prompt TEXT,
+            agent_confirm_timeout_secs INTEGER,
+            voice_idle_timeout_secs INTEGER
+        );
+
+        CREATE TABLE IF NOT EXISTS channel_summaries (
+            channel_id TEXT PRIMARY KEY,
+            summary TEXT NOT NULL,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            refreshed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS channel_settings (
+            guild_id TEXT NOT NULL,
+            channel_id TEXT PRIMARY KEY,
+            enabled BOOLEAN DEFAULT TRUE,
+            memory_start_date DATETIME,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX IF NOT EXISTS idx_channel_guild ON channel_settings (guild_id);
+
+        CREATE TABLE IF NOT EXISTS channel_milestones (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            channel_id TEXT NOT NULL,
+            milestone TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX IF NOT EXISTS idx_milestones_channel_created
+          ON channel_milestones (channel_id, created_at);
+
+        CREATE TABLE IF NOT EXISTS user_memory (
+            user_id TEXT PRIMARY KEY,
+            summary TEXT NOT NULL,
+            enabled BOOLEAN DEFAULT TRUE,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            expires_at DATETIME
+        );
+
+        CREATE TABLE IF NOT EXISTS reminders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id TEXT NOT NULL,
+            channel_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            message TEXT NOT NULL,
+            remind_at DATETIME NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            delivered_at DATETIME
+        );
+        CREATE INDEX IF NOT EXISTS idx_reminders_due ON reminders (remind_at, delivered_at);
+        CREATE INDEX IF NOT EXISTS idx_reminders_user ON reminders (user_id, delivered_at);
+        ",
+    )?;
+
+    add_column(tx, "messages", "embedding BLOB NULL")?;
+    add_column(
+        tx,
+        "channel_summaries",
+        "refreshed_at DATETIME DEFAULT CURRENT_TIMESTAMP",
+    )?;
+    add_column(tx, "settings", "system_prompt TEXT")?;
+    add_column(tx, "settings", "agent_confirm_timeout_secs INTEGER")?;
+    add_column(tx, "settings", "voice_idle_timeout_secs INTEGER")?;
+    Ok(())
+}
+
+/// v2: full-text index over message content (external-content FTS5 table kept in sync by
+/// triggers). Databases that did not have the index yet are backfilled.
+fn messages_fts(tx: &Transaction<'_>) -> anyhow::Result<()> {
+    let fts_exists: bool = tx.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'messages_fts')",
+        [],
+        |row| row.get(0),
+    )?;
+    tx.execute_batch(
+        "
+        CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+            content,
+            content = 'messages',
+            content_rowid = 'id',
+            tokenize = 'porter unicode61'
+        );
+        CREATE TRIGGER IF NOT EXISTS messages_fts_ai AFTER INSERT ON messages BEGIN
+            INSERT INTO messages_fts(rowid, content) VALUES (new.id, new.content);
+        END;
+        CREATE TRIGGER IF NOT EXISTS messages_fts_ad AFTER DELETE ON messages BEGIN
+            INSERT INTO messages_fts(messages_fts, rowid, content)
+            VALUES ('delete', old.id, old.content);
+        END;
+        CREATE TRIGGER IF NOT EXISTS messages_fts_au AFTER UPDATE OF content ON messages BEGIN
+            INSERT INTO messages_fts(messages_fts, rowid, content)
+            VALUES ('delete', old.id, old.content);
+            INSERT INTO messages_fts(rowid, content) VALUES (new.id, new.content);
+        END;
+        ",
+    )?;
+    if !fts_exists {
+        info!("Database: Building full-text index for existing messages...");
+        tx.execute(
+            "INSERT INTO messages_fts(messages_fts) VALUES ('rebuild')",
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+/// v3: record which model (and dimension) produced each stored embedding. Existing
+/// embeddings are left unlabelled, so they count as stale and are re-embedded with the
+/// configured model.
+fn embedding_metadata(tx: &Transaction<'_>) -> anyhow::Result<()> {
+    add_column(tx, "messages", "embedding_model TEXT")?;
+    add_column(tx, "messages", "embedding_dim INTEGER")?;
+    tx.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_messages_embedding_model ON messages (embedding_model);",
+    )?;
+    Ok(())
+}
+
+/// v4: per-message embedding failure tracking so failing messages back off and are
+/// eventually given up on instead of being retried every cycle.
+fn embedding_retries(tx: &Transaction<'_>) -> anyhow::Result<()> {
+    add_column(
+        tx,
+        "messages",
+        "embedding_attempts INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column(tx, "messages", "embedding_retry_at DATETIME")?;
+    add_column(tx, "messages", "embedding_error TEXT")?;
+    Ok(())
+}
+
+/// v5: flag summaries that may quote edited or deleted messages so the summarizer rebuilds
+/// them from the remaining history.
+fn summary_dirty_flag(tx: &Transaction<'_>) -> anyhow::Result<()> {
+    add_column(
+        tx,
+        "channel_summaries",
+        "dirty BOOLEAN NOT NULL DEFAULT FALSE",
+    )?;
+    Ok(())
+}
+
+/// v6: resumable progress for `/settings memory backfill`, one row per channel.
+fn backfill_checkpoints(tx: &Transaction<'_>) -> anyhow::Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS backfill_checkpoints (
+            channel_id TEXT PRIMARY KEY,
+            guild_id TEXT NOT NULL,
+            since DATETIME,
+            before_message_id TEXT,
+            fetched INTEGER NOT NULL DEFAULT 0,
+            saved INTEGER NOT NULL DEFAULT 0,
+            started_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            completed_at DATETIME
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+/// v7: link threads and forum posts to their parent channel. Messages keep the thread id as
+/// `channel_id`; the thread's starter message shares the thread's id.
+fn channel_threads(tx: &Transaction<'_>) -> anyhow::Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS channel_threads (
+            channel_id TEXT PRIMARY KEY,
+            guild_id TEXT NOT NULL,
+            parent_channel_id TEXT NOT NULL,
+            name TEXT NOT NULL DEFAULT '',
+            is_forum_post BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX IF NOT EXISTS idx_channel_threads_parent ON channel_threads (parent_channel_id);
+        ",
+    )?;
+    Ok(())
+}
+
+/// v8: text extracted from message attachments, split into chunks that are embedded and
+/// keyword-indexed like messages. Chunks go away with their message.
+fn attachment_chunks(tx: &Transaction<'_>) -> anyhow::Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS attachment_chunks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            message_id INTEGER NOT NULL,
+            attachment_id TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            is_indexed BOOLEAN NOT NULL DEFAULT FALSE,
+            embedding BLOB NULL,
+            embedding_model TEXT,
+            embedding_dim INTEGER,
+            embedding_attempts INTEGER NOT NULL DEFAULT 0,
+            embedding_retry_at DATETIME,
+            embedding_error TEXT,
+            UNIQUE (attachment_id, chunk_index)
+        );
+        CREATE INDEX IF NOT EXISTS idx_attachment_chunks_message ON attachment_chunks (message_id);
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS attachment_chunks_fts USING fts5(
+            content,
+            content = 'attachment_chunks',
+            content_rowid = 'id',
+            tokenize = 'porter unicode61'
+        );
+        CREATE TRIGGER IF NOT EXISTS attachment_chunks_fts_ai AFTER INSERT ON attachment_chunks BEGIN
+            INSERT INTO attachment_chunks_fts(rowid, content) VALUES (new.id, new.content);
+        END;
+        CREATE TRIGGER IF NOT EXISTS attachment_chunks_fts_ad AFTER DELETE ON attachment_chunks BEGIN
+            INSERT INTO attachment_chunks_fts(attachment_chunks_fts, rowid, content)
+                VALUES ('delete', old.id, old.content);
+        END;
+        CREATE TRIGGER IF NOT EXISTS messages_attachment_chunks_ad AFTER DELETE ON messages BEGIN
+            DELETE FROM attachment_chunks WHERE message_id = old.id;
+        END;
+        ",
+    )?;
+    Ok(())
+}
+
+/// v9: agent runs and the steps (LLM turns and tool calls) they took, looked up through the
+/// ids of the bot messages that show the answer. Steps and message links go away with the run.
+fn agent_runs(tx: &Transaction<'_>) -> anyhow::Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS agent_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id TEXT,
+            channel_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            trigger_message_id TEXT,
+            final_answer TEXT,
+            error TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX IF NOT EXISTS idx_agent_runs_created ON agent_runs (created_at);
+        CREATE INDEX IF NOT EXISTS idx_agent_runs_user ON agent_runs (user_id);
+
+        CREATE TABLE IF NOT EXISTS agent_steps (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            run_id INTEGER NOT NULL,
+            iteration INTEGER NOT NULL,
+            request_summary TEXT NOT NULL,
+            content TEXT,
+            tool_calls TEXT NOT NULL DEFAULT '[]',
+            latency_ms INTEGER NOT NULL DEFAULT 0,
+            UNIQUE (run_id, iteration)
+        );
+
+        CREATE TABLE IF NOT EXISTS agent_run_messages (
+            message_id TEXT PRIMARY KEY,
+            run_id INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_agent_run_messages_run ON agent_run_messages (run_id);
+
+        CREATE TRIGGER IF NOT EXISTS agent_runs_ad AFTER DELETE ON agent_runs BEGIN
+            DELETE FROM agent_steps WHERE run_id = old.id;
+            DELETE FROM agent_run_messages WHERE run_id = old.id;
+        END;
+        ",
+    )?;
+    Ok(())
+}
+
+/// v10: allow/deny/confirm rules for tools and MCP servers, per guild, channel or role.
+fn tool_policies(tx: &Transaction<'_>) -> anyhow::Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS tool_policies (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id TEXT NOT NULL,
+            scope TEXT NOT NULL,
+            scope_id TEXT NOT NULL,
+            target TEXT NOT NULL,
+            target_name TEXT NOT NULL,
+            action TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (guild_id, scope, scope_id, target, target_name)
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Schema produced by `execute_init` before versioned migrations existed.
+    const BASELINE_FIXTURE: &str = include_str!("fixtures/baseline.sql");
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(&format!("PRAGMA table_info({})", table))
+            .unwrap();
+        stmt.query_map([], |row| row.get(1))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fresh_database_reaches_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(current_version(&conn).unwrap(), 0);
+
+        let applied = run(&mut conn).unwrap();
+        assert_eq!(applied, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+
+        // Second run is a no-op.
+        assert!(run(&mut conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_upgrades_baseline_fixture() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+
+        run(&mut conn).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+        let prompt: String = conn
+            .query_row(
+                "SELECT system_prompt FROM settings WHERE guild_id = 'g1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(prompt, "be brief");
+
+        let hits: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'deploy'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hits, 1);
+    }
+
+    #[test]
+    fn test_upgrades_pre_fts_database_with_missing_columns() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                discord_id TEXT NOT NULL UNIQUE,
+                guild_id TEXT NOT NULL,
+                channel_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                content TEXT NOT NULL,
+                timestamp DATETIME NOT NULL,
+                is_indexed BOOLEAN DEFAULT FALSE
+            );
+            CREATE TABLE settings (
+                guild_id TEXT PRIMARY KEY,
+                context_limit INTEGER,
+                context_retention INTEGER
+            );
+            INSERT INTO messages (discord_id, guild_id, channel_id, user_id, content, timestamp)
+            VALUES ('1', 'g1', 'c1', 'u1', 'deploying tonight', '2024-01-01 00:00:00');
+            ",
+        )
+        .unwrap();
+
+        run(&mut conn).unwrap();
+
+        assert!(columns(&conn, "messages").contains(&"embedding".to_string()));
+        let settings = columns(&conn, "settings");
+        assert!(settings.contains(&"system_prompt".to_string()));
+        assert!(settings.contains(&"voice_idle_timeout_secs".to_string()));
+
+        let hits: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'deploy'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hits, 1);
+    }
+
+    #[test]
+    fn test_refuses_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?1, 'future')",
+            [latest_version() + 1],
+        )
+        .unwrap();
+
+        let err = run(&mut conn).unwrap_err();
+        assert!(err.to_string().contains("newer than this build supports"));
+    }
+}
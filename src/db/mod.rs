@@ -1,10 +1,19 @@
 use crate::config::Config;
+use crate::rag::ann::{HnswIndex, VectorIndex};
+use crate::rag::ThreadFilter;
+use crate::tools::policy::{PolicyAction, PolicyScope, PolicyTarget, ToolPolicy};
 use anyhow::Context as AnyhowContext;
 use chrono::{DateTime, NaiveDateTime, Utc};
-use rusqlite::Connection;
-use std::collections::HashSet;
-use std::sync::{Arc, Mutex};
-use tracing::{debug, info};
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, MutexGuard};
+use tracing::{debug, info, warn};
+
+pub mod migrations;
+mod pool;
+
+use pool::ConnectionPool;
 
 fn serialize_embedding(vec: &[f32]) -> Vec<u8> {
     let mut out = Vec::with_capacity(vec.len() * 4);
@@ -14,6 +23,13 @@ fn serialize_embedding(vec: &[f32]) -> Vec<u8> {
     out
 }
 
+fn deserialize_embedding(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
 fn cosine_similarity_bytes(query: &[f32], query_norm: f32, candidate_bytes: &[u8]) -> f32 {
     if query.is_empty() || query_norm == 0.0 {
         return 0.0;
@@ -45,6 +61,72 @@ fn cosine_similarity_bytes(query: &[f32], query_norm: f32, candidate_bytes: &[u8
 const RECENCY_WINDOW_DAYS: i64 = 30;
 const RECENCY_MAX_BOOST: f32 = 0.05;
 
+/// Record a failed embedding attempt for a row of `table` (`messages` or `attachment_chunks`,
+/// which share the embedding bookkeeping columns). See [`Database::record_embedding_failure`].
+fn record_embedding_failure_in(
+    conn: &Connection,
+    table: &str,
+    id: i64,
+    error: &str,
+    max_attempts: u32,
+    backoff: std::time::Duration,
+) -> anyhow::Result<bool> {
+    let attempts: u32 = conn
+        .query_row(
+            &format!("SELECT embedding_attempts FROM {table} WHERE id = ?1"),
+            [id],
+            |row| row.get(0),
+        )
+        .context("Failed to read embedding attempts")?;
+    let attempts = attempts + 1;
+
+    if attempts >= max_attempts {
+        conn.execute(
+            &format!(
+                "UPDATE {table}
+                 SET embedding_attempts = ?2,
+                     embedding_error = ?3,
+                     embedding_retry_at = NULL,
+                     is_indexed = 1,
+                     embedding = NULL,
+                     embedding_model = NULL,
+                     embedding_dim = NULL
+                 WHERE id = ?1"
+            ),
+            (id, attempts, error),
+        )
+        .context("Failed to record embedding failure")?;
+        return Ok(true);
+    }
+
+    let delay_secs = backoff
+        .as_secs()
+        .max(1)
+        .saturating_mul(1u64 << (attempts - 1).min(16));
+    conn.execute(
+        &format!(
+            "UPDATE {table}
+             SET embedding_attempts = ?2,
+                 embedding_error = ?3,
+                 embedding_retry_at = datetime('now', ?4)
+             WHERE id = ?1"
+        ),
+        (id, attempts, error, format!("+{} seconds", delay_secs)),
+    )
+    .context("Failed to record embedding failure")?;
+    Ok(false)
+}
+
+/// Flag a channel's summary for a rebuild (no-op if the channel has no summary yet).
+fn mark_summary_dirty(conn: &Connection, channel_id: &str) -> anyhow::Result<()> {
+    conn.execute(
+        "UPDATE channel_summaries SET dirty = 1 WHERE channel_id = ?1",
+        [channel_id],
+    )
+    .context("Failed to mark channel summary dirty")?;
+    Ok(())
+}
+
 fn parse_sqlite_timestamp(ts: &str) -> Option<DateTime<Utc>> {
     let naive = NaiveDateTime::parse_from_str(ts, "%Y-%m-%d %H:%M:%S").ok()?;
     Some(DateTime::<Utc>::from_naive_utc_and_offset(naive, Utc))
@@ -64,6 +146,13 @@ fn recency_boost(timestamp: &str, now: &DateTime<Utc>) -> f32 {
     }
 }
 
+/// A search candidate with its similarity (after the recency boost) and row id.
+struct ScoredMessage {
+    id: i64,
+    score: f32,
+    message: crate::rag::MessageResult,
+}
+
 fn message_dedupe_key(msg: &crate::rag::MessageResult) -> String {
     format!(
         "{}|{}|{}|{}",
@@ -71,36 +160,246 @@ fn message_dedupe_key(msg: &crate::rag::MessageResult) -> String {
     )
 }
 
+/// Map a `(filename, content, user_id, timestamp, channel_id)` row of an attachment chunk
+/// query to a search result attributed to the message it was attached to.
+fn attachment_chunk_result(row: &rusqlite::Row<'_>) -> rusqlite::Result<crate::rag::MessageResult> {
+    let filename: String = row.get(0)?;
+    let content: String = row.get(1)?;
+    Ok(crate::rag::MessageResult {
+        content: format!("[attachment {}] {}", filename, content),
+        user_id: row.get(2)?,
+        timestamp: row.get(3)?,
+        channel_id: row.get(4)?,
+    })
+}
+
+/// Append the guild scope and optional channel/author/date constraints of `filter` to a query
+/// over `messages m`.
+fn push_search_filter(
+    sql: &mut String,
+    params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+    filter: &crate::rag::SearchFilter,
+) {
+    fn push_in_list(
+        sql: &mut String,
+        params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+        clause: &str,
+        values: &[String],
+    ) {
+        if values.is_empty() {
+            return;
+        }
+        sql.push_str(clause);
+        sql.push_str(" (");
+        sql.push_str(&vec!["?"; values.len()].join(", "));
+        sql.push(')');
+        for value in values {
+            params.push(Box::new(value.clone()));
+        }
+    }
+
+    fn push_channel_list(
+        sql: &mut String,
+        params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+        op: &str,
+        channels: &[String],
+        with_threads: bool,
+    ) {
+        if channels.is_empty() {
+            return;
+        }
+        let placeholders = vec!["?"; channels.len()].join(", ");
+        let joiner = if op == "IN" { "OR" } else { "AND" };
+        if with_threads {
+            sql.push_str(&format!(
+                " AND (m.channel_id {op} ({placeholders}) {joiner} m.channel_id {op} \
+                 (SELECT channel_id FROM channel_threads WHERE parent_channel_id IN ({placeholders})))"
+            ));
+            for channel in channels.iter().chain(channels) {
+                params.push(Box::new(channel.clone()));
+            }
+        } else {
+            sql.push_str(&format!(" AND m.channel_id {op} ({placeholders})"));
+            for channel in channels {
+                params.push(Box::new(channel.clone()));
+            }
+        }
+    }
+
+    sql.push_str(" AND m.guild_id = ?");
+    params.push(Box::new(filter.guild_id.clone()));
+
+    // Unless threads are filtered out, naming a channel also covers the threads created in it.
+    let expand = filter.threads != ThreadFilter::Exclude;
+    push_channel_list(sql, params, "IN", &filter.channels, expand);
+    push_channel_list(sql, params, "NOT IN", &filter.exclude_channels, true);
+    match filter.threads {
+        ThreadFilter::Include => {}
+        ThreadFilter::Exclude => {
+            sql.push_str(" AND m.channel_id NOT IN (SELECT channel_id FROM channel_threads)")
+        }
+        ThreadFilter::Only => {
+            sql.push_str(" AND m.channel_id IN (SELECT channel_id FROM channel_threads)")
+        }
+    }
+    push_in_list(sql, params, " AND m.user_id IN", &filter.authors);
+
+    if let Some(from) = filter.from_date {
+        sql.push_str(" AND m.timestamp >= ?");
+        params.push(Box::new(from.format("%Y-%m-%d %H:%M:%S").to_string()));
+    }
+
+    if let Some(to) = filter.to_date {
+        sql.push_str(" AND m.timestamp <= ?");
+        params.push(Box::new(to.format("%Y-%m-%d %H:%M:%S").to_string()));
+    }
+}
+
+/// Constant from the original reciprocal rank fusion paper; dampens the weight of top ranks.
+const RRF_K: f32 = 60.0;
+
+/// Fuse ranked result lists with reciprocal rank fusion.
+///
+/// Each list contributes `1 / (RRF_K + rank)` per message, so results found by several lists
+/// (e.g. both vector and keyword search) rise to the top. Ties keep the order of earlier lists.
 fn merge_results(
-    primary: Vec<crate::rag::MessageResult>,
-    secondary: Vec<crate::rag::MessageResult>,
+    lists: Vec<Vec<crate::rag::MessageResult>>,
     limit: usize,
 ) -> Vec<crate::rag::MessageResult> {
-    let mut seen = HashSet::new();
-    let mut merged = Vec::new();
-
-    for msg in primary.into_iter().chain(secondary.into_iter()) {
-        let key = message_dedupe_key(&msg);
-        if seen.insert(key) {
-            merged.push(msg);
-            if merged.len() >= limit {
-                break;
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut fused: Vec<(f32, crate::rag::MessageResult)> = Vec::new();
+
+    for list in lists {
+        for (rank, msg) in list.into_iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f32 + 1.0);
+            let key = message_dedupe_key(&msg);
+            match positions.get(&key) {
+                Some(&idx) => fused[idx].0 += score,
+                None => {
+                    positions.insert(key, fused.len());
+                    fused.push((score, msg));
+                }
+            }
+        }
+    }
+
+    // Stable sort keeps insertion (primary-first) order for equal scores.
+    fused.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    fused.truncate(limit);
+    fused.into_iter().map(|(_, msg)| msg).collect()
+}
+
+/// Turn free-form user input into a safe FTS5 MATCH expression.
+///
+/// Supported syntax: `"quoted phrases"`, `prefix*` and `OR` between terms; everything else is
+/// treated as literal terms that must all match. Returns `None` if nothing searchable remains.
+fn fts5_match_expression(query: &str) -> Option<String> {
+    fn quote(term: &str) -> Option<String> {
+        if !term.chars().any(char::is_alphanumeric) {
+            return None;
+        }
+        Some(format!("\"{}\"", term.replace('"', "\"\"")))
+    }
+
+    let mut parts: Vec<String> = Vec::new();
+    let mut rest = query.trim();
+    while !rest.is_empty() {
+        if let Some(after_quote) = rest.strip_prefix('"') {
+            let (phrase, remaining) = match after_quote.find('"') {
+                Some(end) => (&after_quote[..end], &after_quote[end + 1..]),
+                None => (after_quote, ""),
+            };
+            if let Some(quoted) = quote(phrase.trim()) {
+                parts.push(quoted);
+            }
+            rest = remaining.trim_start();
+            continue;
+        }
+
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '"')
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        rest = rest[end..].trim_start();
+
+        if word == "OR" {
+            if parts.last().is_some_and(|p| p != "OR") {
+                parts.push("OR".to_string());
             }
+            continue;
+        }
+        let (term, prefix) = match word.strip_suffix('*') {
+            Some(term) => (term, true),
+            None => (word, false),
+        };
+        if let Some(quoted) = quote(term) {
+            parts.push(if prefix {
+                format!("{}*", quoted)
+            } else {
+                quoted
+            });
         }
     }
 
-    merged
+    if parts.last().is_some_and(|p| p == "OR") {
+        parts.pop();
+    }
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
 }
 
 #[derive(Clone)]
 pub struct Database {
-    conn: Arc<Mutex<Connection>>,
+    pool: Arc<ConnectionPool>,
+    vector_index: VectorIndex,
+    /// Model that new embeddings come from; only vectors from this model are searched.
+    embedding_model: Arc<str>,
 }
 
 pub struct ChannelSummaryRecord {
     pub summary: String,
     pub updated_at: String,
     pub refreshed_at: String,
+    /// Messages covered by the summary were edited or deleted since it was written.
+    pub dirty: bool,
+}
+
+/// A thread or forum post and the channel it was created in. The thread's starter message has
+/// the same id as the thread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelThread {
+    pub channel_id: String,
+    pub guild_id: String,
+    pub parent_channel_id: String,
+    pub name: String,
+    pub is_forum_post: bool,
+}
+
+/// One chunk of text extracted from a message attachment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttachmentChunkRecord {
+    /// Discord id of the message the file was attached to.
+    pub message_discord_id: String,
+    pub filename: String,
+    pub chunk_index: i64,
+    pub content: String,
+}
+
+/// Progress of a `/settings memory backfill` run for one channel.
+#[derive(Debug, Clone)]
+pub struct BackfillCheckpoint {
+    pub channel_id: String,
+    pub guild_id: String,
+    /// Oldest timestamp to backfill (`YYYY-MM-DD HH:MM:SS`); `None` means full history.
+    pub since: Option<String>,
+    /// Oldest message fetched so far; the next page is requested before it.
+    pub before_message_id: Option<String>,
+    pub fetched: i64,
+    pub saved: i64,
+    pub completed_at: Option<String>,
 }
 
 pub struct UserMemoryRecord {
@@ -110,6 +409,74 @@ pub struct UserMemoryRecord {
     pub expires_at: Option<String>,
 }
 
+/// Progress of embedding messages with the active model (see `/embedding_status`).
+pub struct EmbeddingStatus {
+    pub active_model: String,
+    /// Dimensions of the active model's stored vectors (normally exactly one).
+    pub active_dims: Vec<i64>,
+    /// Messages embedded with the active model.
+    pub current: i64,
+    /// Messages embedded with other models, waiting to be re-embedded, by model.
+    pub stale_by_model: Vec<(Option<String>, i64)>,
+    /// Messages that have never been embedded.
+    pub pending: i64,
+    /// Messages skipped by the indexer (too short to embed).
+    pub skipped: i64,
+    /// Messages given up on after repeated embedding failures.
+    pub failed: i64,
+}
+
+impl EmbeddingStatus {
+    pub fn stale(&self) -> i64 {
+        self.stale_by_model.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Share of embeddable messages that are embedded with the active model (0.0..=1.0).
+    pub fn progress(&self) -> f64 {
+        let total = self.current + self.stale() + self.pending;
+        if total == 0 {
+            1.0
+        } else {
+            self.current as f64 / total as f64
+        }
+    }
+}
+
+/// One tool call made by the agent, with its result (or error text).
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AgentToolCallRecord {
+    pub id: String,
+    pub name: String,
+    /// Arguments as the raw JSON string the model produced.
+    pub arguments: String,
+    pub result: String,
+    pub latency_ms: u64,
+}
+
+/// One agent iteration: an LLM turn and the tool calls it asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentStepRecord {
+    /// Size of the request sent to the LLM, e.g. "12 messages, 5 tools".
+    pub request_summary: String,
+    pub content: Option<String>,
+    pub tool_calls: Vec<AgentToolCallRecord>,
+    /// Time spent waiting for the LLM.
+    pub latency_ms: u64,
+}
+
+/// A completed (or failed) agent run and the steps it took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentRunRecord {
+    pub guild_id: Option<String>,
+    pub channel_id: String,
+    pub user_id: String,
+    /// The user message that triggered the run; `None` for slash commands.
+    pub trigger_message_id: Option<String>,
+    pub final_answer: Option<String>,
+    pub error: Option<String>,
+    pub steps: Vec<AgentStepRecord>,
+}
+
 pub struct ReminderRecord {
     pub id: i64,
     pub guild_id: String,
@@ -123,18 +490,36 @@ pub struct ReminderRecord {
 
 impl Database {
     pub fn new(config: &Config) -> anyhow::Result<Self> {
-        let conn = Connection::open(&config.database_url)
-            .with_context(|| format!("Failed to open database at '{}'", config.database_url))?;
+        let pool = ConnectionPool::open(
+            &config.database_url,
+            config.database_read_connections,
+            std::time::Duration::from_millis(config.database_busy_timeout_ms),
+        )?;
+        debug!(
+            "Database: Opened '{}' with 1 writer and {} reader connection(s)",
+            config.database_url,
+            pool.reader_count()
+        );
+
+        // The ANN index lives next to the database file (in-memory databases keep it in memory).
+        let index_path = (config.vector_index_enabled && config.database_url != ":memory:")
+            .then(|| PathBuf::from(format!("{}.hnsw", config.database_url)));
 
         Ok(Self {
-            conn: Arc::new(Mutex::new(conn)),
+            pool: Arc::new(pool),
+            vector_index: VectorIndex::new(index_path, &config.embedding_model),
+            embedding_model: Arc::from(config.embedding_model.as_str()),
         })
     }
 
-    fn lock_conn(&self) -> anyhow::Result<std::sync::MutexGuard<'_, Connection>> {
-        self.conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Database connection lock poisoned"))
+    /// The single writer connection. Use for anything that modifies the database.
+    fn write_conn(&self) -> anyhow::Result<MutexGuard<'_, Connection>> {
+        self.pool.writer()
+    }
+
+    /// A read-only connection; reads here do not wait behind writes.
+    fn read_conn(&self) -> anyhow::Result<MutexGuard<'_, Connection>> {
+        self.pool.reader()
     }
 
     pub async fn run_blocking<T, F>(&self, f: F) -> anyhow::Result<T>
@@ -146,135 +531,126 @@ impl Database {
         tokio::task::spawn_blocking(move || f(&db)).await?
     }
 
+    /// Bring the schema up to date by applying pending migrations (see [`migrations`]).
     pub fn execute_init(&self) -> anyhow::Result<()> {
         info!("Database: Initializing schema...");
-        let conn = self.lock_conn()?;
+        let mut conn = self.write_conn()?;
 
-        // Base schema (idempotent).
-        conn.execute_batch(
-            "
-            CREATE TABLE IF NOT EXISTS messages (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                discord_id TEXT NOT NULL UNIQUE,
-                guild_id TEXT NOT NULL,
-                channel_id TEXT NOT NULL,
-                user_id TEXT NOT NULL,
-                content TEXT NOT NULL,
-                timestamp DATETIME NOT NULL,
-                is_indexed BOOLEAN DEFAULT FALSE
-            );
-            CREATE INDEX IF NOT EXISTS idx_messages_channel_date ON messages (channel_id, timestamp);
-            CREATE INDEX IF NOT EXISTS idx_messages_guild_date ON messages (guild_id, timestamp);
-
-            CREATE TABLE IF NOT EXISTS settings (
-                guild_id TEXT PRIMARY KEY,
-                context_limit INTEGER,
-                context_retention INTEGER,
-                system_prompt TEXT,
-                agent_confirm_timeout_secs INTEGER,
-                voice_idle_timeout_secs INTEGER
+        let applied = migrations::run(&mut conn).context("Failed to migrate database schema")?;
+        if applied.is_empty() {
+            debug!("Database: Schema is up to date");
+        } else {
+            info!(
+                "Database: Applied {} migration(s); schema is at version {}",
+                applied.len(),
+                migrations::latest_version()
             );
+        }
 
-            CREATE TABLE IF NOT EXISTS channel_summaries (
-                channel_id TEXT PRIMARY KEY,
-                summary TEXT NOT NULL,
-                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                refreshed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        // Embeddings stored before model tracking existed stay unlabelled: the model that
+        // produced them is unknown (it may predate an `EMBEDDING_MODEL` change), so the indexer
+        // re-embeds them like any other stale vector.
+        let stale: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM messages
+                 WHERE embedding IS NOT NULL AND embedding_model IS NOT ?1",
+                [&*self.embedding_model],
+                |row| row.get(0),
+            )
+            .context("Failed to count stale embeddings")?;
+        if stale > 0 {
+            warn!(
+                "Database: {} messages were embedded with a different model than '{}'; they are excluded from semantic search until re-embedded",
+                stale, self.embedding_model
             );
+        }
 
-            CREATE TABLE IF NOT EXISTS channel_settings (
-                guild_id TEXT NOT NULL,
-                channel_id TEXT PRIMARY KEY,
-                enabled BOOLEAN DEFAULT TRUE,
-                memory_start_date DATETIME,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            );
-            CREATE INDEX IF NOT EXISTS idx_channel_guild ON channel_settings (guild_id);
+        debug!("Database: Schema initialized successfully");
+        Ok(())
+    }
 
-            CREATE TABLE IF NOT EXISTS channel_milestones (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                channel_id TEXT NOT NULL,
-                milestone TEXT NOT NULL,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            );
-            CREATE INDEX IF NOT EXISTS idx_milestones_channel_created
-              ON channel_milestones (channel_id, created_at);
-
-            CREATE TABLE IF NOT EXISTS user_memory (
-                user_id TEXT PRIMARY KEY,
-                summary TEXT NOT NULL,
-                enabled BOOLEAN DEFAULT TRUE,
-                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                expires_at DATETIME
-            );
+    /// Schema version recorded in the database.
+    pub fn schema_version(&self) -> anyhow::Result<u32> {
+        let conn = self.read_conn()?;
+        migrations::current_version(&conn)
+    }
 
-            CREATE TABLE IF NOT EXISTS reminders (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                guild_id TEXT NOT NULL,
-                channel_id TEXT NOT NULL,
-                user_id TEXT NOT NULL,
-                message TEXT NOT NULL,
-                remind_at DATETIME NOT NULL,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                delivered_at DATETIME
-            );
-            CREATE INDEX IF NOT EXISTS idx_reminders_due ON reminders (remind_at, delivered_at);
-            CREATE INDEX IF NOT EXISTS idx_reminders_user ON reminders (user_id, delivered_at);
-            ",
-        )
-        .context("Failed to initialize database schema")?;
-
-        // Lightweight migrations. These must be safe to run repeatedly.
-        // SQLite doesn't support `ADD COLUMN IF NOT EXISTS`, so we ignore "duplicate column name".
-        if let Err(e) = conn.execute("ALTER TABLE messages ADD COLUMN embedding BLOB NULL", []) {
-            let msg = e.to_string();
-            if !msg.contains("duplicate column name") {
-                return Err(e).context("Failed to migrate: add messages.embedding column");
-            }
-        }
+    /// Load the persisted ANN index (or rebuild it from stored embeddings) and start using it
+    /// for semantic search.
+    ///
+    /// A persisted index is reused when it was built for the configured embedding model; any
+    /// embeddings added since it was saved are inserted. It is rebuilt from scratch when the
+    /// model changed, the file is missing or unreadable, or too many entries point at deleted
+    /// messages.
+    pub fn load_vector_index(&self) -> anyhow::Result<()> {
+        let model = self.vector_index.model();
+        let conn = self.read_conn()?;
 
-        if let Err(e) = conn.execute(
-            "ALTER TABLE channel_summaries ADD COLUMN refreshed_at DATETIME DEFAULT CURRENT_TIMESTAMP",
-            [],
-        ) {
-            let msg = e.to_string();
-            if !msg.contains("duplicate column name") {
-                return Err(e).context("Failed to migrate: add channel_summaries.refreshed_at column");
-            }
-        }
+        let live_ids: HashSet<i64> = {
+            let mut stmt = conn.prepare_cached(
+                "SELECT id FROM messages WHERE embedding IS NOT NULL AND embedding_model = ?1",
+            )?;
+            let rows = stmt.query_map([&model], |row| row.get(0))?;
+            rows.collect::<Result<_, _>>()?
+        };
 
-        if let Err(e) = conn.execute("ALTER TABLE settings ADD COLUMN system_prompt TEXT", []) {
-            let msg = e.to_string();
-            if !msg.contains("duplicate column name") {
-                return Err(e).context("Failed to migrate: add settings.system_prompt column");
+        let (mut index, mut dirty) = match self.vector_index.load_persisted(&model) {
+            Some(index) => {
+                let stale = index.ids().filter(|id| !live_ids.contains(id)).count();
+                if stale * 5 > index.len() {
+                    info!(
+                        "Vector index has {} stale entries out of {}; rebuilding",
+                        stale,
+                        index.len()
+                    );
+                    (HnswIndex::new(&model), true)
+                } else {
+                    (index, false)
+                }
             }
-        }
+            None => (HnswIndex::new(&model), true),
+        };
 
-        if let Err(e) = conn.execute(
-            "ALTER TABLE settings ADD COLUMN agent_confirm_timeout_secs INTEGER",
-            [],
-        ) {
-            let msg = e.to_string();
-            if !msg.contains("duplicate column name") {
-                return Err(e)
-                    .context("Failed to migrate: add settings.agent_confirm_timeout_secs column");
+        let start = std::time::Instant::now();
+        let mut added = 0usize;
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, embedding FROM messages
+             WHERE embedding IS NOT NULL AND embedding_model = ?1
+             ORDER BY id",
+        )?;
+        let mut rows = stmt.query([&model])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            if index.contains(id) {
+                continue;
             }
-        }
-
-        if let Err(e) = conn.execute(
-            "ALTER TABLE settings ADD COLUMN voice_idle_timeout_secs INTEGER",
-            [],
-        ) {
-            let msg = e.to_string();
-            if !msg.contains("duplicate column name") {
-                return Err(e)
-                    .context("Failed to migrate: add settings.voice_idle_timeout_secs column");
+            let embedding = deserialize_embedding(&row.get::<_, Vec<u8>>(1)?);
+            match index.insert(id, &embedding) {
+                Ok(()) => added += 1,
+                Err(e) => warn!("Vector index: skipping message {}: {}", id, e),
             }
         }
+        dirty |= added > 0;
 
-        debug!("Database: Schema initialized successfully");
-        Ok(())
+        info!(
+            "Database: Vector index ready ({} vectors, {} added in {:?})",
+            index.len(),
+            added,
+            start.elapsed()
+        );
+        self.vector_index.install(index, dirty);
+        drop(rows);
+        drop(stmt);
+        drop(conn);
+
+        self.persist_vector_index()
+    }
+
+    /// Save the ANN index next to the database if it changed.
+    pub fn persist_vector_index(&self) -> anyhow::Result<()> {
+        self.vector_index
+            .persist()
+            .context("Failed to persist vector index")
     }
 
     pub fn save_message(
@@ -285,31 +661,100 @@ impl Database {
         user_id: &str,
         content: &str,
         timestamp: i64,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<bool> {
         debug!(
             "Database: Saving message {} from user {} in channel {}",
             discord_id, user_id, channel_id
         );
-        let conn = self.lock_conn()?;
-        conn.execute(
-            "INSERT OR IGNORE INTO messages (discord_id, guild_id, channel_id, user_id, content, timestamp) 
+        let conn = self.write_conn()?;
+        let inserted = conn.prepare_cached(
+            "INSERT OR IGNORE INTO messages (discord_id, guild_id, channel_id, user_id, content, timestamp)
              VALUES (?1, ?2, ?3, ?4, ?5, datetime(?6, 'unixepoch'))",
-            (discord_id, guild_id, channel_id, user_id, content, timestamp),
-        ).context("Failed to save message")?;
-        Ok(())
+        )?
+        .execute((discord_id, guild_id, channel_id, user_id, content, timestamp))
+        .context("Failed to save message")?;
+        Ok(inserted > 0)
+    }
+
+    /// Apply an edit from the gateway. The stored embedding is dropped so the indexer re-embeds
+    /// the new text, and the channel summary is marked dirty. Returns the message's channel if
+    /// a stored message changed.
+    pub fn update_message_content(
+        &self,
+        discord_id: &str,
+        content: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let mut conn = self.write_conn()?;
+        let tx = conn.transaction()?;
+        let channel_id: Option<String> = tx
+            .query_row(
+                "UPDATE messages
+                 SET content = ?2,
+                     embedding = NULL,
+                     embedding_model = NULL,
+                     embedding_dim = NULL,
+                     is_indexed = 0,
+                     embedding_attempts = 0,
+                     embedding_retry_at = NULL,
+                     embedding_error = NULL
+                 WHERE discord_id = ?1 AND content != ?2
+                 RETURNING channel_id",
+                (discord_id, content),
+                |row| row.get(0),
+            )
+            .optional()
+            .context("Failed to update message content")?;
+        if let Some(channel_id) = &channel_id {
+            mark_summary_dirty(&tx, channel_id)?;
+        }
+        tx.commit()?;
+        Ok(channel_id)
+    }
+
+    /// Remove messages deleted on Discord and mark their channel summaries dirty. Keyword
+    /// index rows follow via triggers; ANN entries are re-checked against SQLite at query time
+    /// and dropped on the next rebuild. Returns the number of messages removed.
+    pub fn delete_messages(&self, discord_ids: &[String]) -> anyhow::Result<usize> {
+        if discord_ids.is_empty() {
+            return Ok(0);
+        }
+        let mut conn = self.write_conn()?;
+        let tx = conn.transaction()?;
+        let mut channels = HashSet::new();
+        let mut removed = 0usize;
+        {
+            let mut stmt =
+                tx.prepare("DELETE FROM messages WHERE discord_id = ?1 RETURNING channel_id")?;
+            for discord_id in discord_ids {
+                let channel_id: Option<String> = stmt
+                    .query_row([discord_id], |row| row.get(0))
+                    .optional()
+                    .context("Failed to delete message")?;
+                if let Some(channel_id) = channel_id {
+                    removed += 1;
+                    channels.insert(channel_id);
+                }
+            }
+        }
+        for channel_id in &channels {
+            mark_summary_dirty(&tx, channel_id)?;
+        }
+        tx.commit()?;
+        Ok(removed)
     }
 
     pub fn get_messages_missing_embeddings(
         &self,
         limit: usize,
     ) -> anyhow::Result<Vec<(i64, String)>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare(
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
             "SELECT id, content
              FROM messages
              WHERE embedding IS NULL
                AND is_indexed = 0
                AND length(content) > 0
+               AND (embedding_retry_at IS NULL OR embedding_retry_at <= CURRENT_TIMESTAMP)
              ORDER BY timestamp DESC
              LIMIT ?1",
         )?;
@@ -323,25 +768,135 @@ impl Database {
         Ok(results)
     }
 
+    /// Messages whose stored embedding came from a model other than the active one, newest
+    /// first, so the indexer can re-embed them progressively.
+    pub fn get_messages_with_stale_embeddings(
+        &self,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(i64, String)>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, content
+             FROM messages
+             WHERE embedding IS NOT NULL
+               AND embedding_model IS NOT ?1
+               AND (embedding_retry_at IS NULL OR embedding_retry_at <= CURRENT_TIMESTAMP)
+             ORDER BY timestamp DESC
+             LIMIT ?2",
+        )?;
+
+        let rows = stmt.query_map((&*self.embedding_model, limit), |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+        Ok(results)
+    }
+
+    pub fn get_embedding_status(&self) -> anyhow::Result<EmbeddingStatus> {
+        let conn = self.read_conn()?;
+        let mut status = EmbeddingStatus {
+            active_model: self.embedding_model.to_string(),
+            active_dims: Vec::new(),
+            current: 0,
+            stale_by_model: Vec::new(),
+            pending: 0,
+            skipped: 0,
+            failed: 0,
+        };
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT embedding_model, embedding_dim, COUNT(*)
+             FROM messages
+             WHERE embedding IS NOT NULL
+             GROUP BY embedding_model, embedding_dim
+             ORDER BY COUNT(*) DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (model, dim, count) = row?;
+            if model.as_deref() == Some(&*self.embedding_model) {
+                status.current += count;
+                status.active_dims.extend(dim);
+            } else {
+                match status.stale_by_model.iter_mut().find(|(m, _)| *m == model) {
+                    Some((_, total)) => *total += count,
+                    None => status.stale_by_model.push((model, count)),
+                }
+            }
+        }
+
+        (status.pending, status.skipped, status.failed) = conn.query_row(
+            "SELECT
+                 COALESCE(SUM(is_indexed = 0 AND length(content) > 0), 0),
+                 COALESCE(SUM(is_indexed = 1 AND embedding_error IS NULL), 0),
+                 COALESCE(SUM(is_indexed = 1 AND embedding_error IS NOT NULL), 0)
+             FROM messages
+             WHERE embedding IS NULL",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        Ok(status)
+    }
+
     pub fn set_message_embedding(&self, message_id: i64, embedding: &[f32]) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         let embedding_blob = serialize_embedding(embedding);
-        conn.execute(
+        conn.prepare_cached(
             "UPDATE messages
              SET embedding = ?1,
-                 is_indexed = 1
-             WHERE id = ?2",
-            (embedding_blob, message_id),
-        )
+                 embedding_model = ?2,
+                 embedding_dim = ?3,
+                 is_indexed = 1,
+                 embedding_attempts = 0,
+                 embedding_retry_at = NULL,
+                 embedding_error = NULL
+             WHERE id = ?4",
+        )?
+        .execute((
+            embedding_blob,
+            &*self.embedding_model,
+            embedding.len() as i64,
+            message_id,
+        ))
         .context("Failed to update message embedding")?;
+        self.vector_index.insert(message_id, embedding);
         Ok(())
     }
 
+    /// Record a failed embedding attempt. The message is retried after `backoff * 2^(attempts-1)`
+    /// until `max_attempts` is reached, after which it is given up on (left without an embedding,
+    /// with the last error kept). Returns `true` if the message was given up on.
+    pub fn record_embedding_failure(
+        &self,
+        message_id: i64,
+        error: &str,
+        max_attempts: u32,
+        backoff: std::time::Duration,
+    ) -> anyhow::Result<bool> {
+        let conn = self.write_conn()?;
+        record_embedding_failure_in(&conn, "messages", message_id, error, max_attempts, backoff)
+    }
+
+    /// Mark a message as handled by the indexer without an embedding (dropping any stale one).
     pub fn mark_message_indexed(&self, message_id: i64) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         conn.execute(
             "UPDATE messages
-             SET is_indexed = 1
+             SET is_indexed = 1,
+                 embedding = NULL,
+                 embedding_model = NULL,
+                 embedding_dim = NULL
              WHERE id = ?1",
             [message_id],
         )
@@ -355,11 +910,11 @@ impl Database {
         limit: Option<usize>,
         retention: Option<u64>,
     ) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
 
         // Check if exists first
         let exists = conn
-            .prepare("SELECT 1 FROM settings WHERE guild_id = ?1")?
+            .prepare_cached("SELECT 1 FROM settings WHERE guild_id = ?1")?
             .exists([guild_id.to_string()])?;
 
         if exists {
@@ -388,9 +943,10 @@ impl Database {
         &self,
         guild_id: u64,
     ) -> anyhow::Result<(Option<usize>, Option<u64>)> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn
-            .prepare("SELECT context_limit, context_retention FROM settings WHERE guild_id = ?1")?;
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT context_limit, context_retention FROM settings WHERE guild_id = ?1",
+        )?;
 
         let mut rows = stmt.query([guild_id.to_string()])?;
 
@@ -404,8 +960,9 @@ impl Database {
     }
 
     pub fn get_guild_system_prompt(&self, guild_id: u64) -> anyhow::Result<Option<String>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare("SELECT system_prompt FROM settings WHERE guild_id = ?1")?;
+        let conn = self.read_conn()?;
+        let mut stmt =
+            conn.prepare_cached("SELECT system_prompt FROM settings WHERE guild_id = ?1")?;
         let mut rows = stmt.query([guild_id.to_string()])?;
 
         if let Some(row) = rows.next()? {
@@ -421,7 +978,7 @@ impl Database {
         guild_id: u64,
         prompt: Option<&str>,
     ) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         conn.execute(
             "INSERT INTO settings (guild_id, system_prompt)
              VALUES (?1, ?2)
@@ -432,9 +989,10 @@ impl Database {
     }
 
     pub fn get_guild_agent_confirm_timeout(&self, guild_id: u64) -> anyhow::Result<Option<u64>> {
-        let conn = self.lock_conn()?;
-        let mut stmt =
-            conn.prepare("SELECT agent_confirm_timeout_secs FROM settings WHERE guild_id = ?1")?;
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT agent_confirm_timeout_secs FROM settings WHERE guild_id = ?1",
+        )?;
         let mut rows = stmt.query([guild_id.to_string()])?;
 
         if let Some(row) = rows.next()? {
@@ -450,7 +1008,7 @@ impl Database {
         guild_id: u64,
         timeout_secs: Option<u64>,
     ) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         conn.execute(
             "INSERT INTO settings (guild_id, agent_confirm_timeout_secs)
              VALUES (?1, ?2)
@@ -462,9 +1020,9 @@ impl Database {
     }
 
     pub fn get_guild_voice_idle_timeout(&self, guild_id: u64) -> anyhow::Result<Option<u64>> {
-        let conn = self.lock_conn()?;
-        let mut stmt =
-            conn.prepare("SELECT voice_idle_timeout_secs FROM settings WHERE guild_id = ?1")?;
+        let conn = self.read_conn()?;
+        let mut stmt = conn
+            .prepare_cached("SELECT voice_idle_timeout_secs FROM settings WHERE guild_id = ?1")?;
         let mut rows = stmt.query([guild_id.to_string()])?;
 
         if let Some(row) = rows.next()? {
@@ -480,7 +1038,7 @@ impl Database {
         guild_id: u64,
         timeout_secs: Option<u64>,
     ) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         conn.execute(
             "INSERT INTO settings (guild_id, voice_idle_timeout_secs)
              VALUES (?1, ?2)
@@ -492,34 +1050,36 @@ impl Database {
     }
 
     pub fn save_summary(&self, channel_id: &str, summary: &str) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         conn.execute(
             "INSERT INTO channel_summaries (channel_id, summary, updated_at) 
              VALUES (?1, ?2, CURRENT_TIMESTAMP)
-             ON CONFLICT(channel_id) DO UPDATE SET summary = ?2, updated_at = CURRENT_TIMESTAMP",
+             ON CONFLICT(channel_id) DO UPDATE
+                 SET summary = ?2, updated_at = CURRENT_TIMESTAMP, dirty = 0",
             (channel_id, summary),
         )?;
         Ok(())
     }
 
     pub fn save_summary_refresh(&self, channel_id: &str, summary: &str) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         conn.execute(
             "INSERT INTO channel_summaries (channel_id, summary, updated_at, refreshed_at)
              VALUES (?1, ?2, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
              ON CONFLICT(channel_id) DO UPDATE
                  SET summary = ?2,
                      updated_at = CURRENT_TIMESTAMP,
-                     refreshed_at = CURRENT_TIMESTAMP",
+                     refreshed_at = CURRENT_TIMESTAMP,
+                     dirty = 0",
             (channel_id, summary),
         )?;
         Ok(())
     }
 
     pub fn get_latest_summary(&self, channel_id: &str) -> anyhow::Result<Option<String>> {
-        let conn = self.lock_conn()?;
+        let conn = self.read_conn()?;
         let mut stmt =
-            conn.prepare("SELECT summary FROM channel_summaries WHERE channel_id = ?1")?;
+            conn.prepare_cached("SELECT summary FROM channel_summaries WHERE channel_id = ?1")?;
         let mut rows = stmt.query([channel_id])?;
 
         if let Some(row) = rows.next()? {
@@ -533,9 +1093,9 @@ impl Database {
         &self,
         channel_id: &str,
     ) -> anyhow::Result<Option<ChannelSummaryRecord>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare(
-            "SELECT summary, updated_at, refreshed_at
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT summary, updated_at, refreshed_at, dirty
              FROM channel_summaries
              WHERE channel_id = ?1",
         )?;
@@ -546,19 +1106,33 @@ impl Database {
                 summary: row.get(0)?,
                 updated_at: row.get(1)?,
                 refreshed_at: row.get(2)?,
+                dirty: row.get(3)?,
             }))
         } else {
             Ok(None)
         }
     }
 
+    /// Channels whose summary needs rebuilding after message edits or deletions.
+    pub fn get_dirty_summary_channels(&self) -> anyhow::Result<Vec<String>> {
+        let conn = self.read_conn()?;
+        let mut stmt =
+            conn.prepare_cached("SELECT channel_id FROM channel_summaries WHERE dirty = 1")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        let mut channels = Vec::new();
+        for row in rows {
+            channels.push(row?);
+        }
+        Ok(channels)
+    }
+
     pub fn get_channel_milestones(
         &self,
         channel_id: &str,
         limit: usize,
     ) -> anyhow::Result<Vec<String>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare(
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
             "SELECT milestone
              FROM channel_milestones
              WHERE channel_id = ?1
@@ -580,7 +1154,7 @@ impl Database {
         channel_id: &str,
         milestones: &[String],
     ) -> anyhow::Result<()> {
-        let mut conn = self.lock_conn()?;
+        let mut conn = self.write_conn()?;
         let tx = conn.transaction()?;
 
         tx.execute(
@@ -612,8 +1186,8 @@ impl Database {
         from: chrono::DateTime<chrono::Utc>,
         limit: usize,
     ) -> anyhow::Result<Vec<crate::rag::MessageResult>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare(
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
             "SELECT m.content, m.user_id, m.timestamp, m.channel_id
              FROM messages m
              LEFT JOIN channel_settings s ON m.channel_id = s.channel_id
@@ -643,8 +1217,8 @@ impl Database {
     }
 
     pub fn get_channels_with_activity(&self, lookback_days: i64) -> anyhow::Result<Vec<String>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare(
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
             "SELECT channel_id
              FROM messages
              WHERE timestamp > datetime('now', ?1)
@@ -667,8 +1241,8 @@ impl Database {
         channel_id: &str,
         since: &str,
     ) -> anyhow::Result<usize> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare(
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
             "SELECT COUNT(*)
              FROM messages
              WHERE channel_id = ?1
@@ -678,52 +1252,381 @@ impl Database {
         Ok(count.max(0) as usize)
     }
 
-    // --- Channel Settings ---
+    // --- Threads ---
 
-    pub fn set_channel_enabled(
-        &self,
-        guild_id: &str,
-        channel_id: &str,
-        enabled: bool,
-    ) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
-        conn.execute(
-            "INSERT INTO channel_settings (guild_id, channel_id, enabled, updated_at) 
-             VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)
-             ON CONFLICT(channel_id) DO UPDATE SET enabled = ?3, updated_at = CURRENT_TIMESTAMP",
-            (guild_id, channel_id, enabled),
-        )?;
+    pub fn save_channel_thread(&self, thread: &ChannelThread) -> anyhow::Result<()> {
+        let conn = self.write_conn()?;
+        conn.prepare_cached(
+            "INSERT INTO channel_threads (channel_id, guild_id, parent_channel_id, name, is_forum_post)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(channel_id) DO UPDATE
+                 SET parent_channel_id = ?3, name = ?4, is_forum_post = ?5,
+                     updated_at = CURRENT_TIMESTAMP
+                 WHERE parent_channel_id != ?3 OR name != ?4 OR is_forum_post != ?5",
+        )?
+        .execute((
+            &thread.channel_id,
+            &thread.guild_id,
+            &thread.parent_channel_id,
+            &thread.name,
+            thread.is_forum_post,
+        ))
+        .context("Failed to save channel thread")?;
         Ok(())
     }
 
-    pub fn set_channel_memory_scope(
-        &self,
-        guild_id: &str,
-        channel_id: &str,
-        start_date: Option<String>,
-    ) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
-        conn.execute(
-            "INSERT INTO channel_settings (guild_id, channel_id, memory_start_date, updated_at) 
-             VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)
-             ON CONFLICT(channel_id) DO UPDATE SET memory_start_date = ?3, updated_at = CURRENT_TIMESTAMP",
-            (guild_id, channel_id, start_date),
+    pub fn get_channel_thread(&self, channel_id: &str) -> anyhow::Result<Option<ChannelThread>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT channel_id, guild_id, parent_channel_id, name, is_forum_post
+             FROM channel_threads
+             WHERE channel_id = ?1",
         )?;
-        Ok(())
+        let thread = stmt
+            .query_row([channel_id], |row| {
+                Ok(ChannelThread {
+                    channel_id: row.get(0)?,
+                    guild_id: row.get(1)?,
+                    parent_channel_id: row.get(2)?,
+                    name: row.get(3)?,
+                    is_forum_post: row.get(4)?,
+                })
+            })
+            .optional()?;
+        Ok(thread)
     }
 
-    pub fn get_channel_settings(
+    pub fn get_message_by_discord_id(
         &self,
-        channel_id: &str,
-    ) -> anyhow::Result<Option<(bool, Option<String>)>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare(
-            "SELECT enabled, memory_start_date FROM channel_settings WHERE channel_id = ?1",
+        discord_id: &str,
+    ) -> anyhow::Result<Option<crate::rag::MessageResult>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT content, user_id, timestamp, channel_id FROM messages WHERE discord_id = ?1",
         )?;
-        let mut rows = stmt.query([channel_id])?;
-
-        if let Some(row) = rows.next()? {
-            let enabled: bool = row.get(0)?;
+        let message = stmt
+            .query_row([discord_id], |row| {
+                Ok(crate::rag::MessageResult {
+                    content: row.get(0)?,
+                    user_id: row.get(1)?,
+                    timestamp: row.get(2)?,
+                    channel_id: row.get(3)?,
+                })
+            })
+            .optional()?;
+        Ok(message)
+    }
+
+    // --- Attachments ---
+
+    /// Store the text chunks of one attachment of a stored message. Chunks that already exist
+    /// are kept. Returns the number of chunks added (0 if the message is not stored).
+    pub fn save_attachment_chunks(
+        &self,
+        message_discord_id: &str,
+        attachment_id: &str,
+        filename: &str,
+        chunks: &[String],
+    ) -> anyhow::Result<usize> {
+        let mut conn = self.write_conn()?;
+        let tx = conn.transaction()?;
+        let message_id: Option<i64> = tx
+            .query_row(
+                "SELECT id FROM messages WHERE discord_id = ?1",
+                [message_discord_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(message_id) = message_id else {
+            return Ok(0);
+        };
+
+        let mut inserted = 0usize;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR IGNORE INTO attachment_chunks
+                     (message_id, attachment_id, filename, chunk_index, content)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for (index, chunk) in chunks.iter().enumerate() {
+                inserted += stmt
+                    .execute((message_id, attachment_id, filename, index as i64, chunk))
+                    .context("Failed to save attachment chunk")?;
+            }
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    pub fn has_attachment_chunks(&self, attachment_id: &str) -> anyhow::Result<bool> {
+        let conn = self.read_conn()?;
+        let exists = conn
+            .prepare_cached("SELECT 1 FROM attachment_chunks WHERE attachment_id = ?1")?
+            .exists([attachment_id])?;
+        Ok(exists)
+    }
+
+    /// Attachment text of the given messages, in message, attachment and chunk order.
+    pub fn get_message_attachment_chunks(
+        &self,
+        message_discord_ids: &[String],
+    ) -> anyhow::Result<Vec<AttachmentChunkRecord>> {
+        if message_discord_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.read_conn()?;
+        let placeholders = vec!["?"; message_discord_ids.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT m.discord_id, c.filename, c.chunk_index, c.content
+             FROM attachment_chunks c
+             JOIN messages m ON m.id = c.message_id
+             WHERE m.discord_id IN ({placeholders})
+             ORDER BY m.id, c.id"
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(message_discord_ids), |row| {
+            Ok(AttachmentChunkRecord {
+                message_discord_id: row.get(0)?,
+                filename: row.get(1)?,
+                chunk_index: row.get(2)?,
+                content: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Attachment chunks that need an embedding: never embedded, or embedded with another
+    /// model. Chunks of the newest messages come first.
+    pub fn get_attachment_chunks_missing_embeddings(
+        &self,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(i64, String)>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT c.id, c.content
+             FROM attachment_chunks c
+             JOIN messages m ON m.id = c.message_id
+             WHERE ((c.embedding IS NULL AND c.is_indexed = 0)
+                    OR (c.embedding IS NOT NULL AND c.embedding_model IS NOT ?1))
+               AND (c.embedding_retry_at IS NULL OR c.embedding_retry_at <= CURRENT_TIMESTAMP)
+             ORDER BY m.timestamp DESC, c.chunk_index
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map((&*self.embedding_model, limit), |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    pub fn set_attachment_chunk_embedding(
+        &self,
+        chunk_id: i64,
+        embedding: &[f32],
+    ) -> anyhow::Result<()> {
+        let conn = self.write_conn()?;
+        conn.prepare_cached(
+            "UPDATE attachment_chunks
+             SET embedding = ?1,
+                 embedding_model = ?2,
+                 embedding_dim = ?3,
+                 is_indexed = 1,
+                 embedding_attempts = 0,
+                 embedding_retry_at = NULL,
+                 embedding_error = NULL
+             WHERE id = ?4",
+        )?
+        .execute((
+            serialize_embedding(embedding),
+            &*self.embedding_model,
+            embedding.len() as i64,
+            chunk_id,
+        ))
+        .context("Failed to update attachment chunk embedding")?;
+        Ok(())
+    }
+
+    pub fn mark_attachment_chunk_indexed(&self, chunk_id: i64) -> anyhow::Result<()> {
+        let conn = self.write_conn()?;
+        conn.execute(
+            "UPDATE attachment_chunks
+             SET is_indexed = 1,
+                 embedding = NULL,
+                 embedding_model = NULL,
+                 embedding_dim = NULL
+             WHERE id = ?1",
+            [chunk_id],
+        )
+        .context("Failed to mark attachment chunk as indexed")?;
+        Ok(())
+    }
+
+    /// Like [`Database::record_embedding_failure`], for an attachment chunk.
+    pub fn record_attachment_chunk_failure(
+        &self,
+        chunk_id: i64,
+        error: &str,
+        max_attempts: u32,
+        backoff: std::time::Duration,
+    ) -> anyhow::Result<bool> {
+        let conn = self.write_conn()?;
+        record_embedding_failure_in(
+            &conn,
+            "attachment_chunks",
+            chunk_id,
+            error,
+            max_attempts,
+            backoff,
+        )
+    }
+
+    // --- Backfill Checkpoints ---
+
+    pub fn get_backfill_checkpoint(
+        &self,
+        channel_id: &str,
+    ) -> anyhow::Result<Option<BackfillCheckpoint>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT channel_id, guild_id, since, before_message_id, fetched, saved, completed_at
+             FROM backfill_checkpoints
+             WHERE channel_id = ?1",
+        )?;
+        let checkpoint = stmt
+            .query_row([channel_id], |row| {
+                Ok(BackfillCheckpoint {
+                    channel_id: row.get(0)?,
+                    guild_id: row.get(1)?,
+                    since: row.get(2)?,
+                    before_message_id: row.get(3)?,
+                    fetched: row.get(4)?,
+                    saved: row.get(5)?,
+                    completed_at: row.get(6)?,
+                })
+            })
+            .optional()?;
+        Ok(checkpoint)
+    }
+
+    /// Resume the unfinished backfill for `channel_id` if it covers the same range, otherwise
+    /// start over from the newest message.
+    pub fn start_backfill(
+        &self,
+        guild_id: &str,
+        channel_id: &str,
+        since: Option<&str>,
+    ) -> anyhow::Result<BackfillCheckpoint> {
+        if let Some(checkpoint) = self.get_backfill_checkpoint(channel_id)? {
+            if checkpoint.completed_at.is_none() && checkpoint.since.as_deref() == since {
+                return Ok(checkpoint);
+            }
+        }
+
+        let conn = self.write_conn()?;
+        conn.execute(
+            "INSERT INTO backfill_checkpoints (channel_id, guild_id, since)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT(channel_id) DO UPDATE
+                 SET guild_id = ?2,
+                     since = ?3,
+                     before_message_id = NULL,
+                     fetched = 0,
+                     saved = 0,
+                     started_at = CURRENT_TIMESTAMP,
+                     updated_at = CURRENT_TIMESTAMP,
+                     completed_at = NULL",
+            (channel_id, guild_id, since),
+        )
+        .context("Failed to start backfill")?;
+        Ok(BackfillCheckpoint {
+            channel_id: channel_id.to_string(),
+            guild_id: guild_id.to_string(),
+            since: since.map(str::to_string),
+            before_message_id: None,
+            fetched: 0,
+            saved: 0,
+            completed_at: None,
+        })
+    }
+
+    /// Record a processed page: move the cursor and add to the counters.
+    pub fn advance_backfill(
+        &self,
+        channel_id: &str,
+        before_message_id: &str,
+        fetched: usize,
+        saved: usize,
+    ) -> anyhow::Result<()> {
+        let conn = self.write_conn()?;
+        conn.execute(
+            "UPDATE backfill_checkpoints
+             SET before_message_id = ?2,
+                 fetched = fetched + ?3,
+                 saved = saved + ?4,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE channel_id = ?1",
+            (channel_id, before_message_id, fetched, saved),
+        )
+        .context("Failed to update backfill checkpoint")?;
+        Ok(())
+    }
+
+    pub fn complete_backfill(&self, channel_id: &str) -> anyhow::Result<()> {
+        let conn = self.write_conn()?;
+        conn.execute(
+            "UPDATE backfill_checkpoints
+             SET completed_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP
+             WHERE channel_id = ?1",
+            [channel_id],
+        )
+        .context("Failed to complete backfill")?;
+        Ok(())
+    }
+
+    // --- Channel Settings ---
+
+    pub fn set_channel_enabled(
+        &self,
+        guild_id: &str,
+        channel_id: &str,
+        enabled: bool,
+    ) -> anyhow::Result<()> {
+        let conn = self.write_conn()?;
+        conn.execute(
+            "INSERT INTO channel_settings (guild_id, channel_id, enabled, updated_at) 
+             VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)
+             ON CONFLICT(channel_id) DO UPDATE SET enabled = ?3, updated_at = CURRENT_TIMESTAMP",
+            (guild_id, channel_id, enabled),
+        )?;
+        Ok(())
+    }
+
+    pub fn set_channel_memory_scope(
+        &self,
+        guild_id: &str,
+        channel_id: &str,
+        start_date: Option<String>,
+    ) -> anyhow::Result<()> {
+        let conn = self.write_conn()?;
+        conn.execute(
+            "INSERT INTO channel_settings (guild_id, channel_id, memory_start_date, updated_at) 
+             VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)
+             ON CONFLICT(channel_id) DO UPDATE SET memory_start_date = ?3, updated_at = CURRENT_TIMESTAMP",
+            (guild_id, channel_id, start_date),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_channel_settings(
+        &self,
+        channel_id: &str,
+    ) -> anyhow::Result<Option<(bool, Option<String>)>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT enabled, memory_start_date FROM channel_settings WHERE channel_id = ?1",
+        )?;
+        let mut rows = stmt.query([channel_id])?;
+
+        if let Some(row) = rows.next()? {
+            let enabled: bool = row.get(0)?;
             let scope: Option<String> = row.get(1)?;
             Ok(Some((enabled, scope)))
         } else {
@@ -742,8 +1645,8 @@ impl Database {
         &self,
         guild_id: &str,
     ) -> anyhow::Result<Vec<(String, bool, Option<String>)>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare("SELECT channel_id, enabled, memory_start_date FROM channel_settings WHERE guild_id = ?1")?;
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached("SELECT channel_id, enabled, memory_start_date FROM channel_settings WHERE guild_id = ?1")?;
         let rows = stmt.query_map([guild_id], |row| {
             Ok((row.get(0)?, row.get(1)?, row.get(2)?))
         })?;
@@ -763,7 +1666,7 @@ impl Database {
         summary: &str,
         expires_at: Option<String>,
     ) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         conn.execute(
             "INSERT INTO user_memory (user_id, summary, enabled, updated_at, expires_at)
              VALUES (?1, ?2, 1, CURRENT_TIMESTAMP, ?3)
@@ -778,8 +1681,8 @@ impl Database {
     }
 
     pub fn get_user_memory(&self, user_id: &str) -> anyhow::Result<Option<UserMemoryRecord>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare(
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
             "SELECT summary, enabled, updated_at, expires_at
              FROM user_memory
              WHERE user_id = ?1",
@@ -799,7 +1702,7 @@ impl Database {
     }
 
     pub fn set_user_memory_enabled(&self, user_id: &str, enabled: bool) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         conn.execute(
             "INSERT INTO user_memory (user_id, summary, enabled, updated_at)
              VALUES (?1, '', ?2, CURRENT_TIMESTAMP)
@@ -812,13 +1715,13 @@ impl Database {
     }
 
     pub fn delete_user_memory(&self, user_id: &str) -> anyhow::Result<usize> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         let count = conn.execute("DELETE FROM user_memory WHERE user_id = ?1", [user_id])?;
         Ok(count)
     }
 
     pub fn cleanup_expired_user_memory(&self) -> anyhow::Result<usize> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         let count = conn.execute(
             "DELETE FROM user_memory
              WHERE expires_at IS NOT NULL
@@ -838,7 +1741,7 @@ impl Database {
         message: &str,
         remind_at: &str,
     ) -> anyhow::Result<i64> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         conn.execute(
             "INSERT INTO reminders (guild_id, channel_id, user_id, message, remind_at)
              VALUES (?1, ?2, ?3, ?4, ?5)",
@@ -852,8 +1755,8 @@ impl Database {
         user_id: &str,
         limit: usize,
     ) -> anyhow::Result<Vec<ReminderRecord>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare(
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
             "SELECT id, guild_id, channel_id, user_id, message, remind_at, created_at, delivered_at
              FROM reminders
              WHERE user_id = ?1 AND delivered_at IS NULL
@@ -885,7 +1788,7 @@ impl Database {
         reminder_id: i64,
         user_id: &str,
     ) -> anyhow::Result<usize> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         let count = conn.execute(
             "DELETE FROM reminders
              WHERE id = ?1 AND user_id = ?2 AND delivered_at IS NULL",
@@ -895,8 +1798,8 @@ impl Database {
     }
 
     pub fn get_due_reminders(&self, limit: usize) -> anyhow::Result<Vec<ReminderRecord>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare(
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
             "SELECT id, guild_id, channel_id, user_id, message, remind_at, created_at, delivered_at
              FROM reminders
              WHERE delivered_at IS NULL AND remind_at <= CURRENT_TIMESTAMP
@@ -924,7 +1827,7 @@ impl Database {
     }
 
     pub fn mark_reminder_delivered(&self, reminder_id: i64) -> anyhow::Result<()> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         conn.execute(
             "UPDATE reminders
              SET delivered_at = CURRENT_TIMESTAMP
@@ -934,12 +1837,224 @@ impl Database {
         Ok(())
     }
 
+    // --- Agent Runs ---
+
+    /// Store an agent run, linked to the ids of the bot messages that show its answer.
+    pub fn save_agent_run(
+        &self,
+        run: &AgentRunRecord,
+        reply_message_ids: &[String],
+    ) -> anyhow::Result<i64> {
+        let mut conn = self.write_conn()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO agent_runs
+                 (guild_id, channel_id, user_id, trigger_message_id, final_answer, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (
+                &run.guild_id,
+                &run.channel_id,
+                &run.user_id,
+                &run.trigger_message_id,
+                &run.final_answer,
+                &run.error,
+            ),
+        )
+        .context("Failed to save agent run")?;
+        let run_id = tx.last_insert_rowid();
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO agent_steps
+                     (run_id, iteration, request_summary, content, tool_calls, latency_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for (iteration, step) in run.steps.iter().enumerate() {
+                stmt.execute((
+                    run_id,
+                    iteration as i64,
+                    &step.request_summary,
+                    &step.content,
+                    serde_json::to_string(&step.tool_calls)?,
+                    step.latency_ms as i64,
+                ))
+                .context("Failed to save agent step")?;
+            }
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO agent_run_messages (message_id, run_id) VALUES (?1, ?2)",
+            )?;
+            for message_id in reply_message_ids {
+                stmt.execute((message_id, run_id))?;
+            }
+        }
+        tx.commit()?;
+        Ok(run_id)
+    }
+
+    /// The agent run whose answer is shown in the given bot message.
+    pub fn get_agent_run_for_message(
+        &self,
+        message_id: &str,
+    ) -> anyhow::Result<Option<AgentRunRecord>> {
+        let conn = self.read_conn()?;
+        let run = conn
+            .prepare_cached(
+                "SELECT r.id, r.guild_id, r.channel_id, r.user_id, r.trigger_message_id,
+                        r.final_answer, r.error
+                 FROM agent_run_messages m
+                 JOIN agent_runs r ON r.id = m.run_id
+                 WHERE m.message_id = ?1",
+            )?
+            .query_row([message_id], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    AgentRunRecord {
+                        guild_id: row.get(1)?,
+                        channel_id: row.get(2)?,
+                        user_id: row.get(3)?,
+                        trigger_message_id: row.get(4)?,
+                        final_answer: row.get(5)?,
+                        error: row.get(6)?,
+                        steps: Vec::new(),
+                    },
+                ))
+            })
+            .optional()?;
+        let Some((run_id, mut run)) = run else {
+            return Ok(None);
+        };
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT request_summary, content, tool_calls, latency_ms
+             FROM agent_steps
+             WHERE run_id = ?1
+             ORDER BY iteration",
+        )?;
+        let rows = stmt.query_map([run_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?;
+        for row in rows {
+            let (request_summary, content, tool_calls, latency_ms) = row?;
+            run.steps.push(AgentStepRecord {
+                request_summary,
+                content,
+                tool_calls: serde_json::from_str(&tool_calls)
+                    .context("Invalid stored agent tool calls")?,
+                latency_ms: latency_ms.max(0) as u64,
+            });
+        }
+        Ok(Some(run))
+    }
+
+    /// Removes agent runs older than `retention_hours`. Returns the number of runs deleted.
+    pub fn cleanup_old_agent_runs(&self, retention_hours: u64) -> anyhow::Result<usize> {
+        let conn = self.write_conn()?;
+        let count = conn.execute(
+            "DELETE FROM agent_runs WHERE created_at < datetime('now', ?1)",
+            (format!("-{} hours", retention_hours),),
+        )?;
+        Ok(count)
+    }
+
+    pub fn delete_agent_runs_by_user(&self, user_id: &str) -> anyhow::Result<usize> {
+        let conn = self.write_conn()?;
+        let count = conn.execute("DELETE FROM agent_runs WHERE user_id = ?1", [user_id])?;
+        Ok(count)
+    }
+
+    // --- Tool Policies ---
+
+    /// Add a tool policy, or change the action of the existing one with the same scope and
+    /// target.
+    pub fn set_tool_policy(&self, policy: &ToolPolicy) -> anyhow::Result<()> {
+        let conn = self.write_conn()?;
+        conn.execute(
+            "INSERT INTO tool_policies (guild_id, scope, scope_id, target, target_name, action)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(guild_id, scope, scope_id, target, target_name) DO UPDATE SET
+                action = excluded.action,
+                updated_at = CURRENT_TIMESTAMP",
+            (
+                &policy.guild_id,
+                policy.scope.as_str(),
+                &policy.scope_id,
+                policy.target.as_str(),
+                &policy.target_name,
+                policy.action.as_str(),
+            ),
+        )
+        .context("Failed to save tool policy")?;
+        Ok(())
+    }
+
+    pub fn delete_tool_policy(
+        &self,
+        guild_id: &str,
+        scope: PolicyScope,
+        scope_id: &str,
+        target: PolicyTarget,
+        target_name: &str,
+    ) -> anyhow::Result<usize> {
+        let conn = self.write_conn()?;
+        let count = conn.execute(
+            "DELETE FROM tool_policies
+             WHERE guild_id = ?1 AND scope = ?2 AND scope_id = ?3 AND target = ?4
+               AND target_name = ?5",
+            (
+                guild_id,
+                scope.as_str(),
+                scope_id,
+                target.as_str(),
+                target_name,
+            ),
+        )?;
+        Ok(count)
+    }
+
+    /// All tool policies of a guild, ordered by target and scope.
+    pub fn list_tool_policies(&self, guild_id: &str) -> anyhow::Result<Vec<ToolPolicy>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT guild_id, scope, scope_id, target, target_name, action
+             FROM tool_policies
+             WHERE guild_id = ?1
+             ORDER BY target_name, target, scope, scope_id",
+        )?;
+        let rows = stmt.query_map([guild_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })?;
+        let mut policies = Vec::new();
+        for row in rows {
+            let (guild_id, scope, scope_id, target, target_name, action) = row?;
+            policies.push(ToolPolicy {
+                guild_id,
+                scope: PolicyScope::parse(&scope)?,
+                scope_id,
+                target: PolicyTarget::parse(&target)?,
+                target_name,
+                action: PolicyAction::parse(&action)?,
+            });
+        }
+        Ok(policies)
+    }
+
     pub fn purge_messages(
         &self,
         channel_id: &str,
         before_date: Option<String>,
     ) -> anyhow::Result<usize> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         let count = if let Some(date) = before_date {
             conn.execute(
                 "DELETE FROM messages WHERE channel_id = ?1 AND timestamp < ?",
@@ -952,14 +2067,14 @@ impl Database {
     }
 
     pub fn purge_messages_by_user(&self, user_id: &str) -> anyhow::Result<usize> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         let count = conn.execute("DELETE FROM messages WHERE user_id = ?1", [user_id])?;
         Ok(count)
     }
 
     pub fn get_channels_for_user(&self, user_id: &str) -> anyhow::Result<Vec<String>> {
-        let conn = self.lock_conn()?;
-        let mut stmt = conn.prepare(
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare_cached(
             "SELECT DISTINCT channel_id
              FROM messages
              WHERE user_id = ?1",
@@ -976,7 +2091,7 @@ impl Database {
         if channels.is_empty() {
             return Ok(0);
         }
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         let mut sql = String::from("DELETE FROM channel_summaries WHERE channel_id IN (");
         sql.push_str(&vec!["?"; channels.len()].join(", "));
         sql.push(')');
@@ -991,7 +2106,7 @@ impl Database {
         if channels.is_empty() {
             return Ok(0);
         }
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         let mut sql = String::from("DELETE FROM channel_milestones WHERE channel_id IN (");
         sql.push_str(&vec!["?"; channels.len()].join(", "));
         sql.push(')');
@@ -1005,7 +2120,7 @@ impl Database {
     /// Removes messages older than `retention_hours` from the database.
     /// Returns the number of messages deleted.
     pub fn cleanup_old_messages(&self, retention_hours: u64) -> anyhow::Result<usize> {
-        let conn = self.lock_conn()?;
+        let conn = self.write_conn()?;
         let count = conn.execute(
             "DELETE FROM messages WHERE timestamp < datetime('now', ?1)",
             (format!("-{} hours", retention_hours),),
@@ -1031,9 +2146,15 @@ impl Database {
             let db = self.clone();
             let query = query.to_string();
             let filter = filter.clone();
-            let results =
-                tokio::task::spawn_blocking(move || db.search_messages_keyword(&query, &filter))
-                    .await??;
+            let results = tokio::task::spawn_blocking(move || {
+                let keyword_results = db.search_messages_keyword(&query, &filter)?;
+                let attachment_results = db.search_attachment_chunks_keyword(&query, &filter)?;
+                anyhow::Ok(merge_results(
+                    vec![keyword_results, attachment_results],
+                    limit,
+                ))
+            })
+            .await??;
             debug!(
                 "Database: Keyword search returned {} results",
                 results.len()
@@ -1050,8 +2171,20 @@ impl Database {
             } else {
                 db.search_messages_keyword(&query, &filter)?
             };
-
-            Ok(merge_results(vector_results, keyword_results, limit))
+            let attachment_vector_results =
+                db.search_attachment_chunks_vector(&embedding, &filter)?;
+            let attachment_keyword_results =
+                db.search_attachment_chunks_keyword(&query, &filter)?;
+
+            Ok(merge_results(
+                vec![
+                    vector_results,
+                    keyword_results,
+                    attachment_vector_results,
+                    attachment_keyword_results,
+                ],
+                limit,
+            ))
         })
         .await?
     }
@@ -1061,45 +2194,53 @@ impl Database {
         query: &str,
         filter: &crate::rag::SearchFilter,
     ) -> anyhow::Result<Vec<crate::rag::MessageResult>> {
-        let conn = self.lock_conn()?;
+        let conn = self.read_conn()?;
 
-        let mut sql = String::from(
-            "
-            SELECT m.content, m.user_id, m.timestamp, m.channel_id
-            FROM messages m
-            LEFT JOIN channel_settings s ON m.channel_id = s.channel_id
-            WHERE (s.enabled IS NULL OR s.enabled = 1)
-              AND (s.memory_start_date IS NULL OR m.timestamp >= s.memory_start_date)
-            ",
-        );
+        let match_expr = if query.trim().is_empty() {
+            None
+        } else {
+            match fts5_match_expression(query) {
+                Some(expr) => Some(expr),
+                // Nothing searchable left (e.g. only punctuation).
+                None => return Ok(Vec::new()),
+            }
+        };
 
         let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+        let mut sql = if let Some(expr) = match_expr {
+            params.push(Box::new(expr));
+            String::from(
+                "
+                SELECT m.content, m.user_id, m.timestamp, m.channel_id
+                FROM messages_fts
+                JOIN messages m ON m.id = messages_fts.rowid
+                LEFT JOIN channel_settings s ON m.channel_id = s.channel_id
+                WHERE messages_fts MATCH ?
+                  AND (s.enabled IS NULL OR s.enabled = 1)
+                  AND (s.memory_start_date IS NULL OR m.timestamp >= s.memory_start_date)
+                ",
+            )
+        } else {
+            String::from(
+                "
+                SELECT m.content, m.user_id, m.timestamp, m.channel_id
+                FROM messages m
+                LEFT JOIN channel_settings s ON m.channel_id = s.channel_id
+                WHERE (s.enabled IS NULL OR s.enabled = 1)
+                  AND (s.memory_start_date IS NULL OR m.timestamp >= s.memory_start_date)
+                ",
+            )
+        };
+        let ranked = !params.is_empty();
 
-        if !query.is_empty() {
-            sql.push_str(" AND m.content LIKE ?");
-            params.push(Box::new(format!("%{}%", query)));
-        }
-
-        if !filter.channels.is_empty() {
-            sql.push_str(" AND m.channel_id IN (");
-            sql.push_str(&vec!["?"; filter.channels.len()].join(", "));
-            sql.push(')');
-            for channel in &filter.channels {
-                params.push(Box::new(channel.clone()));
-            }
-        }
-
-        if let Some(from) = filter.from_date {
-            sql.push_str(" AND m.timestamp >= ?");
-            params.push(Box::new(from.format("%Y-%m-%d %H:%M:%S").to_string()));
-        }
+        push_search_filter(&mut sql, &mut params, filter);
 
-        if let Some(to) = filter.to_date {
-            sql.push_str(" AND m.timestamp <= ?");
-            params.push(Box::new(to.format("%Y-%m-%d %H:%M:%S").to_string()));
+        if ranked {
+            // bm25() is lower-is-better; break ties by recency.
+            sql.push_str(" ORDER BY bm25(messages_fts), m.timestamp DESC LIMIT ?");
+        } else {
+            sql.push_str(" ORDER BY m.timestamp DESC LIMIT ?");
         }
-
-        sql.push_str(" ORDER BY m.timestamp DESC LIMIT ?");
         let limit = if filter.limit == 0 {
             5
         } else {
@@ -1107,7 +2248,7 @@ impl Database {
         };
         params.push(Box::new(limit));
 
-        let mut stmt = conn.prepare(&sql)?;
+        let mut stmt = conn.prepare_cached(&sql)?;
         let params_slice: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
 
         let rows = stmt.query_map(&params_slice[..], |row| {
@@ -1127,55 +2268,218 @@ impl Database {
         Ok(results)
     }
 
-    fn search_messages_vector(
+    /// Keyword search over attachment text. Needs a query; results are attributed to the
+    /// message each file was attached to.
+    fn search_attachment_chunks_keyword(
+        &self,
+        query: &str,
+        filter: &crate::rag::SearchFilter,
+    ) -> anyhow::Result<Vec<crate::rag::MessageResult>> {
+        let Some(match_expr) = fts5_match_expression(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.read_conn()?;
+
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(match_expr)];
+        let mut sql = String::from(
+            "
+            SELECT c.filename, c.content, m.user_id, m.timestamp, m.channel_id
+            FROM attachment_chunks_fts
+            JOIN attachment_chunks c ON c.id = attachment_chunks_fts.rowid
+            JOIN messages m ON m.id = c.message_id
+            LEFT JOIN channel_settings s ON m.channel_id = s.channel_id
+            WHERE attachment_chunks_fts MATCH ?
+              AND (s.enabled IS NULL OR s.enabled = 1)
+              AND (s.memory_start_date IS NULL OR m.timestamp >= s.memory_start_date)
+            ",
+        );
+        push_search_filter(&mut sql, &mut params, filter);
+        sql.push_str(" ORDER BY bm25(attachment_chunks_fts), m.timestamp DESC LIMIT ?");
+        params.push(Box::new(filter.limit.clamp(1, 100)));
+
+        let mut stmt = conn.prepare_cached(&sql)?;
+        let params_slice: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let rows = stmt.query_map(&params_slice[..], attachment_chunk_result)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Semantic search over embedded attachment chunks. Chunks are not in the ANN index; the
+    /// chunks of the most recent messages are scored linearly.
+    fn search_attachment_chunks_vector(
         &self,
         query_embedding: &[f32],
         filter: &crate::rag::SearchFilter,
     ) -> anyhow::Result<Vec<crate::rag::MessageResult>> {
-        const MAX_CANDIDATES: usize = 5000;
+        const MAX_CANDIDATES: usize = 2000;
 
         let query_norm = query_embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
         if query_norm == 0.0 {
             return Ok(Vec::new());
         }
-
-        let conn = self.lock_conn()?;
+        let conn = self.read_conn()?;
         let now = Utc::now();
 
         let mut sql = String::from(
             "
-            SELECT m.content, m.user_id, m.timestamp, m.channel_id, m.embedding
-            FROM messages m
+            SELECT c.filename, c.content, m.user_id, m.timestamp, m.channel_id, c.embedding
+            FROM attachment_chunks c
+            JOIN messages m ON m.id = c.message_id
             LEFT JOIN channel_settings s ON m.channel_id = s.channel_id
             WHERE (s.enabled IS NULL OR s.enabled = 1)
               AND (s.memory_start_date IS NULL OR m.timestamp >= s.memory_start_date)
-              AND m.embedding IS NOT NULL
+              AND c.embedding IS NOT NULL
+              AND c.embedding_model = ?
+              AND c.embedding_dim = ?
             ",
         );
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![
+            Box::new(self.embedding_model.to_string()),
+            Box::new(query_embedding.len() as i64),
+        ];
+        push_search_filter(&mut sql, &mut params, filter);
+        sql.push_str(" ORDER BY m.timestamp DESC LIMIT ?");
+        params.push(Box::new(MAX_CANDIDATES));
 
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+        let mut stmt = conn.prepare_cached(&sql)?;
+        let params_slice: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let rows = stmt.query_map(&params_slice[..], |row| {
+            Ok((attachment_chunk_result(row)?, row.get::<_, Vec<u8>>(5)?))
+        })?;
 
-        if !filter.channels.is_empty() {
-            sql.push_str(" AND m.channel_id IN (");
-            sql.push_str(&vec!["?"; filter.channels.len()].join(", "));
-            sql.push(')');
-            for channel in &filter.channels {
-                params.push(Box::new(channel.clone()));
+        let mut scored = Vec::new();
+        for row in rows {
+            let (msg, embedding_bytes) = row?;
+            let score = cosine_similarity_bytes(query_embedding, query_norm, &embedding_bytes);
+            if score > 0.0 {
+                scored.push((score * recency_boost(&msg.timestamp, &now), msg));
             }
         }
+        scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        scored.truncate(filter.limit.clamp(1, 100));
+        Ok(scored.into_iter().map(|(_, msg)| msg).collect())
+    }
+
+    fn search_messages_vector(
+        &self,
+        query_embedding: &[f32],
+        filter: &crate::rag::SearchFilter,
+    ) -> anyhow::Result<Vec<crate::rag::MessageResult>> {
+        const ANN_MIN_CANDIDATES: usize = 500;
+        // Stays well below SQLite's bound-parameter limit for the `IN (...)` list.
+        const ANN_MAX_CANDIDATES: usize = 8000;
 
-        if let Some(from) = filter.from_date {
-            sql.push_str(" AND m.timestamp >= ?");
-            params.push(Box::new(from.format("%Y-%m-%d %H:%M:%S").to_string()));
+        let query_norm = query_embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if query_norm == 0.0 {
+            return Ok(Vec::new());
         }
 
-        if let Some(to) = filter.to_date {
-            sql.push_str(" AND m.timestamp <= ?");
-            params.push(Box::new(to.format("%Y-%m-%d %H:%M:%S").to_string()));
+        let limit = if filter.limit == 0 {
+            5
+        } else {
+            filter.limit.min(100)
+        };
+
+        let mut ann_hits = Vec::new();
+        if self.vector_index.is_ready() {
+            // Filters may discard most candidates; widen the search before giving up on it.
+            let mut wanted = ANN_MIN_CANDIDATES.max(limit * 20).min(ANN_MAX_CANDIDATES);
+            loop {
+                let candidates = self.vector_index.search(query_embedding, wanted);
+                if candidates.is_empty() {
+                    break;
+                }
+                let ids: Vec<i64> = candidates.iter().map(|(id, _)| *id).collect();
+                let scored = self.score_embedded_messages(
+                    query_embedding,
+                    query_norm,
+                    filter,
+                    Some(&ids),
+                    limit,
+                )?;
+                // Once every indexed vector was a candidate the result is complete, even if short.
+                if scored.len() >= limit || candidates.len() >= self.vector_index.len() {
+                    return Ok(scored.into_iter().map(|s| s.message).collect());
+                }
+                if wanted >= ANN_MAX_CANDIDATES {
+                    debug!(
+                        "Database: ANN candidates mostly filtered out ({} of {}), adding a linear scan",
+                        scored.len(),
+                        limit
+                    );
+                    ann_hits = scored;
+                    break;
+                }
+                wanted = (wanted * 4).min(ANN_MAX_CANDIDATES);
+            }
         }
 
-        sql.push_str(" ORDER BY m.timestamp DESC LIMIT ?");
-        params.push(Box::new(MAX_CANDIDATES));
+        // The scan only covers recent messages, so keep older matches the index found.
+        let mut scored =
+            self.score_embedded_messages(query_embedding, query_norm, filter, None, limit)?;
+        for hit in ann_hits {
+            if !scored.iter().any(|s| s.id == hit.id) {
+                scored.push(hit);
+            }
+        }
+        scored.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        scored.truncate(limit);
+        Ok(scored.into_iter().map(|s| s.message).collect())
+    }
+
+    /// Score stored embeddings against the query, honouring channel settings, memory scope and
+    /// `filter`. With `ids`, only those messages are considered; otherwise the most recent
+    /// messages are scanned linearly. Results are sorted by descending score.
+    fn score_embedded_messages(
+        &self,
+        query_embedding: &[f32],
+        query_norm: f32,
+        filter: &crate::rag::SearchFilter,
+        ids: Option<&[i64]>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ScoredMessage>> {
+        const MAX_CANDIDATES: usize = 5000;
+
+        let conn = self.read_conn()?;
+        let now = Utc::now();
+
+        let mut sql = String::from(
+            "
+            SELECT m.content, m.user_id, m.timestamp, m.channel_id, m.embedding, m.id
+            FROM messages m
+            LEFT JOIN channel_settings s ON m.channel_id = s.channel_id
+            WHERE (s.enabled IS NULL OR s.enabled = 1)
+              AND (s.memory_start_date IS NULL OR m.timestamp >= s.memory_start_date)
+              AND m.embedding IS NOT NULL
+              AND m.embedding_model = ?
+              AND m.embedding_dim = ?
+            ",
+        );
+
+        // Vectors from other models (or of another size) are not comparable with the query.
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![
+            Box::new(self.embedding_model.to_string()),
+            Box::new(query_embedding.len() as i64),
+        ];
+
+        if let Some(ids) = ids {
+            sql.push_str(" AND m.id IN (");
+            sql.push_str(&vec!["?"; ids.len()].join(", "));
+            sql.push(')');
+            for id in ids {
+                params.push(Box::new(*id));
+            }
+        }
+
+        push_search_filter(&mut sql, &mut params, filter);
+
+        if ids.is_none() {
+            sql.push_str(" ORDER BY m.timestamp DESC LIMIT ?");
+            params.push(Box::new(MAX_CANDIDATES));
+        }
 
         let mut stmt = conn.prepare(&sql)?;
         let params_slice: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
@@ -1189,12 +2493,13 @@ impl Database {
                     channel_id: row.get(3)?,
                 },
                 row.get::<_, Vec<u8>>(4)?,
+                row.get::<_, i64>(5)?,
             ))
         })?;
 
         let mut scored = Vec::new();
         for row in rows {
-            let (msg, embedding_bytes) = row?;
+            let (msg, embedding_bytes, id) = row?;
             let mut score = cosine_similarity_bytes(query_embedding, query_norm, &embedding_bytes);
             if score < 0.0 {
                 score = 0.0;
@@ -1202,29 +2507,31 @@ impl Database {
             if score > 0.0 {
                 score *= recency_boost(&msg.timestamp, &now);
             }
-            scored.push((score, msg));
+            scored.push(ScoredMessage {
+                id,
+                score,
+                message: msg,
+            });
         }
 
-        scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
-
-        let limit = if filter.limit == 0 {
-            5
-        } else {
-            filter.limit.min(100)
-        };
+        scored.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
         scored.truncate(limit);
 
-        Ok(scored.into_iter().map(|(_, msg)| msg).collect())
+        Ok(scored)
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::config::Config;
     use chrono::{Duration, Utc};
 
-    fn test_config() -> Config {
+    pub(crate) fn test_config() -> Config {
         Config {
             discord_token: "test".to_string(),
             application_id: 0,
@@ -1232,10 +2539,16 @@ mod tests {
             llama_url: "test".to_string(),
             llama_model: "test".to_string(),
             llama_api_key: None,
+            llama_vision: false,
+            vision_max_images: 4,
+            vision_max_image_bytes: 5_242_880,
+            vision_inline_images: true,
             embedding_url: "test".to_string(),
             embedding_model: "test".to_string(),
             embedding_api_key: None,
             database_url: ":memory:".to_string(),
+            database_read_connections: 4,
+            database_busy_timeout_ms: 5000,
             system_prompt: "test".to_string(),
             max_context_messages: 10,
             status_message: "test".to_string(),
@@ -1245,17 +2558,31 @@ mod tests {
             mcp_servers: Vec::new(),
             context_message_limit: 50,
             context_retention_hours: 24,
+            context_max_tokens: 8192,
+            tokenizer: "heuristic".to_string(),
             llm_timeout_secs: 120,
             embedding_timeout_secs: 30,
             mcp_timeout_secs: 60,
+            mcp_health_check_interval_secs: 30,
             voice_idle_timeout_secs: 300,
             dev_guild_id: None,
             register_commands: false,
             mcp_tools_require_confirmation: true,
             agent_confirm_timeout_secs: 300,
+            agent_tool_concurrency: 4,
+            agent_tool_timeout_secs: 120,
+            llm_streaming_enabled: true,
+            stream_edit_interval_ms: 1500,
             embedding_indexer_enabled: true,
             embedding_indexer_batch_size: 25,
             embedding_indexer_interval_secs: 30,
+            embedding_batch_max_inputs: 32,
+            embedding_batch_max_tokens: 8192,
+            embedding_max_attempts: 5,
+            vector_index_enabled: true,
+            attachment_ingest_enabled: true,
+            attachment_max_bytes: 1_048_576,
+            attachment_chunk_chars: 1500,
             summarization_enabled: true,
             summarization_interval_secs: 3600,
             summarization_active_channels_lookback_days: 7,
@@ -1282,7 +2609,7 @@ mod tests {
             .unwrap();
 
         // Verify it exists (we don't have a direct get_message yet, but we can check query)
-        let conn = db.conn.lock().unwrap();
+        let conn = db.write_conn().unwrap();
         let mut stmt = conn
             .prepare("SELECT discord_id FROM messages WHERE discord_id = '1'")
             .unwrap();
@@ -1325,7 +2652,7 @@ mod tests {
             .unwrap();
 
         let id_for = |discord_id: &str| -> i64 {
-            let conn = db.conn.lock().unwrap();
+            let conn = db.write_conn().unwrap();
             conn.query_row(
                 "SELECT id FROM messages WHERE discord_id = ?1",
                 [discord_id],
@@ -1411,7 +2738,7 @@ mod tests {
         db.save_message("m4", guild_id, c3, "u1", "new msg in c3", 1800000000)
             .unwrap(); // After scope
 
-        let filter = crate::rag::SearchFilter::default().with_limit(10);
+        let filter = crate::rag::SearchFilter::new(guild_id).with_limit(10);
         let results = tokio::runtime::Runtime::new()
             .unwrap()
             .block_on(db.search_messages("", vec![], filter))
@@ -1459,7 +2786,7 @@ mod tests {
         // SQLite datetime('now') uses UTC
         // We use relative timestamps in the SQL, but for our mock we save with specific dates to test logic
 
-        let conn = db.conn.lock().unwrap();
+        let conn = db.write_conn().unwrap();
         // Insert manually to bypass save_message which converts unix to datetime
         conn.execute(
             "INSERT INTO messages (discord_id, guild_id, channel_id, user_id, content, timestamp) 
@@ -1479,7 +2806,7 @@ mod tests {
         let deleted = db.cleanup_old_messages(24).unwrap();
         assert_eq!(deleted, 1);
 
-        let conn = db.conn.lock().unwrap();
+        let conn = db.write_conn().unwrap();
         let mut stmt = conn.prepare("SELECT discord_id FROM messages").unwrap();
         let ids: Vec<String> = stmt
             .query_map([], |row| row.get(0))
@@ -1500,14 +2827,14 @@ mod tests {
             .unwrap();
 
         // This should NOT cause SQL injection
-        let filter = crate::rag::SearchFilter::default().with_limit(10);
+        let filter = crate::rag::SearchFilter::new("g1").with_limit(10);
         let result = tokio::runtime::Runtime::new()
             .unwrap()
             .block_on(db.search_messages("'; DROP TABLE messages; --", vec![], filter));
         assert!(result.is_ok());
 
         // Verify table still exists
-        let conn = db.conn.lock().unwrap();
+        let conn = db.write_conn().unwrap();
         assert!(conn.prepare("SELECT 1 FROM messages").is_ok());
     }
 
@@ -1527,7 +2854,7 @@ mod tests {
 
         // Attach small test embeddings.
         let id_for = |discord_id: &str| -> i64 {
-            let conn = db.conn.lock().unwrap();
+            let conn = db.write_conn().unwrap();
             conn.query_row(
                 "SELECT id FROM messages WHERE discord_id = ?1",
                 [discord_id],
@@ -1543,7 +2870,7 @@ mod tests {
         db.set_message_embedding(id_for("m3"), &[0.0, 0.0, 1.0])
             .unwrap();
 
-        let filter = crate::rag::SearchFilter::default().with_limit(3);
+        let filter = crate::rag::SearchFilter::new("g1").with_limit(3);
         let results = tokio::runtime::Runtime::new()
             .unwrap()
             .block_on(db.search_messages("irrelevant", vec![0.0, 1.0, 0.0], filter))
@@ -1569,7 +2896,7 @@ mod tests {
             .unwrap();
 
         let id_for = |discord_id: &str| -> i64 {
-            let conn = db.conn.lock().unwrap();
+            let conn = db.write_conn().unwrap();
             conn.query_row(
                 "SELECT id FROM messages WHERE discord_id = ?1",
                 [discord_id],
@@ -1583,7 +2910,7 @@ mod tests {
         db.set_message_embedding(id_for("new"), &[1.0, 0.0, 0.0])
             .unwrap();
 
-        let filter = crate::rag::SearchFilter::default().with_limit(2);
+        let filter = crate::rag::SearchFilter::new("g1").with_limit(2);
         let results = tokio::runtime::Runtime::new()
             .unwrap()
             .block_on(db.search_messages("query", vec![1.0, 0.0, 0.0], filter))
@@ -1604,7 +2931,7 @@ mod tests {
         db.save_message("m2", "g1", "c1", "u1", "goodbye", 1700000001)
             .unwrap();
 
-        let filter = crate::rag::SearchFilter::default().with_limit(5);
+        let filter = crate::rag::SearchFilter::new("g1").with_limit(5);
         let results = tokio::runtime::Runtime::new()
             .unwrap()
             .block_on(db.search_messages("hello", vec![1.0, 0.0, 0.0], filter))
@@ -1626,7 +2953,7 @@ mod tests {
             .unwrap();
 
         let id_for = |discord_id: &str| -> i64 {
-            let conn = db.conn.lock().unwrap();
+            let conn = db.write_conn().unwrap();
             conn.query_row(
                 "SELECT id FROM messages WHERE discord_id = ?1",
                 [discord_id],
@@ -1638,7 +2965,7 @@ mod tests {
         db.set_message_embedding(id_for("m1"), &[1.0, 0.0, 0.0])
             .unwrap();
 
-        let filter = crate::rag::SearchFilter::default().with_limit(5);
+        let filter = crate::rag::SearchFilter::new("g1").with_limit(5);
         let results = tokio::runtime::Runtime::new()
             .unwrap()
             .block_on(db.search_messages("hello", vec![1.0, 0.0, 0.0], filter))
@@ -1648,6 +2975,881 @@ mod tests {
         assert!(results.iter().any(|r| r.content == "hello world"));
     }
 
+    #[test]
+    fn test_vector_index_search_respects_filters() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message("a1", "guild_a", "ca", "u1", "alpha", 1700000000)
+            .unwrap();
+        db.save_message("a2", "guild_a", "ca", "u1", "beta", 1700000001)
+            .unwrap();
+        db.save_message("b1", "guild_b", "cb", "u2", "alpha elsewhere", 1700000002)
+            .unwrap();
+
+        let id_for = |discord_id: &str| -> i64 {
+            let conn = db.write_conn().unwrap();
+            conn.query_row(
+                "SELECT id FROM messages WHERE discord_id = ?1",
+                [discord_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        db.set_message_embedding(id_for("a1"), &[1.0, 0.0, 0.0])
+            .unwrap();
+        db.set_message_embedding(id_for("b1"), &[1.0, 0.0, 0.0])
+            .unwrap();
+
+        assert!(!db.vector_index.is_ready());
+        db.load_vector_index().unwrap();
+        assert!(db.vector_index.is_ready());
+        assert_eq!(db.vector_index.len(), 2);
+
+        // Embeddings stored after loading go straight into the index.
+        db.set_message_embedding(id_for("a2"), &[0.0, 1.0, 0.0])
+            .unwrap();
+        assert_eq!(db.vector_index.len(), 3);
+
+        let filter = crate::rag::SearchFilter::new("guild_a").with_limit(1);
+        let results = db
+            .search_messages_vector(&[0.1, 1.0, 0.0], &filter)
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].content, "beta");
+
+        let filter = crate::rag::SearchFilter::new("guild_a").with_limit(5);
+        let results = db
+            .search_messages_vector(&[1.0, 0.0, 0.0], &filter)
+            .unwrap();
+        let contents: Vec<_> = results.iter().map(|r| r.content.as_str()).collect();
+        assert_eq!(contents, vec!["alpha", "beta"]);
+    }
+
+    #[test]
+    fn test_vector_index_search_finds_old_matches_past_the_scan_window() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        // Deterministic pseudo-random components around the first axis.
+        let mut state = 0x2545_f491_u64;
+        let mut near_axis = |spread: f32| -> Vec<f32> {
+            std::iter::once(1.0)
+                .chain((0..15).map(|_| {
+                    state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    ((state >> 40) as f32 / (1u64 << 24) as f32 - 0.5) * spread
+                }))
+                .collect()
+        };
+
+        // One old match in guild_a, while 600 closer guild_b messages fill the first ANN
+        // candidates and more unrelated guild_a messages than the linear scan covers follow.
+        let mut embeddings = vec![("a_old".to_string(), near_axis(0.5))];
+        db.save_message("a_old", "guild_a", "ca", "u1", "needle", 1700000000)
+            .unwrap();
+        for i in 0..600 {
+            let id = format!("b{}", i);
+            db.save_message(&id, "guild_b", "cb", "u2", "other", 1700000001 + i)
+                .unwrap();
+            embeddings.push((id, near_axis(0.1)));
+        }
+        for i in 0..5100 {
+            db.save_message(
+                &format!("a{}", i),
+                "guild_a",
+                "ca",
+                "u1",
+                "noise",
+                1700010000 + i,
+            )
+            .unwrap();
+        }
+        for (discord_id, embedding) in &embeddings {
+            let id: i64 = db
+                .write_conn()
+                .unwrap()
+                .query_row(
+                    "SELECT id FROM messages WHERE discord_id = ?1",
+                    [discord_id],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            db.set_message_embedding(id, embedding).unwrap();
+        }
+        db.load_vector_index().unwrap();
+
+        // The noise only needs to fill the scan window, so it stays out of the index.
+        let mut noise = vec![0.0; 16];
+        noise[1] = 1.0;
+        db.write_conn()
+            .unwrap()
+            .execute(
+                "UPDATE messages SET embedding = ?1, embedding_model = 'test', embedding_dim = 16
+                 WHERE content = 'noise'",
+                [serialize_embedding(&noise)],
+            )
+            .unwrap();
+
+        let mut query = vec![0.0; 16];
+        query[0] = 1.0;
+        let filter = crate::rag::SearchFilter::new("guild_a").with_limit(1);
+        let results = db.search_messages_vector(&query, &filter).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].content, "needle");
+    }
+
+    #[test]
+    fn test_embeddings_from_other_models_are_excluded_and_reembedded() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message("m1", "g1", "c1", "u1", "current model", 1700000000)
+            .unwrap();
+        db.save_message("m2", "g1", "c1", "u1", "previous model", 1700000001)
+            .unwrap();
+        db.save_message("m3", "g1", "c1", "u1", "never embedded", 1700000002)
+            .unwrap();
+        let id_for = |discord_id: &str| -> i64 {
+            let conn = db.write_conn().unwrap();
+            conn.query_row(
+                "SELECT id FROM messages WHERE discord_id = ?1",
+                [discord_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        db.set_message_embedding(id_for("m1"), &[1.0, 0.0, 0.0])
+            .unwrap();
+        db.set_message_embedding(id_for("m2"), &[1.0, 0.0, 0.0])
+            .unwrap();
+        db.write_conn()
+            .unwrap()
+            .execute(
+                "UPDATE messages SET embedding_model = 'old-model' WHERE discord_id = 'm2'",
+                [],
+            )
+            .unwrap();
+
+        let filter = crate::rag::SearchFilter::new("g1").with_limit(10);
+        let results = db
+            .search_messages_vector(&[1.0, 0.0, 0.0], &filter)
+            .unwrap();
+        let contents: Vec<_> = results.iter().map(|r| r.content.as_str()).collect();
+        assert_eq!(contents, vec!["current model"]);
+        // A query of another dimension matches nothing rather than scoring 0.
+        assert!(db
+            .search_messages_vector(&[1.0, 0.0], &filter)
+            .unwrap()
+            .is_empty());
+
+        let stale = db.get_messages_with_stale_embeddings(10).unwrap();
+        assert_eq!(stale, vec![(id_for("m2"), "previous model".to_string())]);
+
+        let status = db.get_embedding_status().unwrap();
+        assert_eq!(status.active_model, "test");
+        assert_eq!(status.active_dims, vec![3]);
+        assert_eq!(status.current, 1);
+        assert_eq!(
+            status.stale_by_model,
+            vec![(Some("old-model".to_string()), 1)]
+        );
+        assert_eq!(status.pending, 1);
+
+        db.set_message_embedding(id_for("m2"), &[0.0, 1.0, 0.0])
+            .unwrap();
+        assert!(db
+            .get_messages_with_stale_embeddings(10)
+            .unwrap()
+            .is_empty());
+        let status = db.get_embedding_status().unwrap();
+        assert_eq!((status.current, status.stale()), (2, 0));
+        assert!((status.progress() - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_record_embedding_failure_backs_off_then_gives_up() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+        db.save_message("m1", "g1", "c1", "u1", "cannot embed", 1700000000)
+            .unwrap();
+        let (id, _) = db.get_messages_missing_embeddings(1).unwrap()[0].clone();
+        let backoff = std::time::Duration::from_secs(60);
+
+        assert!(!db.record_embedding_failure(id, "boom", 2, backoff).unwrap());
+        let (attempts, delay): (i64, i64) = db
+            .write_conn()
+            .unwrap()
+            .query_row(
+                "SELECT embedding_attempts,
+                        CAST(strftime('%s', embedding_retry_at) AS INTEGER) - strftime('%s', 'now')
+                 FROM messages WHERE id = ?1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(attempts, 1);
+        assert!((58..=60).contains(&delay));
+        assert!(db.get_messages_missing_embeddings(1).unwrap().is_empty());
+
+        assert!(db
+            .record_embedding_failure(id, "boom again", 2, backoff)
+            .unwrap());
+        let status = db.get_embedding_status().unwrap();
+        assert_eq!((status.pending, status.skipped, status.failed), (0, 0, 1));
+
+        // A later successful embedding clears the failure state.
+        db.set_message_embedding(id, &[1.0, 0.0]).unwrap();
+        let status = db.get_embedding_status().unwrap();
+        assert_eq!((status.current, status.failed), (1, 0));
+    }
+
+    #[test]
+    fn test_execute_init_leaves_unlabelled_embeddings_stale() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message("m1", "g1", "c1", "u1", "legacy vector", 1700000000)
+            .unwrap();
+        db.write_conn()
+            .unwrap()
+            .execute(
+                "UPDATE messages SET embedding = ?1 WHERE discord_id = 'm1'",
+                [serialize_embedding(&[1.0, 0.0, 0.0, 0.0])],
+            )
+            .unwrap();
+
+        db.execute_init().unwrap();
+
+        // The vector may come from an earlier model: it is not searched, but re-embedded.
+        let (model, dim): (Option<String>, Option<i64>) = db
+            .write_conn()
+            .unwrap()
+            .query_row(
+                "SELECT embedding_model, embedding_dim FROM messages WHERE discord_id = 'm1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((model, dim), (None, None));
+        let stale = db.get_messages_with_stale_embeddings(10).unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].1, "legacy vector");
+        let status = db.get_embedding_status().unwrap();
+        assert_eq!(status.current, 0);
+        assert_eq!(status.stale_by_model, vec![(None, 1)]);
+    }
+
+    fn temp_database_url(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("mascord-{}-{}.db", name, std::process::id()))
+            .display()
+            .to_string()
+    }
+
+    fn remove_database_files(database_url: &str) {
+        for suffix in ["", "-wal", "-shm", ".hnsw"] {
+            std::fs::remove_file(format!("{}{}", database_url, suffix)).ok();
+        }
+    }
+
+    #[test]
+    fn test_file_database_reads_do_not_block_writes() {
+        let mut config = test_config();
+        config.database_url = temp_database_url("pooled");
+        config.database_read_connections = 2;
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message("1", "g1", "c1", "u1", "written by the writer", 1700000000)
+            .unwrap();
+        assert_eq!(keyword_search(&db, "writer"), vec!["written by the writer"]);
+
+        // Hold every reader; writes still go through.
+        let r1 = db.read_conn().unwrap();
+        let r2 = db.read_conn().unwrap();
+        db.save_message("2", "g1", "c1", "u1", "second message", 1700000001)
+            .unwrap();
+        drop((r1, r2));
+        assert_eq!(keyword_search(&db, "second"), vec!["second message"]);
+
+        drop(db);
+        remove_database_files(&config.database_url);
+    }
+
+    #[test]
+    fn test_vector_index_persists_and_rebuilds_on_model_change() {
+        let mut config = test_config();
+        config.database_url = temp_database_url("vector-index");
+        let index_path = PathBuf::from(format!("{}.hnsw", config.database_url));
+
+        config.embedding_model = "model-a".to_string();
+        {
+            let db = Database::new(&config).unwrap();
+            db.execute_init().unwrap();
+            db.save_message("m1", "g1", "c1", "u1", "hello", 1700000000)
+                .unwrap();
+            let id: i64 = db
+                .write_conn()
+                .unwrap()
+                .query_row(
+                    "SELECT id FROM messages WHERE discord_id = 'm1'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            db.set_message_embedding(id, &[1.0, 0.0, 0.0]).unwrap();
+            db.load_vector_index().unwrap();
+        }
+        assert_eq!(HnswIndex::load(&index_path).unwrap().model(), "model-a");
+
+        config.embedding_model = "model-b".to_string();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+        db.load_vector_index().unwrap();
+        let rebuilt = HnswIndex::load(&index_path).unwrap();
+        // model-a vectors stay out of the index until they are re-embedded.
+        let indexed_before = db.vector_index.len();
+        let (id, _) = db.get_messages_with_stale_embeddings(1).unwrap()[0].clone();
+        db.set_message_embedding(id, &[0.0, 1.0, 0.0]).unwrap();
+        let indexed_after = db.vector_index.len();
+        drop(db);
+        remove_database_files(&config.database_url);
+
+        assert_eq!(rebuilt.model(), "model-b");
+        assert_eq!(rebuilt.len(), 0);
+        assert_eq!((indexed_before, indexed_after), (0, 1));
+    }
+
+    #[test]
+    fn test_search_is_scoped_to_guild() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message("a1", "guild_a", "ca", "u1", "secret plan A", 1700000000)
+            .unwrap();
+        db.save_message("b1", "guild_b", "cb", "u2", "secret plan B", 1700000001)
+            .unwrap();
+
+        let id_for = |discord_id: &str| -> i64 {
+            let conn = db.write_conn().unwrap();
+            conn.query_row(
+                "SELECT id FROM messages WHERE discord_id = ?1",
+                [discord_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        db.set_message_embedding(id_for("a1"), &[1.0, 0.0, 0.0])
+            .unwrap();
+        db.set_message_embedding(id_for("b1"), &[1.0, 0.0, 0.0])
+            .unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        // Keyword-only path
+        let results = rt
+            .block_on(db.search_messages(
+                "secret",
+                vec![],
+                crate::rag::SearchFilter::new("guild_a").with_limit(10),
+            ))
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].content, "secret plan A");
+
+        // Hybrid path
+        let results = rt
+            .block_on(db.search_messages(
+                "secret",
+                vec![1.0, 0.0, 0.0],
+                crate::rag::SearchFilter::new("guild_b").with_limit(10),
+            ))
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].content, "secret plan B");
+
+        // Naming another guild's channel explicitly must not bypass the scope
+        let results = rt
+            .block_on(
+                db.search_messages(
+                    "secret",
+                    vec![1.0, 0.0, 0.0],
+                    crate::rag::SearchFilter::new("guild_a")
+                        .with_channel("cb".to_string())
+                        .with_limit(10),
+                ),
+            )
+            .unwrap();
+        assert!(results.is_empty());
+
+        // Unknown guild sees nothing
+        let results = rt
+            .block_on(db.search_messages(
+                "secret",
+                vec![1.0, 0.0, 0.0],
+                crate::rag::SearchFilter::new("guild_c").with_limit(10),
+            ))
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_search_author_and_excluded_channel_filters() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message("m1", "g1", "c1", "u1", "topic from u1", 1700000000)
+            .unwrap();
+        db.save_message("m2", "g1", "c1", "u2", "topic from u2", 1700000001)
+            .unwrap();
+        db.save_message("m3", "g1", "c2", "u1", "topic in c2", 1700000002)
+            .unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let results = rt
+            .block_on(
+                db.search_messages(
+                    "topic",
+                    vec![],
+                    crate::rag::SearchFilter::new("g1")
+                        .with_author("u1".to_string())
+                        .with_limit(10),
+                ),
+            )
+            .unwrap();
+        let contents: Vec<_> = results.iter().map(|r| r.content.as_str()).collect();
+        assert_eq!(contents.len(), 2);
+        assert!(contents.contains(&"topic from u1"));
+        assert!(contents.contains(&"topic in c2"));
+
+        let results = rt
+            .block_on(
+                db.search_messages(
+                    "topic",
+                    vec![],
+                    crate::rag::SearchFilter::new("g1")
+                        .with_author("u1".to_string())
+                        .without_channel("c2".to_string())
+                        .with_limit(10),
+                ),
+            )
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].content, "topic from u1");
+    }
+
+    #[test]
+    fn test_search_thread_filters() {
+        use crate::rag::{SearchFilter, ThreadFilter};
+
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_channel_thread(&ChannelThread {
+            channel_id: "t1".to_string(),
+            guild_id: "g1".to_string(),
+            parent_channel_id: "c1".to_string(),
+            name: "release planning".to_string(),
+            is_forum_post: false,
+        })
+        .unwrap();
+        db.save_message("m1", "g1", "c1", "u1", "topic in channel", 1700000000)
+            .unwrap();
+        db.save_message("m2", "g1", "t1", "u1", "topic in thread", 1700000001)
+            .unwrap();
+        db.save_message("m3", "g1", "c2", "u1", "topic elsewhere", 1700000002)
+            .unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let search = |filter: SearchFilter| -> Vec<String> {
+            let mut contents: Vec<String> = rt
+                .block_on(db.search_messages("topic", vec![], filter.with_limit(10)))
+                .unwrap()
+                .into_iter()
+                .map(|r| r.content)
+                .collect();
+            contents.sort();
+            contents
+        };
+
+        // A channel filter covers the threads created in that channel.
+        assert_eq!(
+            search(SearchFilter::new("g1").with_channel("c1".to_string())),
+            vec!["topic in channel", "topic in thread"]
+        );
+        assert_eq!(
+            search(
+                SearchFilter::new("g1")
+                    .with_channel("c1".to_string())
+                    .with_threads(ThreadFilter::Exclude)
+            ),
+            vec!["topic in channel"]
+        );
+        assert_eq!(
+            search(SearchFilter::new("g1").with_threads(ThreadFilter::Only)),
+            vec!["topic in thread"]
+        );
+        // Excluding a channel also excludes its threads.
+        assert_eq!(
+            search(SearchFilter::new("g1").without_channel("c1".to_string())),
+            vec!["topic elsewhere"]
+        );
+    }
+
+    #[test]
+    fn test_attachment_chunks_are_searchable_and_deleted_with_message() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message("m1", "g1", "c1", "u1", "here is the log", 1700000000)
+            .unwrap();
+        db.save_message("m2", "g2", "c2", "u1", "other guild", 1700000001)
+            .unwrap();
+        let chunks = vec![
+            "starting worker".to_string(),
+            "panicked at segmentation fault".to_string(),
+        ];
+        assert_eq!(
+            db.save_attachment_chunks("m1", "a1", "crash.log", &chunks)
+                .unwrap(),
+            2
+        );
+        // Saving again keeps the existing chunks; unknown messages are ignored.
+        assert_eq!(
+            db.save_attachment_chunks("m1", "a1", "crash.log", &chunks)
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            db.save_attachment_chunks("missing", "a2", "x.txt", &chunks)
+                .unwrap(),
+            0
+        );
+        db.save_attachment_chunks("m2", "a3", "other.log", &chunks)
+            .unwrap();
+        assert!(db.has_attachment_chunks("a1").unwrap());
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let filter = crate::rag::SearchFilter::new("g1").with_limit(10);
+        let results = rt
+            .block_on(db.search_messages("segmentation", vec![], filter.clone()))
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].content,
+            "[attachment crash.log] panicked at segmentation fault"
+        );
+        assert_eq!(results[0].channel_id, "c1");
+
+        // Chunk embeddings are found by vector search too.
+        let pending = db.get_attachment_chunks_missing_embeddings(10).unwrap();
+        assert_eq!(pending.len(), 4);
+        for (id, content) in pending {
+            let embedding = if content.contains("segmentation") {
+                [0.0, 1.0]
+            } else {
+                [1.0, 0.0]
+            };
+            db.set_attachment_chunk_embedding(id, &embedding).unwrap();
+        }
+        assert!(db
+            .get_attachment_chunks_missing_embeddings(10)
+            .unwrap()
+            .is_empty());
+        let results = rt
+            .block_on(db.search_messages("crash", vec![0.0, 1.0], filter.clone()))
+            .unwrap();
+        assert_eq!(
+            results[0].content,
+            "[attachment crash.log] panicked at segmentation fault"
+        );
+
+        let stored = db
+            .get_message_attachment_chunks(&["m1".to_string()])
+            .unwrap();
+        assert_eq!(
+            stored
+                .iter()
+                .map(|c| (c.filename.as_str(), c.chunk_index))
+                .collect::<Vec<_>>(),
+            vec![("crash.log", 0), ("crash.log", 1)]
+        );
+
+        db.delete_messages(&["m1".to_string()]).unwrap();
+        assert!(!db.has_attachment_chunks("a1").unwrap());
+        assert!(rt
+            .block_on(db.search_messages("segmentation", vec![], filter))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_channel_thread_roundtrip() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        let mut thread = ChannelThread {
+            channel_id: "t1".to_string(),
+            guild_id: "g1".to_string(),
+            parent_channel_id: "f1".to_string(),
+            name: "Bug: crash on join".to_string(),
+            is_forum_post: true,
+        };
+        db.save_channel_thread(&thread).unwrap();
+        assert_eq!(db.get_channel_thread("t1").unwrap(), Some(thread.clone()));
+        assert_eq!(db.get_channel_thread("c1").unwrap(), None);
+
+        thread.name = "Fixed: crash on join".to_string();
+        db.save_channel_thread(&thread).unwrap();
+        assert_eq!(
+            db.get_channel_thread("t1").unwrap().unwrap().name,
+            "Fixed: crash on join"
+        );
+
+        // The starter message shares the thread's id.
+        db.save_message("t1", "g1", "t1", "u1", "It crashes when I join", 1700000000)
+            .unwrap();
+        let starter = db.get_message_by_discord_id("t1").unwrap().unwrap();
+        assert_eq!(starter.content, "It crashes when I join");
+    }
+
+    fn keyword_search(db: &Database, query: &str) -> Vec<String> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(db.search_messages(
+                query,
+                vec![],
+                crate::rag::SearchFilter::new("g1").with_limit(10),
+            ))
+            .unwrap()
+            .into_iter()
+            .map(|r| r.content)
+            .collect()
+    }
+
+    #[test]
+    fn test_fts_phrase_prefix_and_stemming() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message(
+            "m1",
+            "g1",
+            "c1",
+            "u1",
+            "the database migration failed",
+            1700000000,
+        )
+        .unwrap();
+        db.save_message(
+            "m2",
+            "g1",
+            "c1",
+            "u1",
+            "migration of the database",
+            1700000001,
+        )
+        .unwrap();
+        db.save_message("m3", "g1", "c1", "u1", "we are running late", 1700000002)
+            .unwrap();
+
+        // Plain terms match regardless of order
+        assert_eq!(keyword_search(&db, "database migration").len(), 2);
+        // Quoted phrase requires adjacency
+        assert_eq!(
+            keyword_search(&db, "\"database migration\""),
+            vec!["the database migration failed"]
+        );
+        // Prefix query
+        assert_eq!(keyword_search(&db, "migra*").len(), 2);
+        // Porter stemming: "runs" matches "running"
+        assert_eq!(keyword_search(&db, "runs"), vec!["we are running late"]);
+        // OR between terms
+        assert_eq!(keyword_search(&db, "failed OR late").len(), 2);
+    }
+
+    #[test]
+    fn test_fts_ranks_by_bm25() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message(
+            "weak",
+            "g1",
+            "c1",
+            "u1",
+            "rust is mentioned once among many other words here",
+            1700000002,
+        )
+        .unwrap();
+        db.save_message("strong", "g1", "c1", "u1", "rust rust rust", 1700000000)
+            .unwrap();
+
+        let results = keyword_search(&db, "rust");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0], "rust rust rust");
+    }
+
+    #[test]
+    fn test_fts_stays_in_sync_with_messages() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message("m1", "g1", "c1", "u1", "ephemeral note", 1700000000)
+            .unwrap();
+        assert_eq!(keyword_search(&db, "ephemeral").len(), 1);
+
+        {
+            let conn = db.write_conn().unwrap();
+            conn.execute(
+                "UPDATE messages SET content = 'edited note' WHERE discord_id = 'm1'",
+                [],
+            )
+            .unwrap();
+        }
+        assert!(keyword_search(&db, "ephemeral").is_empty());
+        assert_eq!(keyword_search(&db, "edited").len(), 1);
+
+        db.purge_messages("c1", None).unwrap();
+        assert!(keyword_search(&db, "edited").is_empty());
+    }
+
+    #[test]
+    fn test_message_edits_and_deletions_invalidate_derived_data() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        db.save_message("m1", "g1", "c1", "u1", "launch is friday", 1700000000)
+            .unwrap();
+        db.save_message("m2", "g1", "c1", "u2", "bring snacks", 1700000001)
+            .unwrap();
+        db.save_message("m3", "g1", "c2", "u1", "other channel", 1700000002)
+            .unwrap();
+        for (id, _) in db.get_messages_missing_embeddings(10).unwrap() {
+            db.set_message_embedding(id, &[1.0, 0.0]).unwrap();
+        }
+        db.save_summary("c1", "Launch is friday.").unwrap();
+        db.save_summary("c2", "Other things.").unwrap();
+        assert!(db.get_dirty_summary_channels().unwrap().is_empty());
+
+        // Edits replace the content and queue the message for re-embedding.
+        assert_eq!(
+            db.update_message_content("m1", "launch is monday").unwrap(),
+            Some("c1".to_string())
+        );
+        assert_eq!(
+            db.update_message_content("m1", "launch is monday").unwrap(),
+            None
+        );
+        assert_eq!(db.update_message_content("unknown", "x").unwrap(), None);
+        let pending = db.get_messages_missing_embeddings(10).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1, "launch is monday");
+        assert!(keyword_search(&db, "friday").is_empty());
+        assert!(db.get_summary_record("c1").unwrap().unwrap().dirty);
+        assert_eq!(db.get_dirty_summary_channels().unwrap(), vec!["c1"]);
+
+        // Saving a new summary clears the flag.
+        db.save_summary_refresh("c1", "Launch is monday.").unwrap();
+        assert!(!db.get_summary_record("c1").unwrap().unwrap().dirty);
+
+        let ids = ["m2".to_string(), "m3".to_string(), "unknown".to_string()];
+        assert_eq!(db.delete_messages(&ids).unwrap(), 2);
+        assert!(keyword_search(&db, "snacks").is_empty());
+        let mut dirty = db.get_dirty_summary_channels().unwrap();
+        dirty.sort();
+        assert_eq!(dirty, vec!["c1", "c2"]);
+        assert_eq!(db.get_embedding_status().unwrap().pending, 1);
+    }
+
+    #[test]
+    fn test_fts_backfills_existing_messages() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+
+        // Simulate an unversioned database created before the FTS index existed.
+        {
+            let conn = db.write_conn().unwrap();
+            conn.execute_batch(
+                "
+                DROP TRIGGER messages_fts_ai;
+                DROP TRIGGER messages_fts_ad;
+                DROP TRIGGER messages_fts_au;
+                DROP TABLE messages_fts;
+                DROP TABLE schema_version;
+                INSERT INTO messages (discord_id, guild_id, channel_id, user_id, content, timestamp)
+                VALUES ('legacy', 'g1', 'c1', 'u1', 'legacy content', datetime('now'));
+                ",
+            )
+            .unwrap();
+        }
+
+        db.execute_init().unwrap();
+        assert_eq!(keyword_search(&db, "legacy"), vec!["legacy content"]);
+
+        // Re-running init must not duplicate index entries.
+        db.execute_init().unwrap();
+        assert_eq!(keyword_search(&db, "legacy").len(), 1);
+    }
+
+    #[test]
+    fn test_fts5_match_expression() {
+        assert_eq!(
+            fts5_match_expression("hello world").as_deref(),
+            Some("\"hello\" \"world\"")
+        );
+        assert_eq!(
+            fts5_match_expression("\"exact phrase\" pre*").as_deref(),
+            Some("\"exact phrase\" \"pre\"*")
+        );
+        assert_eq!(
+            fts5_match_expression("a OR b OR").as_deref(),
+            Some("\"a\" OR \"b\"")
+        );
+        assert_eq!(
+            fts5_match_expression("'; DROP TABLE messages; --").as_deref(),
+            Some("\"DROP\" \"TABLE\" \"messages;\"")
+        );
+        assert_eq!(fts5_match_expression("!!! --"), None);
+        assert_eq!(fts5_match_expression("OR"), None);
+    }
+
+    #[test]
+    fn test_merge_results_reciprocal_rank_fusion() {
+        let msg = |content: &str| crate::rag::MessageResult {
+            content: content.to_string(),
+            user_id: "u1".to_string(),
+            timestamp: "2024-01-01 00:00:00".to_string(),
+            channel_id: "c1".to_string(),
+        };
+
+        let vector = vec![msg("a"), msg("b"), msg("c")];
+        let keyword = vec![msg("c"), msg("d")];
+        let merged: Vec<_> = merge_results(vec![vector, keyword], 4)
+            .into_iter()
+            .map(|m| m.content)
+            .collect();
+
+        // "c" appears in both lists and wins; equal scores keep vector-first order.
+        assert_eq!(merged, vec!["c", "a", "b", "d"]);
+    }
+
     #[test]
     fn test_user_channel_cleanup_helpers() {
         let config = test_config();
@@ -1671,10 +3873,10 @@ mod tests {
 
         db.replace_channel_milestones(
             "c1",
-            &vec!["milestone 1".to_string(), "milestone 2".to_string()],
+            &["milestone 1".to_string(), "milestone 2".to_string()],
         )
         .unwrap();
-        db.replace_channel_milestones("c2", &vec!["milestone 3".to_string()])
+        db.replace_channel_milestones("c2", &["milestone 3".to_string()])
             .unwrap();
 
         let summaries_deleted = db.delete_channel_summaries(&channels).unwrap();
@@ -1686,4 +3888,90 @@ mod tests {
         let remaining = db.get_latest_summary("c3").unwrap();
         assert!(remaining.is_some());
     }
+
+    #[test]
+    fn test_agent_run_round_trip_by_reply_message() {
+        let db = Database::new(&test_config()).unwrap();
+        db.execute_init().unwrap();
+
+        let run = AgentRunRecord {
+            guild_id: Some("g1".to_string()),
+            channel_id: "c1".to_string(),
+            user_id: "u1".to_string(),
+            trigger_message_id: Some("100".to_string()),
+            final_answer: Some("It is sunny.".to_string()),
+            error: None,
+            steps: vec![
+                AgentStepRecord {
+                    request_summary: "3 messages, 2 tools".to_string(),
+                    content: None,
+                    tool_calls: vec![AgentToolCallRecord {
+                        id: "call_1".to_string(),
+                        name: "web_search".to_string(),
+                        arguments: r#"{"query":"weather"}"#.to_string(),
+                        result: r#"{"forecast":"sunny"}"#.to_string(),
+                        latency_ms: 40,
+                    }],
+                    latency_ms: 900,
+                },
+                AgentStepRecord {
+                    request_summary: "5 messages, 2 tools".to_string(),
+                    content: Some("It is sunny.".to_string()),
+                    tool_calls: Vec::new(),
+                    latency_ms: 700,
+                },
+            ],
+        };
+        db.save_agent_run(&run, &["201".to_string(), "202".to_string()])
+            .unwrap();
+
+        assert_eq!(db.get_agent_run_for_message("202").unwrap(), Some(run));
+        assert_eq!(db.get_agent_run_for_message("100").unwrap(), None);
+
+        assert_eq!(db.delete_agent_runs_by_user("u1").unwrap(), 1);
+        assert_eq!(db.get_agent_run_for_message("201").unwrap(), None);
+        let conn = db.read_conn().unwrap();
+        let steps: i64 = conn
+            .query_row("SELECT COUNT(*) FROM agent_steps", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(steps, 0);
+    }
+
+    #[test]
+    fn test_tool_policies_upsert_list_and_delete() {
+        let db = Database::new(&test_config()).unwrap();
+        db.execute_init().unwrap();
+
+        let mut policy = ToolPolicy {
+            guild_id: "g1".to_string(),
+            scope: PolicyScope::Channel,
+            scope_id: "c1".to_string(),
+            target: PolicyTarget::Server,
+            target_name: "github".to_string(),
+            action: PolicyAction::Deny,
+        };
+        db.set_tool_policy(&policy).unwrap();
+        policy.action = PolicyAction::Confirm;
+        db.set_tool_policy(&policy).unwrap();
+        db.set_tool_policy(&ToolPolicy {
+            guild_id: "g2".to_string(),
+            ..policy.clone()
+        })
+        .unwrap();
+
+        assert_eq!(db.list_tool_policies("g1").unwrap(), vec![policy.clone()]);
+        assert_eq!(
+            db.delete_tool_policy(
+                "g1",
+                PolicyScope::Channel,
+                "c1",
+                PolicyTarget::Server,
+                "github"
+            )
+            .unwrap(),
+            1
+        );
+        assert!(db.list_tool_policies("g1").unwrap().is_empty());
+        assert_eq!(db.list_tool_policies("g2").unwrap().len(), 1);
+    }
 }
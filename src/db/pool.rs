@@ -0,0 +1,180 @@
+//! SQLite connections for [`Database`](super::Database): one writer plus a small set of
+//! read-only connections.
+//!
+//! File databases run in WAL mode so readers never block the writer (and vice versa); only
+//! writes serialize. In-memory databases cannot share state across connections, so they get
+//! no readers and every call goes through the writer.
+
+use anyhow::Context as AnyhowContext;
+use rusqlite::{Connection, OpenFlags};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::time::Duration;
+
+/// Prepared statements kept per connection (`prepare_cached`).
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+pub(super) struct ConnectionPool {
+    writer: Mutex<Connection>,
+    readers: Vec<Mutex<Connection>>,
+    next_reader: AtomicUsize,
+}
+
+impl ConnectionPool {
+    pub(super) fn open(
+        database_url: &str,
+        read_connections: usize,
+        busy_timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let writer = Connection::open(database_url)
+            .with_context(|| format!("Failed to open database at '{}'", database_url))?;
+        configure(&writer, busy_timeout)?;
+
+        let in_memory = database_url == ":memory:";
+        if !in_memory {
+            let mode: String = writer
+                .query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))
+                .context("Failed to enable WAL journaling")?;
+            if !mode.eq_ignore_ascii_case("wal") {
+                tracing::warn!(
+                    "Database: WAL journaling unavailable (journal_mode = {}); readers may block writes",
+                    mode
+                );
+            }
+            writer
+                .execute_batch("PRAGMA synchronous = NORMAL;")
+                .context("Failed to set synchronous mode")?;
+        }
+
+        let readers = if in_memory {
+            Vec::new()
+        } else {
+            (0..read_connections)
+                .map(|_| {
+                    let conn = Connection::open_with_flags(
+                        database_url,
+                        OpenFlags::SQLITE_OPEN_READ_ONLY
+                            | OpenFlags::SQLITE_OPEN_URI
+                            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                    )
+                    .with_context(|| {
+                        format!("Failed to open read connection to '{}'", database_url)
+                    })?;
+                    configure(&conn, busy_timeout)?;
+                    Ok(Mutex::new(conn))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?
+        };
+
+        Ok(Self {
+            writer: Mutex::new(writer),
+            readers,
+            next_reader: AtomicUsize::new(0),
+        })
+    }
+
+    pub(super) fn writer(&self) -> anyhow::Result<MutexGuard<'_, Connection>> {
+        self.writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Database connection lock poisoned"))
+    }
+
+    /// A read-only connection: the first idle reader, or the next one in turn if all are busy.
+    /// Falls back to the writer when there are no readers.
+    pub(super) fn reader(&self) -> anyhow::Result<MutexGuard<'_, Connection>> {
+        if self.readers.is_empty() {
+            return self.writer();
+        }
+        for reader in &self.readers {
+            match reader.try_lock() {
+                Ok(guard) => return Ok(guard),
+                Err(TryLockError::WouldBlock) => continue,
+                Err(TryLockError::Poisoned(_)) => {
+                    return Err(anyhow::anyhow!("Database connection lock poisoned"))
+                }
+            }
+        }
+        let idx = self.next_reader.fetch_add(1, Ordering::Relaxed) % self.readers.len();
+        self.readers[idx]
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Database connection lock poisoned"))
+    }
+
+    pub(super) fn reader_count(&self) -> usize {
+        self.readers.len()
+    }
+}
+
+fn configure(conn: &Connection, busy_timeout: Duration) -> anyhow::Result<()> {
+    conn.busy_timeout(busy_timeout)
+        .context("Failed to set busy timeout")?;
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_database_uses_wal_and_independent_readers() {
+        let path =
+            std::env::temp_dir().join(format!("mascord-pool-test-{}.db", std::process::id()));
+        let url = path.display().to_string();
+        let pool = ConnectionPool::open(&url, 2, Duration::from_millis(1000)).unwrap();
+        assert_eq!(pool.reader_count(), 2);
+
+        {
+            let writer = pool.writer().unwrap();
+            let mode: String = writer
+                .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(mode, "wal");
+            writer
+                .execute_batch("CREATE TABLE t (v INTEGER); INSERT INTO t VALUES (1);")
+                .unwrap();
+        }
+
+        // An open read transaction neither blocks the writer nor sees its later commits.
+        let reader = pool.reader().unwrap();
+        reader.execute_batch("BEGIN").unwrap();
+        let before: i64 = reader
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .unwrap();
+        pool.writer()
+            .unwrap()
+            .execute("INSERT INTO t VALUES (2)", [])
+            .unwrap();
+        let during: i64 = reader
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .unwrap();
+        reader.execute_batch("COMMIT").unwrap();
+        assert_eq!((before, during), (1, 1));
+
+        // A second reader is handed out while the first is held, and readers reject writes.
+        let other = pool.reader().unwrap();
+        let after: i64 = other
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(after, 2);
+        assert!(other.execute("INSERT INTO t VALUES (3)", []).is_err());
+
+        drop(reader);
+        drop(other);
+        drop(pool);
+        for suffix in ["", "-wal", "-shm"] {
+            std::fs::remove_file(format!("{}{}", url, suffix)).ok();
+        }
+    }
+
+    #[test]
+    fn test_in_memory_database_reads_through_writer() {
+        let pool = ConnectionPool::open(":memory:", 4, Duration::from_millis(1000)).unwrap();
+        assert_eq!(pool.reader_count(), 0);
+        pool.writer()
+            .unwrap()
+            .execute_batch("CREATE TABLE t (v INTEGER);")
+            .unwrap();
+        assert!(pool.reader().unwrap().prepare("SELECT v FROM t").is_ok());
+    }
+}
@@ -1,3 +1,13 @@
+-- Reference snapshot of the current schema. The authoritative definitions are the
+-- versioned steps in src/db/migrations.rs; update this file when adding one.
+
+-- Applied migrations
+CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER PRIMARY KEY,
+    description TEXT NOT NULL,
+    applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
 -- Standard message storage
 CREATE TABLE IF NOT EXISTS messages (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -8,12 +18,72 @@ CREATE TABLE IF NOT EXISTS messages (
     content TEXT NOT NULL,
     timestamp DATETIME NOT NULL,
     is_indexed BOOLEAN DEFAULT FALSE,
-    embedding BLOB NULL
+    embedding BLOB NULL,
+    embedding_model TEXT,   -- EMBEDDING_MODEL that produced `embedding`
+    embedding_dim INTEGER,  -- number of f32 components in `embedding`
+    embedding_attempts INTEGER NOT NULL DEFAULT 0, -- consecutive failed embedding attempts
+    embedding_retry_at DATETIME,                    -- next attempt after a failure (exponential backoff)
+    embedding_error TEXT                            -- last failure; kept once the indexer gives up
 );
 
 -- Index for date and channel filtering
 CREATE INDEX IF NOT EXISTS idx_messages_channel_date ON messages (channel_id, timestamp);
 CREATE INDEX IF NOT EXISTS idx_messages_guild_date ON messages (guild_id, timestamp);
+CREATE INDEX IF NOT EXISTS idx_messages_embedding_model ON messages (embedding_model);
+
+-- Full-text keyword index (bm25-ranked), kept in sync with messages by triggers
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+    content,
+    content = 'messages',
+    content_rowid = 'id',
+    tokenize = 'porter unicode61'
+);
+CREATE TRIGGER IF NOT EXISTS messages_fts_ai AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_fts(rowid, content) VALUES (new.id, new.content);
+END;
+CREATE TRIGGER IF NOT EXISTS messages_fts_ad AFTER DELETE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+END;
+CREATE TRIGGER IF NOT EXISTS messages_fts_au AFTER UPDATE OF content ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+    INSERT INTO messages_fts(rowid, content) VALUES (new.id, new.content);
+END;
+
+-- Text extracted from message attachments, one row per chunk (deleted with the message)
+CREATE TABLE IF NOT EXISTS attachment_chunks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL,    -- messages.id
+    attachment_id TEXT NOT NULL,    -- Discord attachment id
+    filename TEXT NOT NULL,
+    chunk_index INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    is_indexed BOOLEAN NOT NULL DEFAULT FALSE,
+    embedding BLOB NULL,
+    embedding_model TEXT,
+    embedding_dim INTEGER,
+    embedding_attempts INTEGER NOT NULL DEFAULT 0,
+    embedding_retry_at DATETIME,
+    embedding_error TEXT,
+    UNIQUE (attachment_id, chunk_index)
+);
+CREATE INDEX IF NOT EXISTS idx_attachment_chunks_message ON attachment_chunks (message_id);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS attachment_chunks_fts USING fts5(
+    content,
+    content = 'attachment_chunks',
+    content_rowid = 'id',
+    tokenize = 'porter unicode61'
+);
+CREATE TRIGGER IF NOT EXISTS attachment_chunks_fts_ai AFTER INSERT ON attachment_chunks BEGIN
+    INSERT INTO attachment_chunks_fts(rowid, content) VALUES (new.id, new.content);
+END;
+CREATE TRIGGER IF NOT EXISTS attachment_chunks_fts_ad AFTER DELETE ON attachment_chunks BEGIN
+    INSERT INTO attachment_chunks_fts(attachment_chunks_fts, rowid, content)
+        VALUES ('delete', old.id, old.content);
+END;
+CREATE TRIGGER IF NOT EXISTS messages_attachment_chunks_ad AFTER DELETE ON messages BEGIN
+    DELETE FROM attachment_chunks WHERE message_id = old.id;
+END;
 
 CREATE TABLE IF NOT EXISTS settings (
     guild_id TEXT PRIMARY KEY,
@@ -28,7 +98,8 @@ CREATE TABLE IF NOT EXISTS channel_summaries (
     channel_id TEXT PRIMARY KEY,
     summary TEXT NOT NULL,
     updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-    refreshed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    refreshed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    dirty BOOLEAN NOT NULL DEFAULT FALSE
 );
 
 CREATE TABLE IF NOT EXISTS channel_settings (
@@ -41,6 +112,19 @@ CREATE TABLE IF NOT EXISTS channel_settings (
 );
 CREATE INDEX IF NOT EXISTS idx_channel_guild ON channel_settings (guild_id);
 
+-- Threads and forum posts (messages use the thread id as channel_id; the starter message
+-- shares the thread's id)
+CREATE TABLE IF NOT EXISTS channel_threads (
+    channel_id TEXT PRIMARY KEY,
+    guild_id TEXT NOT NULL,
+    parent_channel_id TEXT NOT NULL,
+    name TEXT NOT NULL DEFAULT '',
+    is_forum_post BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS idx_channel_threads_parent ON channel_threads (parent_channel_id);
+
 CREATE TABLE IF NOT EXISTS channel_milestones (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     channel_id TEXT NOT NULL,
@@ -70,5 +154,72 @@ CREATE TABLE IF NOT EXISTS reminders (
 CREATE INDEX IF NOT EXISTS idx_reminders_due ON reminders (remind_at, delivered_at);
 CREATE INDEX IF NOT EXISTS idx_reminders_user ON reminders (user_id, delivered_at);
 
+-- Resumable `/settings memory backfill` progress (cursor pages backwards through history)
+CREATE TABLE IF NOT EXISTS backfill_checkpoints (
+    channel_id TEXT PRIMARY KEY,
+    guild_id TEXT NOT NULL,
+    since DATETIME,
+    before_message_id TEXT,
+    fetched INTEGER NOT NULL DEFAULT 0,
+    saved INTEGER NOT NULL DEFAULT 0,
+    started_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    completed_at DATETIME
+);
+
+-- Agent runs keyed by the bot messages showing the answer; each step is one LLM turn with
+-- its tool calls (JSON array of id, name, arguments, result, latency_ms)
+CREATE TABLE IF NOT EXISTS agent_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id TEXT,
+    channel_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    trigger_message_id TEXT,
+    final_answer TEXT,
+    error TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS idx_agent_runs_created ON agent_runs (created_at);
+CREATE INDEX IF NOT EXISTS idx_agent_runs_user ON agent_runs (user_id);
+
+CREATE TABLE IF NOT EXISTS agent_steps (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    run_id INTEGER NOT NULL,
+    iteration INTEGER NOT NULL,
+    request_summary TEXT NOT NULL,
+    content TEXT,
+    tool_calls TEXT NOT NULL DEFAULT '[]',
+    latency_ms INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (run_id, iteration)
+);
+
+CREATE TABLE IF NOT EXISTS agent_run_messages (
+    message_id TEXT PRIMARY KEY,
+    run_id INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_agent_run_messages_run ON agent_run_messages (run_id);
+
+CREATE TRIGGER IF NOT EXISTS agent_runs_ad AFTER DELETE ON agent_runs BEGIN
+    DELETE FROM agent_steps WHERE run_id = old.id;
+    DELETE FROM agent_run_messages WHERE run_id = old.id;
+END;
+
+-- Per-guild tool rules (`/settings tools`). scope: guild | channel | role (scope_id is the
+-- guild, channel or role id); target: tool | server (an MCP server); action: allow | confirm | deny
+CREATE TABLE IF NOT EXISTS tool_policies (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id TEXT NOT NULL,
+    scope TEXT NOT NULL,
+    scope_id TEXT NOT NULL,
+    target TEXT NOT NULL,
+    target_name TEXT NOT NULL,
+    action TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (guild_id, scope, scope_id, target, target_name)
+);
+
 -- Note: sqlite-vec setup usually involves virtual tables.
 -- Mascord currently uses in-process Rust vector scoring over BLOB embeddings.
+-- Candidates come from an HNSW index (src/rag/ann.rs) persisted next to the
+-- database as `<DATABASE_URL>.hnsw`; `messages.embedding` remains the source of truth.
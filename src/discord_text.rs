@@ -57,6 +57,16 @@ pub fn extract_message_text(message: &serenity::Message) -> String {
         }
     }
 
+    for attachment in &message.attachments {
+        match attachment.description.as_deref().map(str::trim) {
+            Some(description) if !description.is_empty() => parts.push(format!(
+                "[attachment: {} ({})]",
+                attachment.filename, description
+            )),
+            _ => parts.push(format!("[attachment: {}]", attachment.filename)),
+        }
+    }
+
     parts.join("\n")
 }
 
@@ -110,7 +120,7 @@ fn preprocess_tables(input: &str) -> String {
                         if row_line.trim().is_empty() {
                             break;
                         }
-                        rows.push(split_table_row(&row_line));
+                        rows.push(split_table_row(row_line));
                     }
                     output.extend(format_table_block(headers, rows));
                     continue;
@@ -222,7 +232,7 @@ impl DiscordRenderer {
             }
             Tag::List(start) => {
                 let kind = match start {
-                    Some(value) => ListKind::Ordered { next_index: value as u64 },
+                    Some(value) => ListKind::Ordered { next_index: value },
                     None => ListKind::Unordered,
                 };
                 self.list_stack.push(ListState { kind });
@@ -235,11 +245,17 @@ impl DiscordRenderer {
             Tag::Strikethrough => self.write_raw("~~"),
             Tag::CodeBlock(kind) => self.start_code_block(kind),
             Tag::Link { dest_url, .. } => {
-                self.link_stack.push(LinkState { destination: dest_url.to_string(), is_image: false });
+                self.link_stack.push(LinkState {
+                    destination: dest_url.to_string(),
+                    is_image: false,
+                });
                 self.output_stack.push(String::new());
             }
             Tag::Image { dest_url, .. } => {
-                self.link_stack.push(LinkState { destination: dest_url.to_string(), is_image: true });
+                self.link_stack.push(LinkState {
+                    destination: dest_url.to_string(),
+                    is_image: true,
+                });
                 self.output_stack.push(String::new());
             }
             Tag::Table(_) | Tag::TableHead | Tag::TableRow | Tag::TableCell => {}
@@ -523,7 +539,10 @@ fn looks_like_table_separator(line: &str) -> bool {
 }
 
 fn split_table_row(line: &str) -> Vec<String> {
-    let mut cells: Vec<String> = line.split('|').map(|cell| cell.trim().to_string()).collect();
+    let mut cells: Vec<String> = line
+        .split('|')
+        .map(|cell| cell.trim().to_string())
+        .collect();
 
     while matches!(cells.first(), Some(cell) if cell.is_empty()) {
         cells.remove(0);
@@ -562,8 +581,7 @@ fn format_table_block(headers: Vec<String>, rows: Vec<Vec<String>>) -> Vec<Strin
                 if cell.is_empty() {
                     continue;
                 }
-                if let Some(header) = headers.get(idx).map(|h| h.trim()).filter(|h| !h.is_empty())
-                {
+                if let Some(header) = headers.get(idx).map(|h| h.trim()).filter(|h| !h.is_empty()) {
                     parts.push(format!("{}: {}", header, cell));
                 } else {
                     parts.push(cell.to_string());
@@ -1,28 +1,76 @@
+use crate::config::Config;
 use crate::db::Database;
 use crate::llm::LlmClient;
+use async_openai::error::OpenAIError;
+use futures::future::BoxFuture;
 use std::sync::Arc;
 use tokio::time::Duration;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+/// Rough token estimate used to size embedding requests.
+const CHARS_PER_TOKEN: usize = 4;
+/// Retries within one cycle for requests that fail transiently (timeouts, connection errors).
+const TRANSIENT_RETRIES: u32 = 3;
+
+/// A row whose content is embedded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EmbeddingTarget {
+    Message(i64),
+    AttachmentChunk(i64),
+}
+
+impl std::fmt::Display for EmbeddingTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Message(id) => write!(f, "message {}", id),
+            Self::AttachmentChunk(id) => write!(f, "attachment chunk {}", id),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EmbeddingIndexerPolicy {
+    /// Messages (and attachment chunks) picked up per cycle.
+    pub batch_size: usize,
+    pub interval: Duration,
+    pub max_inputs_per_request: usize,
+    pub max_tokens_per_request: usize,
+    /// Failed attempts after which a message is no longer retried.
+    pub max_attempts: u32,
+    /// First delay between transient retries; doubles on each retry.
+    pub retry_base_delay: Duration,
+}
+
+impl EmbeddingIndexerPolicy {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            batch_size: config.embedding_indexer_batch_size,
+            interval: Duration::from_secs(config.embedding_indexer_interval_secs),
+            max_inputs_per_request: config.embedding_batch_max_inputs.max(1),
+            max_tokens_per_request: config.embedding_batch_max_tokens.max(1),
+            max_attempts: config.embedding_max_attempts.max(1),
+            retry_base_delay: Duration::from_secs(1),
+        }
+    }
+}
 
 pub struct EmbeddingIndexer {
     db: Database,
     llm: Arc<LlmClient>,
-    batch_size: usize,
-    interval: Duration,
+    policy: EmbeddingIndexerPolicy,
 }
 
 impl EmbeddingIndexer {
-    pub fn new(db: Database, llm: Arc<LlmClient>, batch_size: usize, interval: Duration) -> Self {
-        Self {
-            db,
-            llm,
-            batch_size,
-            interval,
-        }
+    pub fn new(db: Database, llm: Arc<LlmClient>, config: &Config) -> Self {
+        Self::with_policy(db, llm, EmbeddingIndexerPolicy::from_config(config))
+    }
+
+    pub fn with_policy(db: Database, llm: Arc<LlmClient>, policy: EmbeddingIndexerPolicy) -> Self {
+        Self { db, llm, policy }
     }
 
     pub async fn run(self) {
-        let mut ticker = tokio::time::interval(self.interval);
+        let mut ticker = tokio::time::interval(self.policy.interval);
         loop {
             ticker.tick().await;
             match self.process_batch().await {
@@ -34,34 +82,308 @@ impl EmbeddingIndexer {
     }
 
     async fn process_batch(&self) -> anyhow::Result<usize> {
-        let batch_size = self.batch_size;
+        let batch_size = self.policy.batch_size;
         let db = self.db.clone();
-        let pending =
-            tokio::task::spawn_blocking(move || db.get_messages_missing_embeddings(batch_size))
-                .await??;
+        let pending = tokio::task::spawn_blocking(move || {
+            // New messages first; spare capacity re-embeds vectors from a previous model.
+            let mut pending = db.get_messages_missing_embeddings(batch_size)?;
+            if pending.len() < batch_size {
+                let stale = db.get_messages_with_stale_embeddings(batch_size - pending.len())?;
+                if !stale.is_empty() {
+                    debug!(
+                        "Embedding indexer: re-embedding {} messages from a previous model",
+                        stale.len()
+                    );
+                }
+                pending.extend(stale);
+            }
+            let mut pending: Vec<(EmbeddingTarget, String)> = pending
+                .into_iter()
+                .map(|(id, content)| (EmbeddingTarget::Message(id), content))
+                .collect();
+            // Attachment text after messages.
+            if pending.len() < batch_size {
+                let chunks =
+                    db.get_attachment_chunks_missing_embeddings(batch_size - pending.len())?;
+                pending.extend(
+                    chunks
+                        .into_iter()
+                        .map(|(id, content)| (EmbeddingTarget::AttachmentChunk(id), content)),
+                );
+            }
+            anyhow::Ok(pending)
+        })
+        .await??;
 
-        let mut indexed = 0usize;
-        for (id, content) in pending {
+        let mut inputs = Vec::with_capacity(pending.len());
+        for (target, content) in pending {
             // Skip very short messages to reduce embedding noise/cost.
             if content.trim().len() < 3 {
                 let db = self.db.clone();
-                tokio::task::spawn_blocking(move || db.mark_message_indexed(id)).await??;
+                tokio::task::spawn_blocking(move || match target {
+                    EmbeddingTarget::Message(id) => db.mark_message_indexed(id),
+                    EmbeddingTarget::AttachmentChunk(id) => db.mark_attachment_chunk_indexed(id),
+                })
+                .await??;
                 continue;
             }
+            inputs.push((
+                target,
+                truncate_to_tokens(&content, self.policy.max_tokens_per_request),
+            ));
+        }
+
+        let mut indexed = 0usize;
+        for request in split_requests(
+            &inputs,
+            self.policy.max_inputs_per_request,
+            self.policy.max_tokens_per_request,
+        ) {
+            match self.embed_items(request).await {
+                Ok(n) => indexed += n,
+                Err(e) => {
+                    // Transient failure: leave the rest for the next cycle without counting
+                    // it against the messages.
+                    warn!(
+                        "Embedding indexer: embedding service unavailable, retrying next cycle: {}",
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+
+        if indexed > 0 {
+            let db = self.db.clone();
+            tokio::task::spawn_blocking(move || db.persist_vector_index()).await??;
+        }
+
+        Ok(indexed)
+    }
 
-            match self.llm.get_embeddings(&content).await {
-                Ok(embedding) => {
+    /// Embed `items` in one request and store the results. If the service rejects the request,
+    /// the items are split in half until the offending message is isolated and its failure is
+    /// recorded. Returns the number of items embedded; errors only for transient failures.
+    fn embed_items<'a>(
+        &'a self,
+        items: &'a [(EmbeddingTarget, String)],
+    ) -> BoxFuture<'a, anyhow::Result<usize>> {
+        Box::pin(async move {
+            let texts: Vec<String> = items.iter().map(|(_, text)| text.clone()).collect();
+            match self.request_with_retry(&texts).await {
+                Ok(embeddings) => {
                     let db = self.db.clone();
-                    tokio::task::spawn_blocking(move || db.set_message_embedding(id, &embedding))
-                        .await??;
-                    indexed += 1;
+                    let targets: Vec<EmbeddingTarget> =
+                        items.iter().map(|(target, _)| *target).collect();
+                    tokio::task::spawn_blocking(move || {
+                        for (target, embedding) in targets.into_iter().zip(embeddings) {
+                            match target {
+                                EmbeddingTarget::Message(id) => {
+                                    db.set_message_embedding(id, &embedding)?
+                                }
+                                EmbeddingTarget::AttachmentChunk(id) => {
+                                    db.set_attachment_chunk_embedding(id, &embedding)?
+                                }
+                            }
+                        }
+                        anyhow::Ok(())
+                    })
+                    .await??;
+                    Ok(items.len())
+                }
+                Err(e) if is_rejected(&e) && items.len() > 1 => {
+                    let (left, right) = items.split_at(items.len() / 2);
+                    Ok(self.embed_items(left).await? + self.embed_items(right).await?)
                 }
+                Err(e) if is_rejected(&e) => {
+                    let (target, _) = items[0];
+                    let error = e.to_string();
+                    let db = self.db.clone();
+                    let max_attempts = self.policy.max_attempts;
+                    let backoff = self.policy.interval;
+                    let gave_up = tokio::task::spawn_blocking(move || match target {
+                        EmbeddingTarget::Message(id) => {
+                            db.record_embedding_failure(id, &error, max_attempts, backoff)
+                        }
+                        EmbeddingTarget::AttachmentChunk(id) => {
+                            db.record_attachment_chunk_failure(id, &error, max_attempts, backoff)
+                        }
+                    })
+                    .await??;
+                    if gave_up {
+                        warn!(
+                            "Embedding indexer: giving up on {} after {} attempts: {}",
+                            target, max_attempts, e
+                        );
+                    } else {
+                        debug!("Embedding indexer: failed to embed {}: {}", target, e);
+                    }
+                    Ok(0)
+                }
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    async fn request_with_retry(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let mut delay = self.policy.retry_base_delay;
+        let mut retries = 0;
+        loop {
+            match self.llm.get_embeddings_batch(texts).await {
+                Ok(embeddings) => return Ok(embeddings),
+                Err(e) if is_rejected(&e) || retries >= TRANSIENT_RETRIES => return Err(e),
                 Err(e) => {
-                    debug!("Embedding indexer: failed to embed message {}: {}", id, e);
+                    retries += 1;
+                    debug!(
+                        "Embedding indexer: request for {} input(s) failed ({}); retry {}/{} in {:?}",
+                        texts.len(),
+                        e,
+                        retries,
+                        TRANSIENT_RETRIES,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
                 }
             }
         }
+    }
+}
 
-        Ok(indexed)
+/// Whether the embedding service refused the request itself (4xx), as opposed to a timeout or
+/// connection problem. Only rejections are attributed to the messages in the request.
+fn is_rejected(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<OpenAIError>(),
+        Some(OpenAIError::ApiError(_))
+    )
+}
+
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Cut `text` so that it fits in `max_tokens` on its own.
+fn truncate_to_tokens(text: &str, max_tokens: usize) -> String {
+    let max_chars = max_tokens.saturating_mul(CHARS_PER_TOKEN);
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => text[..idx].to_string(),
+        None => text.to_string(),
+    }
+}
+
+/// Group inputs into requests of at most `max_inputs` items and `max_tokens` estimated tokens.
+fn split_requests<T>(
+    inputs: &[(T, String)],
+    max_inputs: usize,
+    max_tokens: usize,
+) -> Vec<&[(T, String)]> {
+    let mut requests = Vec::new();
+    let mut start = 0;
+    let mut tokens = 0;
+    for (i, (_, text)) in inputs.iter().enumerate() {
+        let cost = estimate_tokens(text);
+        if i > start && (i - start >= max_inputs || tokens + cost > max_tokens) {
+            requests.push(&inputs[start..i]);
+            start = i;
+            tokens = 0;
+        }
+        tokens += cost;
+    }
+    if start < inputs.len() {
+        requests.push(&inputs[start..]);
+    }
+    requests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::tests::test_config;
+    use crate::llm::mock_server::{api_error, embeddings, MockLlmServer, MockResponse};
+
+    fn item(id: i64, chars: usize) -> (i64, String) {
+        (id, "x".repeat(chars))
+    }
+
+    #[test]
+    fn test_split_requests_respects_input_and_token_limits() {
+        let inputs = vec![
+            item(1, 40),
+            item(2, 40),
+            item(3, 40),
+            item(4, 400),
+            item(5, 4),
+        ];
+        let sizes: Vec<usize> = split_requests(&inputs, 2, 100)
+            .iter()
+            .map(|r| r.len())
+            .collect();
+        // 10 + 10 tokens (input limit), 10, then the 100-token input alone, then the rest.
+        assert_eq!(sizes, vec![2, 1, 1, 1]);
+
+        assert!(split_requests::<i64>(&[], 2, 100).is_empty());
+    }
+
+    #[test]
+    fn test_truncate_to_tokens_is_char_safe() {
+        assert_eq!(truncate_to_tokens("short", 10), "short");
+        assert_eq!(truncate_to_tokens("ééééééé", 1), "éééé");
+    }
+
+    #[tokio::test]
+    async fn test_rejected_batch_isolates_and_backs_off_failing_message() {
+        let config = test_config();
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+        for (i, content) in ["oldest message", "middle message", "newest message"]
+            .iter()
+            .enumerate()
+        {
+            db.save_message(
+                &i.to_string(),
+                "g1",
+                "c1",
+                "u1",
+                content,
+                1700000000 + i as i64,
+            )
+            .unwrap();
+        }
+
+        // Pending order is newest first: [newest, middle, oldest].
+        let server = MockLlmServer::start(vec![
+            MockResponse::Error(400, api_error("input rejected")),
+            // Left half [newest] is the bad one.
+            MockResponse::Error(400, api_error("input rejected")),
+            MockResponse::Json(embeddings(&[(0, vec![1.0, 0.0]), (1, vec![0.0, 1.0])])),
+        ])
+        .await;
+        let policy = EmbeddingIndexerPolicy {
+            batch_size: 10,
+            interval: Duration::from_secs(60),
+            max_inputs_per_request: 10,
+            max_tokens_per_request: 1000,
+            max_attempts: 2,
+            retry_base_delay: Duration::from_millis(1),
+        };
+        let indexer = EmbeddingIndexer::with_policy(
+            db.clone(),
+            Arc::new(LlmClient::for_mock_server(&server)),
+            policy,
+        );
+
+        assert_eq!(indexer.process_batch().await.unwrap(), 2);
+        let requests = server.requests();
+        let sizes: Vec<usize> = requests
+            .iter()
+            .map(|r| r["input"].as_array().unwrap().len())
+            .collect();
+        assert_eq!(sizes, vec![3, 1, 2]);
+
+        // The failing message backs off instead of being retried next cycle.
+        assert!(db.get_messages_missing_embeddings(10).unwrap().is_empty());
+        let status = db.get_embedding_status().unwrap();
+        assert_eq!((status.current, status.pending, status.failed), (2, 1, 0));
     }
 }
@@ -8,6 +8,7 @@ pub mod indexer;
 pub mod llm;
 pub mod mcp;
 pub mod mention;
+pub mod message_events;
 pub mod rag;
 pub mod reminders;
 pub mod reply;
@@ -22,6 +23,8 @@ pub struct Data {
     pub config: config::Config,
     pub http_client: reqwest::Client,
     pub llm_client: llm::LlmClient,
+    /// Token counting for prompt budgets (`TOKENIZER`)
+    pub tokenizer: std::sync::Arc<dyn llm::tokenizer::Tokenizer>,
     pub db: db::Database,
     pub cache: cache::MessageCache,
     pub tools: std::sync::Arc<tools::ToolRegistry>,
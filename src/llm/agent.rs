@@ -1,18 +1,68 @@
+use crate::db::{AgentRunRecord, AgentStepRecord, AgentToolCallRecord, Database};
 use crate::llm::client::LlmClient;
-use crate::llm::confirm::{confirm_tool_execution, ToolConfirmationContext};
-use crate::tools::{Tool, ToolRegistry};
+use crate::llm::confirm::{confirm_tool_calls, ToolConfirmationContext};
+use crate::llm::vision;
+use crate::tools::policy::ToolPolicies;
+use crate::tools::{Tool, ToolContext, ToolRegistry};
 use crate::Data;
 use async_openai::types::{
     ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs,
-    ChatCompletionRequestMessage, ChatCompletionRequestToolMessageArgs,
+    ChatCompletionRequestMessage, ChatCompletionRequestToolMessageArgs, ChatCompletionToolType,
+    FunctionCall,
 };
+use poise::serenity_prelude::MessageId;
 use serde_json::Value;
 use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{watch, Semaphore};
 
 pub struct Agent {
     llm: Arc<LlmClient>,
     tools: Arc<ToolRegistry>,
     mcp_manager: Arc<crate::mcp::client::McpClientManager>,
+    tool_concurrency: usize,
+    tool_timeout: std::time::Duration,
+}
+
+/// The outcome of one agent run and the steps that led to it.
+pub struct AgentRunOutput {
+    pub result: anyhow::Result<String>,
+    pub steps: Vec<AgentStepRecord>,
+}
+
+impl AgentRunOutput {
+    /// Store the run with its outcome, keyed by the ids of the messages that show the answer,
+    /// so replies to any of them can see the tool calls. Failures are only logged.
+    pub async fn save(
+        self,
+        db: &Database,
+        tool_ctx: &ToolContext,
+        trigger_message_id: Option<MessageId>,
+        reply_ids: &[MessageId],
+    ) {
+        let Some(channel_id) = tool_ctx.channel_id else {
+            return;
+        };
+        let run = AgentRunRecord {
+            guild_id: tool_ctx.guild_id.map(|id| id.to_string()),
+            channel_id: channel_id.to_string(),
+            user_id: tool_ctx
+                .user_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            trigger_message_id: trigger_message_id.map(|id| id.to_string()),
+            final_answer: self.result.as_ref().ok().cloned(),
+            error: self.result.as_ref().err().map(|e| e.to_string()),
+            steps: self.steps,
+        };
+        let reply_ids: Vec<String> = reply_ids.iter().map(|id| id.to_string()).collect();
+        if let Err(e) = db
+            .run_blocking(move |db| db.save_agent_run(&run, &reply_ids))
+            .await
+        {
+            tracing::warn!("Failed to save agent run: {}", e);
+        }
+    }
 }
 
 impl Agent {
@@ -21,41 +71,110 @@ impl Agent {
             llm: Arc::new(crate::llm::LlmClient::new(&data.config)),
             tools: data.tools.clone(),
             mcp_manager: data.mcp_manager.clone(),
+            tool_concurrency: data.config.agent_tool_concurrency,
+            tool_timeout: std::time::Duration::from_secs(data.config.agent_tool_timeout_secs),
         }
     }
 
     pub async fn run(
         &self,
+        tool_ctx: &ToolContext,
         messages: Vec<ChatCompletionRequestMessage>,
         max_iterations: usize,
-    ) -> anyhow::Result<String> {
-        self.run_inner(None, messages, max_iterations).await
+    ) -> AgentRunOutput {
+        self.run_inner(None, tool_ctx, messages, max_iterations, None)
+            .await
     }
 
     pub async fn run_with_confirmation<'a>(
         &self,
         confirmation: ToolConfirmationContext<'a>,
+        tool_ctx: &ToolContext,
         messages: Vec<ChatCompletionRequestMessage>,
         max_iterations: usize,
-    ) -> anyhow::Result<String> {
-        self.run_inner(Some(&confirmation), messages, max_iterations)
-            .await
+    ) -> AgentRunOutput {
+        self.run_inner(
+            Some(&confirmation),
+            tool_ctx,
+            messages,
+            max_iterations,
+            None,
+        )
+        .await
+    }
+
+    /// Like `run_with_confirmation`, but streams the LLM output.
+    ///
+    /// `progress` holds the text generated so far in the current iteration. It is cleared at the
+    /// start of every LLM turn, so text emitted before a tool call is replaced by the next answer.
+    pub async fn run_with_confirmation_streaming<'a>(
+        &self,
+        confirmation: ToolConfirmationContext<'a>,
+        tool_ctx: &ToolContext,
+        messages: Vec<ChatCompletionRequestMessage>,
+        max_iterations: usize,
+        progress: &watch::Sender<String>,
+    ) -> AgentRunOutput {
+        self.run_inner(
+            Some(&confirmation),
+            tool_ctx,
+            messages,
+            max_iterations,
+            Some(progress),
+        )
+        .await
     }
 
     async fn run_inner<'a>(
         &self,
         confirmation: Option<&ToolConfirmationContext<'a>>,
+        tool_ctx: &ToolContext,
+        messages: Vec<ChatCompletionRequestMessage>,
+        max_iterations: usize,
+        progress: Option<&watch::Sender<String>>,
+    ) -> AgentRunOutput {
+        let mut steps = Vec::new();
+        let result = self
+            .run_steps(
+                confirmation,
+                tool_ctx,
+                messages,
+                max_iterations,
+                progress,
+                &mut steps,
+            )
+            .await;
+        AgentRunOutput { result, steps }
+    }
+
+    /// The agent loop. Each finished iteration is pushed to `steps`, also when the run fails.
+    async fn run_steps<'a>(
+        &self,
+        confirmation: Option<&ToolConfirmationContext<'a>>,
+        tool_ctx: &ToolContext,
         mut messages: Vec<ChatCompletionRequestMessage>,
         max_iterations: usize,
+        progress: Option<&watch::Sender<String>>,
+        steps: &mut Vec<AgentStepRecord>,
     ) -> anyhow::Result<String> {
+        let policies = match ToolPolicies::load(tool_ctx).await {
+            Ok(policies) => policies,
+            Err(e) => {
+                tracing::warn!("Failed to load tool policies: {:#}", e);
+                ToolPolicies::default()
+            }
+        };
+        let tool_ctx = &tool_ctx.clone().with_policies(policies);
         for i in 0..max_iterations {
             tracing::info!("Agent iteration {}/{}", i + 1, max_iterations);
             // Get all available tools (built-in + MCP)
-            let mut all_tools = self.tools.list_tools();
+            let mut all_tools = self.tools.list_tools_for(tool_ctx);
             let builtin_count = all_tools.len();
-            let mcp_tools = self.mcp_manager.list_all_tools().await;
+            let mut mcp_tools = self.mcp_manager.list_all_tools().await;
+            mcp_tools.retain(|t| t.is_available(tool_ctx));
             let mcp_count = mcp_tools.len();
             all_tools.extend(mcp_tools);
+            let all_tools = tool_ctx.policies.apply(all_tools);
             tracing::debug!(
                 "Agent tools available: builtin={}, mcp={}, total={}",
                 builtin_count,
@@ -78,54 +197,79 @@ impl Agent {
                 })
                 .collect();
 
-            let response = self
-                .llm
-                .chat_with_tools(messages.clone(), Some(tool_definitions))
-                .await?;
-            let choice = response
-                .choices
-                .first()
-                .ok_or_else(|| anyhow::anyhow!("No response from LLM"))?;
-
-            let assistant_message = &choice.message;
+            let mut step = AgentStepRecord {
+                request_summary: format!(
+                    "{} messages, {} tools",
+                    messages.len(),
+                    tool_definitions.len()
+                ),
+                content: None,
+                tool_calls: Vec::new(),
+                latency_ms: 0,
+            };
+            let started = Instant::now();
+            let (content, tool_calls) = loop {
+                match self
+                    .complete(messages.clone(), tool_definitions.clone(), progress)
+                    .await
+                {
+                    Ok(response) => break response,
+                    // Models without image support reject image content; continue as text.
+                    Err(e) if vision::has_images(&messages) && vision::is_rejection(&e) => {
+                        tracing::warn!(
+                            "Chat model rejected the request with images, retrying without them: {}",
+                            e
+                        );
+                        messages = vision::strip_images(messages);
+                    }
+                    Err(e) => return Err(e),
+                }
+            };
+            step.latency_ms = started.elapsed().as_millis() as u64;
+            step.content = content.clone();
 
             // Convert assistant response to request message for history
-            let request_assistant_message = if let Some(tool_calls) = &assistant_message.tool_calls
-            {
+            let request_assistant_message = if let Some(tool_calls) = &tool_calls {
                 ChatCompletionRequestAssistantMessageArgs::default()
                     .tool_calls(tool_calls.clone())
                     .build()?
             } else {
                 ChatCompletionRequestAssistantMessageArgs::default()
-                    .content(assistant_message.content.clone().unwrap_or_default())
+                    .content(content.clone().unwrap_or_default())
                     .build()?
             };
 
             messages.push(request_assistant_message.into());
 
-            if let Some(tool_calls) = &assistant_message.tool_calls {
+            if let Some(tool_calls) = &tool_calls {
                 tracing::info!("LLM requested {} tool calls", tool_calls.len());
-                for tool_call in tool_calls {
-                    let result = self
-                        .execute_tool_call(tool_call, &all_tools, confirmation)
-                        .await?;
-
+                let results = self
+                    .execute_tool_calls(tool_calls, &all_tools, confirmation, tool_ctx)
+                    .await;
+                for (tool_call, (result, latency_ms)) in tool_calls.iter().zip(results) {
+                    let result = result.to_string();
+                    step.tool_calls.push(AgentToolCallRecord {
+                        id: tool_call.id.clone(),
+                        name: tool_call.function.name.clone(),
+                        arguments: tool_call.function.arguments.clone(),
+                        result: result.clone(),
+                        latency_ms,
+                    });
                     messages.push(
                         ChatCompletionRequestToolMessageArgs::default()
                             .tool_call_id(tool_call.id.clone())
-                            .content(result.to_string())
+                            .content(result)
                             .build()?
                             .into(),
                     );
                 }
+                steps.push(step);
                 // Continue the loop to let the LLM see the results
             } else {
+                steps.push(step);
                 // No more tool calls, return final content
                 tracing::info!("Agent task completed after {} iterations", i + 1);
-                return Ok(assistant_message
-                    .content
-                    .clone()
-                    .unwrap_or_else(|| "...".to_string()));
+                return Ok(content.unwrap_or_else(|| "...".to_string()));
             }
         }
 
@@ -136,48 +280,556 @@ impl Agent {
         Err(anyhow::anyhow!("I've reached my reasoning limit for this task ({} steps). To improve results, try breaking your request into smaller, more specific steps.", max_iterations))
     }
 
-    async fn execute_tool_call(
+    /// One LLM turn: the answer text and the requested tool calls, if any.
+    async fn complete(
+        &self,
+        messages: Vec<ChatCompletionRequestMessage>,
+        tool_definitions: Vec<Value>,
+        progress: Option<&watch::Sender<String>>,
+    ) -> anyhow::Result<(Option<String>, Option<Vec<ChatCompletionMessageToolCall>>)> {
+        match progress {
+            Some(progress) => {
+                progress.send_replace(String::new());
+                let response = self
+                    .llm
+                    .chat_with_tools_stream(messages, Some(tool_definitions), |delta| {
+                        progress.send_modify(|text| text.push_str(delta))
+                    })
+                    .await?;
+                let tool_calls = (!response.tool_calls.is_empty()).then_some(response.tool_calls);
+                let content = (!response.content.is_empty()).then_some(response.content);
+                Ok((content, tool_calls))
+            }
+            None => {
+                let response = self
+                    .llm
+                    .chat_with_tools(messages, Some(tool_definitions))
+                    .await?;
+                let choice = response
+                    .choices
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("No response from LLM"))?;
+                Ok((choice.message.content, choice.message.tool_calls))
+            }
+        }
+    }
+
+    /// Run the tool calls of one LLM turn. Calls that need confirmation are approved with a
+    /// single prompt, then all approved calls run concurrently (up to `AGENT_TOOL_CONCURRENCY`).
+    /// Returns each call's result, or a [`ToolError`] for the model, with its latency, in the
+    /// order of `tool_calls`.
+    async fn execute_tool_calls(
         &self,
-        tool_call: &ChatCompletionMessageToolCall,
+        tool_calls: &[ChatCompletionMessageToolCall],
         available_tools: &[Arc<dyn Tool>],
         confirmation: Option<&ToolConfirmationContext<'_>>,
-    ) -> anyhow::Result<Value> {
-        let name = &tool_call.function.name;
-        let arguments: Value = serde_json::from_str(&tool_call.function.arguments)?;
-
-        tracing::info!(
-            "Agent executing tool: {} with arguments: {}",
-            name,
-            arguments
-        );
+        tool_ctx: &ToolContext,
+    ) -> Vec<(Value, u64)> {
+        let mut results: Vec<(Value, u64)> = vec![(Value::Null, 0); tool_calls.len()];
+        let mut ready = Vec::new();
+        for (i, tool_call) in tool_calls.iter().enumerate() {
+            match resolve_tool_call(tool_call, available_tools) {
+                Ok((tool, arguments)) => ready.push((i, tool, arguments)),
+                Err(e) => {
+                    tracing::warn!("Rejected tool call {}: {}", tool_call.function.name, e);
+                    results[i].0 = e.to_value(&tool_call.function.name);
+                }
+            }
+        }
 
-        let tool = available_tools
-            .iter()
-            .find(|t| t.name() == name)
-            .ok_or_else(|| {
-                tracing::error!("Tool not found: {}", name);
-                anyhow::anyhow!("Tool not found: {}", name)
-            })?;
-
-        if tool.requires_confirmation() {
-            let Some(confirm_ctx) = confirmation else {
-                return Err(anyhow::anyhow!(
-                    "Tool '{}' requires confirmation, but this conversation does not support interactive confirmation.",
-                    name
-                ));
+        let (needs_confirmation, mut approved): (Vec<_>, Vec<_>) = ready
+            .into_iter()
+            .partition(|(_, tool, _)| tool.requires_confirmation());
+        if !needs_confirmation.is_empty() {
+            let decision = match confirmation {
+                None => Err(ToolError::new(
+                    ToolErrorCode::ConfirmationRequired,
+                    "This tool requires confirmation, but this conversation does not support interactive confirmation.",
+                )),
+                Some(confirm_ctx) => {
+                    let calls: Vec<(&str, &Value)> = needs_confirmation
+                        .iter()
+                        .map(|(_, tool, arguments)| (tool.name(), arguments))
+                        .collect();
+                    match confirm_tool_calls(confirm_ctx, &calls).await {
+                        Ok(true) => Ok(()),
+                        Ok(false) => Err(ToolError::new(
+                            ToolErrorCode::Cancelled,
+                            "The user cancelled this tool call or did not confirm it in time.",
+                        )),
+                        Err(e) => Err(ToolError::new(
+                            ToolErrorCode::Cancelled,
+                            format!("Could not ask the user for confirmation: {}", e),
+                        )),
+                    }
+                }
             };
+            match decision {
+                Ok(()) => approved.extend(needs_confirmation),
+                Err(e) => {
+                    for (i, tool, _) in needs_confirmation {
+                        results[i].0 = e.to_value(tool.name());
+                    }
+                }
+            }
+        }
+
+        let limit = Semaphore::new(self.tool_concurrency.max(1));
+        let runs: Vec<_> = approved
+            .into_iter()
+            .map(|(i, tool, arguments)| {
+                let limit = &limit;
+                async move {
+                    let _permit = limit.acquire().await;
+                    let name = tool.name();
+                    tracing::info!(
+                        "Agent executing tool: {} with arguments: {}",
+                        name,
+                        arguments
+                    );
+                    let started = Instant::now();
+                    let result = match tokio::time::timeout(
+                        self.tool_timeout,
+                        tool.execute(tool_ctx, arguments),
+                    )
+                    .await
+                    {
+                        Ok(Ok(value)) => Ok(value),
+                        Ok(Err(e)) if is_timeout(&e) => {
+                            Err(ToolError::new(ToolErrorCode::Timeout, e.to_string()))
+                        }
+                        Ok(Err(e)) => Err(ToolError::new(
+                            ToolErrorCode::ExecutionFailed,
+                            e.to_string(),
+                        )),
+                        Err(_) => Err(ToolError::new(
+                            ToolErrorCode::Timeout,
+                            format!(
+                                "Tool did not finish within {}s",
+                                self.tool_timeout.as_secs_f32()
+                            ),
+                        )),
+                    };
+                    let result = match result {
+                        Ok(value) => {
+                            tracing::debug!("Tool {} returned: {}", name, value);
+                            value
+                        }
+                        Err(e) => {
+                            tracing::error!("Tool {} failed: {}", name, e);
+                            e.to_value(name)
+                        }
+                    };
+                    (i, result, started.elapsed().as_millis() as u64)
+                }
+            })
+            .collect();
+        for (i, result, latency_ms) in futures::future::join_all(runs).await {
+            results[i] = (result, latency_ms);
+        }
+        results
+    }
+}
+
+/// Why a tool call produced no result. Sent to the model as the tool message so it can retry
+/// with fixed arguments or explain the problem, instead of ending the run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolErrorCode {
+    /// No available tool has the requested name.
+    NotFound,
+    /// The arguments are not valid JSON or do not match the tool's `parameters_schema`.
+    InvalidArguments,
+    /// The user declined the confirmation prompt or let it time out.
+    Cancelled,
+    /// The tool needs confirmation, which this conversation cannot ask for.
+    ConfirmationRequired,
+    /// The tool did not finish within `AGENT_TOOL_TIMEOUT_SECS` (or its own timeout).
+    Timeout,
+    /// The tool ran and returned an error.
+    ExecutionFailed,
+}
+
+impl ToolErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::NotFound => "not_found",
+            Self::InvalidArguments => "invalid_arguments",
+            Self::Cancelled => "cancelled",
+            Self::ConfirmationRequired => "confirmation_required",
+            Self::Timeout => "timeout",
+            Self::ExecutionFailed => "execution_failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolError {
+    pub code: ToolErrorCode,
+    pub message: String,
+}
+
+impl ToolError {
+    pub fn new(code: ToolErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// The tool result returned to the model in place of the output of `tool`.
+    pub fn to_value(&self, tool: &str) -> Value {
+        serde_json::json!({
+            "error": { "code": self.code.as_str(), "tool": tool, "message": self.message }
+        })
+    }
+}
+
+impl std::fmt::Display for ToolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code.as_str(), self.message)
+    }
+}
+
+/// Look up the tool a call refers to, parse its arguments and check them against the tool's
+/// `parameters_schema`. Empty arguments are read as `{}`.
+fn resolve_tool_call(
+    tool_call: &ChatCompletionMessageToolCall,
+    available_tools: &[Arc<dyn Tool>],
+) -> Result<(Arc<dyn Tool>, Value), ToolError> {
+    let name = &tool_call.function.name;
+    let tool = available_tools
+        .iter()
+        .find(|t| t.name() == name)
+        .ok_or_else(|| {
+            ToolError::new(ToolErrorCode::NotFound, format!("Tool not found: {}", name))
+        })?;
+
+    let raw = tool_call.function.arguments.trim();
+    let arguments: Value = if raw.is_empty() {
+        serde_json::json!({})
+    } else {
+        serde_json::from_str(raw).map_err(|e| {
+            ToolError::new(
+                ToolErrorCode::InvalidArguments,
+                format!("Arguments are not valid JSON: {}", e),
+            )
+        })?
+    };
+    validate_arguments(&tool.parameters_schema(), &arguments)?;
+    Ok((tool.clone(), arguments))
+}
+
+/// Check `arguments` against a JSON Schema. A schema that does not compile is not enforced.
+fn validate_arguments(schema: &Value, arguments: &Value) -> Result<(), ToolError> {
+    let validator = match jsonschema::validator_for(schema) {
+        Ok(validator) => validator,
+        Err(e) => {
+            tracing::debug!("Skipping argument validation, invalid tool schema: {}", e);
+            return Ok(());
+        }
+    };
+    let problems: Vec<String> = validator
+        .iter_errors(arguments)
+        .take(5)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            if path.is_empty() {
+                e.to_string()
+            } else {
+                format!("{}: {}", path, e)
+            }
+        })
+        .collect();
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ToolError::new(
+            ToolErrorCode::InvalidArguments,
+            format!(
+                "Arguments do not match the tool's parameters: {}",
+                problems.join("; ")
+            ),
+        ))
+    }
+}
+
+/// Whether a tool error is a timeout (e.g. an MCP call exceeding `MCP_TIMEOUT_SECS`).
+fn is_timeout(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .any(|cause| cause.is::<tokio::time::error::Elapsed>())
+}
+
+/// The tool calls of a stored run as assistant and tool messages, so a follow-up sees what
+/// was looked up. Results longer than `max_result_chars` are cut, and only the most recent
+/// steps whose arguments and results fit in `max_total_chars` are kept.
+pub fn transcript_messages(
+    steps: &[AgentStepRecord],
+    max_result_chars: usize,
+    max_total_chars: usize,
+) -> anyhow::Result<Vec<ChatCompletionRequestMessage>> {
+    let step_chars = |step: &AgentStepRecord| -> usize {
+        step.tool_calls
+            .iter()
+            .map(|c| c.arguments.chars().count() + c.result.chars().count().min(max_result_chars))
+            .sum()
+    };
+    let mut kept = Vec::new();
+    let mut total = 0;
+    for step in steps.iter().rev().filter(|s| !s.tool_calls.is_empty()) {
+        total += step_chars(step);
+        if total > max_total_chars {
+            break;
+        }
+        kept.push(step);
+    }
+    kept.reverse();
+
+    let mut messages = Vec::new();
+    for step in kept {
+        let tool_calls = step
+            .tool_calls
+            .iter()
+            .map(|call| ChatCompletionMessageToolCall {
+                id: call.id.clone(),
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionCall {
+                    name: call.name.clone(),
+                    arguments: call.arguments.clone(),
+                },
+            })
+            .collect::<Vec<_>>();
+        messages.push(
+            ChatCompletionRequestAssistantMessageArgs::default()
+                .tool_calls(tool_calls)
+                .build()?
+                .into(),
+        );
+        for call in &step.tool_calls {
+            let mut result: String = call.result.chars().take(max_result_chars).collect();
+            if result.len() < call.result.len() {
+                result.push_str(" [truncated]");
+            }
+            messages.push(
+                ChatCompletionRequestToolMessageArgs::default()
+                    .tool_call_id(call.id.clone())
+                    .content(result)
+                    .build()?
+                    .into(),
+            );
+        }
+    }
+    Ok(messages)
+}
 
-            let confirmed = confirm_tool_execution(confirm_ctx, name, &arguments).await?;
-            if !confirmed {
-                return Err(anyhow::anyhow!("Tool execution cancelled."));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server::{completion, tool_calls_completion, MockLlmServer, MockResponse};
+    use crate::tools::ToolRegistry;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Sleeps briefly and records how many calls were running at the same time.
+    struct SlowTool {
+        name: &'static str,
+        running: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Tool for SlowTool {
+        fn name(&self) -> &str {
+            self.name
+        }
+        fn description(&self) -> &str {
+            "test tool"
+        }
+        fn parameters_schema(&self) -> Value {
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "fail": { "type": "boolean" },
+                    "sleep_ms": { "type": "integer" }
+                },
+                "additionalProperties": false
+            })
+        }
+        async fn execute(&self, _ctx: &ToolContext, params: Value) -> anyhow::Result<Value> {
+            let now = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            let sleep_ms = params["sleep_ms"].as_u64().unwrap_or(50);
+            tokio::time::sleep(std::time::Duration::from_millis(sleep_ms)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            if params["fail"] == true {
+                anyhow::bail!("{} broke", self.name);
             }
+            Ok(serde_json::json!({ "ok": self.name }))
         }
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_run_concurrently_and_errors_reach_the_model_with_codes() {
+        let server = MockLlmServer::start(vec![
+            MockResponse::Json(tool_calls_completion(&[
+                ("call_1", "tool_a", "{}"),
+                ("call_2", "tool_b", r#"{"fail":true}"#),
+                ("call_3", "tool_c", "{}"),
+                ("call_4", "missing_tool", "{}"),
+                ("call_5", "tool_a", "not json"),
+                ("call_6", "tool_c", r#"{"fail":"yes"}"#),
+                ("call_7", "tool_b", r#"{"sleep_ms":5000}"#),
+                ("call_8", "tool_c", ""),
+            ])),
+            MockResponse::Json(completion("All done.")),
+        ])
+        .await;
 
-        let result = tool.execute(arguments).await;
-        match &result {
-            Ok(v) => tracing::debug!("Tool {} returned: {}", name, v),
-            Err(e) => tracing::error!("Tool {} failed: {}", name, e),
+        let config = crate::context::tests::mock_config();
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let mut registry = ToolRegistry::new();
+        for name in ["tool_a", "tool_b", "tool_c"] {
+            registry.register(Arc::new(SlowTool {
+                name,
+                running: running.clone(),
+                peak: peak.clone(),
+            }));
         }
-        result
+        let llm = LlmClient::for_mock_server(&server);
+        let agent = Agent {
+            llm: Arc::new(llm.clone()),
+            tools: Arc::new(registry),
+            mcp_manager: Arc::new(crate::mcp::client::McpClientManager::new(&config).unwrap()),
+            tool_concurrency: 2,
+            tool_timeout: std::time::Duration::from_millis(300),
+        };
+        let tool_ctx = ToolContext::for_tests(config, agent.mcp_manager.clone());
+
+        let output = agent
+            .run(
+                &tool_ctx,
+                vec![
+                    async_openai::types::ChatCompletionRequestUserMessageArgs::default()
+                        .content("check everything")
+                        .build()
+                        .unwrap()
+                        .into(),
+                ],
+                5,
+            )
+            .await;
+        assert_eq!(output.result.unwrap(), "All done.");
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+        // Results come back in call order; failures are coded errors, not aborted runs.
+        let messages = server.requests()[1]["messages"].clone();
+        let results: Vec<Value> = messages.as_array().unwrap()[2..]
+            .iter()
+            .map(|m| serde_json::from_str(m["content"].as_str().unwrap()).unwrap())
+            .collect();
+        assert_eq!(messages[2]["tool_call_id"], "call_1");
+        assert_eq!(results[0], serde_json::json!({ "ok": "tool_a" }));
+        assert_eq!(
+            results[1],
+            serde_json::json!({
+                "error": { "code": "execution_failed", "tool": "tool_b", "message": "tool_b broke" }
+            })
+        );
+        assert_eq!(results[2], serde_json::json!({ "ok": "tool_c" }));
+        let codes: Vec<&str> = results[3..]
+            .iter()
+            .map(|r| r["error"]["code"].as_str().unwrap_or("none"))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                "not_found",
+                "invalid_arguments",
+                "invalid_arguments",
+                "timeout",
+                "none"
+            ]
+        );
+        assert!(results[5]["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("/fail"));
+        assert_eq!(results[7], serde_json::json!({ "ok": "tool_c" }));
+
+        let steps = output.steps;
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].tool_calls.len(), 8);
+        assert_eq!(steps[1].content.as_deref(), Some("All done."));
+    }
+
+    fn call(id: &str, result: &str) -> AgentToolCallRecord {
+        AgentToolCallRecord {
+            id: id.to_string(),
+            name: "search_messages".to_string(),
+            arguments: r#"{"query":"release"}"#.to_string(),
+            result: result.to_string(),
+            latency_ms: 5,
+        }
+    }
+
+    #[test]
+    fn test_transcript_messages_pairs_calls_with_results() {
+        let steps = vec![
+            AgentStepRecord {
+                request_summary: "4 messages, 3 tools".to_string(),
+                content: None,
+                tool_calls: vec![call("call_1", "short"), call("call_2", &"x".repeat(50))],
+                latency_ms: 100,
+            },
+            AgentStepRecord {
+                request_summary: "7 messages, 3 tools".to_string(),
+                content: Some("Done.".to_string()),
+                tool_calls: Vec::new(),
+                latency_ms: 80,
+            },
+        ];
+
+        let messages = transcript_messages(&steps, 10, 1000).unwrap();
+        let json = serde_json::to_value(&messages).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 3);
+        assert_eq!(json[0]["role"], "assistant");
+        assert_eq!(json[0]["tool_calls"][1]["id"], "call_2");
+        assert_eq!(
+            json[0]["tool_calls"][0]["function"]["arguments"],
+            r#"{"query":"release"}"#
+        );
+        assert_eq!(json[1]["role"], "tool");
+        assert_eq!(json[1]["tool_call_id"], "call_1");
+        assert_eq!(json[1]["content"], "short");
+        assert_eq!(
+            json[2]["content"],
+            format!("{} [truncated]", "x".repeat(10))
+        );
+    }
+
+    #[test]
+    fn test_transcript_messages_keep_recent_steps_within_total_budget() {
+        let steps: Vec<AgentStepRecord> = (1..=10)
+            .map(|i| AgentStepRecord {
+                request_summary: String::new(),
+                content: None,
+                tool_calls: vec![call(&format!("call_{}", i), &"x".repeat(100))],
+                latency_ms: 10,
+            })
+            .collect();
+
+        // Each step is 19 characters of arguments plus 50 of (cut) result.
+        let messages = transcript_messages(&steps, 50, 3 * 69).unwrap();
+        let json = serde_json::to_value(&messages).unwrap();
+        let ids: Vec<&str> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|m| m["tool_call_id"].as_str())
+            .collect();
+        assert_eq!(ids, vec!["call_8", "call_9", "call_10"]);
+        assert!(transcript_messages(&steps, 50, 10).unwrap().is_empty());
     }
 }
@@ -2,15 +2,81 @@ use crate::config::Config;
 use async_openai::{
     config::OpenAIConfig,
     types::{
+        ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk,
         ChatCompletionRequestMessage, ChatCompletionTool, ChatCompletionToolType,
-        CreateChatCompletionRequestArgs, FunctionObject,
+        CreateChatCompletionRequest, CreateChatCompletionRequestArgs, FinishReason, FunctionCall,
+        FunctionObject,
     },
     Client,
 };
+use futures::StreamExt;
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::time::Instant;
 use tracing::{debug, error, info};
 
+/// A chat completion reassembled from streamed SSE deltas.
+#[derive(Debug, Default)]
+pub struct StreamedChatResponse {
+    pub content: String,
+    pub tool_calls: Vec<ChatCompletionMessageToolCall>,
+    pub finish_reason: Option<FinishReason>,
+}
+
+#[derive(Default)]
+struct PartialToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Collects streamed tool-call fragments (keyed by their `index`) into complete calls.
+#[derive(Default)]
+struct ToolCallAccumulator {
+    calls: BTreeMap<u32, PartialToolCall>,
+}
+
+impl ToolCallAccumulator {
+    fn push(&mut self, chunk: &ChatCompletionMessageToolCallChunk) {
+        let entry = self.calls.entry(chunk.index).or_default();
+        if let Some(id) = chunk.id.as_deref().filter(|id| !id.is_empty()) {
+            entry.id = id.to_string();
+        }
+        if let Some(function) = &chunk.function {
+            if let Some(name) = &function.name {
+                entry.name.push_str(name);
+            }
+            if let Some(arguments) = &function.arguments {
+                entry.arguments.push_str(arguments);
+            }
+        }
+    }
+
+    fn finish(self) -> Vec<ChatCompletionMessageToolCall> {
+        self.calls
+            .into_iter()
+            .filter(|(_, call)| !call.name.is_empty())
+            .map(|(index, call)| ChatCompletionMessageToolCall {
+                // Some servers omit ids on streamed calls; the agent still needs one to pair results.
+                id: if call.id.is_empty() {
+                    format!("call_{}", index)
+                } else {
+                    call.id
+                },
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionCall {
+                    name: call.name,
+                    arguments: if call.arguments.trim().is_empty() {
+                        "{}".to_string()
+                    } else {
+                        call.arguments
+                    },
+                },
+            })
+            .collect()
+    }
+}
+
 #[derive(Clone)]
 pub struct LlmClient {
     chat_client: Client<OpenAIConfig>,
@@ -49,14 +115,11 @@ impl LlmClient {
         }
     }
 
-    pub async fn chat_with_tools(
+    fn build_chat_request(
         &self,
         messages: Vec<ChatCompletionRequestMessage>,
         tools: Option<Vec<Value>>,
-    ) -> anyhow::Result<async_openai::types::CreateChatCompletionResponse> {
-        use tokio::time::{timeout, Duration};
-        let llm_timeout = Duration::from_secs(self.chat_timeout);
-
+    ) -> anyhow::Result<CreateChatCompletionRequest> {
         let mut request_builder = CreateChatCompletionRequestArgs::default();
         request_builder.model(&self.chat_model).messages(messages);
 
@@ -78,7 +141,18 @@ impl LlmClient {
             }
         }
 
-        let request = request_builder.build()?;
+        Ok(request_builder.build()?)
+    }
+
+    pub async fn chat_with_tools(
+        &self,
+        messages: Vec<ChatCompletionRequestMessage>,
+        tools: Option<Vec<Value>>,
+    ) -> anyhow::Result<async_openai::types::CreateChatCompletionResponse> {
+        use tokio::time::{timeout, Duration};
+        let llm_timeout = Duration::from_secs(self.chat_timeout);
+
+        let request = self.build_chat_request(messages, tools)?;
 
         debug!(
             "Sending chat request to {} (timeout: {}s)...",
@@ -101,6 +175,81 @@ impl LlmClient {
         Ok(response)
     }
 
+    /// Streaming variant of `chat_with_tools`.
+    ///
+    /// `on_content` is called with every content delta as it arrives. Tool-call fragments are
+    /// reassembled and returned once the stream ends. The timeout applies to the gap between
+    /// chunks rather than the whole response, so long answers are not cut off mid-stream.
+    pub async fn chat_with_tools_stream<F>(
+        &self,
+        messages: Vec<ChatCompletionRequestMessage>,
+        tools: Option<Vec<Value>>,
+        mut on_content: F,
+    ) -> anyhow::Result<StreamedChatResponse>
+    where
+        F: FnMut(&str) + Send,
+    {
+        use tokio::time::{timeout, Duration};
+        let llm_timeout = Duration::from_secs(self.chat_timeout);
+
+        let request = self.build_chat_request(messages, tools)?;
+
+        debug!(
+            "Sending streaming chat request to {} (idle timeout: {}s)...",
+            self.chat_model, self.chat_timeout
+        );
+        let start = Instant::now();
+        let mut stream = self.chat_client.chat().create_stream(request).await?;
+
+        let mut response = StreamedChatResponse::default();
+        let mut tool_calls = ToolCallAccumulator::default();
+        let mut first_chunk_at = None;
+
+        loop {
+            let next = timeout(llm_timeout, stream.next()).await.map_err(|_| {
+                error!(
+                    "LLM stream stalled for {}s without new data",
+                    llm_timeout.as_secs()
+                );
+                anyhow::anyhow!(
+                    "LLM stream stalled for {}s without new data",
+                    llm_timeout.as_secs()
+                )
+            })?;
+            let Some(chunk) = next else {
+                break;
+            };
+            let chunk = chunk?;
+            first_chunk_at.get_or_insert_with(|| start.elapsed());
+
+            for choice in chunk.choices.into_iter().filter(|c| c.index == 0) {
+                if let Some(content) = choice.delta.content.as_deref() {
+                    if !content.is_empty() {
+                        response.content.push_str(content);
+                        on_content(content);
+                    }
+                }
+                for tool_call in choice.delta.tool_calls.iter().flatten() {
+                    tool_calls.push(tool_call);
+                }
+                if choice.finish_reason.is_some() {
+                    response.finish_reason = choice.finish_reason;
+                }
+            }
+        }
+
+        response.tool_calls = tool_calls.finish();
+
+        info!(
+            "LLM streaming request to {} completed in {:?} (first chunk after {:?})",
+            self.chat_model,
+            start.elapsed(),
+            first_chunk_at.unwrap_or_default()
+        );
+
+        Ok(response)
+    }
+
     pub async fn chat(
         &self,
         messages: Vec<ChatCompletionRequestMessage>,
@@ -129,15 +278,31 @@ impl LlmClient {
     }
 
     pub async fn get_embeddings(&self, text: &str) -> anyhow::Result<Vec<f32>> {
+        self.get_embeddings_batch(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("No embedding returned"))
+    }
+
+    /// Embed several inputs with a single request. Results are returned in input order.
+    pub async fn get_embeddings_batch(&self, inputs: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
         use async_openai::types::CreateEmbeddingRequestArgs;
         use tokio::time::{timeout, Duration};
 
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+
         let request = CreateEmbeddingRequestArgs::default()
             .model(&self.embedding_model)
-            .input(text)
+            .input(inputs.to_vec())
             .build()?;
 
-        debug!("Sending embedding request to {}...", self.embedding_model);
+        debug!(
+            "Sending embedding request for {} input(s) to {}...",
+            inputs.len(),
+            self.embedding_model
+        );
         let start = Instant::now();
         let response = timeout(
             Duration::from_secs(self.embedding_timeout),
@@ -157,20 +322,179 @@ impl LlmClient {
 
         let duration = start.elapsed();
         info!(
-            "Embedding request to {} completed in {:?}",
-            self.embedding_model, duration
+            "Embedding request to {} ({} input(s)) completed in {:?}",
+            self.embedding_model,
+            inputs.len(),
+            duration
         );
 
-        let embedding = response
-            .data
-            .first()
-            .ok_or_else(|| {
-                error!("No embedding returned from API");
-                anyhow::anyhow!("No embedding returned")
-            })?
-            .embedding
-            .clone();
-
-        Ok(embedding)
+        if response.data.len() != inputs.len() {
+            error!(
+                "Embedding API returned {} embeddings for {} inputs",
+                response.data.len(),
+                inputs.len()
+            );
+            return Err(anyhow::anyhow!(
+                "Embedding API returned {} embeddings for {} inputs",
+                response.data.len(),
+                inputs.len()
+            ));
+        }
+
+        let mut data = response.data;
+        data.sort_by_key(|e| e.index);
+        Ok(data.into_iter().map(|e| e.embedding).collect())
+    }
+}
+
+#[cfg(test)]
+impl LlmClient {
+    /// Client pointed at a [`MockLlmServer`](crate::llm::mock_server::MockLlmServer) for both
+    /// chat and embeddings.
+    pub(crate) fn for_mock_server(server: &crate::llm::mock_server::MockLlmServer) -> Self {
+        let config = OpenAIConfig::new()
+            .with_api_base(server.base_url())
+            .with_api_key("unused");
+        LlmClient {
+            chat_client: Client::with_config(config.clone()),
+            embedding_client: Client::with_config(config),
+            chat_model: "mock-model".to_string(),
+            embedding_model: "mock-embedding".to_string(),
+            chat_timeout: 5,
+            embedding_timeout: 5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server::{
+        completion, content_chunk, embeddings, finish_chunk, tool_call_chunk, MockLlmServer,
+        MockResponse,
+    };
+    use async_openai::types::ChatCompletionRequestUserMessageArgs;
+    use std::sync::{Arc, Mutex};
+
+    fn client_for(server: &MockLlmServer) -> LlmClient {
+        LlmClient::for_mock_server(server)
+    }
+
+    fn user_message(text: &str) -> Vec<ChatCompletionRequestMessage> {
+        vec![ChatCompletionRequestUserMessageArgs::default()
+            .content(text)
+            .build()
+            .unwrap()
+            .into()]
+    }
+
+    #[tokio::test]
+    async fn test_stream_collects_content_deltas() {
+        let server = MockLlmServer::start(vec![MockResponse::Stream(vec![
+            content_chunk("Hel"),
+            content_chunk("lo, "),
+            content_chunk("world"),
+            finish_chunk("stop"),
+        ])])
+        .await;
+        let client = client_for(&server);
+
+        let deltas = Arc::new(Mutex::new(Vec::new()));
+        let sink = deltas.clone();
+        let response = client
+            .chat_with_tools_stream(user_message("hi"), None, move |delta| {
+                sink.lock().unwrap().push(delta.to_string())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(response.content, "Hello, world");
+        assert!(response.tool_calls.is_empty());
+        assert_eq!(response.finish_reason, Some(FinishReason::Stop));
+        assert_eq!(*deltas.lock().unwrap(), vec!["Hel", "lo, ", "world"]);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn test_stream_reassembles_tool_call_fragments() {
+        let server = MockLlmServer::start(vec![MockResponse::Stream(vec![
+            tool_call_chunk(0, Some("call_a"), Some("web_search"), ""),
+            tool_call_chunk(1, Some("call_b"), Some("get_user_memory"), "{\"user_id\":"),
+            tool_call_chunk(0, None, None, "{\"query\":"),
+            tool_call_chunk(0, None, None, "\"rust\"}"),
+            tool_call_chunk(1, None, None, "42}"),
+            tool_call_chunk(2, None, Some("list_reminders"), ""),
+            finish_chunk("tool_calls"),
+        ])])
+        .await;
+        let client = client_for(&server);
+
+        let response = client
+            .chat_with_tools_stream(user_message("hi"), None, |_| {})
+            .await
+            .unwrap();
+
+        assert!(response.content.is_empty());
+        assert_eq!(response.finish_reason, Some(FinishReason::ToolCalls));
+        assert_eq!(response.tool_calls.len(), 3);
+        assert_eq!(response.tool_calls[0].id, "call_a");
+        assert_eq!(response.tool_calls[0].function.name, "web_search");
+        assert_eq!(
+            response.tool_calls[0].function.arguments,
+            "{\"query\":\"rust\"}"
+        );
+        assert_eq!(response.tool_calls[1].id, "call_b");
+        assert_eq!(
+            response.tool_calls[1].function.arguments,
+            "{\"user_id\":42}"
+        );
+        assert_eq!(response.tool_calls[2].id, "call_2");
+        assert_eq!(response.tool_calls[2].function.arguments, "{}");
+    }
+
+    #[tokio::test]
+    async fn test_non_streaming_chat_still_works() {
+        let server =
+            MockLlmServer::start(vec![MockResponse::Json(completion("plain answer"))]).await;
+        let client = client_for(&server);
+
+        let answer = client.chat(user_message("hi")).await.unwrap();
+        assert_eq!(answer, "plain answer");
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_batch_sends_array_and_orders_by_index() {
+        let server = MockLlmServer::start(vec![MockResponse::Json(embeddings(&[
+            (1, vec![0.0, 1.0]),
+            (0, vec![1.0, 0.0]),
+        ]))])
+        .await;
+        let client = client_for(&server);
+
+        let vectors = client
+            .get_embeddings_batch(&["first".to_string(), "second".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["input"], serde_json::json!(["first", "second"]));
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_batch_rejects_count_mismatch() {
+        let server =
+            MockLlmServer::start(vec![MockResponse::Json(embeddings(&[(0, vec![1.0])]))]).await;
+        let client = client_for(&server);
+
+        let err = client
+            .get_embeddings_batch(&["a".to_string(), "b".to_string()])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("1 embeddings for 2 inputs"));
     }
 }
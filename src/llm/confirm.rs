@@ -26,28 +26,48 @@ impl<'a> ToolConfirmationContext<'a> {
     }
 }
 
-pub async fn confirm_tool_execution(
+/// Characters of pretty-printed arguments shown per prompt, shared by the listed calls.
+const CONFIRM_ARGS_CHARS: usize = 3000;
+
+/// Ask the requesting user to approve a batch of tool calls with a single prompt. Returns
+/// `false` when the user cancels or does not answer in time.
+pub async fn confirm_tool_calls(
     ctx: &ToolConfirmationContext<'_>,
-    tool_name: &str,
-    args: &Value,
+    calls: &[(&str, &Value)],
 ) -> anyhow::Result<bool> {
     use serenity::{
         ButtonStyle, CreateActionRow, CreateButton, CreateEmbed, CreateInteractionResponse,
         CreateInteractionResponseMessage, CreateMessage,
     };
 
-    let mut args_pretty = serde_json::to_string_pretty(args).unwrap_or_else(|_| args.to_string());
-    if args_pretty.len() > 1000 {
-        args_pretty.truncate(1000);
-        args_pretty.push('…');
+    let args_limit = (CONFIRM_ARGS_CHARS / calls.len().max(1)).max(200);
+    let mut description = format!("Requested by: <@{}>\n", ctx.user_id);
+    for (i, (tool_name, args)) in calls.iter().enumerate() {
+        let mut args_pretty =
+            serde_json::to_string_pretty(args).unwrap_or_else(|_| args.to_string());
+        if args_pretty.chars().count() > args_limit {
+            args_pretty = args_pretty.chars().take(args_limit).collect();
+            args_pretty.push('…');
+        }
+        let heading = if calls.len() == 1 {
+            format!("Requested tool: `{}`", tool_name)
+        } else {
+            format!("{}. `{}`", i + 1, tool_name)
+        };
+        description.push_str(&format!(
+            "\n{}\nArguments:\n```json\n{}\n```",
+            heading, args_pretty
+        ));
     }
 
+    let title = if calls.len() == 1 {
+        "Tool Confirmation Required".to_string()
+    } else {
+        format!("Confirmation Required for {} Tools", calls.len())
+    };
     let embed = CreateEmbed::new()
-        .title("Tool Confirmation Required")
-        .description(format!(
-            "Requested tool: `{}`\nRequested by: <@{}>\n\nArguments:\n```json\n{}\n```",
-            tool_name, ctx.user_id, args_pretty
-        ))
+        .title(title)
+        .description(description)
         .color(0xFEE75C);
 
     let confirm_btn = CreateButton::new("confirm_tool")
@@ -109,10 +129,10 @@ pub async fn confirm_tool_execution(
         };
 
         if let Some(confirmed) = decision {
-            let status = if confirmed {
-                "Confirmed. Executing tool…"
-            } else {
-                "Cancelled."
+            let status = match (confirmed, calls.len()) {
+                (true, 1) => "Confirmed. Executing tool…".to_string(),
+                (true, n) => format!("Confirmed. Executing {} tools…", n),
+                (false, _) => "Cancelled.".to_string(),
             };
 
             let _ = interaction
@@ -0,0 +1,243 @@
+//! Minimal OpenAI-compatible HTTP server for exercising the LLM client in tests.
+//!
+//! Each incoming request pops the next scripted response. Streaming responses are written as
+//! `text/event-stream` chunks followed by `data: [DONE]`, mirroring llama.cpp / OpenAI.
+
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+pub enum MockResponse {
+    /// A regular (non-streaming) JSON body.
+    Json(Value),
+    /// A sequence of SSE `data:` payloads.
+    Stream(Vec<Value>),
+    /// A non-2xx status with a JSON error body.
+    Error(u16, Value),
+}
+
+pub struct MockLlmServer {
+    base_url: String,
+    requests: Arc<Mutex<Vec<Value>>>,
+}
+
+impl MockLlmServer {
+    pub async fn start(responses: Vec<MockResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let queue = Arc::new(Mutex::new(VecDeque::from(responses)));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let requests_clone = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let queue = queue.clone();
+                let requests = requests_clone.clone();
+                tokio::spawn(async move {
+                    let _ = handle_connection(socket, queue, requests).await;
+                });
+            }
+        });
+
+        Self {
+            base_url: format!("http://{}/v1", addr),
+            requests,
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// JSON bodies of all requests received so far.
+    pub fn requests(&self) -> Vec<Value> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn handle_connection(
+    mut socket: TcpStream,
+    queue: Arc<Mutex<VecDeque<MockResponse>>>,
+    requests: Arc<Mutex<Vec<Value>>>,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let headers = String::from_utf8_lossy(&buf[..header_end]).to_lowercase();
+    let content_length = headers
+        .lines()
+        .find_map(|l| l.strip_prefix("content-length:"))
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while buf.len() < header_end + content_length {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let body = &buf[header_end..];
+    if let Ok(value) = serde_json::from_slice::<Value>(body) {
+        requests.lock().unwrap().push(value);
+    }
+
+    let next = queue.lock().unwrap().pop_front();
+    match next {
+        Some(MockResponse::Json(value)) => {
+            let body = value.to_string();
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            socket.write_all(head.as_bytes()).await?;
+            socket.write_all(body.as_bytes()).await?;
+        }
+        Some(MockResponse::Stream(events)) => {
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+                )
+                .await?;
+            for event in events {
+                socket
+                    .write_all(format!("data: {}\n\n", event).as_bytes())
+                    .await?;
+                socket.flush().await?;
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+            socket.write_all(b"data: [DONE]\n\n").await?;
+        }
+        Some(MockResponse::Error(status, value)) => {
+            let body = value.to_string();
+            let head = format!(
+                "HTTP/1.1 {} Error\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            socket.write_all(head.as_bytes()).await?;
+            socket.write_all(body.as_bytes()).await?;
+        }
+        None => {
+            let body = r#"{"error":{"message":"no scripted response left"}}"#;
+            let head = format!(
+                "HTTP/1.1 500 Internal Server Error\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            socket.write_all(head.as_bytes()).await?;
+            socket.write_all(body.as_bytes()).await?;
+        }
+    }
+    socket.shutdown().await
+}
+
+fn stream_chunk(delta: Value, finish_reason: Option<&str>) -> Value {
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": "mock-model",
+        "choices": [{
+            "index": 0,
+            "delta": delta,
+            "finish_reason": finish_reason
+        }]
+    })
+}
+
+/// A streamed content delta.
+pub fn content_chunk(text: &str) -> Value {
+    stream_chunk(json!({ "role": "assistant", "content": text }), None)
+}
+
+/// A streamed tool-call fragment. `id` and `name` are usually only present on the first fragment.
+pub fn tool_call_chunk(index: u32, id: Option<&str>, name: Option<&str>, arguments: &str) -> Value {
+    let mut function = json!({ "arguments": arguments });
+    if let Some(name) = name {
+        function["name"] = json!(name);
+    }
+    let mut call = json!({ "index": index, "function": function });
+    if let Some(id) = id {
+        call["id"] = json!(id);
+        call["type"] = json!("function");
+    }
+    stream_chunk(json!({ "tool_calls": [call] }), None)
+}
+
+/// The terminal chunk carrying `finish_reason`.
+pub fn finish_chunk(reason: &str) -> Value {
+    stream_chunk(json!({}), Some(reason))
+}
+
+/// A non-streaming completion with plain text content.
+pub fn completion(text: &str) -> Value {
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "created": 0,
+        "model": "mock-model",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": text },
+            "finish_reason": "stop"
+        }]
+    })
+}
+
+/// A non-streaming completion asking for tool calls, given as `(id, name, arguments)`.
+pub fn tool_calls_completion(calls: &[(&str, &str, &str)]) -> Value {
+    let tool_calls: Vec<Value> = calls
+        .iter()
+        .map(|(id, name, arguments)| {
+            json!({
+                "id": id,
+                "type": "function",
+                "function": { "name": name, "arguments": arguments }
+            })
+        })
+        .collect();
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "created": 0,
+        "model": "mock-model",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": null, "tool_calls": tool_calls },
+            "finish_reason": "tool_calls"
+        }]
+    })
+}
+
+/// An embeddings response; `data` entries are listed in the given order with their indices.
+pub fn embeddings(vectors: &[(u32, Vec<f32>)]) -> Value {
+    let data: Vec<Value> = vectors
+        .iter()
+        .map(|(index, embedding)| {
+            json!({ "object": "embedding", "index": index, "embedding": embedding })
+        })
+        .collect();
+    json!({
+        "object": "list",
+        "model": "mock-embedding",
+        "data": data,
+        "usage": { "prompt_tokens": 0, "total_tokens": 0 }
+    })
+}
+
+/// An OpenAI-style error body, e.g. for a 400 response.
+pub fn api_error(message: &str) -> Value {
+    json!({ "error": { "message": message, "type": "invalid_request_error", "param": null, "code": null } })
+}
@@ -1,5 +1,9 @@
 pub mod agent;
 pub mod client;
 pub mod confirm;
+#[cfg(test)]
+pub(crate) mod mock_server;
+pub mod tokenizer;
+pub mod vision;
 
 pub use client::LlmClient;
@@ -0,0 +1,196 @@
+//! Token counting for prompt budgeting (`TOKENIZER`).
+
+use crate::config::Config;
+use anyhow::Context as AnyhowContext;
+use async_trait::async_trait;
+use lru::LruCache;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Rough characters per token, used by [`HeuristicTokenizer`].
+pub const CHARS_PER_TOKEN: usize = 4;
+/// Concurrent `/tokenize` requests when counting many texts at once.
+const TOKENIZE_CONCURRENCY: usize = 8;
+/// Token counts cached by [`LlamaCppTokenizer`], keyed by text hash.
+const TOKENIZE_CACHE_SIZE: usize = 4096;
+
+/// Counts tokens the way the chat model will.
+#[async_trait]
+pub trait Tokenizer: Send + Sync {
+    /// Token counts for each of `texts`, in order.
+    async fn count_tokens(&self, texts: &[String]) -> anyhow::Result<Vec<usize>>;
+
+    async fn count(&self, text: &str) -> anyhow::Result<usize> {
+        let counts = self.count_tokens(&[text.to_string()]).await?;
+        Ok(counts.first().copied().unwrap_or(0))
+    }
+}
+
+/// Build the tokenizer selected by `TOKENIZER`: `cl100k` (default), `o200k`, `llama` (the
+/// llama.cpp server's `/tokenize` endpoint) or `heuristic` (~4 characters per token).
+pub fn from_config(config: &Config, http: reqwest::Client) -> Arc<dyn Tokenizer> {
+    match config.tokenizer.trim().to_ascii_lowercase().as_str() {
+        "cl100k" | "cl100k_base" => Arc::new(BpeTokenizer::cl100k()),
+        "o200k" | "o200k_base" => Arc::new(BpeTokenizer::o200k()),
+        "llama" | "llama.cpp" => Arc::new(LlamaCppTokenizer::new(http, &config.llama_url)),
+        "heuristic" => Arc::new(HeuristicTokenizer),
+        other => {
+            warn!(
+                "Unknown TOKENIZER '{}'; estimating ~{} characters per token",
+                other, CHARS_PER_TOKEN
+            );
+            Arc::new(HeuristicTokenizer)
+        }
+    }
+}
+
+/// Estimate only: ~4 characters per token. Also the fallback when another tokenizer fails.
+pub struct HeuristicTokenizer;
+
+impl HeuristicTokenizer {
+    pub fn estimate(text: &str) -> usize {
+        text.chars().count().div_ceil(CHARS_PER_TOKEN)
+    }
+}
+
+#[async_trait]
+impl Tokenizer for HeuristicTokenizer {
+    async fn count_tokens(&self, texts: &[String]) -> anyhow::Result<Vec<usize>> {
+        Ok(texts.iter().map(|t| Self::estimate(t)).collect())
+    }
+}
+
+/// tiktoken BPE vocabularies, bundled with the binary. Not the exact vocabulary of most local
+/// models, but far closer than a character estimate.
+pub struct BpeTokenizer {
+    bpe: &'static tiktoken_rs::CoreBPE,
+}
+
+impl BpeTokenizer {
+    pub fn cl100k() -> Self {
+        Self {
+            bpe: tiktoken_rs::cl100k_base_singleton(),
+        }
+    }
+
+    pub fn o200k() -> Self {
+        Self {
+            bpe: tiktoken_rs::o200k_base_singleton(),
+        }
+    }
+}
+
+#[async_trait]
+impl Tokenizer for BpeTokenizer {
+    async fn count_tokens(&self, texts: &[String]) -> anyhow::Result<Vec<usize>> {
+        Ok(texts
+            .iter()
+            .map(|t| self.bpe.encode_ordinary(t).len())
+            .collect())
+    }
+}
+
+/// The chat model's own tokenizer, through the llama.cpp server's `POST /tokenize`.
+pub struct LlamaCppTokenizer {
+    http: reqwest::Client,
+    url: String,
+    cache: Mutex<LruCache<u64, usize>>,
+}
+
+impl LlamaCppTokenizer {
+    /// `llama_url` is the OpenAI-compatible base (`.../v1`); `/tokenize` lives at the root.
+    pub fn new(http: reqwest::Client, llama_url: &str) -> Self {
+        let base = llama_url.trim_end_matches('/');
+        let base = base.strip_suffix("/v1").unwrap_or(base);
+        Self {
+            http,
+            url: format!("{}/tokenize", base),
+            cache: Mutex::new(LruCache::new(
+                NonZeroUsize::new(TOKENIZE_CACHE_SIZE).expect("cache size is non-zero"),
+            )),
+        }
+    }
+
+    async fn tokenize(&self, text: &str) -> anyhow::Result<usize> {
+        let key = {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            text.hash(&mut hasher);
+            hasher.finish()
+        };
+        if let Some(count) = self.cache.lock().unwrap().get(&key) {
+            return Ok(*count);
+        }
+
+        let response: serde_json::Value = self
+            .http
+            .post(&self.url)
+            .json(&serde_json::json!({ "content": text }))
+            .send()
+            .await
+            .context("Failed to reach the tokenize endpoint")?
+            .error_for_status()
+            .context("Tokenize request failed")?
+            .json()
+            .await
+            .context("Invalid tokenize response")?;
+        let count = response["tokens"]
+            .as_array()
+            .map(Vec::len)
+            .context("Tokenize response has no tokens")?;
+
+        self.cache.lock().unwrap().put(key, count);
+        Ok(count)
+    }
+}
+
+#[async_trait]
+impl Tokenizer for LlamaCppTokenizer {
+    async fn count_tokens(&self, texts: &[String]) -> anyhow::Result<Vec<usize>> {
+        let mut counts = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(TOKENIZE_CONCURRENCY) {
+            let requests: Vec<_> = batch.iter().map(|text| self.tokenize(text)).collect();
+            counts.extend(futures::future::try_join_all(requests).await?);
+        }
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server::{MockLlmServer, MockResponse};
+
+    #[tokio::test]
+    async fn test_bpe_and_heuristic_counts() {
+        let texts = vec!["Hello world, this is a test.".to_string(), String::new()];
+        assert_eq!(
+            BpeTokenizer::cl100k().count_tokens(&texts).await.unwrap(),
+            vec![8, 0]
+        );
+        assert_eq!(
+            HeuristicTokenizer.count_tokens(&texts).await.unwrap(),
+            vec![7, 0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_llama_tokenizer_uses_root_endpoint_and_caches() {
+        let server = MockLlmServer::start(vec![
+            MockResponse::Json(serde_json::json!({ "tokens": [1, 2, 3] })),
+            MockResponse::Json(serde_json::json!({ "tokens": [4] })),
+        ])
+        .await;
+        let tokenizer = LlamaCppTokenizer::new(reqwest::Client::new(), server.base_url());
+        assert!(tokenizer.url.ends_with("/tokenize") && !tokenizer.url.contains("/v1"));
+
+        assert_eq!(tokenizer.count("three tokens").await.unwrap(), 3);
+        // The cached text is not requested again.
+        let texts = vec!["three tokens".to_string(), "one".to_string()];
+        assert_eq!(tokenizer.count_tokens(&texts).await.unwrap(), vec![3, 1]);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["content"], "three tokens");
+    }
+}
@@ -0,0 +1,309 @@
+use crate::config::Config;
+use crate::services::attachments::{attachment_kind, download_attachment, AttachmentKind};
+use crate::Data;
+use async_openai::error::OpenAIError;
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestMessageContentPartImage,
+    ChatCompletionRequestMessageContentPartText, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageArgs, ChatCompletionRequestUserMessageContent,
+    ChatCompletionRequestUserMessageContentPart, ImageUrl,
+};
+use base64::Engine;
+use serenity::model::channel::{Attachment, Message};
+use tracing::{debug, warn};
+
+/// Image formats accepted by OpenAI-compatible vision endpoints.
+const SUPPORTED_IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/webp", "image/gif"];
+
+/// Picks the image attachments to send to a vision-capable chat model (`LLAMA_VISION`).
+pub struct ImageCollector {
+    http: reqwest::Client,
+    max_images: usize,
+    max_bytes: u64,
+    inline: bool,
+}
+
+impl ImageCollector {
+    /// `None` when vision input is disabled.
+    pub fn new(http: reqwest::Client, config: &Config) -> Option<Self> {
+        config.llama_vision.then(|| Self {
+            http,
+            max_images: config.vision_max_images,
+            max_bytes: config.vision_max_image_bytes,
+            inline: config.vision_inline_images,
+        })
+    }
+
+    /// Image URLs (or base64 data URLs when inlining) for `attachments`, in order. Unsupported
+    /// formats, images over the size limit and images beyond the count limit are skipped.
+    pub async fn collect(&self, attachments: &[&Attachment]) -> Vec<String> {
+        let mut urls = Vec::new();
+        for &attachment in attachments {
+            if urls.len() >= self.max_images {
+                debug!(
+                    "Vision: skipping '{}', image limit of {} reached",
+                    attachment.filename, self.max_images
+                );
+                continue;
+            }
+            let Some(content_type) = image_content_type(attachment) else {
+                continue;
+            };
+            if u64::from(attachment.size) > self.max_bytes {
+                debug!(
+                    "Vision: skipping '{}' ({} bytes > limit of {})",
+                    attachment.filename, attachment.size, self.max_bytes
+                );
+                continue;
+            }
+            if !self.inline {
+                urls.push(attachment.url.clone());
+                continue;
+            }
+            match download_attachment(&self.http, &attachment.url, self.max_bytes).await {
+                Ok(bytes) => urls.push(format!(
+                    "data:{};base64,{}",
+                    content_type,
+                    base64::engine::general_purpose::STANDARD.encode(bytes)
+                )),
+                Err(e) => warn!("Vision: failed to load '{}': {:#}", attachment.filename, e),
+            }
+        }
+        urls
+    }
+}
+
+/// Images on `message` and on the message it replies to, for the model. Empty when vision is
+/// disabled.
+pub async fn collect_message_images(data: &Data, message: &Message) -> Vec<String> {
+    let Some(collector) = ImageCollector::new(data.http_client.clone(), &data.config) else {
+        return Vec::new();
+    };
+    let referenced = message.referenced_message.as_deref();
+    let attachments: Vec<&Attachment> = message
+        .attachments
+        .iter()
+        .chain(referenced.into_iter().flat_map(|m| &m.attachments))
+        .collect();
+    collector.collect(&attachments).await
+}
+
+/// The MIME type of an image attachment in a format vision endpoints accept.
+fn image_content_type(attachment: &Attachment) -> Option<String> {
+    let content_type = attachment.content_type.as_deref();
+    if attachment_kind(&attachment.filename, content_type) != Some(AttachmentKind::Image) {
+        return None;
+    }
+    let content_type = content_type?.split(';').next()?.trim().to_ascii_lowercase();
+    SUPPORTED_IMAGE_TYPES
+        .contains(&content_type.as_str())
+        .then_some(content_type)
+}
+
+/// A user message with `text` and, when there are any, `image_urls` as image content parts.
+pub fn user_message(
+    text: String,
+    image_urls: &[String],
+) -> anyhow::Result<ChatCompletionRequestMessage> {
+    if image_urls.is_empty() {
+        return Ok(ChatCompletionRequestUserMessageArgs::default()
+            .content(text)
+            .build()?
+            .into());
+    }
+    let mut parts = vec![ChatCompletionRequestUserMessageContentPart::Text(
+        ChatCompletionRequestMessageContentPartText { text },
+    )];
+    parts.extend(image_urls.iter().map(|url| {
+        ChatCompletionRequestUserMessageContentPart::ImageUrl(
+            ChatCompletionRequestMessageContentPartImage {
+                image_url: ImageUrl {
+                    url: url.clone(),
+                    detail: None,
+                },
+            },
+        )
+    }));
+    Ok(ChatCompletionRequestUserMessageArgs::default()
+        .content(ChatCompletionRequestUserMessageContent::Array(parts))
+        .build()?
+        .into())
+}
+
+pub fn has_images(messages: &[ChatCompletionRequestMessage]) -> bool {
+    messages.iter().any(|message| image_count(message) > 0)
+}
+
+fn image_count(message: &ChatCompletionRequestMessage) -> usize {
+    match message {
+        ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+            content: ChatCompletionRequestUserMessageContent::Array(parts),
+            ..
+        }) => parts
+            .iter()
+            .filter(|part| {
+                matches!(
+                    part,
+                    ChatCompletionRequestUserMessageContentPart::ImageUrl(_)
+                )
+            })
+            .count(),
+        _ => 0,
+    }
+}
+
+/// Replace image content parts with a short note so the conversation can be retried as text.
+pub fn strip_images(
+    messages: Vec<ChatCompletionRequestMessage>,
+) -> Vec<ChatCompletionRequestMessage> {
+    messages
+        .into_iter()
+        .map(|message| {
+            let images = image_count(&message);
+            let ChatCompletionRequestMessage::User(mut user) = message else {
+                return message;
+            };
+            if let ChatCompletionRequestUserMessageContent::Array(parts) = &user.content {
+                let mut text: Vec<String> = parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        ChatCompletionRequestUserMessageContentPart::Text(t) => {
+                            Some(t.text.clone())
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                if images > 0 {
+                    text.push(format!(
+                        "[{} image(s) attached but not shown: the model does not accept images]",
+                        images
+                    ));
+                }
+                user.content = ChatCompletionRequestUserMessageContent::Text(text.join("\n"));
+            }
+            ChatCompletionRequestMessage::User(user)
+        })
+        .collect()
+}
+
+/// Whether a chat request failed because the server refused it, as a model without image
+/// support does for image content. Timeouts and connection errors do not count.
+pub fn is_rejection(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<OpenAIError>(),
+        Some(OpenAIError::ApiError(_) | OpenAIError::StreamError(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server::{api_error, completion, MockLlmServer, MockResponse};
+    use crate::llm::LlmClient;
+
+    fn attachment(filename: &str, content_type: &str, size: u32) -> Attachment {
+        serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "filename": filename,
+            "size": size,
+            "url": format!("https://cdn.example.com/{}", filename),
+            "proxy_url": format!("https://media.example.com/{}", filename),
+            "content_type": content_type,
+        }))
+        .unwrap()
+    }
+
+    fn collector(max_images: usize, max_bytes: u64) -> ImageCollector {
+        ImageCollector {
+            http: reqwest::Client::new(),
+            max_images,
+            max_bytes,
+            inline: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collect_applies_format_size_and_count_limits() {
+        let attachments = [
+            attachment("shot.png", "image/png", 100),
+            attachment("notes.txt", "text/plain", 100),
+            attachment("huge.jpg", "image/jpeg", 10_000),
+            attachment("icon.svg", "image/svg+xml", 100),
+            attachment("photo.webp", "image/webp", 100),
+            attachment("third.gif", "image/gif", 100),
+        ];
+        let attachments: Vec<&Attachment> = attachments.iter().collect();
+        let urls = collector(2, 1_000).collect(&attachments).await;
+        assert_eq!(
+            urls,
+            vec![
+                "https://cdn.example.com/shot.png",
+                "https://cdn.example.com/photo.webp"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_user_message_with_images_and_strip_images() {
+        let plain = user_message("[alice]: hi".to_string(), &[]).unwrap();
+        assert!(!has_images(std::slice::from_ref(&plain)));
+
+        let with_image = user_message(
+            "[alice]: what's wrong here?".to_string(),
+            &["data:image/png;base64,AAAA".to_string()],
+        )
+        .unwrap();
+        let json = serde_json::to_value(&with_image).unwrap();
+        assert_eq!(json["content"][0]["type"], "text");
+        assert_eq!(json["content"][1]["type"], "image_url");
+        assert_eq!(
+            json["content"][1]["image_url"]["url"],
+            "data:image/png;base64,AAAA"
+        );
+
+        let messages = vec![plain, with_image];
+        assert!(has_images(&messages));
+        let stripped = strip_images(messages);
+        assert!(!has_images(&stripped));
+        let json = serde_json::to_value(&stripped[1]).unwrap();
+        assert_eq!(
+            json["content"],
+            "[alice]: what's wrong here?\n\
+             [1 image(s) attached but not shown: the model does not accept images]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_requests_are_detected_for_both_request_kinds() {
+        let server = MockLlmServer::start(vec![
+            MockResponse::Error(400, api_error("image input is not supported")),
+            MockResponse::Error(400, api_error("image input is not supported")),
+            MockResponse::Json(completion("ok")),
+        ])
+        .await;
+        let client = LlmClient::for_mock_server(&server);
+        let messages = vec![user_message(
+            "look".to_string(),
+            &["https://cdn.example.com/a.png".to_string()],
+        )
+        .unwrap()];
+
+        let error = client
+            .chat_with_tools(messages.clone(), None)
+            .await
+            .unwrap_err();
+        assert!(is_rejection(&error));
+        let error = client
+            .chat_with_tools_stream(messages.clone(), None, |_| {})
+            .await
+            .unwrap_err();
+        assert!(is_rejection(&error));
+
+        let response = client
+            .chat_with_tools(strip_images(messages), None)
+            .await
+            .unwrap();
+        assert_eq!(response.choices[0].message.content.as_deref(), Some("ok"));
+        assert!(server.requests()[2]["messages"][0]["content"].is_string());
+    }
+}
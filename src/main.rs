@@ -41,6 +41,18 @@ async fn main() -> anyhow::Result<()> {
     let mut config = Config::from_env()?;
     info!("Configuration loaded successfully");
 
+    // `--migrate-only`: bring the database schema up to date and exit without connecting to Discord.
+    if std::env::args().skip(1).any(|arg| arg == "--migrate-only") {
+        let db = mascord::db::Database::new(&config).context("Failed to open database")?;
+        db.execute_init().context("Failed to initialize database")?;
+        info!(
+            "Database at '{}' is at schema version {}; exiting (--migrate-only)",
+            config.database_url,
+            db.schema_version()?
+        );
+        return Ok(());
+    }
+
     // Fetch dynamic application info (ID and Owners) only if APPLICATION_ID is missing
     let (app_id, owner_id) = if config.application_id != 0 {
         if config.owner_id.is_none() {
@@ -104,13 +116,14 @@ async fn main() -> anyhow::Result<()> {
                 reminder::reminder(),
                 admin::shutdown(),
                 admin::restart(),
+                admin::embedding_status(),
                 mcp::mcp(),
                 settings::settings(), // /settings context
             ],
             event_handler: |ctx, event, _framework, data| {
                 Box::pin(async move {
-                    if let serenity::FullEvent::Message { new_message } = event {
-                        if !new_message.author.bot {
+                    match event {
+                        serenity::FullEvent::Message { new_message } if !new_message.author.bot => {
                             // Check if channel tracking is enabled
                             let cache_message = new_message.clone();
                             let channel_id = new_message.channel_id.to_string();
@@ -122,12 +135,16 @@ async fn main() -> anyhow::Result<()> {
                             let content = new_message.content.clone();
                             let message_id = new_message.id.to_string();
                             let timestamp = new_message.timestamp.unix_timestamp();
+                            let thread = mascord::message_events::message_thread(ctx, new_message);
 
                             match data
                                 .db
                                 .run_blocking(move |db| {
                                     let enabled = db.is_channel_tracking_enabled(&channel_id)?;
                                     if enabled {
+                                        if let Some(thread) = &thread {
+                                            db.save_channel_thread(thread)?;
+                                        }
                                         db.save_message(
                                             &message_id,
                                             &guild_id,
@@ -144,6 +161,28 @@ async fn main() -> anyhow::Result<()> {
                                 Ok(true) => {
                                     // Populate internal cache after persistence check
                                     data.cache.insert(cache_message);
+
+                                    // Awaited so a mention with an attached file sees its text.
+                                    if data.config.attachment_ingest_enabled
+                                        && !new_message.attachments.is_empty()
+                                    {
+                                        let attachments =
+                                            mascord::services::attachments::AttachmentService::new(
+                                                data.db.clone(),
+                                                data.http_client.clone(),
+                                                &data.config,
+                                            );
+                                        if let Err(e) = attachments
+                                            .ingest_message_with_timeout(new_message)
+                                            .await
+                                        {
+                                            tracing::error!(
+                                                "Failed to ingest attachments of message {}: {}",
+                                                new_message.id,
+                                                e
+                                            );
+                                        }
+                                    }
                                 }
                                 Ok(false) => {}
                                 Err(e) => {
@@ -182,6 +221,68 @@ async fn main() -> anyhow::Result<()> {
                                 }
                             }
                         }
+                        serenity::FullEvent::ThreadCreate { thread }
+                        | serenity::FullEvent::ThreadUpdate { new: thread, .. } => {
+                            if let Err(e) =
+                                mascord::message_events::handle_thread_upsert(ctx, thread, data)
+                                    .await
+                            {
+                                tracing::error!("Failed to record thread {}: {}", thread.id, e);
+                            }
+                        }
+                        serenity::FullEvent::MessageUpdate { event, .. } => {
+                            if let Err(e) =
+                                mascord::message_events::handle_message_update(event, data).await
+                            {
+                                tracing::error!(
+                                    "Failed to apply edit to message {} in channel {}: {}",
+                                    event.id,
+                                    event.channel_id,
+                                    e
+                                );
+                            }
+                        }
+                        serenity::FullEvent::MessageDelete {
+                            channel_id,
+                            deleted_message_id,
+                            ..
+                        } => {
+                            if let Err(e) = mascord::message_events::handle_messages_deleted(
+                                *channel_id,
+                                std::slice::from_ref(deleted_message_id),
+                                data,
+                            )
+                            .await
+                            {
+                                tracing::error!(
+                                    "Failed to remove deleted message {} in channel {}: {}",
+                                    deleted_message_id,
+                                    channel_id,
+                                    e
+                                );
+                            }
+                        }
+                        serenity::FullEvent::MessageDeleteBulk {
+                            channel_id,
+                            multiple_deleted_messages_ids,
+                            ..
+                        } => {
+                            if let Err(e) = mascord::message_events::handle_messages_deleted(
+                                *channel_id,
+                                multiple_deleted_messages_ids,
+                                data,
+                            )
+                            .await
+                            {
+                                tracing::error!(
+                                    "Failed to remove {} deleted messages in channel {}: {}",
+                                    multiple_deleted_messages_ids.len(),
+                                    channel_id,
+                                    e
+                                );
+                            }
+                        }
+                        _ => {}
                     }
                     Ok(())
                 })
@@ -237,25 +338,36 @@ async fn main() -> anyhow::Result<()> {
                 let db = mascord::db::Database::new(&config).context("Failed to open database")?;
                 db.execute_init().context("Failed to initialize database")?;
 
+                // Build the ANN index in the background; semantic search scans linearly until it is ready.
+                if config.vector_index_enabled {
+                    let db = db.clone();
+                    tokio::task::spawn_blocking(move || {
+                        if let Err(e) = db.load_vector_index() {
+                            error!("Failed to load vector index: {:#}", e);
+                        }
+                    });
+                }
+
                 // Initialize cache with capacity of 1000 messages
                 let cache = mascord::cache::MessageCache::new(1000);
 
+                // Shared HTTP client (also used by yt-dlp sources)
+                let http_client = reqwest::Client::new();
+                let tokenizer = mascord::llm::tokenizer::from_config(&config, http_client.clone());
+
                 // Initialize Tools
                 let mut registry = mascord::tools::ToolRegistry::new();
                 registry.register(std::sync::Arc::new(mascord::tools::builtin::music::PlayMusicTool));
-                registry.register(std::sync::Arc::new(mascord::tools::builtin::rag::SearchLocalHistoryTool {
-                    db: db.clone(),
-                    llm: llm_client.clone(),
-                }));
-                registry.register(std::sync::Arc::new(
-                    mascord::tools::builtin::user_memory::GetUserMemoryTool { db: db.clone() },
-                ));
+                registry.register(std::sync::Arc::new(mascord::tools::builtin::rag::SearchLocalHistoryTool));
+                registry.register(std::sync::Arc::new(mascord::tools::builtin::user_memory::GetUserMemoryTool));
+                registry.register(std::sync::Arc::new(mascord::tools::builtin::mcp_resource::ReadMcpResourceTool));
                 let tools = std::sync::Arc::new(registry);
 
                 // Initialize MCP
                 let mcp_manager = std::sync::Arc::new(
                     mascord::mcp::client::McpClientManager::new(&config)
                         .context("Failed to initialize MCP manager")?
+                        .with_reserved_tool_names(tools.list_tools().iter().map(|t| t.name().to_string()))
                 );
 
                 // Connect to MCP servers (best-effort) and warm up tool discovery.
@@ -316,15 +428,24 @@ async fn main() -> anyhow::Result<()> {
                     });
                 }
 
+                if config.mcp_health_check_interval_secs > 0 {
+                    // Ping MCP servers and restart dead ones (crashed child processes, dropped streams).
+                    mcp_manager.spawn_supervisor(std::time::Duration::from_secs(
+                        config.mcp_health_check_interval_secs,
+                    ));
+                }
+
                 if config.summarization_enabled {
                     // Start background summarization task (tick interval configurable; triggers decide per-channel work)
                     let db_clone = db.clone();
                     let llm_clone = llm_client.clone();
+                    let tokenizer_clone = tokenizer.clone();
                     let config_clone = config.clone();
                     tokio::spawn(async move {
                         let manager = mascord::summarize::SummarizationManager::new(
                             db_clone,
                             llm_clone,
+                            tokenizer_clone,
                             &config_clone,
                         );
                         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(
@@ -425,6 +546,15 @@ async fn main() -> anyhow::Result<()> {
                                     tracing::error!("Long-term cleanup error: {}", e);
                                 }
                             }
+                            match db_cleanup.cleanup_old_agent_runs(retention_hours) {
+                                Ok(count) if count > 0 => {
+                                    info!("Long-term cleanup: deleted {} old agent runs", count);
+                                }
+                                Ok(_) => {}
+                                Err(e) => {
+                                    tracing::error!("Long-term agent run cleanup error: {}", e);
+                                }
+                            }
                         }
                     });
                 } else {
@@ -474,17 +604,13 @@ async fn main() -> anyhow::Result<()> {
                 if config.embedding_indexer_enabled {
                     // Start background embedding indexer (best-effort, non-blocking).
                     // This avoids embedding calls on the Discord event handler hot path.
-                    let db_index = db.clone();
-                    let llm_index = std::sync::Arc::new(llm_client.clone());
-                    let batch_size = config.embedding_indexer_batch_size;
-                    let interval_secs = config.embedding_indexer_interval_secs;
+                    let indexer = mascord::indexer::EmbeddingIndexer::new(
+                        db.clone(),
+                        std::sync::Arc::new(llm_client.clone()),
+                        &config,
+                    );
                     tokio::spawn(async move {
-                        mascord::indexer::EmbeddingIndexer::new(
-                            db_index,
-                            llm_index,
-                            batch_size,
-                            tokio::time::Duration::from_secs(interval_secs),
-                        )
+                        indexer
                         .run()
                         .await;
                     });
@@ -494,8 +620,9 @@ async fn main() -> anyhow::Result<()> {
 
                 Ok(Data {
                     config,
-                    http_client: reqwest::Client::new(),
+                    http_client,
                     llm_client,
+                    tokenizer,
                     db,
                     cache,
                     tools,
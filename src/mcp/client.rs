@@ -1,39 +1,225 @@
 use crate::config::Config;
 use crate::mcp::config::{McpServerConfig, McpTransport};
-use crate::tools::Tool;
-use anyhow::{anyhow, Result};
+use crate::mcp::naming::namespaced_tool_name;
+use crate::tools::{Tool, ToolContext};
+use anyhow::{anyhow, Context as AnyhowContext, Result};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use rmcp::{
-    model::CallToolRequestParams,
-    service::{RoleClient, RunningService, ServiceExt},
-    transport::child_process::TokioChildProcess,
+    handler::client::ClientHandler,
+    model::{
+        CallToolRequestParams, ClientRequest, GetPromptRequestParams, GetPromptResult, PingRequest,
+        Prompt, ReadResourceRequestParams, ReadResourceResult, Resource, ServerCapabilities,
+    },
+    service::{NotificationContext, Peer, RoleClient, RunningService, ServiceError, ServiceExt},
+    transport::{
+        child_process::TokioChildProcess,
+        streamable_http_client::StreamableHttpClientTransportConfig, StreamableHttpClientTransport,
+    },
 };
 use serde_json::Value;
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use tokio::process::Command;
 use tokio::sync::Mutex;
 use tracing::{debug, error, info, warn};
 
-type RunningMcpService = RunningService<RoleClient, ()>;
+type RunningMcpService = RunningService<RoleClient, McpClientHandler>;
 type McpServiceMap = HashMap<String, Arc<RunningMcpService>>;
 type SharedMcpServiceMap = Arc<Mutex<McpServiceMap>>;
 
+/// Delay before the second reconnection attempt; doubles with each failure.
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(2);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// Health of a server connected through `McpClientManager::connect`, kept by the supervisor.
+#[derive(Debug, Clone, Default)]
+pub struct ServerHealth {
+    /// Round trip of the last successful ping.
+    pub latency: Option<Duration>,
+    pub last_checked: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// Successful reconnections since startup.
+    pub restart_count: u32,
+    /// Failed connection attempts since the connection dropped (or since the first attempt).
+    pub reconnect_failures: u32,
+    next_reconnect: Option<Instant>,
+}
+
+impl ServerHealth {
+    fn record_error(&mut self, error: String) {
+        self.last_error = Some(error);
+        self.last_error_at = Some(Utc::now());
+    }
+}
+
+/// Cached tools by server name.
+type ServerTools = HashMap<String, Vec<Arc<dyn Tool>>>;
+
+/// Tool listings of the connected servers, fetched on connect and refreshed when a server
+/// sends `notifications/tools/list_changed` or on `/mcp refresh`.
+#[derive(Clone)]
+struct ToolCatalog {
+    tools: Arc<RwLock<ServerTools>>,
+    /// Names taken by built-in tools; MCP tools may not shadow them.
+    reserved: Arc<HashSet<String>>,
+    /// Connected servers that advertise resources.
+    resource_servers: Arc<RwLock<HashSet<String>>>,
+    timeout_secs: u64,
+    require_confirmation: bool,
+}
+
+impl ToolCatalog {
+    /// Fetch the tools of `server_name` and replace its cached listing. Returns the tool count.
+    ///
+    /// Tools are exposed under their namespaced name. A tool whose name collides with a
+    /// built-in, another server's tool or an earlier tool of the same server is skipped.
+    async fn refresh(&self, server_name: &str, peer: &Peer<RoleClient>) -> Result<usize> {
+        debug!(
+            "MCP client: Discovering tools from server '{}'...",
+            server_name
+        );
+        // Add timeout to prevent hanging on unresponsive MCP servers
+        let tools = tokio::time::timeout(
+            Duration::from_secs(self.timeout_secs),
+            peer.list_all_tools(),
+        )
+        .await
+        .map_err(|_| anyhow!("Tool discovery timed out after {}s", self.timeout_secs))?
+        .context("Tool discovery failed")?;
+        debug!(
+            "MCP client: Found {} tools on server '{}'",
+            tools.len(),
+            server_name
+        );
+
+        let mut catalog = self.tools.write().unwrap();
+        let taken_by_servers: HashMap<&str, &str> = catalog
+            .iter()
+            .filter(|(name, _)| name.as_str() != server_name)
+            .flat_map(|(name, tools)| tools.iter().map(move |t| (t.name(), name.as_str())))
+            .collect();
+        let mut seen: HashMap<String, String> = HashMap::new();
+        let mut wrappers: Vec<Arc<dyn Tool>> = Vec::with_capacity(tools.len());
+        for tool in tools {
+            let name = namespaced_tool_name(server_name, &tool.name);
+            if self.reserved.contains(&name) {
+                warn!(
+                    "MCP client: Skipping tool '{}' of server '{}': '{}' is a built-in tool",
+                    tool.name, server_name, name
+                );
+                continue;
+            }
+            if let Some(other) = taken_by_servers.get(name.as_str()) {
+                warn!(
+                    "MCP client: Skipping tool '{}' of server '{}': '{}' is already provided by server '{}'",
+                    tool.name, server_name, name, other
+                );
+                continue;
+            }
+            if let Some(first) = seen.get(&name) {
+                warn!(
+                    "MCP client: Skipping tool '{}' of server '{}': '{}' is already used by its tool '{}'",
+                    tool.name, server_name, name, first
+                );
+                continue;
+            }
+            seen.insert(name.clone(), tool.name.to_string());
+
+            // In rmcp 0.14+, description is Option<Cow>
+            let desc = tool
+                .description
+                .as_ref()
+                .map(|d| d.to_string())
+                .unwrap_or_else(|| "(no description)".to_string());
+            wrappers.push(Arc::new(McpToolWrapper {
+                server_name: server_name.to_string(),
+                peer: peer.clone(),
+                name,
+                tool_name: tool.name.to_string(),
+                description: desc,
+                input_schema: serde_json::Value::Object((*tool.input_schema).clone()),
+                timeout_secs: self.timeout_secs,
+                requires_confirmation: self.require_confirmation,
+            }));
+        }
+        let count = wrappers.len();
+        catalog.insert(server_name.to_string(), wrappers);
+        Ok(count)
+    }
+
+    fn contains(&self, server_name: &str) -> bool {
+        self.tools.read().unwrap().contains_key(server_name)
+    }
+
+    fn remove(&self, server_name: &str) {
+        self.tools.write().unwrap().remove(server_name);
+        self.resource_servers.write().unwrap().remove(server_name);
+    }
+}
+
+/// Client side of an MCP session: keeps the server's catalog entry current.
+pub struct McpClientHandler {
+    server_name: String,
+    catalog: ToolCatalog,
+}
+
+impl ClientHandler for McpClientHandler {
+    async fn on_tool_list_changed(&self, context: NotificationContext<RoleClient>) {
+        info!(
+            "MCP client: Server '{}' changed its tools; refreshing",
+            self.server_name
+        );
+        // Not awaited here: the listing request is answered through this same session.
+        let catalog = self.catalog.clone();
+        let server_name = self.server_name.clone();
+        tokio::spawn(async move {
+            if let Err(e) = catalog.refresh(&server_name, &context.peer).await {
+                warn!(
+                    "MCP client: Failed to refresh tools of server '{}': {:#}",
+                    server_name, e
+                );
+            }
+        });
+    }
+}
+
 pub struct McpClientManager {
     services: SharedMcpServiceMap,
+    /// Configs of the servers connected through `connect`, used to reconnect them.
+    configs: Mutex<HashMap<String, McpServerConfig>>,
+    health: std::sync::Mutex<HashMap<String, ServerHealth>>,
+    catalog: ToolCatalog,
     timeout_secs: u64,
-    require_confirmation: bool,
 }
 
 impl McpClientManager {
     pub fn new(config: &Config) -> Result<Self> {
         Ok(Self {
             services: Arc::new(Mutex::new(HashMap::new())),
+            configs: Mutex::new(HashMap::new()),
+            health: std::sync::Mutex::new(HashMap::new()),
+            catalog: ToolCatalog {
+                tools: Arc::new(RwLock::new(HashMap::new())),
+                reserved: Arc::new(HashSet::new()),
+                resource_servers: Arc::new(RwLock::new(HashSet::new())),
+                timeout_secs: config.mcp_timeout_secs,
+                require_confirmation: config.mcp_tools_require_confirmation,
+            },
             timeout_secs: config.mcp_timeout_secs,
-            require_confirmation: config.mcp_tools_require_confirmation,
         })
     }
 
+    /// Names MCP tools may not use, typically those of the built-in tools. Conflicting MCP
+    /// tools are skipped with a warning.
+    pub fn with_reserved_tool_names(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.catalog.reserved = Arc::new(names.into_iter().collect());
+        self
+    }
+
     pub async fn connect(&self, config: &McpServerConfig) -> Result<()> {
         {
             let services_lock = self.services.lock().await;
@@ -43,67 +229,51 @@ impl McpClientManager {
             }
         }
 
-        info!(
-            "MCP client: Connecting to server '{}' via {:?}...",
-            config.name, config.transport
-        );
-        let running = match config.transport {
-            McpTransport::Stdio => {
-                let mut cmd = Command::new(config.command.as_ref().ok_or_else(|| {
-                    error!(
-                        "MCP client: Command not specified for stdio transport on server '{}'",
-                        config.name
-                    );
-                    anyhow!("Command not specified for stdio transport")
-                })?);
-                if let Some(args) = &config.args {
-                    cmd.args(args);
-                }
-                if let Some(env) = &config.env {
-                    cmd.envs(env);
-                }
-
-                let transport = TokioChildProcess::new(cmd).map_err(|e| {
-                    error!(
-                        "MCP client: Failed to start child process for server '{}': {}",
-                        config.name, e
-                    );
-                    e
-                })?;
-                ().serve(transport).await.map_err(|e| {
-                    error!(
-                        "MCP client: Failed to serve transport for server '{}': {}",
-                        config.name, e
-                    );
-                    e
-                })?
+        // Supervised from here on, so a server that is down now is retried with backoff.
+        self.configs
+            .lock()
+            .await
+            .insert(config.name.clone(), config.clone());
+        self.health
+            .lock()
+            .unwrap()
+            .entry(config.name.clone())
+            .or_default();
+        let running = match open(config, self.handler(&config.name)).await {
+            Ok(running) => Arc::new(running),
+            Err(e) => {
+                self.schedule_reconnect(&config.name, "Connect", &e);
+                return Err(e);
             }
-            McpTransport::Sse => {
-                warn!(
-                    "MCP client: SSE transport requested for server '{}' but not implemented",
+        };
+        {
+            let mut services_lock = self.services.lock().await;
+            if services_lock.contains_key(&config.name) {
+                debug!(
+                    "MCP server '{}' connected while connection was in-flight; keeping existing connection",
                     config.name
                 );
-                return Err(anyhow!("SSE transport not yet implemented"));
+                return Ok(());
             }
-        };
-
-        info!(
-            "MCP client: Successfully connected to server '{}'",
-            config.name
-        );
-        let mut services_lock = self.services.lock().await;
-        if services_lock.contains_key(&config.name) {
-            debug!(
-                "MCP server '{}' connected while connection was in-flight; keeping existing connection",
-                config.name
-            );
-            return Ok(());
+            services_lock.insert(config.name.clone(), running.clone());
         }
-        services_lock.insert(config.name.clone(), Arc::new(running));
+        self.record_capabilities(&config.name, &running);
+        // A server without a listing stays connected; the supervisor retries discovery.
+        self.refresh_server(&config.name, &running).await.ok();
         Ok(())
     }
 
+    fn handler(&self, server_name: &str) -> McpClientHandler {
+        McpClientHandler {
+            server_name: server_name.to_string(),
+            catalog: self.catalog.clone(),
+        }
+    }
+
     pub async fn disconnect(&self, name: &str) -> Result<()> {
+        self.configs.lock().await.remove(name);
+        self.health.lock().unwrap().remove(name);
+        self.catalog.remove(name);
         let mut services_lock = self.services.lock().await;
         if services_lock.remove(name).is_some() {
             info!("MCP client: Disconnected from server '{}'", name);
@@ -122,77 +292,500 @@ impl McpClientManager {
         services_lock.keys().cloned().collect()
     }
 
+    /// Health of the supervised servers, by name. Includes servers waiting to reconnect.
+    pub fn server_health(&self) -> HashMap<String, ServerHealth> {
+        self.health.lock().unwrap().clone()
+    }
+
+    /// The cached tools of all live servers. Makes no requests, so slow servers cannot stall
+    /// the agent.
     pub async fn list_all_tools(&self) -> Vec<Arc<dyn Tool>> {
+        let live: Vec<String> = {
+            let services = self.services.lock().await;
+            services
+                .iter()
+                // Dead connections would only time out; the supervisor replaces them.
+                .filter(|(_, service)| !is_dead(service))
+                .map(|(name, _)| name.clone())
+                .collect()
+        };
+        let catalog = self.catalog.tools.read().unwrap();
+        live.iter()
+            .filter_map(|name| catalog.get(name))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Re-fetch the tool listing of one server, or of every live server. Returns the tool
+    /// count or the error per server.
+    pub async fn refresh_tools(&self, name: Option<&str>) -> Vec<(String, Result<usize>)> {
         let services_snapshot: Vec<(String, Arc<RunningMcpService>)> = {
             let services = self.services.lock().await;
             services
                 .iter()
-                .map(|(name, service)| (name.clone(), service.clone()))
+                .filter(|(server_name, _)| name.is_none_or(|n| n == server_name.as_str()))
+                .map(|(server_name, service)| (server_name.clone(), service.clone()))
                 .collect()
         };
-        let mut all_tools = Vec::new();
-        let require_confirmation = self.require_confirmation;
+        let refreshes = services_snapshot
+            .iter()
+            .map(|(server_name, service)| self.refresh_server(server_name, service));
+        let results = futures::future::join_all(refreshes).await;
+        services_snapshot
+            .into_iter()
+            .map(|(server_name, _)| server_name)
+            .zip(results)
+            .collect()
+    }
 
-        for (server_name, service) in services_snapshot {
-            debug!(
-                "MCP client: Discovering tools from server '{}'...",
-                server_name
+    async fn refresh_server(&self, name: &str, service: &Arc<RunningMcpService>) -> Result<usize> {
+        if is_dead(service) {
+            return Err(anyhow!("Server '{}' is not connected", name));
+        }
+        let result = self.catalog.refresh(name, service.peer()).await;
+        if let Err(e) = &result {
+            warn!(
+                "MCP client: Failed to discover tools from server '{}': {:#}",
+                name, e
             );
-            // Add timeout to prevent hanging on unresponsive MCP servers
-            let discovery_timeout = tokio::time::Duration::from_secs(self.timeout_secs);
-            let tools_result =
-                tokio::time::timeout(discovery_timeout, service.list_all_tools()).await;
-
-            match tools_result {
-                Ok(Ok(tools)) => {
-                    debug!(
-                        "MCP client: Found {} tools on server '{}'",
-                        tools.len(),
-                        server_name
-                    );
-                    for tool in tools {
-                        // In rmcp 0.14+, description is Option<Cow>
-                        let desc = tool
-                            .description
-                            .as_ref()
-                            .map(|d| d.to_string())
-                            .unwrap_or_else(|| "(no description)".to_string());
-
-                        all_tools.push(Arc::new(McpToolWrapper {
-                            server_name: server_name.clone(),
-                            service: service.clone(),
-                            name: tool.name.to_string(),
-                            description: desc,
-                            input_schema: serde_json::Value::Object((*tool.input_schema).clone()),
-                            timeout_secs: self.timeout_secs,
-                            requires_confirmation: require_confirmation,
-                        }) as Arc<dyn Tool>);
+            self.record_error(name, format!("{:#}", e));
+            let dead = e.downcast_ref::<ServiceError>().is_some_and(|e| {
+                matches!(
+                    e,
+                    ServiceError::TransportSend(_) | ServiceError::TransportClosed
+                )
+            });
+            if dead {
+                self.drop_service(name, service).await;
+            }
+        }
+        result
+    }
+
+    /// The connected servers that advertise resources, sorted by name.
+    pub fn resource_servers(&self) -> Vec<String> {
+        let mut servers: Vec<String> = self
+            .catalog
+            .resource_servers
+            .read()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        servers.sort();
+        servers
+    }
+
+    /// The resources of `server`, or of every connected server that advertises resources.
+    pub async fn list_resources(
+        &self,
+        server: Option<&str>,
+    ) -> Vec<(String, Result<Vec<Resource>>)> {
+        let services_snapshot: Vec<(String, Arc<RunningMcpService>)> = {
+            let services = self.services.lock().await;
+            services
+                .iter()
+                .filter(|(name, service)| {
+                    server.is_none_or(|s| s == name.as_str())
+                        && !is_dead(service)
+                        && capabilities(service).is_some_and(|c| c.resources.is_some())
+                })
+                .map(|(name, service)| (name.clone(), service.clone()))
+                .collect()
+        };
+        let listings = services_snapshot
+            .iter()
+            .map(|(_, service)| self.request("Resource listing", service.list_all_resources()));
+        let results = futures::future::join_all(listings).await;
+        services_snapshot
+            .into_iter()
+            .map(|(name, _)| name)
+            .zip(results)
+            .collect()
+    }
+
+    pub async fn read_resource(&self, server: &str, uri: &str) -> Result<ReadResourceResult> {
+        let service = self.live_service(server).await?;
+        self.request(
+            "Resource read",
+            service.read_resource(ReadResourceRequestParams {
+                meta: None,
+                uri: uri.to_string(),
+            }),
+        )
+        .await
+    }
+
+    /// Connected servers that advertise prompts.
+    pub async fn prompt_servers(&self) -> Vec<String> {
+        let services = self.services.lock().await;
+        services
+            .iter()
+            .filter(|(_, service)| {
+                !is_dead(service) && capabilities(service).is_some_and(|c| c.prompts.is_some())
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    pub async fn list_prompts(&self, server: &str) -> Result<Vec<Prompt>> {
+        let service = self.live_service(server).await?;
+        self.request("Prompt listing", service.list_all_prompts())
+            .await
+    }
+
+    pub async fn get_prompt(
+        &self,
+        server: &str,
+        name: &str,
+        arguments: serde_json::Map<String, Value>,
+    ) -> Result<GetPromptResult> {
+        let service = self.live_service(server).await?;
+        self.request(
+            "Prompt",
+            service.get_prompt(GetPromptRequestParams {
+                meta: None,
+                name: name.to_string(),
+                arguments: Some(arguments),
+            }),
+        )
+        .await
+    }
+
+    /// Values the server suggests for a prompt argument. Empty when the server does not
+    /// support completions.
+    pub async fn complete_prompt_argument(
+        &self,
+        server: &str,
+        prompt: &str,
+        argument: &str,
+        value: &str,
+    ) -> Result<Vec<String>> {
+        let service = self.live_service(server).await?;
+        if capabilities(&service).is_none_or(|c| c.completions.is_none()) {
+            return Ok(Vec::new());
+        }
+        self.request(
+            "Argument completion",
+            service.complete_prompt_simple(prompt, argument, value),
+        )
+        .await
+    }
+
+    async fn live_service(&self, name: &str) -> Result<Arc<RunningMcpService>> {
+        let service = self
+            .services
+            .lock()
+            .await
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("MCP server '{}' is not connected", name))?;
+        if is_dead(&service) {
+            return Err(anyhow!("MCP server '{}' is reconnecting", name));
+        }
+        Ok(service)
+    }
+
+    /// Await an MCP request, bounded by `MCP_TIMEOUT_SECS`.
+    async fn request<T>(
+        &self,
+        what: &str,
+        request: impl Future<Output = Result<T, ServiceError>>,
+    ) -> Result<T> {
+        tokio::time::timeout(Duration::from_secs(self.timeout_secs), request)
+            .await
+            .map_err(|_| anyhow!("{} timed out after {}s", what, self.timeout_secs))?
+            .with_context(|| format!("{} failed", what))
+    }
+
+    /// Run `check_health` every `interval` until the manager is dropped.
+    pub fn spawn_supervisor(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let manager = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick completes immediately; servers were just connected.
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                manager.check_health().await;
+            }
+        })
+    }
+
+    /// Ping every connected server, drop the ones that are dead or do not answer within
+    /// `MCP_TIMEOUT_SECS`, then reconnect dropped servers whose backoff has elapsed.
+    pub async fn check_health(&self) {
+        let services_snapshot: Vec<(String, Arc<RunningMcpService>)> = {
+            let services = self.services.lock().await;
+            services
+                .iter()
+                .map(|(name, service)| (name.clone(), service.clone()))
+                .collect()
+        };
+        let timeout = Duration::from_secs(self.timeout_secs);
+        let pings = services_snapshot.iter().map(|(_, service)| async move {
+            if is_dead(service) {
+                return Err("Transport closed".to_string());
+            }
+            let started = Instant::now();
+            match tokio::time::timeout(
+                timeout,
+                service.send_request(ClientRequest::PingRequest(PingRequest::default())),
+            )
+            .await
+            {
+                Ok(Ok(_)) => Ok(started.elapsed()),
+                Ok(Err(e)) => Err(format!("Ping failed: {}", e)),
+                Err(_) => Err(format!("Ping timed out after {}s", timeout.as_secs())),
+            }
+        });
+        let results = futures::future::join_all(pings).await;
+
+        for ((name, service), result) in services_snapshot.iter().zip(results) {
+            match result {
+                Ok(latency) => {
+                    if let Some(health) = self.health.lock().unwrap().get_mut(name) {
+                        health.latency = Some(latency);
+                        health.last_checked = Some(Utc::now());
+                    }
+                    if !self.catalog.contains(name) {
+                        self.refresh_server(name, service).await.ok();
                     }
                 }
-                Ok(Err(e)) => {
-                    tracing::warn!(
-                        "MCP client: Failed to discover tools from server '{}': {}",
-                        server_name,
-                        e
-                    );
+                Err(e) => {
+                    warn!("MCP client: Server '{}' is unhealthy: {}", name, e);
+                    if let Some(health) = self.health.lock().unwrap().get_mut(name) {
+                        health.latency = None;
+                        health.last_checked = Some(Utc::now());
+                        health.record_error(e);
+                    }
+                    self.drop_service(name, service).await;
                 }
-                Err(_) => {
-                    tracing::warn!(
-                        "MCP client: Tool discovery timed out for server '{}' after {}s",
-                        server_name,
-                        self.timeout_secs
-                    );
+            }
+        }
+
+        self.reconnect_dropped().await;
+    }
+
+    fn record_capabilities(&self, name: &str, service: &RunningMcpService) {
+        if capabilities(service).is_some_and(|c| c.resources.is_some()) {
+            self.catalog
+                .resource_servers
+                .write()
+                .unwrap()
+                .insert(name.to_string());
+        }
+    }
+
+    fn record_error(&self, name: &str, error: String) {
+        if let Some(health) = self.health.lock().unwrap().get_mut(name) {
+            health.record_error(error);
+        }
+    }
+
+    async fn drop_service(&self, name: &str, service: &Arc<RunningMcpService>) {
+        let mut services = self.services.lock().await;
+        if services.get(name).is_some_and(|s| Arc::ptr_eq(s, service)) {
+            services.remove(name);
+            self.catalog.remove(name);
+            warn!("MCP client: Connection to server '{}' dropped", name);
+        }
+    }
+
+    /// Reconnect configured servers without a connection, with exponential backoff between
+    /// failed attempts. Tools of a reconnecting server are missing until it is back.
+    async fn reconnect_dropped(&self) {
+        let missing: Vec<McpServerConfig> = {
+            let configs = self.configs.lock().await;
+            let services = self.services.lock().await;
+            configs
+                .values()
+                .filter(|c| !services.contains_key(&c.name))
+                .cloned()
+                .collect()
+        };
+        let now = Instant::now();
+        let due: Vec<McpServerConfig> = {
+            let health = self.health.lock().unwrap();
+            missing
+                .into_iter()
+                .filter(|c| {
+                    health
+                        .get(&c.name)
+                        .and_then(|h| h.next_reconnect)
+                        .is_none_or(|at| at <= now)
+                })
+                .collect()
+        };
+
+        let timeout = Duration::from_secs(self.timeout_secs);
+        let attempts = due.iter().map(|config| async move {
+            info!("MCP client: Reconnecting to server '{}'...", config.name);
+            tokio::time::timeout(timeout, open(config, self.handler(&config.name)))
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("timed out after {:?}", timeout)))
+        });
+        let results = futures::future::join_all(attempts).await;
+
+        for (config, result) in due.into_iter().zip(results) {
+            match result {
+                Ok(running) => {
+                    // Removed with `disconnect` while reconnecting.
+                    if !self.configs.lock().await.contains_key(&config.name) {
+                        continue;
+                    }
+                    if let Some(health) = self.health.lock().unwrap().get_mut(&config.name) {
+                        health.restart_count += 1;
+                        health.reconnect_failures = 0;
+                        health.next_reconnect = None;
+                    }
+                    info!("MCP client: Reconnected to server '{}'", config.name);
+                    let running = self
+                        .services
+                        .lock()
+                        .await
+                        .entry(config.name.clone())
+                        .or_insert_with(|| Arc::new(running))
+                        .clone();
+                    self.record_capabilities(&config.name, &running);
+                    self.refresh_server(&config.name, &running).await.ok();
                 }
+                Err(e) => self.schedule_reconnect(&config.name, "Reconnect", &e),
             }
         }
-        all_tools
     }
+
+    /// Record a failed (re)connection attempt and back off exponentially before the next one.
+    fn schedule_reconnect(&self, name: &str, attempt: &str, error: &anyhow::Error) {
+        if let Some(health) = self.health.lock().unwrap().get_mut(name) {
+            let delay = RECONNECT_BASE_DELAY
+                .saturating_mul(2u32.saturating_pow(health.reconnect_failures))
+                .min(RECONNECT_MAX_DELAY);
+            health.reconnect_failures += 1;
+            health.next_reconnect = Some(Instant::now() + delay);
+            health.record_error(format!("{} failed: {:#}", attempt, error));
+            warn!(
+                "MCP client: {} to server '{}' failed (retrying in {:?}): {:#}",
+                attempt, name, delay, error
+            );
+        }
+    }
+}
+
+/// Whether the session ended, e.g. because a child process exited or a stream closed.
+fn is_dead(service: &RunningMcpService) -> bool {
+    service.is_closed() || service.is_transport_closed()
+}
+
+fn capabilities(service: &RunningMcpService) -> Option<&ServerCapabilities> {
+    service.peer_info().map(|info| &info.capabilities)
+}
+
+/// Start an MCP session with a server over its configured transport.
+async fn open(config: &McpServerConfig, handler: McpClientHandler) -> Result<RunningMcpService> {
+    info!(
+        "MCP client: Connecting to server '{}' via {:?}...",
+        config.name, config.transport
+    );
+    let running = match config.transport {
+        McpTransport::Stdio => {
+            let mut cmd = Command::new(config.command.as_ref().ok_or_else(|| {
+                error!(
+                    "MCP client: Command not specified for stdio transport on server '{}'",
+                    config.name
+                );
+                anyhow!("Command not specified for stdio transport")
+            })?);
+            if let Some(args) = &config.args {
+                cmd.args(args);
+            }
+            if let Some(env) = &config.env {
+                cmd.envs(env);
+            }
+
+            let transport = TokioChildProcess::new(cmd).map_err(|e| {
+                error!(
+                    "MCP client: Failed to start child process for server '{}': {}",
+                    config.name, e
+                );
+                e
+            })?;
+            handler.serve(transport).await
+        }
+        McpTransport::Sse => {
+            let url = remote_url(config)?;
+            let transport =
+                super::sse::connect(http_client(config)?, url, config.bearer_token.clone())
+                    .await
+                    .with_context(|| {
+                        format!("Failed to open SSE transport for server '{}'", config.name)
+                    })?;
+            handler.serve(transport).await
+        }
+        McpTransport::StreamableHttp => {
+            let mut transport_config =
+                StreamableHttpClientTransportConfig::with_uri(remote_url(config)?);
+            if let Some(token) = &config.bearer_token {
+                transport_config = transport_config.auth_header(token.clone());
+            }
+            let transport =
+                StreamableHttpClientTransport::with_client(http_client(config)?, transport_config);
+            handler.serve(transport).await
+        }
+    }
+    .map_err(|e| {
+        error!(
+            "MCP client: Failed to serve transport for server '{}': {}",
+            config.name, e
+        );
+        e
+    })?;
+
+    info!(
+        "MCP client: Successfully connected to server '{}'",
+        config.name
+    );
+    Ok(running)
+}
+
+fn remote_url(config: &McpServerConfig) -> Result<&str> {
+    config.url.as_deref().ok_or_else(|| {
+        anyhow!(
+            "URL not specified for {:?} transport on server '{}'",
+            config.transport,
+            config.name
+        )
+    })
+}
+
+/// HTTP client sending the server's configured `headers` with every request.
+fn http_client(config: &McpServerConfig) -> Result<reqwest::Client> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    for (name, value) in config.headers.iter().flatten() {
+        headers.insert(
+            reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid header name '{}'", name))?,
+            reqwest::header::HeaderValue::from_str(value)
+                .with_context(|| format!("Invalid value for header '{}'", name))?,
+        );
+    }
+    Ok(reqwest::Client::builder()
+        .default_headers(headers)
+        .build()?)
 }
 
 pub struct McpToolWrapper {
     server_name: String,
-    service: Arc<RunningService<RoleClient, ()>>,
+    peer: Peer<RoleClient>,
+    /// Namespaced name offered to the model.
     name: String,
+    /// Name of the tool on its server.
+    tool_name: String,
     description: String,
     input_schema: Value,
     timeout_secs: u64,
@@ -217,40 +810,382 @@ impl Tool for McpToolWrapper {
         self.requires_confirmation
     }
 
-    async fn execute(&self, params: Value) -> Result<Value> {
+    fn server(&self) -> Option<&str> {
+        Some(&self.server_name)
+    }
+
+    async fn execute(&self, ctx: &ToolContext, params: Value) -> Result<Value> {
         use tokio::time::{timeout, Duration};
 
         debug!(
-            "MCP tool client: Executing '{}' on server '{}'...",
-            self.name, self.server_name
+            "MCP tool client: Executing '{}' on server '{}' (guild={:?}, user={:?})...",
+            self.tool_name, self.server_name, ctx.guild_id, ctx.user_id
         );
 
         let result = timeout(
             Duration::from_secs(self.timeout_secs),
-            self.service.call_tool(CallToolRequestParams {
-                name: self.name.clone().into(),
+            self.peer.call_tool(CallToolRequestParams {
+                name: self.tool_name.clone().into(),
                 arguments: params.as_object().cloned(),
                 meta: Default::default(),
                 task: None,
             }),
         )
         .await
-        .map_err(|_| {
+        .map_err(|elapsed| {
             error!(
                 "MCP tool '{}' timed out after {}s",
                 self.name, self.timeout_secs
             );
-            anyhow!(
+            anyhow::Error::new(elapsed).context(format!(
                 "MCP tool '{}' timed out after {}s",
-                self.name,
-                self.timeout_secs
-            )
+                self.name, self.timeout_secs
+            ))
         })??;
 
         info!(
             "MCP tool '{}' on server '{}' executed successfully",
-            self.name, self.server_name
+            self.tool_name, self.server_name
         );
         Ok(serde_json::to_value(result)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::mock_server::MockMcpServer;
+
+    fn server_config(toml_src: &str) -> McpServerConfig {
+        toml::from_str(toml_src).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_streamable_http_sends_headers_and_calls_tools() {
+        let server = MockMcpServer::start("secret").await;
+        let config = crate::context::tests::mock_config();
+        let manager = McpClientManager::new(&config).unwrap();
+
+        let remote = server_config(&format!(
+            r#"
+            name = "remote"
+            transport = "http"
+            url = "{}/mcp"
+            bearer_token = "secret"
+            headers = {{ X-Api-Key = "key-1" }}
+            "#,
+            server.base_url()
+        ));
+        assert!(matches!(remote.transport, McpTransport::StreamableHttp));
+        let wrong_token = McpServerConfig {
+            name: "wrong".to_string(),
+            bearer_token: Some("nope".to_string()),
+            ..remote.clone()
+        };
+        assert!(manager.connect(&wrong_token).await.is_err());
+        manager.connect(&remote).await.unwrap();
+
+        let tools = manager.list_all_tools().await;
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name(), "remote__echo");
+        assert_eq!(tools[0].server(), Some("remote"));
+
+        let tool_ctx = ToolContext::for_tests(config.clone(), Arc::new(manager));
+        let result = tools[0]
+            .execute(&tool_ctx, serde_json::json!({ "message": "hello" }))
+            .await
+            .unwrap();
+        assert_eq!(result["content"][0]["text"], "hello");
+
+        let headers = server.headers();
+        let authorized: Vec<_> = headers
+            .iter()
+            .filter(|h| h.get("authorization").map(String::as_str) == Some("Bearer secret"))
+            .collect();
+        assert!(authorized.len() >= 3);
+        assert!(authorized
+            .iter()
+            .all(|h| h.get("x-api-key").map(String::as_str) == Some("key-1")));
+    }
+
+    #[tokio::test]
+    async fn test_supervisor_restarts_dropped_sse_server_and_records_health() {
+        let server = MockMcpServer::start("secret").await;
+        let mut config = crate::context::tests::mock_config();
+        config.mcp_timeout_secs = 2;
+        let manager = McpClientManager::new(&config).unwrap();
+        let legacy = server_config(&format!(
+            r#"
+            name = "legacy"
+            transport = "sse"
+            url = "{}/sse"
+            bearer_token = "secret"
+            "#,
+            server.base_url()
+        ));
+        manager.connect(&legacy).await.unwrap();
+        assert_eq!(manager.list_all_tools().await.len(), 1);
+        manager.check_health().await;
+        assert!(manager.server_health()["legacy"].latency.is_some());
+
+        server.drop_sse_streams();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while server.sse_connections() < 2 {
+            assert!(Instant::now() < deadline, "server was not reconnected");
+            manager.check_health().await;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(manager.list_all_tools().await.len(), 1);
+        assert_eq!(
+            manager.list_active_servers().await,
+            vec!["legacy".to_string()]
+        );
+
+        manager.check_health().await;
+        let health = manager.server_health()["legacy"].clone();
+        assert_eq!(health.restart_count, 1);
+        assert!(health.latency.is_some());
+        assert!(health.last_error.is_some() && health.last_error_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_server_down_at_connect_is_retried() {
+        let server = MockMcpServer::start("not-yet").await;
+        let mut config = crate::context::tests::mock_config();
+        config.mcp_timeout_secs = 2;
+        let manager = McpClientManager::new(&config).unwrap();
+        let remote = server_config(&format!(
+            r#"
+            name = "remote"
+            transport = "http"
+            url = "{}/mcp"
+            bearer_token = "secret"
+            "#,
+            server.base_url()
+        ));
+        assert!(manager.connect(&remote).await.is_err());
+        let health = manager.server_health()["remote"].clone();
+        assert_eq!(health.reconnect_failures, 1);
+        assert!(health.last_error.unwrap().starts_with("Connect failed"));
+
+        server.set_token("secret");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while manager.list_active_servers().await.is_empty() {
+            assert!(Instant::now() < deadline, "server was not retried");
+            manager.check_health().await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(manager.list_all_tools().await.len(), 1);
+        assert_eq!(manager.server_health()["remote"].reconnect_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_tool_catalog_is_cached_and_refreshed_on_list_changed() {
+        let server = MockMcpServer::start("secret").await;
+        let config = crate::context::tests::mock_config();
+        let manager = McpClientManager::new(&config).unwrap();
+        let legacy = server_config(&format!(
+            r#"
+            name = "legacy"
+            transport = "sse"
+            url = "{}/sse"
+            bearer_token = "secret"
+            "#,
+            server.base_url()
+        ));
+        manager.connect(&legacy).await.unwrap();
+
+        // Agent iterations read the listing fetched on connect.
+        for _ in 0..5 {
+            assert_eq!(manager.list_all_tools().await.len(), 1);
+        }
+        assert_eq!(server.tool_list_requests(), 1);
+
+        server.set_tools(&["echo", "shout"]);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while manager.list_all_tools().await.len() != 2 {
+            assert!(Instant::now() < deadline, "catalog was not refreshed");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(server.tool_list_requests(), 2);
+
+        server.set_tools(&["echo"]);
+        let refreshed = manager.refresh_tools(Some("legacy")).await;
+        assert_eq!(refreshed.len(), 1);
+        assert_eq!(refreshed[0].0, "legacy");
+        assert!(refreshed[0].1.is_ok());
+        assert!(manager.refresh_tools(Some("missing")).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tool_name_conflicts_are_skipped() {
+        let server = MockMcpServer::start("secret").await;
+        server.set_tools(&["echo", "a.b", "a_b"]);
+        let config = crate::context::tests::mock_config();
+        let manager = McpClientManager::new(&config)
+            .unwrap()
+            .with_reserved_tool_names(["remote__echo".to_string()]);
+        let remote_config = |name: &str, transport: &str, path: &str| {
+            server_config(&format!(
+                r#"
+                name = "{}"
+                transport = "{}"
+                url = "{}{}"
+                bearer_token = "secret"
+                "#,
+                name,
+                transport,
+                server.base_url(),
+                path
+            ))
+        };
+
+        // `echo` shadows a built-in; `a_b` sanitizes to the same name as `a.b`.
+        manager
+            .connect(&remote_config("remote", "http", "/mcp"))
+            .await
+            .unwrap();
+        // Server names that sanitize to the same prefix: the first server keeps its tools.
+        manager
+            .connect(&remote_config("my.server", "http", "/mcp"))
+            .await
+            .unwrap();
+        manager
+            .connect(&remote_config("my_server", "sse", "/sse"))
+            .await
+            .unwrap();
+
+        let mut tools: Vec<(String, String)> = manager
+            .list_all_tools()
+            .await
+            .iter()
+            .map(|t| (t.name().to_string(), t.server().unwrap().to_string()))
+            .collect();
+        tools.sort();
+        assert_eq!(
+            tools,
+            vec![
+                ("my_server__a_b".to_string(), "my.server".to_string()),
+                ("my_server__echo".to_string(), "my.server".to_string()),
+                ("remote__a_b".to_string(), "remote".to_string()),
+            ]
+        );
+        let refreshed = manager.refresh_tools(Some("my_server")).await;
+        assert_eq!(refreshed[0].1.as_ref().unwrap(), &0);
+    }
+
+    #[tokio::test]
+    async fn test_resources_and_prompts() {
+        use crate::tools::builtin::mcp_resource::ReadMcpResourceTool;
+
+        let server = MockMcpServer::start("secret").await;
+        let config = crate::context::tests::mock_config();
+        let manager = Arc::new(McpClientManager::new(&config).unwrap());
+        assert!(manager.resource_servers().is_empty());
+        let remote = server_config(&format!(
+            r#"
+            name = "remote"
+            transport = "http"
+            url = "{}/mcp"
+            bearer_token = "secret"
+            "#,
+            server.base_url()
+        ));
+        manager.connect(&remote).await.unwrap();
+        assert_eq!(manager.resource_servers(), vec!["remote".to_string()]);
+
+        let tool_ctx = ToolContext::for_tests(config.clone(), manager.clone());
+        let tool = ReadMcpResourceTool;
+        assert!(tool.is_available(&tool_ctx));
+        let listing = tool
+            .execute(&tool_ctx, serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(listing["resources"][0]["server"], "remote");
+        assert_eq!(listing["resources"][0]["uri"], "mem://notes");
+        let read = tool
+            .execute(
+                &tool_ctx,
+                serde_json::json!({ "server": "remote", "uri": "mem://notes" }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(read["contents"][0]["text"], "Remember the milk");
+        assert!(manager
+            .read_resource("remote", "mem://other")
+            .await
+            .is_err());
+
+        assert_eq!(manager.prompt_servers().await, vec!["remote".to_string()]);
+        let prompts = manager.list_prompts("remote").await.unwrap();
+        assert_eq!(prompts[0].name, "review");
+        let mut arguments = serde_json::Map::new();
+        arguments.insert("language".to_string(), "rust".into());
+        let prompt = manager
+            .get_prompt("remote", "review", arguments)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&prompt.messages[0].content).unwrap()["text"],
+            "Review this rust code"
+        );
+        assert_eq!(
+            manager
+                .complete_prompt_argument("remote", "review", "language", "ru")
+                .await
+                .unwrap(),
+            vec!["rust".to_string()]
+        );
+
+        manager.disconnect("remote").await.unwrap();
+        assert!(manager.resource_servers().is_empty());
+        assert!(manager.list_prompts("remote").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_server_deny_policy_blocks_resources() {
+        use crate::tools::builtin::mcp_resource::ReadMcpResourceTool;
+        use crate::tools::policy::{
+            PolicyAction, PolicyScope, PolicyTarget, ToolPolicies, ToolPolicy,
+        };
+
+        let server = MockMcpServer::start("secret").await;
+        let config = crate::context::tests::mock_config();
+        let manager = Arc::new(McpClientManager::new(&config).unwrap());
+        let remote = server_config(&format!(
+            r#"
+            name = "remote"
+            transport = "http"
+            url = "{}/mcp"
+            bearer_token = "secret"
+            "#,
+            server.base_url()
+        ));
+        manager.connect(&remote).await.unwrap();
+
+        let deny = ToolPolicy {
+            guild_id: "1".to_string(),
+            scope: PolicyScope::Guild,
+            scope_id: "1".to_string(),
+            target: PolicyTarget::Server,
+            target_name: "remote".to_string(),
+            action: PolicyAction::Deny,
+        };
+        let tool_ctx = ToolContext::for_tests(config.clone(), manager.clone())
+            .with_policies(ToolPolicies::new(vec![deny], &[], &[]));
+        let tool = ReadMcpResourceTool;
+        assert!(!tool.is_available(&tool_ctx));
+        let listing = tool
+            .execute(&tool_ctx, serde_json::json!({}))
+            .await
+            .unwrap();
+        assert!(listing.get("resources").is_none());
+        assert!(tool
+            .execute(
+                &tool_ctx,
+                serde_json::json!({ "server": "remote", "uri": "mem://notes" }),
+            )
+            .await
+            .is_err());
+    }
+}
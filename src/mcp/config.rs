@@ -6,10 +6,14 @@ use std::collections::HashMap;
 pub enum McpTransport {
     #[default]
     Stdio,
+    /// Legacy HTTP+SSE transport: an event stream plus a POST endpoint it announces.
     Sse,
+    /// Streamable HTTP transport (MCP 2025-03-26): JSON-RPC over POST to a single endpoint.
+    #[serde(rename = "streamable_http")]
+    StreamableHttp,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Clone, Serialize)]
 pub struct McpServerConfig {
     pub name: String,
     pub transport: McpTransport,
@@ -17,6 +21,31 @@ pub struct McpServerConfig {
     pub args: Option<Vec<String>>,
     pub url: Option<String>,
     pub env: Option<HashMap<String, String>>,
+    /// Extra HTTP headers sent to remote (`sse`, `streamable_http`) servers.
+    pub headers: Option<HashMap<String, String>>,
+    /// Sent as `Authorization: Bearer <token>` to remote servers.
+    pub bearer_token: Option<String>,
+}
+
+impl std::fmt::Debug for McpServerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("McpServerConfig")
+            .field("name", &self.name)
+            .field("transport", &self.transport)
+            .field("command", &self.command)
+            .field("args", &self.args)
+            .field("url", &self.url)
+            .field("env", &self.env)
+            .field(
+                "headers",
+                &self.headers.as_ref().map(|h| h.keys().collect::<Vec<_>>()),
+            )
+            .field(
+                "bearer_token",
+                &self.bearer_token.as_ref().map(|_| "[REDACTED]"),
+            )
+            .finish()
+    }
 }
 
 impl<'de> Deserialize<'de> for McpServerConfig {
@@ -33,6 +62,8 @@ impl<'de> Deserialize<'de> for McpServerConfig {
             args: Option<Vec<String>>,
             url: Option<String>,
             env: Option<HashMap<String, String>>,
+            headers: Option<HashMap<String, String>>,
+            bearer_token: Option<String>,
         }
 
         let raw = RawMcpServerConfig::deserialize(deserializer)?;
@@ -41,10 +72,13 @@ impl<'de> Deserialize<'de> for McpServerConfig {
         let transport = match transport_raw.to_lowercase().as_str() {
             "stdio" | "child_process" | "child-process" => McpTransport::Stdio,
             "sse" => McpTransport::Sse,
+            "streamable_http" | "streamable-http" | "streamablehttp" => {
+                McpTransport::StreamableHttp
+            }
             // Back-compat: many MCP examples call this "http" but use stdio-based servers.
             "http" | "https" => {
                 if raw.url.is_some() {
-                    McpTransport::Sse
+                    McpTransport::StreamableHttp
                 } else {
                     McpTransport::Stdio
                 }
@@ -59,6 +93,8 @@ impl<'de> Deserialize<'de> for McpServerConfig {
             args: raw.args,
             url: raw.url,
             env: raw.env,
+            headers: raw.headers,
+            bearer_token: raw.bearer_token,
         })
     }
 }
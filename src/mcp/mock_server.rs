@@ -0,0 +1,335 @@
+//! Minimal MCP server over HTTP for exercising the remote transports in tests.
+//!
+//! Serves `echo`-style tools, a `mem://notes` resource and a `review` prompt over both transports: Streamable HTTP (`POST /mcp`, answered with a
+//! JSON body) and legacy HTTP+SSE (`GET /sse` streams responses to messages POSTed to the
+//! endpoint it announces). Every request must carry the configured bearer token.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+#[derive(Default)]
+struct State {
+    token: String,
+    /// Open `GET /sse` streams by session id.
+    sse_sessions: HashMap<u64, mpsc::UnboundedSender<Value>>,
+    next_session: u64,
+    sse_connections: usize,
+    /// Request headers (lowercased names) of every request received.
+    headers: Vec<HashMap<String, String>>,
+    tools: Vec<String>,
+    tool_list_requests: usize,
+}
+
+pub struct MockMcpServer {
+    base_url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockMcpServer {
+    pub async fn start(token: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State {
+            token: token.to_string(),
+            tools: vec!["echo".to_string()],
+            ..State::default()
+        }));
+
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let state = state_clone.clone();
+                tokio::spawn(async move {
+                    let _ = handle_connection(socket, state).await;
+                });
+            }
+        });
+
+        Self {
+            base_url: format!("http://{}", addr),
+            state,
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Accept a different bearer token from now on, e.g. to let a rejected client in.
+    pub fn set_token(&self, token: &str) {
+        self.state.lock().unwrap().token = token.to_string();
+    }
+
+    /// Close every open SSE stream, as a server restart would.
+    pub fn drop_sse_streams(&self) {
+        self.state.lock().unwrap().sse_sessions.clear();
+    }
+
+    /// Number of `GET /sse` streams opened so far.
+    pub fn sse_connections(&self) -> usize {
+        self.state.lock().unwrap().sse_connections
+    }
+
+    /// Replace the served tools and notify open SSE streams with `tools/list_changed`.
+    pub fn set_tools(&self, names: &[&str]) {
+        let mut state = self.state.lock().unwrap();
+        state.tools = names.iter().map(|n| n.to_string()).collect();
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/tools/list_changed"
+        });
+        for sender in state.sse_sessions.values() {
+            let _ = sender.send(notification.clone());
+        }
+    }
+
+    /// Number of `tools/list` requests received so far.
+    pub fn tool_list_requests(&self) -> usize {
+        self.state.lock().unwrap().tool_list_requests
+    }
+
+    /// Headers of all requests received so far.
+    pub fn headers(&self) -> Vec<HashMap<String, String>> {
+        self.state.lock().unwrap().headers.clone()
+    }
+}
+
+async fn handle_connection(mut socket: TcpStream, state: Arc<Mutex<State>>) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.lines();
+    let request_line = lines.next().unwrap_or_default().to_string();
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+    let content_length = headers
+        .get("content-length")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    while buf.len() < header_end + content_length {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let body = serde_json::from_slice::<Value>(&buf[header_end..]).unwrap_or(Value::Null);
+
+    let authorized = {
+        let mut state = state.lock().unwrap();
+        state.headers.push(headers.clone());
+        headers.get("authorization") == Some(&format!("Bearer {}", state.token))
+    };
+    if !authorized {
+        return respond(&mut socket, "401 Unauthorized", &[], "").await;
+    }
+
+    match (method.as_str(), path.as_str()) {
+        ("POST", "/mcp") => match handle_message(&body, &state) {
+            Some(response) => {
+                respond(
+                    &mut socket,
+                    "200 OK",
+                    &[
+                        ("Content-Type", "application/json"),
+                        ("Mcp-Session-Id", "mock-session"),
+                    ],
+                    &response.to_string(),
+                )
+                .await
+            }
+            None => respond(&mut socket, "202 Accepted", &[], "").await,
+        },
+        ("GET", "/sse") => {
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let session = {
+                let mut state = state.lock().unwrap();
+                state.next_session += 1;
+                state.sse_connections += 1;
+                let session = state.next_session;
+                state.sse_sessions.insert(session, tx);
+                session
+            };
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+                )
+                .await?;
+            socket
+                .write_all(
+                    format!("event: endpoint\ndata: /messages?session={}\n\n", session).as_bytes(),
+                )
+                .await?;
+            socket.flush().await?;
+            while let Some(message) = rx.recv().await {
+                socket
+                    .write_all(format!("event: message\ndata: {}\n\n", message).as_bytes())
+                    .await?;
+                socket.flush().await?;
+            }
+            socket.shutdown().await
+        }
+        ("POST", p) if p.starts_with("/messages?session=") => {
+            let session = p
+                .trim_start_matches("/messages?session=")
+                .parse::<u64>()
+                .unwrap_or(0);
+            let sender = state.lock().unwrap().sse_sessions.get(&session).cloned();
+            let Some(sender) = sender else {
+                return respond(&mut socket, "404 Not Found", &[], "").await;
+            };
+            if let Some(response) = handle_message(&body, &state) {
+                let _ = sender.send(response);
+            }
+            respond(&mut socket, "202 Accepted", &[], "").await
+        }
+        ("GET", "/mcp") => respond(&mut socket, "405 Method Not Allowed", &[], "").await,
+        ("DELETE", "/mcp") => respond(&mut socket, "200 OK", &[], "").await,
+        _ => respond(&mut socket, "404 Not Found", &[], "").await,
+    }
+}
+
+async fn respond(
+    socket: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\n", status);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    ));
+    socket.write_all(head.as_bytes()).await?;
+    socket.write_all(body.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+/// The JSON-RPC response to `message`, or `None` for notifications.
+fn handle_message(message: &Value, state: &Mutex<State>) -> Option<Value> {
+    let id = message.get("id")?.clone();
+    let result = match message["method"].as_str().unwrap_or_default() {
+        "initialize" => json!({
+            "protocolVersion": message["params"]["protocolVersion"],
+            "capabilities": { "tools": {}, "resources": {}, "prompts": {}, "completions": {} },
+            "serverInfo": { "name": "mock-mcp", "version": "0.1.0" }
+        }),
+        "tools/list" => {
+            let mut state = state.lock().unwrap();
+            state.tool_list_requests += 1;
+            let tools: Vec<Value> = state
+                .tools
+                .iter()
+                .map(|name| {
+                    json!({
+                        "name": name,
+                        "description": "Echo a message back",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": { "message": { "type": "string" } },
+                            "required": ["message"]
+                        }
+                    })
+                })
+                .collect();
+            json!({ "tools": tools })
+        }
+        "tools/call" => {
+            let name = message["params"]["name"].as_str().unwrap_or_default();
+            if !state.lock().unwrap().tools.iter().any(|t| t == name) {
+                return Some(json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32602, "message": format!("Unknown tool: {}", name) }
+                }));
+            }
+            json!({
+            "content": [{
+                "type": "text",
+                "text": message["params"]["arguments"]["message"]
+            }],
+            "isError": false
+            })
+        }
+        "resources/list" => json!({
+            "resources": [{ "uri": "mem://notes", "name": "notes", "mimeType": "text/plain" }]
+        }),
+        "resources/read" => {
+            let uri = message["params"]["uri"].as_str().unwrap_or_default();
+            if uri != "mem://notes" {
+                return Some(json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32002, "message": format!("Resource not found: {}", uri) }
+                }));
+            }
+            json!({
+                "contents": [{ "uri": uri, "mimeType": "text/plain", "text": "Remember the milk" }]
+            })
+        }
+        "prompts/list" => json!({
+            "prompts": [{
+                "name": "review",
+                "description": "Review code",
+                "arguments": [
+                    { "name": "language", "required": true },
+                    { "name": "style" }
+                ]
+            }]
+        }),
+        "prompts/get" => json!({
+            "messages": [{
+                "role": "user",
+                "content": {
+                    "type": "text",
+                    "text": format!(
+                        "Review this {} code",
+                        message["params"]["arguments"]["language"].as_str().unwrap_or("?")
+                    )
+                }
+            }]
+        }),
+        "completion/complete" => {
+            let typed = message["params"]["argument"]["value"]
+                .as_str()
+                .unwrap_or_default();
+            let values: Vec<&str> = ["rust", "python"]
+                .into_iter()
+                .filter(|v| v.starts_with(typed))
+                .collect();
+            json!({ "completion": { "values": values } })
+        }
+        "ping" => json!({}),
+        method => {
+            return Some(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": format!("Method not found: {}", method) }
+            }))
+        }
+    };
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+}
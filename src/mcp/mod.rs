@@ -1,2 +1,6 @@
 pub mod client;
 pub mod config;
+#[cfg(test)]
+pub(crate) mod mock_server;
+pub mod naming;
+pub mod sse;
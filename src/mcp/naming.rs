@@ -0,0 +1,81 @@
+//! Names under which MCP tools are offered to the model.
+//!
+//! Each tool is exposed as `server__tool`, so two servers can both provide `search` and no
+//! server can shadow a built-in tool. Function names must match `^[a-zA-Z0-9_-]{1,64}$`, so
+//! other characters become `_` and long names are shortened with a hash suffix that keeps them
+//! distinct.
+
+/// Separator between the server and tool parts of a namespaced name.
+pub const SEPARATOR: &str = "__";
+
+/// Longest function name the chat completions API accepts.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Hex digits of the hash appended to shortened names.
+const HASH_LEN: usize = 8;
+
+/// The name a server's tool is exposed under.
+pub fn namespaced_tool_name(server: &str, tool: &str) -> String {
+    let name = format!("{}{}{}", sanitize(server), SEPARATOR, sanitize(tool));
+    if name.len() <= MAX_NAME_LEN {
+        return name;
+    }
+    // Hash the original parts: two long names sharing a prefix must stay distinct.
+    let hash = fnv1a(format!("{}{}{}", server, SEPARATOR, tool).as_bytes());
+    format!(
+        "{}_{:0width$x}",
+        &name[..MAX_NAME_LEN - HASH_LEN - 1],
+        hash,
+        width = HASH_LEN
+    )
+}
+
+/// Replace characters outside `[a-zA-Z0-9_-]` with `_`.
+fn sanitize(part: &str) -> String {
+    let sanitized: String = part
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.is_empty() {
+        "_".to_string()
+    } else {
+        sanitized
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &b| {
+        (hash ^ u32::from(b)).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespaced_tool_name_sanitizes_and_limits_length() {
+        assert_eq!(namespaced_tool_name("github", "search"), "github__search");
+        assert_eq!(
+            namespaced_tool_name("my server", "files.read/v2"),
+            "my_server__files_read_v2"
+        );
+        assert_eq!(namespaced_tool_name("", "ping"), "___ping");
+
+        let long_a = namespaced_tool_name("server", &format!("{}_a", "x".repeat(80)));
+        let long_b = namespaced_tool_name("server", &format!("{}_b", "x".repeat(80)));
+        assert_eq!(long_a.len(), MAX_NAME_LEN);
+        assert_eq!(long_b.len(), MAX_NAME_LEN);
+        assert_ne!(long_a, long_b);
+        assert!(long_a.starts_with("server__xxx"));
+        assert!(long_a
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'));
+    }
+}
@@ -0,0 +1,151 @@
+//! Client side of the legacy MCP HTTP+SSE transport (protocol 2024-11-05).
+//!
+//! The client opens an event stream; the server's first `endpoint` event names the URL that
+//! client messages are POSTed to, and every server message arrives as a `message` event on the
+//! stream. rmcp only ships the newer Streamable HTTP client, so this one is kept small: when the
+//! stream drops the transport closes, and `McpClientManager` reconnects with a fresh session.
+
+use anyhow::{anyhow, Context as AnyhowContext, Result};
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use reqwest::header::ACCEPT;
+use reqwest::Url;
+use rmcp::model::{ClientJsonRpcMessage, ServerJsonRpcMessage};
+use sse_stream::SseStream;
+use tracing::{debug, warn};
+
+/// Buffered messages in each direction.
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Open the event stream at `url` and wait for the server to announce its message endpoint.
+///
+/// Returns a (sink, stream) pair that rmcp accepts as a transport.
+pub async fn connect(
+    client: reqwest::Client,
+    url: &str,
+    bearer_token: Option<String>,
+) -> Result<(
+    mpsc::Sender<ClientJsonRpcMessage>,
+    mpsc::Receiver<ServerJsonRpcMessage>,
+)> {
+    let base = Url::parse(url).with_context(|| format!("Invalid SSE URL '{}'", url))?;
+    let mut request = client.get(base.clone()).header(ACCEPT, "text/event-stream");
+    if let Some(token) = &bearer_token {
+        request = request.bearer_auth(token);
+    }
+    let response = request
+        .send()
+        .await
+        .context("Failed to open the SSE stream")?
+        .error_for_status()
+        .context("SSE stream request failed")?;
+    let mut events = SseStream::from_bytes_stream(response.bytes_stream()).boxed();
+
+    let endpoint = loop {
+        match events.next().await {
+            Some(Ok(event)) if event.event.as_deref() == Some("endpoint") => {
+                break resolve_endpoint(&base, &event.data.unwrap_or_default())?;
+            }
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => {
+                return Err(anyhow!(
+                    "SSE stream failed before the endpoint event: {}",
+                    e
+                ))
+            }
+            None => return Err(anyhow!("SSE stream closed before the endpoint event")),
+        }
+    };
+    debug!("MCP SSE: {} posts messages to {}", base, endpoint);
+
+    let (outgoing_tx, mut outgoing_rx) = mpsc::channel::<ClientJsonRpcMessage>(CHANNEL_CAPACITY);
+    let (mut incoming_tx, incoming_rx) = mpsc::channel::<ServerJsonRpcMessage>(CHANNEL_CAPACITY);
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                message = outgoing_rx.next() => {
+                    // The client side was dropped.
+                    let Some(message) = message else { break };
+                    // Sent in order: `initialized` must not overtake `initialize`.
+                    let mut request = client.post(endpoint.clone()).json(&message);
+                    if let Some(token) = &bearer_token {
+                        request = request.bearer_auth(token);
+                    }
+                    match request.send().await.and_then(|r| r.error_for_status()) {
+                        Ok(_) => {}
+                        Err(e) => warn!("MCP SSE: failed to post a message to {}: {}", endpoint, e),
+                    }
+                }
+                event = events.next() => match event {
+                    Some(Ok(event)) => {
+                        if !matches!(event.event.as_deref(), None | Some("message")) {
+                            continue;
+                        }
+                        let Some(data) = event.data else { continue };
+                        match serde_json::from_str::<ServerJsonRpcMessage>(&data) {
+                            Ok(message) => {
+                                if incoming_tx.send(message).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e) => warn!("MCP SSE: ignoring invalid message from {}: {}", base, e),
+                        }
+                    }
+                    Some(Err(e)) => {
+                        warn!("MCP SSE: stream from {} failed: {}", base, e);
+                        break;
+                    }
+                    None => {
+                        warn!("MCP SSE: stream from {} closed", base);
+                        break;
+                    }
+                },
+            }
+        }
+        // Dropping both channels closes the transport, which ends the rmcp service.
+    });
+
+    Ok((outgoing_tx, incoming_rx))
+}
+
+/// The message endpoint announced by an `endpoint` event, relative to the stream URL.
+///
+/// Every POST carries the bearer token, so the endpoint must share the stream's scheme, host
+/// and port; otherwise a server or proxy could have the credentials sent elsewhere.
+fn resolve_endpoint(base: &Url, data: &str) -> Result<Url> {
+    let endpoint = base
+        .join(data.trim())
+        .with_context(|| format!("Invalid endpoint event '{}'", data))?;
+    if endpoint.origin() != base.origin() {
+        return Err(anyhow!(
+            "Refusing SSE endpoint '{}': it is not on the same origin as '{}'",
+            endpoint,
+            base
+        ));
+    }
+    Ok(endpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_endpoint_requires_same_origin() {
+        let base = Url::parse("https://mcp.example.com:8443/sse").unwrap();
+        assert_eq!(
+            resolve_endpoint(&base, " /messages?session=1 ")
+                .unwrap()
+                .as_str(),
+            "https://mcp.example.com:8443/messages?session=1"
+        );
+        for endpoint in [
+            "https://evil.example.com:8443/messages",
+            "http://mcp.example.com:8443/messages",
+            "https://mcp.example.com/messages",
+            "//evil.example.com/messages",
+        ] {
+            assert!(resolve_endpoint(&base, endpoint).is_err(), "{}", endpoint);
+        }
+    }
+}
@@ -1,9 +1,11 @@
-use crate::commands::chat::send_embed_reply;
-use crate::context::ConversationContext;
+use crate::commands::chat::{run_agent_with_reply, StreamingEmbedReply};
+use crate::context::{ConversationContext, PromptSections};
 use crate::discord_text::{extract_message_text, strip_bot_mentions};
 use crate::llm::confirm::ToolConfirmationContext;
+use crate::llm::vision;
 use crate::services::user_memory::UserMemoryService;
 use crate::system_prompt;
+use crate::tools::ToolContext;
 use crate::{Data, Error};
 use async_openai::types::{
     ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
@@ -24,7 +26,7 @@ pub async fn handle_mention(
     );
 
     let prompt = strip_bot_mentions(&new_message.content, data.bot_id);
-    if prompt.trim().is_empty() {
+    if prompt.trim().is_empty() && new_message.attachments.is_empty() {
         // Avoid noisy replies when someone only pings the bot.
         return Ok(());
     }
@@ -51,18 +53,20 @@ pub async fn handle_mention(
     let user_id = new_message.author.id.get();
 
     // Build messages with configurable system prompt
-    let mut messages: Vec<ChatCompletionRequestMessage> =
-        vec![ChatCompletionRequestSystemMessageArgs::default()
+    let mut sections = PromptSections::default();
+    sections.instructions.push(
+        ChatCompletionRequestSystemMessageArgs::default()
             .content(system_prompt)
             .build()?
-            .into()];
+            .into(),
+    );
 
     // Inject current date/time context
     if let Ok(datetime_msg) = ChatCompletionRequestSystemMessageArgs::default()
         .content(system_prompt::build_datetime_system_message())
         .build()
     {
-        messages.push(datetime_msg.into());
+        sections.instructions.push(datetime_msg.into());
     }
 
     if skip_memory {
@@ -70,7 +74,7 @@ pub async fn handle_mention(
             .content("Temporary no-memory request: do not use or update user memory. Do not call get_user_memory.")
             .build()
         {
-            messages.push(msg.into());
+            sections.instructions.push(msg.into());
         }
     }
 
@@ -82,7 +86,7 @@ pub async fn handle_mention(
         ))
         .build()
     {
-        messages.push(meta_msg.into());
+        sections.instructions.push(meta_msg.into());
     }
     let memory_record = if skip_memory {
         None
@@ -98,7 +102,7 @@ pub async fn handle_mention(
             )
             .build()
         {
-            messages.push(help_msg.into());
+            sections.instructions.push(help_msg.into());
         }
     }
 
@@ -109,7 +113,7 @@ pub async fn handle_mention(
                 .content(snippet)
                 .build()
             {
-                messages.push(msg.into());
+                sections.user_memory.push(msg.into());
             }
         }
     }
@@ -124,7 +128,7 @@ pub async fn handle_mention(
         Some(data.bot_id),
         Some(new_message.id.get()),
     );
-    messages.extend(context_messages.await);
+    sections.add_channel_context(context_messages.await);
 
     // If this mention is itself a reply, include the referenced message explicitly.
     if let Some(referenced) = new_message.referenced_message.as_deref() {
@@ -142,17 +146,29 @@ pub async fn handle_mention(
                         .build()?
                         .into()
                 };
-            messages.push(referenced_msg);
+            sections.current.push(referenced_msg);
         }
     }
 
-    // Add the current user message (with mention stripped)
-    messages.push(
-        ChatCompletionRequestUserMessageArgs::default()
-            .content(format!("[{}]: {}", new_message.author.name, prompt))
-            .build()?
-            .into(),
-    );
+    // Add the current user message (with mention stripped) and the text of its attachments,
+    // which were ingested before this handler ran.
+    // With vision enabled, images on this message and the one it replies to are attached.
+    let attachment_text =
+        ConversationContext::attachment_text_for_message(data.db.clone(), new_message).await;
+    let images = vision::collect_message_images(data, new_message).await;
+    sections.current.push(vision::user_message(
+        format!(
+            "[{}]: {}\n{}",
+            new_message.author.name, prompt, attachment_text
+        )
+        .trim_end()
+        .to_string(),
+        &images,
+    )?);
+
+    let messages = sections
+        .fit(data.tokenizer.as_ref(), data.config.context_max_tokens)
+        .await;
 
     let typing = new_message.channel_id.start_typing(&ctx.http);
 
@@ -164,8 +180,27 @@ pub async fn handle_mention(
         std::time::Duration::from_secs(confirm_timeout_secs),
     );
 
-    let response = match agent.run_with_confirmation(confirm_ctx, messages, 10).await {
-        Ok(r) => r,
+    let mut reply =
+        StreamingEmbedReply::for_channel(&ctx.http, new_message.channel_id, Some(new_message.id));
+    let tool_ctx = ToolContext::for_invocation(
+        data,
+        ctx,
+        new_message.guild_id,
+        new_message.channel_id,
+        new_message.author.id,
+    )
+    .with_member_roles(new_message.member.as_ref().map(|m| m.roles.clone()));
+    let output = run_agent_with_reply(
+        &agent,
+        &data.config,
+        confirm_ctx,
+        &tool_ctx,
+        messages,
+        &mut reply,
+    )
+    .await;
+    let response = match &output.result {
+        Ok(r) => r.clone(),
         Err(e) => {
             error!("Agent error handling mention: {}", e);
             format!("❌ Assistant Error: {}", e)
@@ -175,13 +210,10 @@ pub async fn handle_mention(
     drop(typing);
 
     // Reply directly to the mention message
-    let sent_ids = send_embed_reply(
-        &ctx.http,
-        new_message.channel_id,
-        &response,
-        Some(new_message.id),
-    )
-    .await?;
+    let sent_ids = reply.finish(&response).await?;
+    output
+        .save(&data.db, &tool_ctx, Some(new_message.id), &sent_ids)
+        .await;
 
     if !skip_memory && memory_enabled {
         let llm = data.llm_client.clone();
@@ -0,0 +1,125 @@
+use crate::db::ChannelThread;
+use crate::{Data, Error};
+use poise::serenity_prelude as serenity;
+use tracing::{debug, info};
+
+/// Thread metadata for `channel` if it is a thread or forum post. Forum posts are recognised by
+/// their parent's channel type, looked up in the cache.
+pub fn channel_thread(
+    ctx: &serenity::Context,
+    channel: &serenity::GuildChannel,
+) -> Option<ChannelThread> {
+    use serenity::ChannelType;
+
+    if !matches!(
+        channel.kind,
+        ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread
+    ) {
+        return None;
+    }
+    let parent_id = channel.parent_id?;
+    let is_forum_post = ctx.cache.guild(channel.guild_id).is_some_and(|guild| {
+        guild
+            .channels
+            .get(&parent_id)
+            .is_some_and(|parent| parent.kind == ChannelType::Forum)
+    });
+
+    Some(ChannelThread {
+        channel_id: channel.id.to_string(),
+        guild_id: channel.guild_id.to_string(),
+        parent_channel_id: parent_id.to_string(),
+        name: channel.name.clone(),
+        is_forum_post,
+    })
+}
+
+/// Thread metadata for the channel a message was posted in, if the cache knows it as a thread.
+pub fn message_thread(
+    ctx: &serenity::Context,
+    message: &serenity::Message,
+) -> Option<ChannelThread> {
+    let guild_id = message.guild_id?;
+    let thread = ctx
+        .cache
+        .guild(guild_id)?
+        .threads
+        .iter()
+        .find(|thread| thread.id == message.channel_id)?
+        .clone();
+    channel_thread(ctx, &thread)
+}
+
+/// Handle a created or updated thread: record (or refresh) its link to the parent channel.
+pub async fn handle_thread_upsert(
+    ctx: &serenity::Context,
+    thread: &serenity::GuildChannel,
+    data: &Data,
+) -> Result<(), Error> {
+    let Some(thread) = channel_thread(ctx, thread) else {
+        return Ok(());
+    };
+    debug!(
+        "Recording thread {} under channel {}",
+        thread.channel_id, thread.parent_channel_id
+    );
+    data.db
+        .run_blocking(move |db| db.save_channel_thread(&thread))
+        .await?;
+    Ok(())
+}
+
+/// Handle a message edit: store the new content (queuing it for re-embedding) and evict the
+/// cached copy so context is rebuilt from the database.
+pub async fn handle_message_update(
+    event: &serenity::MessageUpdateEvent,
+    data: &Data,
+) -> Result<(), Error> {
+    // Embed/pin/reaction updates arrive without content.
+    let Some(content) = event.content.clone() else {
+        return Ok(());
+    };
+    if event.author.as_ref().is_some_and(|author| author.bot) {
+        return Ok(());
+    }
+
+    let message_id = event.id.to_string();
+    data.cache.remove(&message_id);
+
+    let updated = data
+        .db
+        .run_blocking(move |db| db.update_message_content(&message_id, &content))
+        .await?;
+    if updated.is_some() {
+        debug!(
+            "Updated edited message {} in channel {}",
+            event.id, event.channel_id
+        );
+    }
+    Ok(())
+}
+
+/// Handle one or more deleted messages: remove their rows (and embeddings) and evict them from
+/// the cache. Affected channel summaries are marked for a rebuild.
+pub async fn handle_messages_deleted(
+    channel_id: serenity::ChannelId,
+    message_ids: &[serenity::MessageId],
+    data: &Data,
+) -> Result<(), Error> {
+    let ids: Vec<String> = message_ids.iter().map(|id| id.to_string()).collect();
+    for id in &ids {
+        data.cache.remove(id);
+    }
+
+    let removed = data
+        .db
+        .run_blocking(move |db| db.delete_messages(&ids))
+        .await?;
+    if removed > 0 {
+        info!(
+            "Removed {} deleted message(s) from channel {}",
+            removed, channel_id
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,698 @@
+//! In-process approximate nearest-neighbour index (HNSW) over message embeddings.
+//!
+//! Vectors are L2-normalised on insert so similarity is a plain dot product (cosine).
+//! The index only produces candidates; callers re-score and filter against SQLite.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{info, warn};
+
+const MAGIC: &[u8; 8] = b"MHNSW001";
+const DEFAULT_M: usize = 16;
+const DEFAULT_EF_CONSTRUCTION: usize = 100;
+/// Nodes live on at most this many layers (see `random_level`).
+const MAX_LAYERS: usize = 17;
+
+#[derive(Clone, Copy, PartialEq)]
+struct Scored(f32, u32);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .total_cmp(&other.0)
+            .then_with(|| self.1.cmp(&other.1))
+    }
+}
+
+#[derive(Clone)]
+struct Node {
+    id: i64,
+    vector: Vec<f32>,
+    /// Neighbour lists, one per layer the node lives on (index 0 = bottom layer).
+    links: Vec<Vec<u32>>,
+}
+
+#[derive(Clone)]
+pub struct HnswIndex {
+    model: String,
+    dim: usize,
+    m: usize,
+    ef_construction: usize,
+    nodes: Vec<Node>,
+    by_id: HashMap<i64, u32>,
+    entry: Option<u32>,
+    rng_state: u64,
+}
+
+impl HnswIndex {
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            dim: 0,
+            m: DEFAULT_M,
+            ef_construction: DEFAULT_EF_CONSTRUCTION,
+            nodes: Vec::new(),
+            by_id: HashMap::new(),
+            entry: None,
+            rng_state: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn contains(&self, id: i64) -> bool {
+        self.by_id.contains_key(&id)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = i64> + '_ {
+        self.nodes.iter().map(|n| n.id)
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.m * 2
+        } else {
+            self.m
+        }
+    }
+
+    fn random_level(&mut self) -> usize {
+        // xorshift64*; deterministic so rebuilds produce the same graph.
+        self.rng_state ^= self.rng_state >> 12;
+        self.rng_state ^= self.rng_state << 25;
+        self.rng_state ^= self.rng_state >> 27;
+        let bits = self.rng_state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        let uniform = ((bits >> 11) as f64 + 1.0) / ((1u64 << 53) as f64 + 1.0);
+        let ml = 1.0 / (self.m as f64).ln();
+        ((-uniform.ln() * ml).floor() as usize).min(16)
+    }
+
+    fn similarity(&self, query: &[f32], node: u32) -> f32 {
+        dot(query, &self.nodes[node as usize].vector)
+    }
+
+    /// Insert or replace the vector for `id`. Zero vectors are ignored.
+    pub fn insert(&mut self, id: i64, vector: &[f32]) -> anyhow::Result<()> {
+        if self.dim == 0 {
+            self.dim = vector.len();
+        }
+        if vector.len() != self.dim {
+            return Err(anyhow::anyhow!(
+                "embedding dimension {} does not match index dimension {}",
+                vector.len(),
+                self.dim
+            ));
+        }
+        let Some(vector) = normalize(vector) else {
+            return Ok(());
+        };
+
+        if let Some(&existing) = self.by_id.get(&id) {
+            // Re-embedded message: keep its links and swap the vector in place.
+            self.nodes[existing as usize].vector = vector;
+            return Ok(());
+        }
+
+        let level = self.random_level();
+        let new_idx = self.nodes.len() as u32;
+        self.nodes.push(Node {
+            id,
+            vector,
+            links: vec![Vec::new(); level + 1],
+        });
+        self.by_id.insert(id, new_idx);
+
+        let Some(mut entry) = self.entry else {
+            self.entry = Some(new_idx);
+            return Ok(());
+        };
+
+        let query = self.nodes[new_idx as usize].vector.clone();
+        let top_level = self.nodes[entry as usize].links.len() - 1;
+
+        for layer in (level + 1..=top_level).rev() {
+            entry = self.greedy_step(&query, entry, layer);
+        }
+
+        let mut entry_points = vec![entry];
+        for layer in (0..=level.min(top_level)).rev() {
+            let candidates = self.search_layer(&query, &entry_points, self.ef_construction, layer);
+            let neighbours = self.select_neighbours(&candidates, self.max_links(layer));
+
+            self.nodes[new_idx as usize].links[layer] = neighbours.clone();
+            for &neighbour in &neighbours {
+                self.link(neighbour, new_idx, layer);
+            }
+            entry_points = candidates.into_iter().map(|s| s.1).collect();
+        }
+
+        if level > top_level {
+            self.entry = Some(new_idx);
+        }
+        Ok(())
+    }
+
+    /// Pick up to `max_links` of `candidates` (most similar first), preferring ones that are
+    /// closer to the base vector than to any neighbour already picked. Without this, nodes at
+    /// the edge of a dense cluster lose all incoming links and become unreachable.
+    fn select_neighbours(&self, candidates: &[Scored], max_links: usize) -> Vec<u32> {
+        let mut selected: Vec<u32> = Vec::with_capacity(max_links);
+        let mut pruned = Vec::new();
+        for candidate in candidates {
+            if selected.len() == max_links {
+                break;
+            }
+            let vector = &self.nodes[candidate.1 as usize].vector;
+            if selected
+                .iter()
+                .all(|&s| self.similarity(vector, s) < candidate.0)
+            {
+                selected.push(candidate.1);
+            } else {
+                pruned.push(candidate.1);
+            }
+        }
+        // Fill the remaining slots with the closest pruned candidates.
+        let free = max_links - selected.len();
+        selected.extend(pruned.into_iter().take(free));
+        selected
+    }
+
+    /// Add `to` to `from`'s neighbour list on `layer`, pruning to a diverse set of neighbours.
+    fn link(&mut self, from: u32, to: u32, layer: usize) {
+        let max_links = self.max_links(layer);
+        let links = &mut self.nodes[from as usize].links[layer];
+        if links.contains(&to) {
+            return;
+        }
+        links.push(to);
+        if links.len() <= max_links {
+            return;
+        }
+
+        let base = self.nodes[from as usize].vector.clone();
+        let mut scored: Vec<Scored> = self.nodes[from as usize].links[layer]
+            .iter()
+            .map(|&n| Scored(self.similarity(&base, n), n))
+            .collect();
+        scored.sort_by(|a, b| b.cmp(a));
+        self.nodes[from as usize].links[layer] = self.select_neighbours(&scored, max_links);
+    }
+
+    fn greedy_step(&self, query: &[f32], mut current: u32, layer: usize) -> u32 {
+        let mut best = self.similarity(query, current);
+        loop {
+            let mut improved = false;
+            for &neighbour in &self.nodes[current as usize].links[layer] {
+                let sim = self.similarity(query, neighbour);
+                if sim > best {
+                    best = sim;
+                    current = neighbour;
+                    improved = true;
+                }
+            }
+            if !improved {
+                return current;
+            }
+        }
+    }
+
+    /// Best-first search on one layer. Returns up to `ef` nodes, most similar first.
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry_points: &[u32],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Scored> {
+        let mut visited: HashSet<u32> = entry_points.iter().copied().collect();
+        let mut candidates: BinaryHeap<Scored> = BinaryHeap::new();
+        let mut results: BinaryHeap<Reverse<Scored>> = BinaryHeap::new();
+
+        for &ep in entry_points {
+            let scored = Scored(self.similarity(query, ep), ep);
+            candidates.push(scored);
+            results.push(Reverse(scored));
+            if results.len() > ef {
+                results.pop();
+            }
+        }
+
+        while let Some(candidate) = candidates.pop() {
+            let worst = results.peek().map(|r| r.0 .0).unwrap_or(f32::MIN);
+            if candidate.0 < worst && results.len() >= ef {
+                break;
+            }
+            let node = &self.nodes[candidate.1 as usize];
+            let Some(links) = node.links.get(layer) else {
+                continue;
+            };
+            for &neighbour in links {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let scored = Scored(self.similarity(query, neighbour), neighbour);
+                let worst = results.peek().map(|r| r.0 .0).unwrap_or(f32::MIN);
+                if results.len() < ef || scored.0 > worst {
+                    candidates.push(scored);
+                    results.push(Reverse(scored));
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        let mut out: Vec<Scored> = results.into_iter().map(|r| r.0).collect();
+        out.sort_by(|a, b| b.cmp(a));
+        out
+    }
+
+    /// Up to `k` `(message id, cosine similarity)` pairs, most similar first.
+    pub fn search(&self, query: &[f32], k: usize, ef: usize) -> Vec<(i64, f32)> {
+        let Some(mut entry) = self.entry else {
+            return Vec::new();
+        };
+        if query.len() != self.dim || k == 0 {
+            return Vec::new();
+        }
+        let Some(query) = normalize(query) else {
+            return Vec::new();
+        };
+
+        let top_level = self.nodes[entry as usize].links.len() - 1;
+        for layer in (1..=top_level).rev() {
+            entry = self.greedy_step(&query, entry, layer);
+        }
+
+        self.search_layer(&query, &[entry], ef.max(k), 0)
+            .into_iter()
+            .take(k)
+            .map(|s| (self.nodes[s.1 as usize].id, s.0))
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp = path.with_extension("tmp");
+        {
+            let mut w = BufWriter::new(std::fs::File::create(&tmp)?);
+            w.write_all(MAGIC)?;
+            write_u32(&mut w, self.model.len() as u32)?;
+            w.write_all(self.model.as_bytes())?;
+            write_u32(&mut w, self.dim as u32)?;
+            write_u32(&mut w, self.m as u32)?;
+            write_u32(&mut w, self.ef_construction as u32)?;
+            w.write_all(&self.rng_state.to_le_bytes())?;
+            w.write_all(&self.entry.map(i64::from).unwrap_or(-1).to_le_bytes())?;
+            write_u32(&mut w, self.nodes.len() as u32)?;
+            for node in &self.nodes {
+                w.write_all(&node.id.to_le_bytes())?;
+                for value in &node.vector {
+                    w.write_all(&value.to_le_bytes())?;
+                }
+                write_u32(&mut w, node.links.len() as u32)?;
+                for links in &node.links {
+                    write_u32(&mut w, links.len() as u32)?;
+                    for &link in links {
+                        write_u32(&mut w, link)?;
+                    }
+                }
+            }
+            w.flush()?;
+        }
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Read an index written by `save`. Counts in the file are checked against its length
+    /// before anything is allocated, so a corrupt file is an error rather than an abort.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut r = BufReader::new(file);
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(anyhow::anyhow!("unrecognised vector index file format"));
+        }
+        let model_len = checked_count(read_u32(&mut r)?, 1, file_len, "model name length")?;
+        let mut model = vec![0u8; model_len];
+        r.read_exact(&mut model)?;
+        let dim = checked_count(read_u32(&mut r)?, 4, file_len, "dimension")?;
+        let m = read_u32(&mut r)? as usize;
+        let ef_construction = read_u32(&mut r)? as usize;
+        let mut buf8 = [0u8; 8];
+        r.read_exact(&mut buf8)?;
+        let rng_state = u64::from_le_bytes(buf8);
+        r.read_exact(&mut buf8)?;
+        let entry = i64::from_le_bytes(buf8);
+        // Each node takes at least its id, vector and layer count.
+        let count = checked_count(read_u32(&mut r)?, 12 + dim * 4, file_len, "node count")?;
+
+        let mut nodes = Vec::with_capacity(count);
+        let mut by_id = HashMap::with_capacity(count);
+        for idx in 0..count {
+            r.read_exact(&mut buf8)?;
+            let id = i64::from_le_bytes(buf8);
+            let mut vector = Vec::with_capacity(dim);
+            let mut buf4 = [0u8; 4];
+            for _ in 0..dim {
+                r.read_exact(&mut buf4)?;
+                vector.push(f32::from_le_bytes(buf4));
+            }
+            let layers = read_u32(&mut r)? as usize;
+            if layers > MAX_LAYERS {
+                return Err(anyhow::anyhow!("corrupt vector index: {} layers", layers));
+            }
+            let mut links = Vec::with_capacity(layers);
+            for _ in 0..layers {
+                let n = checked_count(read_u32(&mut r)?, 4, file_len, "link count")?;
+                let mut layer = Vec::with_capacity(n);
+                for _ in 0..n {
+                    let link = read_u32(&mut r)?;
+                    if link as usize >= count {
+                        return Err(anyhow::anyhow!("corrupt vector index: dangling link"));
+                    }
+                    layer.push(link);
+                }
+                links.push(layer);
+            }
+            by_id.insert(id, idx as u32);
+            nodes.push(Node { id, vector, links });
+        }
+
+        Ok(Self {
+            model: String::from_utf8(model)?,
+            dim,
+            m,
+            ef_construction,
+            nodes,
+            by_id,
+            entry: u32::try_from(entry).ok().filter(|&e| (e as usize) < count),
+            rng_state,
+        })
+    }
+}
+
+/// Shared, optionally persisted handle to the HNSW index used by `Database`.
+///
+/// The index is only consulted once `mark_ready` has been called (after loading or rebuilding
+/// from SQLite); until then searches fall back to the linear scan.
+#[derive(Clone)]
+pub struct VectorIndex {
+    path: Option<PathBuf>,
+    state: Arc<RwLock<IndexState>>,
+    /// Serialises writers of the index file.
+    persist_lock: Arc<Mutex<()>>,
+}
+
+struct IndexState {
+    index: HnswIndex,
+    ready: bool,
+    dirty: bool,
+}
+
+impl VectorIndex {
+    pub fn new(path: Option<PathBuf>, model: &str) -> Self {
+        Self {
+            path,
+            state: Arc::new(RwLock::new(IndexState {
+                index: HnswIndex::new(model),
+                ready: false,
+                dirty: false,
+            })),
+            persist_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, IndexState> {
+        self.state.read().unwrap_or_else(|poisoned| {
+            warn!("Vector index lock poisoned; recovering");
+            poisoned.into_inner()
+        })
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, IndexState> {
+        self.state.write().unwrap_or_else(|poisoned| {
+            warn!("Vector index lock poisoned; recovering");
+            poisoned.into_inner()
+        })
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.read().ready
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn model(&self) -> String {
+        self.read().index.model().to_string()
+    }
+
+    /// Load the persisted index if it exists and was built for `model`.
+    pub fn load_persisted(&self, model: &str) -> Option<HnswIndex> {
+        let path = self.path.as_ref()?;
+        if !path.exists() {
+            return None;
+        }
+        match HnswIndex::load(path) {
+            Ok(index) if index.model() == model => Some(index),
+            Ok(index) => {
+                info!(
+                    "Vector index was built for model '{}' but '{}' is configured; rebuilding",
+                    index.model(),
+                    model
+                );
+                None
+            }
+            Err(e) => {
+                warn!("Failed to load vector index from {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Swap in a fully built index and start serving queries from it.
+    pub fn install(&self, index: HnswIndex, dirty: bool) {
+        let mut state = self.write();
+        state.index = index;
+        state.ready = true;
+        state.dirty = dirty;
+    }
+
+    pub fn insert(&self, id: i64, vector: &[f32]) {
+        let mut state = self.write();
+        if !state.ready {
+            return;
+        }
+        match state.index.insert(id, vector) {
+            Ok(()) => state.dirty = true,
+            Err(e) => warn!("Vector index: skipping message {}: {}", id, e),
+        }
+    }
+
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<(i64, f32)> {
+        let state = self.read();
+        if !state.ready {
+            return Vec::new();
+        }
+        state.index.search(query, k, k * 2)
+    }
+
+    /// Write the index to disk if it changed since the last save. The file is written from a
+    /// snapshot, so searches and inserts are not blocked while it is saved.
+    pub fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let _writer = self.persist_lock.lock().unwrap_or_else(|p| p.into_inner());
+        let snapshot = {
+            let mut state = self.write();
+            if !state.ready || !state.dirty {
+                return Ok(());
+            }
+            // Inserts made while saving mark the index dirty again for the next save.
+            state.dirty = false;
+            state.index.clone()
+        };
+        if let Err(e) = snapshot.save(path) {
+            self.write().dirty = true;
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn normalize(vector: &[f32]) -> Option<Vec<f32>> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 || !norm.is_finite() {
+        return None;
+    }
+    Some(vector.iter().map(|x| x / norm).collect())
+}
+
+/// `value` as a count of items taking `item_size` bytes each, if that many could fit in a
+/// file of `file_len` bytes.
+fn checked_count(value: u32, item_size: usize, file_len: u64, what: &str) -> anyhow::Result<usize> {
+    let count = value as usize;
+    match count.checked_mul(item_size) {
+        Some(bytes) if bytes as u64 <= file_len => Ok(count),
+        _ => Err(anyhow::anyhow!(
+            "corrupt vector index: {} {} exceeds the file size",
+            what,
+            count
+        )),
+    }
+}
+
+fn write_u32(w: &mut impl Write, value: u32) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn read_u32(r: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudo_random_vectors(count: usize, dim: usize) -> Vec<Vec<f32>> {
+        let mut state = 42u64;
+        (0..count)
+            .map(|_| {
+                (0..dim)
+                    .map(|_| {
+                        state = state
+                            .wrapping_mul(6364136223846793005)
+                            .wrapping_add(1442695040888963407);
+                        ((state >> 33) as f32 / u32::MAX as f32) - 0.25
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn brute_force(vectors: &[Vec<f32>], query: &[f32], k: usize) -> Vec<i64> {
+        let q = normalize(query).unwrap();
+        let mut scored: Vec<(f32, i64)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (dot(&q, &normalize(v).unwrap()), i as i64))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().take(k).map(|(_, id)| id).collect()
+    }
+
+    #[test]
+    fn test_hnsw_recall_against_brute_force() {
+        let vectors = pseudo_random_vectors(2000, 16);
+        let mut index = HnswIndex::new("test-model");
+        for (i, v) in vectors.iter().enumerate() {
+            index.insert(i as i64, v).unwrap();
+        }
+
+        let queries = pseudo_random_vectors(20, 16);
+        let mut hits = 0;
+        for q in &queries {
+            let expected: HashSet<i64> = brute_force(&vectors, q, 10).into_iter().collect();
+            let found = index.search(q, 10, 64);
+            hits += found.iter().filter(|(id, _)| expected.contains(id)).count();
+        }
+        let recall = hits as f32 / (queries.len() * 10) as f32;
+        assert!(recall >= 0.9, "recall too low: {}", recall);
+    }
+
+    #[test]
+    fn test_hnsw_rejects_dimension_mismatch_and_replaces_vectors() {
+        let mut index = HnswIndex::new("test-model");
+        index.insert(1, &[1.0, 0.0, 0.0]).unwrap();
+        index.insert(2, &[0.0, 1.0, 0.0]).unwrap();
+        assert!(index.insert(3, &[1.0, 0.0]).is_err());
+
+        index.insert(1, &[0.0, 0.0, 1.0]).unwrap();
+        assert_eq!(index.len(), 2);
+        let top = index.search(&[0.0, 0.0, 1.0], 1, 8);
+        assert_eq!(top[0].0, 1);
+    }
+
+    #[test]
+    fn test_hnsw_save_and_load_roundtrip() {
+        let vectors = pseudo_random_vectors(300, 8);
+        let mut index = HnswIndex::new("model-a");
+        for (i, v) in vectors.iter().enumerate() {
+            index.insert(i as i64 + 100, v).unwrap();
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("mascord-hnsw-test-{}.bin", std::process::id()));
+        index.save(&path).unwrap();
+        let loaded = HnswIndex::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.model(), "model-a");
+        assert_eq!(loaded.len(), 300);
+        assert_eq!(loaded.dim(), 8);
+        assert_eq!(
+            index.search(&vectors[7], 5, 32),
+            loaded.search(&vectors[7], 5, 32)
+        );
+    }
+
+    #[test]
+    fn test_hnsw_load_rejects_oversized_counts() {
+        let mut index = HnswIndex::new("m");
+        index.insert(1, &[1.0, 0.0]).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("mascord-hnsw-corrupt-{}.bin", std::process::id()));
+        index.save(&path).unwrap();
+        let valid = std::fs::read(&path).unwrap();
+
+        // Model name length, then the node count (after model, dim, m, ef, rng and entry).
+        let count_offset = 8 + 4 + 1 + 4 * 3 + 8 + 8;
+        for offset in [8, count_offset] {
+            let mut corrupt = valid.clone();
+            corrupt[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            std::fs::write(&path, &corrupt).unwrap();
+            assert!(HnswIndex::load(&path).is_err());
+        }
+        std::fs::remove_file(&path).ok();
+    }
+}
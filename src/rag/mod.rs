@@ -1,14 +1,46 @@
 use chrono::{DateTime, Utc};
 
-#[derive(Default, Clone)]
+pub mod ann;
+
+/// How messages posted in threads and forum posts are treated by a search.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ThreadFilter {
+    /// Search threads too; a channel filter also covers the threads created in that channel.
+    #[default]
+    Include,
+    /// Leave out messages posted in threads.
+    Exclude,
+    /// Only search messages posted in threads.
+    Only,
+}
+
+/// Filters for history search. Every search is scoped to exactly one guild.
+#[derive(Clone)]
 pub struct SearchFilter {
+    pub guild_id: String,
     pub channels: Vec<String>,
+    pub exclude_channels: Vec<String>,
+    pub authors: Vec<String>,
+    pub threads: ThreadFilter,
     pub from_date: Option<DateTime<Utc>>,
     pub to_date: Option<DateTime<Utc>>,
     pub limit: usize,
 }
 
 impl SearchFilter {
+    pub fn new(guild_id: impl Into<String>) -> Self {
+        Self {
+            guild_id: guild_id.into(),
+            channels: Vec::new(),
+            exclude_channels: Vec::new(),
+            authors: Vec::new(),
+            threads: ThreadFilter::default(),
+            from_date: None,
+            to_date: None,
+            limit: 0,
+        }
+    }
+
     pub fn with_limit(mut self, limit: usize) -> Self {
         self.limit = limit;
         self
@@ -23,6 +55,21 @@ impl SearchFilter {
         self.channels.push(channel_id);
         self
     }
+
+    pub fn without_channel(mut self, channel_id: String) -> Self {
+        self.exclude_channels.push(channel_id);
+        self
+    }
+
+    pub fn with_author(mut self, user_id: String) -> Self {
+        self.authors.push(user_id);
+        self
+    }
+
+    pub fn with_threads(mut self, threads: ThreadFilter) -> Self {
+        self.threads = threads;
+        self
+    }
 }
 
 pub struct MessageResult {
@@ -1,16 +1,25 @@
-use crate::commands::chat::send_embed_reply;
-use crate::context::ConversationContext;
+use crate::commands::chat::{run_agent_with_reply, StreamingEmbedReply};
+use crate::context::{ConversationContext, PromptSections};
 use crate::discord_text::extract_message_text;
+use crate::llm::agent::transcript_messages;
 use crate::llm::confirm::ToolConfirmationContext;
+use crate::llm::{tokenizer, vision};
 use crate::services::user_memory::UserMemoryService;
 use crate::system_prompt;
+use crate::tools::ToolContext;
 use crate::{Data, Error};
 use async_openai::types::{
     ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
     ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
 };
 use poise::serenity_prelude as serenity;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
+
+/// Characters kept of each tool result carried over from the answer being replied to.
+const REHYDRATED_TOOL_RESULT_CHARS: usize = 1500;
+/// Share of `CONTEXT_MAX_TOKENS` the carried-over tool calls may take.
+/// `sections.current` is never trimmed, so the transcript is capped here instead.
+const REHYDRATED_TRANSCRIPT_BUDGET_PERCENT: usize = 25;
 
 /// Handle a message that is a reply to the bot
 pub async fn handle_reply(
@@ -45,18 +54,20 @@ pub async fn handle_reply(
     let user_id = new_message.author.id.get();
 
     // Build messages with configurable system prompt
-    let mut messages: Vec<ChatCompletionRequestMessage> =
-        vec![ChatCompletionRequestSystemMessageArgs::default()
+    let mut sections = PromptSections::default();
+    sections.instructions.push(
+        ChatCompletionRequestSystemMessageArgs::default()
             .content(system_prompt)
             .build()?
-            .into()];
+            .into(),
+    );
 
     // Inject current date/time context
     if let Ok(datetime_msg) = ChatCompletionRequestSystemMessageArgs::default()
         .content(system_prompt::build_datetime_system_message())
         .build()
     {
-        messages.push(datetime_msg.into());
+        sections.instructions.push(datetime_msg.into());
     }
 
     if skip_memory {
@@ -64,7 +75,7 @@ pub async fn handle_reply(
             .content("Temporary no-memory request: do not use or update user memory. Do not call get_user_memory.")
             .build()
         {
-            messages.push(msg.into());
+            sections.instructions.push(msg.into());
         }
     }
 
@@ -76,7 +87,7 @@ pub async fn handle_reply(
         ))
         .build()
     {
-        messages.push(meta_msg.into());
+        sections.instructions.push(meta_msg.into());
     }
     let memory_record = if skip_memory {
         None
@@ -92,7 +103,7 @@ pub async fn handle_reply(
             )
             .build()
         {
-            messages.push(help_msg.into());
+            sections.instructions.push(help_msg.into());
         }
     }
 
@@ -103,7 +114,7 @@ pub async fn handle_reply(
                 .content(snippet)
                 .build()
             {
-                messages.push(msg.into());
+                sections.user_memory.push(msg.into());
             }
         }
     }
@@ -118,7 +129,7 @@ pub async fn handle_reply(
         Some(data.bot_id),
         Some(new_message.id.get()),
     );
-    messages.extend(context_messages.await);
+    sections.add_channel_context(context_messages.await);
 
     // Include the message being replied to directly in the prompt (embed-safe).
     if let Some(referenced) = new_message.referenced_message.as_deref() {
@@ -138,13 +149,44 @@ pub async fn handle_reply(
                         .build()?
                         .into()
                 };
-                messages.push(prev_msg);
+                sections.current.push(prev_msg);
             }
         }
 
+        // The tool calls behind the answer being replied to, so the follow-up can build on
+        // their results instead of calling the tools again. Only replayed for the user who
+        // ran them: results may hold data only that user was allowed to see.
+        let referenced_id = referenced.id.to_string();
+        match data
+            .db
+            .run_blocking(move |db| db.get_agent_run_for_message(&referenced_id))
+            .await
+        {
+            Ok(Some(run)) if run.user_id == new_message.author.id.to_string() => {
+                let max_total_chars = data.config.context_max_tokens
+                    * tokenizer::CHARS_PER_TOKEN
+                    * REHYDRATED_TRANSCRIPT_BUDGET_PERCENT
+                    / 100;
+                sections.current.extend(transcript_messages(
+                    &run.steps,
+                    REHYDRATED_TOOL_RESULT_CHARS,
+                    max_total_chars,
+                )?)
+            }
+            Ok(Some(_)) => debug!(
+                "Not replaying the tool calls of message {}: it answered another user",
+                referenced.id
+            ),
+            Ok(None) => {}
+            Err(e) => warn!(
+                "Failed to load the agent run for message {}: {}",
+                referenced.id, e
+            ),
+        }
+
         let referenced_text = extract_message_text(referenced);
         if !referenced_text.trim().is_empty() {
-            messages.push(
+            sections.current.push(
                 ChatCompletionRequestAssistantMessageArgs::default()
                     .content(referenced_text)
                     .build()?
@@ -153,17 +195,24 @@ pub async fn handle_reply(
         }
     }
 
-    // Add the current user message (the reply)
-    messages.push(
-        ChatCompletionRequestUserMessageArgs::default()
-            .content(format!(
-                "[{}]: {}",
-                new_message.author.name,
-                new_message.content.clone()
-            ))
-            .build()?
-            .into(),
-    );
+    // Add the current user message (the reply) and the text of its attachments. With vision
+    // enabled, images on the reply and on the message it answers are attached.
+    let attachment_text =
+        ConversationContext::attachment_text_for_message(data.db.clone(), new_message).await;
+    let images = vision::collect_message_images(data, new_message).await;
+    sections.current.push(vision::user_message(
+        format!(
+            "[{}]: {}\n{}",
+            new_message.author.name, new_message.content, attachment_text
+        )
+        .trim_end()
+        .to_string(),
+        &images,
+    )?);
+
+    let messages = sections
+        .fit(data.tokenizer.as_ref(), data.config.context_max_tokens)
+        .await;
 
     // Send a "Thinking..." message or use typing indicator
     let typing = new_message.channel_id.start_typing(&ctx.http);
@@ -175,8 +224,27 @@ pub async fn handle_reply(
         new_message.author.id,
         std::time::Duration::from_secs(confirm_timeout_secs),
     );
-    let response = match agent.run_with_confirmation(confirm_ctx, messages, 10).await {
-        Ok(r) => r,
+    let mut reply =
+        StreamingEmbedReply::for_channel(&ctx.http, new_message.channel_id, Some(new_message.id));
+    let tool_ctx = ToolContext::for_invocation(
+        data,
+        ctx,
+        new_message.guild_id,
+        new_message.channel_id,
+        new_message.author.id,
+    )
+    .with_member_roles(new_message.member.as_ref().map(|m| m.roles.clone()));
+    let output = run_agent_with_reply(
+        &agent,
+        &data.config,
+        confirm_ctx,
+        &tool_ctx,
+        messages,
+        &mut reply,
+    )
+    .await;
+    let response = match &output.result {
+        Ok(r) => r.clone(),
         Err(e) => {
             error!("Agent error handling reply: {}", e);
             format!("❌ Assistant Error: {}", e)
@@ -187,13 +255,10 @@ pub async fn handle_reply(
     drop(typing);
 
     // Handle long responses with embeds and reply to the user's message
-    let sent_ids = send_embed_reply(
-        &ctx.http,
-        new_message.channel_id,
-        &response,
-        Some(new_message.id),
-    )
-    .await?;
+    let sent_ids = reply.finish(&response).await?;
+    output
+        .save(&data.db, &tool_ctx, Some(new_message.id), &sent_ids)
+        .await;
 
     if !skip_memory && memory_enabled {
         let llm = data.llm_client.clone();
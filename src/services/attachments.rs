@@ -0,0 +1,384 @@
+use crate::config::Config;
+use crate::db::Database;
+use anyhow::Context as AnyhowContext;
+use serenity::model::channel::{Attachment, Message};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Upper bound on chunks stored per attachment; the rest of a very long file is dropped.
+pub const MAX_CHUNKS_PER_ATTACHMENT: usize = 200;
+/// Longest a caller waits for the attachments of one message to be downloaded and extracted.
+pub const INGEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// File extensions (and extension-less names) treated as plain text.
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt",
+    "text",
+    "md",
+    "markdown",
+    "rst",
+    "log",
+    "csv",
+    "tsv",
+    "json",
+    "jsonl",
+    "ndjson",
+    "yaml",
+    "yml",
+    "toml",
+    "ini",
+    "cfg",
+    "conf",
+    "env",
+    "xml",
+    "html",
+    "htm",
+    "css",
+    "scss",
+    "sql",
+    "graphql",
+    "proto",
+    "sh",
+    "bash",
+    "zsh",
+    "fish",
+    "ps1",
+    "bat",
+    "rs",
+    "py",
+    "js",
+    "mjs",
+    "cjs",
+    "jsx",
+    "ts",
+    "tsx",
+    "go",
+    "java",
+    "kt",
+    "kts",
+    "scala",
+    "c",
+    "h",
+    "cc",
+    "cpp",
+    "hpp",
+    "cs",
+    "rb",
+    "php",
+    "swift",
+    "lua",
+    "pl",
+    "r",
+    "dart",
+    "vue",
+    "svelte",
+    "ex",
+    "exs",
+    "hs",
+    "ml",
+    "zig",
+    "nix",
+    "tf",
+    "gradle",
+    "diff",
+    "patch",
+    "dockerfile",
+    "makefile",
+];
+
+/// How the text of an attachment is obtained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentKind {
+    /// Downloaded and decoded as UTF-8.
+    Text,
+    /// Downloaded and run through PDF text extraction.
+    Pdf,
+    /// Not downloaded; only the alt text (if any) is stored.
+    Image,
+}
+
+/// Classify an attachment by content type and file name. `None` for unsupported files.
+pub fn attachment_kind(filename: &str, content_type: Option<&str>) -> Option<AttachmentKind> {
+    let name = filename.to_ascii_lowercase();
+    let extension = name.rsplit_once('.').map_or(name.as_str(), |(_, ext)| ext);
+    let content_type = content_type
+        .map(|ct| {
+            ct.split(';')
+                .next()
+                .unwrap_or(ct)
+                .trim()
+                .to_ascii_lowercase()
+        })
+        .unwrap_or_default();
+
+    if extension == "pdf" || content_type == "application/pdf" {
+        Some(AttachmentKind::Pdf)
+    } else if content_type.starts_with("image/") {
+        Some(AttachmentKind::Image)
+    } else if content_type.starts_with("text/")
+        || matches!(
+            content_type.as_str(),
+            "application/json" | "application/xml" | "application/x-yaml" | "application/toml"
+        )
+        || TEXT_EXTENSIONS.contains(&extension)
+    {
+        Some(AttachmentKind::Text)
+    } else {
+        None
+    }
+}
+
+/// Extract plain text from a downloaded attachment.
+pub fn extract_text(kind: AttachmentKind, bytes: &[u8]) -> anyhow::Result<String> {
+    let text = match kind {
+        AttachmentKind::Text => {
+            if bytes.contains(&0) {
+                anyhow::bail!("File looks binary");
+            }
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+        AttachmentKind::Pdf => {
+            pdf_extract::extract_text_from_mem(bytes).context("Failed to extract PDF text")?
+        }
+        AttachmentKind::Image => anyhow::bail!("Images have no extractable text"),
+    };
+    Ok(text.replace("\r\n", "\n"))
+}
+
+/// Split text into chunks of at most `max_chars` characters, breaking between lines where
+/// possible. Blank chunks are dropped.
+pub fn chunk_text(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_chars = 0;
+
+    let mut flush = |current: &mut String, current_chars: &mut usize| {
+        if !current.trim().is_empty() {
+            chunks.push(current.trim_end().to_string());
+        }
+        current.clear();
+        *current_chars = 0;
+    };
+
+    for line in text.split_inclusive('\n') {
+        let line_chars = line.chars().count();
+        if current_chars + line_chars <= max_chars {
+            current.push_str(line);
+            current_chars += line_chars;
+            continue;
+        }
+        flush(&mut current, &mut current_chars);
+        if line_chars <= max_chars {
+            current.push_str(line);
+            current_chars = line_chars;
+            continue;
+        }
+        // A single line longer than a chunk is split at character boundaries.
+        let chars: Vec<char> = line.chars().collect();
+        for piece in chars.chunks(max_chars) {
+            current.extend(piece);
+            current_chars = piece.len();
+            if current_chars == max_chars {
+                flush(&mut current, &mut current_chars);
+            }
+        }
+    }
+    flush(&mut current, &mut current_chars);
+    chunks
+}
+
+/// Downloads text-like attachments (plain text, source code, JSON, PDFs, ...) of stored
+/// messages, and stores their text in chunks for the embedding indexer and search.
+pub struct AttachmentService {
+    db: Database,
+    http: reqwest::Client,
+    max_bytes: u64,
+    chunk_chars: usize,
+}
+
+impl AttachmentService {
+    pub fn new(db: Database, http: reqwest::Client, config: &Config) -> Self {
+        Self {
+            db,
+            http,
+            max_bytes: config.attachment_max_bytes,
+            chunk_chars: config.attachment_chunk_chars,
+        }
+    }
+
+    /// Ingest the supported attachments of a message that is already stored. Attachments that
+    /// were ingested before are skipped; a failing attachment does not stop the others.
+    /// Returns the number of chunks stored.
+    pub async fn ingest_message(&self, message: &Message) -> anyhow::Result<usize> {
+        let mut stored = 0;
+        for attachment in &message.attachments {
+            match self.ingest_attachment(message, attachment).await {
+                Ok(count) => stored += count,
+                Err(e) => warn!(
+                    "Failed to ingest attachment '{}' of message {}: {:#}",
+                    attachment.filename, message.id, e
+                ),
+            }
+        }
+        Ok(stored)
+    }
+
+    /// `ingest_message`, given up after `INGEST_TIMEOUT` so a slow download or a large PDF
+    /// cannot hold up the caller. Chunks stored before the timeout are kept.
+    pub async fn ingest_message_with_timeout(&self, message: &Message) -> anyhow::Result<usize> {
+        tokio::time::timeout(INGEST_TIMEOUT, self.ingest_message(message))
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out after {:?}", INGEST_TIMEOUT))?
+    }
+
+    async fn ingest_attachment(
+        &self,
+        message: &Message,
+        attachment: &Attachment,
+    ) -> anyhow::Result<usize> {
+        let Some(kind) = attachment_kind(&attachment.filename, attachment.content_type.as_deref())
+        else {
+            debug!("Skipping unsupported attachment '{}'", attachment.filename);
+            return Ok(0);
+        };
+        let attachment_id = attachment.id.to_string();
+        let id = attachment_id.clone();
+        if self
+            .db
+            .run_blocking(move |db| db.has_attachment_chunks(&id))
+            .await?
+        {
+            return Ok(0);
+        }
+
+        let text = if kind == AttachmentKind::Image {
+            // Images are only searchable through their alt text.
+            match attachment.description.as_deref().map(str::trim) {
+                Some(description) if !description.is_empty() => description.to_string(),
+                _ => return Ok(0),
+            }
+        } else {
+            if u64::from(attachment.size) > self.max_bytes {
+                debug!(
+                    "Skipping attachment '{}' ({} bytes > limit of {})",
+                    attachment.filename, attachment.size, self.max_bytes
+                );
+                return Ok(0);
+            }
+            let bytes = download_attachment(&self.http, &attachment.url, self.max_bytes).await?;
+            tokio::task::spawn_blocking(move || extract_text(kind, &bytes))
+                .await
+                .context("Text extraction panicked")??
+        };
+
+        let mut chunks = chunk_text(&text, self.chunk_chars);
+        if chunks.len() > MAX_CHUNKS_PER_ATTACHMENT {
+            warn!(
+                "Attachment '{}' has {} chunks; keeping the first {}",
+                attachment.filename,
+                chunks.len(),
+                MAX_CHUNKS_PER_ATTACHMENT
+            );
+            chunks.truncate(MAX_CHUNKS_PER_ATTACHMENT);
+        }
+        if chunks.is_empty() {
+            return Ok(0);
+        }
+
+        let message_id = message.id.to_string();
+        let filename = attachment.filename.clone();
+        let stored = self
+            .db
+            .run_blocking(move |db| {
+                db.save_attachment_chunks(&message_id, &attachment_id, &filename, &chunks)
+            })
+            .await?;
+        debug!(
+            "Stored {} chunk(s) of attachment '{}' from message {}",
+            stored, attachment.filename, message.id
+        );
+        Ok(stored)
+    }
+}
+
+/// Download an attachment, failing if it is larger than `max_bytes`.
+pub async fn download_attachment(
+    http: &reqwest::Client,
+    url: &str,
+    max_bytes: u64,
+) -> anyhow::Result<Vec<u8>> {
+    let response = http
+        .get(url)
+        .send()
+        .await
+        .context("Failed to download attachment")?
+        .error_for_status()
+        .context("Failed to download attachment")?;
+    let bytes = response
+        .bytes()
+        .await
+        .context("Failed to read attachment")?;
+    if bytes.len() as u64 > max_bytes {
+        anyhow::bail!("Attachment is larger than the limit of {} bytes", max_bytes);
+    }
+    Ok(bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attachment_kind() {
+        assert_eq!(
+            attachment_kind("notes.MD", None),
+            Some(AttachmentKind::Text)
+        );
+        assert_eq!(
+            attachment_kind("main.rs", Some("application/octet-stream")),
+            Some(AttachmentKind::Text)
+        );
+        assert_eq!(
+            attachment_kind("Dockerfile", None),
+            Some(AttachmentKind::Text)
+        );
+        assert_eq!(
+            attachment_kind("data", Some("application/json; charset=utf-8")),
+            Some(AttachmentKind::Text)
+        );
+        assert_eq!(attachment_kind("spec.pdf", None), Some(AttachmentKind::Pdf));
+        assert_eq!(
+            attachment_kind("photo.png", Some("image/png")),
+            Some(AttachmentKind::Image)
+        );
+        assert_eq!(
+            attachment_kind("archive.zip", Some("application/zip")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extract_text_rejects_binary() {
+        assert_eq!(
+            extract_text(AttachmentKind::Text, b"line one\r\nline two").unwrap(),
+            "line one\nline two"
+        );
+        assert!(extract_text(AttachmentKind::Text, b"\x7fELF\0\0").is_err());
+    }
+
+    #[test]
+    fn test_chunk_text_breaks_on_lines_and_splits_long_lines() {
+        let text = "alpha\nbeta\ngamma\n\n\n";
+        assert_eq!(chunk_text(text, 11), vec!["alpha\nbeta", "gamma"]);
+
+        let long = "é".repeat(25);
+        let chunks = chunk_text(&long, 10);
+        assert_eq!(
+            chunks.iter().map(|c| c.chars().count()).collect::<Vec<_>>(),
+            vec![10, 10, 5]
+        );
+
+        assert!(chunk_text("  \n\n", 10).is_empty());
+    }
+}
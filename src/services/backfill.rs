@@ -0,0 +1,295 @@
+use crate::db::{BackfillCheckpoint, ChannelThread, Database};
+use crate::services::attachments::AttachmentService;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serenity::model::channel::Message;
+use std::time::Duration;
+use tracing::warn;
+
+/// Messages requested per history page (Discord's maximum).
+pub const PAGE_SIZE: u8 = 100;
+/// Pause between history pages so a long backfill leaves rate-limit headroom for the bot.
+pub const PAGE_DELAY: Duration = Duration::from_millis(500);
+
+/// Result of storing one page of channel history.
+pub struct PageOutcome {
+    /// Messages on the page within the backfill range.
+    pub fetched: usize,
+    /// Messages that were not stored yet.
+    pub saved: usize,
+    /// No older history to fetch (start of channel, `since`/memory scope reached, or tracking
+    /// disabled).
+    pub done: bool,
+}
+
+/// Imports channel history fetched through the REST API into the message store. Progress is
+/// checkpointed per page so an interrupted backfill resumes where it stopped.
+pub struct BackfillService {
+    db: Database,
+    attachments: Option<AttachmentService>,
+}
+
+impl BackfillService {
+    pub fn new(db: Database) -> Self {
+        Self {
+            db,
+            attachments: None,
+        }
+    }
+
+    /// Also ingest the attachments of imported messages, like live ingestion does.
+    pub fn with_attachments(mut self, attachments: AttachmentService) -> Self {
+        self.attachments = Some(attachments);
+        self
+    }
+
+    /// Record the parent channel of a backfilled thread or forum post.
+    pub async fn link_thread(&self, thread: ChannelThread) -> anyhow::Result<()> {
+        self.db
+            .run_blocking(move |db| db.save_channel_thread(&thread))
+            .await
+    }
+
+    /// Start a backfill, or resume the unfinished one for the same channel and range.
+    pub async fn start(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        since: Option<String>,
+    ) -> anyhow::Result<BackfillCheckpoint> {
+        let guild_id = guild_id.to_string();
+        let channel_id = channel_id.to_string();
+        self.db
+            .run_blocking(move |db| db.start_backfill(&guild_id, &channel_id, since.as_deref()))
+            .await
+    }
+
+    /// Store a page of history (as returned by Discord, newest first) and advance the
+    /// checkpoint. Messages older than `since` or the channel's memory scope end the backfill;
+    /// bot messages are skipped like in live ingestion. Stored messages are left unindexed so
+    /// the embedding indexer picks them up. Threads started from messages on the page are
+    /// linked to the channel, and attachments are ingested when enabled.
+    pub async fn ingest_page(
+        &self,
+        checkpoint: &mut BackfillCheckpoint,
+        mut page: Vec<Message>,
+    ) -> anyhow::Result<PageOutcome> {
+        page.sort_by_key(|m| std::cmp::Reverse(m.id));
+        let full_page = page.len() >= PAGE_SIZE as usize;
+        let state = checkpoint.clone();
+
+        let outcome = self
+            .db
+            .run_blocking(move |db| {
+                let (enabled, scope) = db
+                    .get_channel_settings(&state.channel_id)?
+                    .unwrap_or((true, None));
+                if !enabled {
+                    return Ok((
+                        PageOutcome {
+                            fetched: 0,
+                            saved: 0,
+                            done: true,
+                        },
+                        None,
+                        page,
+                    ));
+                }
+                let cutoff = match (state.since, scope) {
+                    (Some(since), Some(scope)) => Some(since.max(scope)),
+                    (since, scope) => since.or(scope),
+                };
+
+                let mut fetched = 0;
+                let mut saved = 0;
+                let mut reached_cutoff = false;
+                for message in &page {
+                    let timestamp = message.timestamp.unix_timestamp();
+                    if let Some(cutoff) = &cutoff {
+                        if format_sqlite_utc(timestamp) < *cutoff {
+                            reached_cutoff = true;
+                            break;
+                        }
+                    }
+                    fetched += 1;
+                    // Forum posts have no starter message, so these are plain threads.
+                    if let Some(thread) = &message.thread {
+                        db.save_channel_thread(&ChannelThread {
+                            channel_id: thread.id.to_string(),
+                            guild_id: state.guild_id.clone(),
+                            parent_channel_id: state.channel_id.clone(),
+                            name: thread.name.clone(),
+                            is_forum_post: false,
+                        })?;
+                    }
+                    if message.author.bot {
+                        continue;
+                    }
+                    if db.save_message(
+                        &message.id.to_string(),
+                        &state.guild_id,
+                        &state.channel_id,
+                        &message.author.id.to_string(),
+                        &message.content,
+                        timestamp,
+                    )? {
+                        saved += 1;
+                    }
+                }
+
+                let cursor = page.last().map(|m| m.id.to_string());
+                if let Some(cursor) = &cursor {
+                    db.advance_backfill(&state.channel_id, cursor, fetched, saved)?;
+                }
+                let done = reached_cutoff || !full_page;
+                Ok((
+                    PageOutcome {
+                        fetched,
+                        saved,
+                        done,
+                    },
+                    cursor,
+                    page,
+                ))
+            })
+            .await?;
+
+        let (outcome, cursor, page) = outcome;
+        if let Some(attachments) = &self.attachments {
+            // Attachments already ingested are skipped, so resumed pages are cheap.
+            for message in page.iter().take(outcome.fetched) {
+                if message.author.bot || message.attachments.is_empty() {
+                    continue;
+                }
+                if let Err(e) = attachments.ingest_message_with_timeout(message).await {
+                    warn!(
+                        "Failed to ingest attachments of backfilled message {}: {}",
+                        message.id, e
+                    );
+                }
+            }
+        }
+        if cursor.is_some() {
+            checkpoint.before_message_id = cursor;
+        }
+        checkpoint.fetched += outcome.fetched as i64;
+        checkpoint.saved += outcome.saved as i64;
+        Ok(outcome)
+    }
+
+    pub async fn complete(&self, checkpoint: &BackfillCheckpoint) -> anyhow::Result<()> {
+        let channel_id = checkpoint.channel_id.clone();
+        self.db
+            .run_blocking(move |db| db.complete_backfill(&channel_id))
+            .await
+    }
+}
+
+/// Parse a `since` argument (`YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS`, UTC) into the format
+/// stored in SQLite.
+pub fn parse_since(input: &str) -> Option<String> {
+    let input = input.trim();
+    let naive = NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })?;
+    Some(naive.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+fn format_sqlite_utc(unix_timestamp: i64) -> String {
+    DateTime::<Utc>::from_timestamp(unix_timestamp, 0)
+        .unwrap_or_default()
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::tests::test_config;
+    use serenity::model::id::{ChannelId, MessageId, UserId};
+    use serenity::model::timestamp::Timestamp;
+
+    fn history_message(id: u64, unix_timestamp: i64, bot: bool) -> Message {
+        let mut msg = Message::default();
+        msg.id = MessageId::new(id);
+        msg.channel_id = ChannelId::new(100);
+        msg.author.id = UserId::new(7);
+        msg.author.bot = bot;
+        msg.content = format!("history message {}", id);
+        msg.timestamp = Timestamp::from_unix_timestamp(unix_timestamp).unwrap();
+        msg
+    }
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(
+            parse_since("2024-03-01").as_deref(),
+            Some("2024-03-01 00:00:00")
+        );
+        assert_eq!(
+            parse_since(" 2024-03-01 12:30:00 ").as_deref(),
+            Some("2024-03-01 12:30:00")
+        );
+        assert!(parse_since("yesterday").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_backfill_honors_scope_and_resumes_from_checkpoint() {
+        let db = Database::new(&test_config()).unwrap();
+        db.execute_init().unwrap();
+        // 1_700_000_000 = 2023-11-14 22:13:20 UTC
+        db.set_channel_memory_scope("1", "100", Some("2023-11-14 22:00:00".to_string()))
+            .unwrap();
+        db.save_message("5", "1", "100", "7", "history message 5", 1_700_000_050)
+            .unwrap();
+
+        let service = BackfillService::new(db.clone());
+        let mut checkpoint = service.start(1, 100, None).await.unwrap();
+
+        // A full page of newer history, then a partial one crossing the memory scope.
+        let page: Vec<Message> = (0..PAGE_SIZE as u64)
+            .map(|i| history_message(1000 - i, 1_700_000_500 - i as i64, i == 0))
+            .collect();
+        let outcome = service.ingest_page(&mut checkpoint, page).await.unwrap();
+        assert_eq!(
+            (outcome.fetched, outcome.saved, outcome.done),
+            (100, 99, false)
+        );
+        assert_eq!(checkpoint.before_message_id.as_deref(), Some("901"));
+
+        // Resuming picks up the stored cursor.
+        let resumed = service.start(1, 100, None).await.unwrap();
+        assert_eq!(resumed.before_message_id.as_deref(), Some("901"));
+        assert_eq!(resumed.saved, 99);
+
+        let mut thread_starter = history_message(4, 1_700_000_000, false);
+        let mut thread = serenity::model::channel::GuildChannel::default();
+        thread.id = ChannelId::new(200);
+        thread.name = "release notes".to_string();
+        thread_starter.thread = Some(thread);
+        let page = vec![
+            history_message(5, 1_700_000_050, false),
+            thread_starter,
+            history_message(3, 1_699_000_000, false),
+        ];
+        let outcome = service.ingest_page(&mut checkpoint, page).await.unwrap();
+        // Message 5 already existed; message 3 predates the memory scope.
+        assert_eq!((outcome.fetched, outcome.saved, outcome.done), (2, 1, true));
+        let linked = db.get_channel_thread("200").unwrap().unwrap();
+        assert_eq!(linked.parent_channel_id, "100");
+        assert_eq!(linked.name, "release notes");
+        service.complete(&checkpoint).await.unwrap();
+
+        let stored = db.get_backfill_checkpoint("100").unwrap().unwrap();
+        assert_eq!((stored.fetched, stored.saved), (102, 100));
+        assert!(stored.completed_at.is_some());
+        assert_eq!(db.get_embedding_status().unwrap().pending, 101);
+
+        // A finished backfill starts over on the next run.
+        let restarted = service.start(1, 100, None).await.unwrap();
+        assert!(restarted.before_message_id.is_none());
+    }
+}
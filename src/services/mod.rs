@@ -1,2 +1,5 @@
+pub mod attachments;
+pub mod backfill;
+pub mod music;
 pub mod reminder;
 pub mod user_memory;
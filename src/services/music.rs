@@ -0,0 +1,231 @@
+use crate::db::Database;
+use anyhow::anyhow;
+use poise::serenity_prelude as serenity;
+use songbird::input::{Compose, YoutubeDl};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Shared voice/queue logic used by the music slash commands and the `play_music` agent tool.
+#[derive(Clone)]
+pub struct MusicService {
+    db: Database,
+    http_client: reqwest::Client,
+    youtube_cookies: Option<String>,
+    default_idle_timeout_secs: u64,
+}
+
+/// Result of a successful enqueue.
+#[derive(Debug, Clone)]
+pub struct QueuedTrack {
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub duration: Option<Duration>,
+    pub voice_channel_id: serenity::ChannelId,
+    /// 1-based position in the queue (1 means it is playing now).
+    pub position: usize,
+    /// Whether the bot had to join the voice channel for this request.
+    pub joined: bool,
+}
+
+impl MusicService {
+    pub fn new(
+        db: Database,
+        http_client: reqwest::Client,
+        youtube_cookies: Option<String>,
+        default_idle_timeout_secs: u64,
+    ) -> Self {
+        Self {
+            db,
+            http_client,
+            youtube_cookies,
+            default_idle_timeout_secs,
+        }
+    }
+
+    /// Voice channel the given user is currently connected to, if any.
+    pub fn user_voice_channel(
+        ctx: &serenity::Context,
+        guild_id: serenity::GuildId,
+        user_id: serenity::UserId,
+    ) -> Option<serenity::ChannelId> {
+        let guild = ctx.cache.guild(guild_id)?;
+        guild
+            .voice_states
+            .get(&user_id)
+            .and_then(|vs| vs.channel_id)
+    }
+
+    async fn songbird(ctx: &serenity::Context) -> anyhow::Result<Arc<songbird::Songbird>> {
+        songbird::get(ctx)
+            .await
+            .ok_or_else(|| anyhow!("Songbird Voice client not initialized"))
+    }
+
+    /// Join the requester's voice channel and install the idle auto-leave handler.
+    pub async fn join(
+        &self,
+        ctx: &serenity::Context,
+        guild_id: serenity::GuildId,
+        user_id: serenity::UserId,
+    ) -> anyhow::Result<serenity::ChannelId> {
+        let channel_id = Self::user_voice_channel(ctx, guild_id, user_id)
+            .ok_or_else(|| anyhow!("You must be in a voice channel to use this command"))?;
+
+        info!(
+            "Attempting to join voice channel {} for guild {}",
+            channel_id, guild_id
+        );
+
+        let manager = Self::songbird(ctx).await?;
+        let handler_lock = manager
+            .join(guild_id, channel_id)
+            .await
+            .map_err(|e| anyhow!("Failed to join voice channel: {}", e))?;
+        info!(
+            "Successfully joined voice channel {} for guild {}",
+            channel_id, guild_id
+        );
+
+        let gid = guild_id.get();
+        let idle_timeout_secs = self
+            .db
+            .run_blocking(move |db| db.get_guild_voice_idle_timeout(gid))
+            .await?
+            .unwrap_or(self.default_idle_timeout_secs);
+
+        // Add idle handler to leave after a period of no tracks
+        let mut handler = handler_lock.lock().await;
+        handler.add_global_event(
+            songbird::Event::Track(songbird::TrackEvent::End),
+            crate::voice::events::IdleHandler {
+                guild_id,
+                manager: manager.clone(),
+                idle_timeout_secs,
+            },
+        );
+
+        Ok(channel_id)
+    }
+
+    /// Resolve `query` (URL or search terms) with yt-dlp and append it to the guild queue,
+    /// joining the requester's voice channel first if the bot is not connected yet.
+    pub async fn play(
+        &self,
+        ctx: &serenity::Context,
+        guild_id: serenity::GuildId,
+        user_id: serenity::UserId,
+        query: &str,
+    ) -> anyhow::Result<QueuedTrack> {
+        let manager = Self::songbird(ctx).await?;
+
+        let joined = manager.get(guild_id).is_none();
+        if joined {
+            info!(
+                "Not in a voice channel, attempting auto-join for guild {}",
+                guild_id
+            );
+            self.join(ctx, guild_id, user_id).await?;
+        }
+        let handler_lock = manager
+            .get(guild_id)
+            .ok_or_else(|| anyhow!("Failed to retrieve handler after join"))?;
+
+        let mut source = self.build_source(query);
+
+        // Preflight to surface age restriction errors and learn the track title.
+        let metadata = match source.aux_metadata().await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                let msg = e.to_string();
+                if looks_age_restricted(&msg) && !self.cookies_available() {
+                    return Err(anyhow!("This video appears to be age-restricted. Configure `YOUTUBE_COOKIES` with a valid cookies file to play age-restricted videos."));
+                }
+                return Err(anyhow!("Failed to fetch audio metadata: {}", msg));
+            }
+        };
+
+        info!("Queueing audio for guild {}: {}", guild_id, query);
+        let mut handler = handler_lock.lock().await;
+        let voice_channel_id = handler
+            .current_channel()
+            .map(|c| serenity::ChannelId::new(c.0.get()))
+            .or_else(|| Self::user_voice_channel(ctx, guild_id, user_id))
+            .ok_or_else(|| anyhow!("Not connected to a voice channel"))?;
+        handler.enqueue_input(source.into()).await;
+        let position = handler.queue().len();
+
+        Ok(QueuedTrack {
+            title: metadata.title,
+            url: metadata.source_url,
+            duration: metadata.duration,
+            voice_channel_id,
+            position,
+            joined,
+        })
+    }
+
+    fn cookies_available(&self) -> bool {
+        self.youtube_cookies
+            .as_deref()
+            .is_some_and(|p| std::path::Path::new(p).exists())
+    }
+
+    fn build_source(&self, query: &str) -> YoutubeDl<'static> {
+        let cookies_ok = self.cookies_available();
+        if self.youtube_cookies.is_some() && !cookies_ok {
+            warn!(
+                "YOUTUBE_COOKIES set but file not found at '{:?}'; proceeding without cookies",
+                self.youtube_cookies
+            );
+        }
+
+        let source = if is_url(query) {
+            YoutubeDl::new(self.http_client.clone(), query.to_string())
+        } else {
+            YoutubeDl::new_search(self.http_client.clone(), query.to_string())
+        };
+
+        // Pass args directly to yt-dlp via Songbird.
+        let mut args = vec!["--no-playlist".to_string()];
+        if let (Some(path), true) = (self.youtube_cookies.as_ref(), cookies_ok) {
+            args.push("--cookies".to_string());
+            args.push(path.clone());
+        }
+        source.user_args(args)
+    }
+}
+
+fn is_url(query: &str) -> bool {
+    query.starts_with("http://") || query.starts_with("https://")
+}
+
+fn looks_age_restricted(error: &str) -> bool {
+    let msg = error.to_lowercase();
+    msg.contains("confirm your age")
+        || msg.contains("age-restricted")
+        || msg.contains("sign in to confirm your age")
+        || msg.contains("age restricted")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://www.youtube.com/watch?v=abc"));
+        assert!(is_url("http://youtu.be/abc"));
+        assert!(!is_url("lofi hip hop radio"));
+        assert!(!is_url("youtube.com/watch?v=abc"));
+    }
+
+    #[test]
+    fn test_looks_age_restricted() {
+        assert!(looks_age_restricted(
+            "ERROR: Sign in to confirm your age. This video may be inappropriate"
+        ));
+        assert!(looks_age_restricted("Video is Age-Restricted"));
+        assert!(!looks_age_restricted("HTTP Error 404: Not Found"));
+    }
+}
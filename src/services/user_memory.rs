@@ -13,6 +13,7 @@ pub struct UserDataPurgeResult {
     pub cache_deleted: usize,
     pub summaries_deleted: usize,
     pub milestones_deleted: usize,
+    pub agent_runs_deleted: usize,
 }
 
 impl UserMemoryService {
@@ -245,6 +246,14 @@ Return updated memory as 1-6 bullet points, max 1200 characters."
                 .unwrap_or(0)
         };
 
+        let agent_runs_deleted = self
+            .db
+            .run_blocking({
+                let user_id_str = user_id_str.clone();
+                move |db| db.delete_agent_runs_by_user(&user_id_str)
+            })
+            .await?;
+
         let cache_deleted = self.cache.purge_user_messages(user_id);
 
         Ok(UserDataPurgeResult {
@@ -253,6 +262,7 @@ Return updated memory as 1-6 bullet points, max 1200 characters."
             cache_deleted,
             summaries_deleted,
             milestones_deleted,
+            agent_runs_deleted,
         })
     }
 }
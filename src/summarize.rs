@@ -1,8 +1,10 @@
 use crate::config::Config;
-use crate::db::ChannelSummaryRecord;
 use crate::db::Database;
+use crate::db::{ChannelSummaryRecord, ChannelThread};
+use crate::llm::tokenizer::{HeuristicTokenizer, Tokenizer};
 use crate::llm::LlmClient;
 use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use std::sync::Arc;
 use tracing::{info, warn};
 
 #[derive(Clone)]
@@ -35,14 +37,21 @@ impl SummarizationPolicy {
 pub struct SummarizationManager {
     db: Database,
     llm: LlmClient,
+    tokenizer: Arc<dyn Tokenizer>,
     policy: SummarizationPolicy,
 }
 
 impl SummarizationManager {
-    pub fn new(db: Database, llm: LlmClient, config: &Config) -> Self {
+    pub fn new(
+        db: Database,
+        llm: LlmClient,
+        tokenizer: Arc<dyn Tokenizer>,
+        config: &Config,
+    ) -> Self {
         Self {
             db,
             llm,
+            tokenizer,
             policy: SummarizationPolicy::from_config(config),
         }
     }
@@ -58,14 +67,20 @@ impl SummarizationManager {
             .run_blocking(move |db| db.get_summary_record(&channel_id_str))
             .await?;
 
-        let refresh_due = record
-            .as_ref()
-            .and_then(|r| parse_sqlite_utc(&r.refreshed_at))
-            .is_some_and(|ts| now - ts > Duration::weeks(self.policy.refresh_weeks));
+        // A dirty summary may quote edited or deleted messages: rebuild it from the remaining
+        // history without feeding the old summary or its milestones back in.
+        let rebuild = record.as_ref().is_some_and(|r| r.dirty);
+        let record = if rebuild { None } else { record };
+
+        let refresh_due = rebuild
+            || record
+                .as_ref()
+                .and_then(|r| parse_sqlite_utc(&r.refreshed_at))
+                .is_some_and(|ts| now - ts > Duration::weeks(self.policy.refresh_weeks));
 
         // For normal updates: only summarize new messages since last `updated_at`.
         // For first summaries: use the last `days` days.
-        // For periodic refresh: rebuild from the last 14 days.
+        // For periodic refresh and rebuilds: summarize the last 14 days.
         let from_ts = if refresh_due {
             now - Duration::days(self.policy.refresh_days_lookback)
         } else if let Some(r) = record
@@ -84,17 +99,43 @@ impl SummarizationManager {
             .await?;
 
         let channel_id_str = channel_id.to_string();
-        let milestones = self
-            .db
-            .run_blocking(move |db| db.get_channel_milestones(&channel_id_str, 20))
-            .await
-            .unwrap_or_default();
+        let milestones = if rebuild {
+            Vec::new()
+        } else {
+            self.db
+                .run_blocking(move |db| db.get_channel_milestones(&channel_id_str, 20))
+                .await
+                .unwrap_or_default()
+        };
 
         if messages.is_empty() {
-            info!("No messages to summarize for channel: {}", channel_id);
+            if rebuild {
+                // Nothing left to summarize; drop the summary rather than keep deleted content.
+                let channels = vec![channel_id.to_string()];
+                self.db
+                    .run_blocking(move |db| {
+                        db.delete_channel_summaries(&channels)?;
+                        db.delete_channel_milestones(&channels)
+                    })
+                    .await?;
+                info!(
+                    "Removed summary for channel {} (no messages left)",
+                    channel_id
+                );
+            } else {
+                info!("No messages to summarize for channel: {}", channel_id);
+            }
             return Ok(());
         }
 
+        // Forum posts are summarized on their own, framed by the post's title.
+        let channel_id_str = channel_id.to_string();
+        let forum_post = self
+            .db
+            .run_blocking(move |db| db.get_channel_thread(&channel_id_str))
+            .await?
+            .filter(|t| t.is_forum_post);
+
         // 2. Format messages for the summarizer
         let mut text_to_summarize = String::new();
         for msg in messages.iter().rev() {
@@ -112,6 +153,10 @@ impl SummarizationManager {
             &milestones,
             &text_to_summarize,
         );
+        let prompt = match &forum_post {
+            Some(post) => format!("{}{}", forum_post_preamble(post), prompt),
+            None => prompt,
+        };
 
         let summary = self.llm.completion(&prompt).await?;
         let summary = self
@@ -134,7 +179,7 @@ impl SummarizationManager {
         }
 
         if let Ok(milestones) = self.extract_milestones(&summary).await {
-            if !milestones.is_empty() {
+            if rebuild || !milestones.is_empty() {
                 let channel_id_str = channel_id.to_string();
                 if let Err(e) = self
                     .db
@@ -158,7 +203,17 @@ impl SummarizationManager {
     pub async fn get_active_channels(&self) -> anyhow::Result<Vec<String>> {
         let db = self.db.clone();
         let lookback_days = self.policy.active_channels_lookback_days;
-        tokio::task::spawn_blocking(move || db.get_channels_with_activity(lookback_days)).await?
+        tokio::task::spawn_blocking(move || {
+            let mut channels = db.get_channels_with_activity(lookback_days)?;
+            // Summaries invalidated by edits/deletions are rebuilt even in quiet channels.
+            for channel_id in db.get_dirty_summary_channels()? {
+                if !channels.contains(&channel_id) {
+                    channels.push(channel_id);
+                }
+            }
+            Ok(channels)
+        })
+        .await?
     }
 
     pub async fn should_summarize_channel(&self, channel_id: &str) -> anyhow::Result<bool> {
@@ -169,6 +224,10 @@ impl SummarizationManager {
             .run_blocking(move |db| db.get_summary_record(&channel_id_str))
             .await?;
 
+        if record.as_ref().is_some_and(|r| r.dirty) {
+            return Ok(true);
+        }
+
         let refresh_due = record
             .as_ref()
             .and_then(|r| parse_sqlite_utc(&r.refreshed_at))
@@ -194,9 +253,21 @@ impl SummarizationManager {
                 .await?
         };
 
-        // Initial summaries: require a little more activity to avoid spammy summaries.
+        // Initial summaries: require a little more activity to avoid spammy summaries. Forum
+        // posts are short-lived, so they only need the smaller follow-up threshold.
         if record.is_none() {
-            return Ok(new_messages >= self.policy.initial_min_messages);
+            let channel_id_str = channel_id.to_string();
+            let is_forum_post = self
+                .db
+                .run_blocking(move |db| db.get_channel_thread(&channel_id_str))
+                .await?
+                .is_some_and(|t| t.is_forum_post);
+            let min_messages = if is_forum_post {
+                self.policy.trigger_min_new_messages
+            } else {
+                self.policy.initial_min_messages
+            };
+            return Ok(new_messages >= min_messages);
         }
 
         let summary_age_hours = record
@@ -219,15 +290,14 @@ impl SummarizationManager {
         summary: &str,
         max_tokens: usize,
     ) -> anyhow::Result<String> {
-        // If we don't have an exact tokenizer, approximate: ~4 chars per token for English.
-        let approx_tokens = summary.chars().count() / 4;
-        if approx_tokens <= max_tokens {
+        let tokens = self.count_tokens(summary).await;
+        if tokens <= max_tokens {
             return Ok(summary.to_string());
         }
 
         warn!(
-            "Summary exceeds cap (approx {} tokens > {}); compressing",
-            approx_tokens, max_tokens
+            "Summary exceeds cap ({} tokens > {}); compressing",
+            tokens, max_tokens
         );
 
         // Try up to two compression passes.
@@ -238,8 +308,7 @@ impl SummarizationManager {
 Keep it accurate and preserve key decisions, constraints, and ongoing threads.\n\nSUMMARY:\n{current}\n\nCONDENSED SUMMARY:"
             );
             current = self.llm.completion(&prompt).await?;
-            let approx = current.chars().count() / 4;
-            if approx <= max_tokens {
+            if self.count_tokens(&current).await <= max_tokens {
                 break;
             }
         }
@@ -247,6 +316,13 @@ Keep it accurate and preserve key decisions, constraints, and ongoing threads.\n
         Ok(current)
     }
 
+    async fn count_tokens(&self, text: &str) -> usize {
+        self.tokenizer.count(text).await.unwrap_or_else(|e| {
+            warn!("Token counting failed, estimating instead: {}", e);
+            HeuristicTokenizer::estimate(text)
+        })
+    }
+
     async fn extract_milestones(&self, summary: &str) -> anyhow::Result<Vec<String>> {
         const MAX_MILESTONES: usize = 6;
         let prompt = format!(
@@ -265,6 +341,14 @@ fn parse_sqlite_utc(ts: &str) -> Option<DateTime<Utc>> {
     Some(DateTime::<Utc>::from_naive_utc_and_offset(naive, Utc))
 }
 
+fn forum_post_preamble(post: &ChannelThread) -> String {
+    format!(
+        "The messages below belong to the single forum post \"{}\". Summarize this post on its \
+own: the question or topic raised, the answers given, and whether it was resolved.\n\n",
+        post.name
+    )
+}
+
 fn build_summary_prompt(
     record: Option<&ChannelSummaryRecord>,
     refresh_due: bool,
@@ -41,7 +41,7 @@ pub fn get_datetime_context() -> String {
 /// # Returns:
 /// A formatted string suitable for use as a ChatCompletionRequestSystemMessage content
 pub fn build_datetime_system_message() -> String {
-    format!("{}", get_datetime_context())
+    get_datetime_context()
 }
 
 #[cfg(test)]
@@ -55,7 +55,7 @@ mod tests {
         assert!(context.contains("Current date/time:"));
         assert!(context.contains("UTC"));
         assert!(context.contains("Local time:"));
-        assert!(context.contains("T"));  // RFC3339 format includes 'T'
+        assert!(context.contains("T")); // RFC3339 format includes 'T'
     }
 
     #[test]
@@ -1,4 +1,4 @@
-use crate::tools::Tool;
+use crate::tools::{Tool, ToolContext};
 use async_trait::async_trait;
 use serde_json::{json, Value};
 
@@ -21,7 +21,10 @@ impl Tool for ShutdownTool {
     fn requires_confirmation(&self) -> bool {
         true
     }
-    async fn execute(&self, _params: Value) -> anyhow::Result<Value> {
+    fn is_available(&self, ctx: &ToolContext) -> bool {
+        ctx.is_owner()
+    }
+    async fn execute(&self, _ctx: &ToolContext, _params: Value) -> anyhow::Result<Value> {
         // Implementation will trigger shutdown
         Ok(json!({"status": "error", "message": "Not yet implemented"}))
     }
@@ -0,0 +1,116 @@
+use crate::tools::{Tool, ToolContext};
+use async_trait::async_trait;
+use rmcp::model::ResourceContents;
+use serde_json::{json, Value};
+
+/// Longest resource text handed to the model, in characters.
+const MAX_RESOURCE_CHARS: usize = 20_000;
+
+pub struct ReadMcpResourceTool;
+
+#[async_trait]
+impl Tool for ReadMcpResourceTool {
+    fn name(&self) -> &str {
+        "read_mcp_resource"
+    }
+    fn description(&self) -> &str {
+        "List or read resources (files, documents, records) exposed by connected MCP servers. Call without a uri to list the available resources, then with the server and uri of one to read it."
+    }
+    fn parameters_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "server": {
+                    "type": "string",
+                    "description": "MCP server name. Required to read; optional when listing"
+                },
+                "uri": {
+                    "type": "string",
+                    "description": "Resource URI to read. Omit to list resources"
+                }
+            },
+            "required": []
+        })
+    }
+    fn is_available(&self, ctx: &ToolContext) -> bool {
+        ctx.mcp_manager
+            .resource_servers()
+            .iter()
+            .any(|server| !ctx.policies.denies_server(server))
+    }
+    async fn execute(&self, ctx: &ToolContext, params: Value) -> anyhow::Result<Value> {
+        let server = params["server"].as_str().filter(|s| !s.is_empty());
+        let Some(uri) = params["uri"].as_str().filter(|s| !s.is_empty()) else {
+            return Ok(list_resources(ctx, server).await);
+        };
+        let server = server.ok_or_else(|| anyhow::anyhow!("Missing server"))?;
+        if ctx.policies.denies_server(server) {
+            anyhow::bail!("MCP server '{}' is disabled here by a tool policy", server);
+        }
+
+        let result = ctx.mcp_manager.read_resource(server, uri).await?;
+        let contents: Vec<Value> = result.contents.iter().map(describe_contents).collect();
+        Ok(json!({
+            "server": server,
+            "uri": uri,
+            "contents": contents
+        }))
+    }
+}
+
+async fn list_resources(ctx: &ToolContext, server: Option<&str>) -> Value {
+    let mut resources = Vec::new();
+    let mut errors = Vec::new();
+    for (server, result) in ctx.mcp_manager.list_resources(server).await {
+        if ctx.policies.denies_server(&server) {
+            continue;
+        }
+        match result {
+            Ok(listing) => resources.extend(listing.into_iter().map(|r| {
+                json!({
+                    "server": server,
+                    "uri": r.raw.uri,
+                    "name": r.raw.name,
+                    "description": r.raw.description,
+                    "mime_type": r.raw.mime_type
+                })
+            })),
+            Err(e) => errors.push(json!({ "server": server, "error": format!("{:#}", e) })),
+        }
+    }
+    if resources.is_empty() && errors.is_empty() {
+        return json!({"result": "No MCP resources are available."});
+    }
+    json!({ "resources": resources, "errors": errors })
+}
+
+/// Text contents, cut to `MAX_RESOURCE_CHARS`; binary contents are described, not included.
+fn describe_contents(contents: &ResourceContents) -> Value {
+    match contents {
+        ResourceContents::TextResourceContents {
+            uri,
+            mime_type,
+            text,
+            ..
+        } => {
+            let truncated = text.chars().count() > MAX_RESOURCE_CHARS;
+            let text: String = text.chars().take(MAX_RESOURCE_CHARS).collect();
+            json!({
+                "uri": uri,
+                "mime_type": mime_type,
+                "text": text,
+                "truncated": truncated
+            })
+        }
+        ResourceContents::BlobResourceContents {
+            uri,
+            mime_type,
+            blob,
+            ..
+        } => json!({
+            "uri": uri,
+            "mime_type": mime_type,
+            "note": format!("Binary content omitted (about {} bytes)", blob.len() / 4 * 3)
+        }),
+    }
+}
@@ -1,4 +1,5 @@
 pub mod admin;
+pub mod mcp_resource;
 pub mod music;
 pub mod rag;
 pub mod user_memory;
@@ -1,4 +1,5 @@
-use crate::tools::Tool;
+use crate::services::music::MusicService;
+use crate::tools::{Tool, ToolContext};
 use async_trait::async_trait;
 use serde_json::{json, Value};
 
@@ -10,7 +11,7 @@ impl Tool for PlayMusicTool {
         "play_music"
     }
     fn description(&self) -> &str {
-        "Play music from a YouTube URL or search query"
+        "Play music from a YouTube URL or search query in the requesting user's voice channel. The user must be connected to a voice channel in this server."
     }
     fn parameters_schema(&self) -> Value {
         json!({
@@ -24,8 +25,38 @@ impl Tool for PlayMusicTool {
             "required": ["query"]
         })
     }
-    async fn execute(&self, _params: Value) -> anyhow::Result<Value> {
-        // Implementation will call existing music service
-        Ok(json!({"status": "error", "message": "Not yet implemented"}))
+    fn is_available(&self, ctx: &ToolContext) -> bool {
+        ctx.guild_id.is_some() && ctx.serenity.is_some()
+    }
+    async fn execute(&self, ctx: &ToolContext, params: Value) -> anyhow::Result<Value> {
+        let query = params["query"]
+            .as_str()
+            .map(str::trim)
+            .filter(|q| !q.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Missing query"))?;
+
+        let (Some(serenity_ctx), Some(guild_id), Some(user_id)) =
+            (ctx.serenity.as_ref(), ctx.guild_id, ctx.user_id)
+        else {
+            anyhow::bail!("Music playback is only available inside a server conversation");
+        };
+
+        // Failures (not in voice, age-restricted, ...) reach the model as coded tool errors.
+        let music = MusicService::new(
+            ctx.db.clone(),
+            ctx.http_client.clone(),
+            ctx.config.youtube_cookies.clone(),
+            ctx.config.voice_idle_timeout_secs,
+        );
+        let track = music.play(serenity_ctx, guild_id, user_id, query).await?;
+        Ok(json!({
+            "status": "queued",
+            "title": track.title.unwrap_or_else(|| query.to_string()),
+            "url": track.url,
+            "duration_secs": track.duration.map(|d| d.as_secs()),
+            "queue_position": track.position,
+            "voice_channel_id": track.voice_channel_id.to_string(),
+            "joined_voice_channel": track.joined
+        }))
     }
 }
@@ -1,12 +1,9 @@
-use crate::db::Database;
-use crate::tools::Tool;
+use crate::rag::ThreadFilter;
+use crate::tools::{Tool, ToolContext};
 use async_trait::async_trait;
 use serde_json::{json, Value};
 
-pub struct SearchLocalHistoryTool {
-    pub db: Database,
-    pub llm: crate::llm::LlmClient,
-}
+pub struct SearchLocalHistoryTool;
 
 #[async_trait]
 impl Tool for SearchLocalHistoryTool {
@@ -23,21 +20,46 @@ impl Tool for SearchLocalHistoryTool {
                 "query": {
                     "type": "string",
                     "description": "Search query containing keywords"
+                },
+                "author_id": {
+                    "type": "string",
+                    "description": "Optional Discord user id to only search messages written by that user"
+                },
+                "threads": {
+                    "type": "string",
+                    "enum": ["include", "exclude", "only"],
+                    "description": "Whether to search messages in threads and forum posts (default: include)"
                 }
             },
             "required": ["query"]
         })
     }
-    async fn execute(&self, params: Value) -> anyhow::Result<Value> {
+    fn is_available(&self, ctx: &ToolContext) -> bool {
+        ctx.guild_id.is_some()
+    }
+    async fn execute(&self, ctx: &ToolContext, params: Value) -> anyhow::Result<Value> {
         let query = params["query"]
             .as_str()
             .ok_or_else(|| anyhow::anyhow!("Missing query"))?;
 
-        let filter = crate::rag::SearchFilter::default().with_limit(5);
+        let Some(guild_id) = ctx.guild_id else {
+            return Ok(json!({"result": "History search is only available inside a server."}));
+        };
+
+        // Always scope to the invoking guild so one server can never read another's history.
+        let mut filter = crate::rag::SearchFilter::new(guild_id.to_string()).with_limit(5);
+        if let Some(author_id) = params["author_id"].as_str().filter(|s| !s.is_empty()) {
+            filter = filter.with_author(author_id.to_string());
+        }
+        match params["threads"].as_str() {
+            Some("exclude") => filter = filter.with_threads(ThreadFilter::Exclude),
+            Some("only") => filter = filter.with_threads(ThreadFilter::Only),
+            _ => {}
+        }
 
         // Prefer semantic search when embeddings are available; fall back to keyword search if embedding fails.
-        let embedding = self.llm.get_embeddings(query).await.unwrap_or_default();
-        let results = self.db.search_messages(query, embedding, filter).await?;
+        let embedding = ctx.llm.get_embeddings(query).await.unwrap_or_default();
+        let results = ctx.db.search_messages(query, embedding, filter).await?;
 
         if results.is_empty() {
             return Ok(json!({"result": "No messages found matching the query."}));
@@ -58,7 +80,7 @@ impl Tool for SearchLocalHistoryTool {
             query, raw_history
         );
 
-        let result_summary = self.llm.completion(&prompt).await?;
+        let result_summary = ctx.llm.completion(&prompt).await?;
 
         Ok(json!({
             "result": result_summary,
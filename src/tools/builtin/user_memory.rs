@@ -1,12 +1,10 @@
-use crate::db::Database;
-use crate::tools::Tool;
+use crate::tools::{Tool, ToolContext};
 use async_trait::async_trait;
 use chrono::{DateTime, NaiveDateTime, Utc};
+use poise::serenity_prelude::Permissions;
 use serde_json::{json, Value};
 
-pub struct GetUserMemoryTool {
-    pub db: Database,
-}
+pub struct GetUserMemoryTool;
 
 #[async_trait]
 impl Tool for GetUserMemoryTool {
@@ -15,7 +13,7 @@ impl Tool for GetUserMemoryTool {
     }
 
     fn description(&self) -> &str {
-        "Fetch the user's full global memory profile (opt-in). Use when detailed preferences or background are needed. Requires user_id."
+        "Fetch the user's full global memory profile (opt-in). Use when detailed preferences or background are needed. Defaults to the requesting user; other users' profiles require administrator permission."
     }
 
     fn parameters_schema(&self) -> Value {
@@ -27,17 +25,27 @@ impl Tool for GetUserMemoryTool {
                     "description": "Discord user id for the profile to fetch"
                 }
             },
-            "required": ["user_id"]
+            "required": []
         })
     }
 
-    async fn execute(&self, params: Value) -> anyhow::Result<Value> {
+    async fn execute(&self, ctx: &ToolContext, params: Value) -> anyhow::Result<Value> {
+        let invoker_id = ctx.user_id.map(|id| id.to_string());
         let user_id = params["user_id"]
             .as_str()
-            .ok_or_else(|| anyhow::anyhow!("Missing user_id"))?
-            .to_string();
+            .map(str::to_string)
+            .or_else(|| invoker_id.clone())
+            .ok_or_else(|| anyhow::anyhow!("Missing user_id"))?;
+
+        if invoker_id.as_deref() != Some(user_id.as_str())
+            && !ctx.has_permissions(Permissions::ADMINISTRATOR)
+        {
+            return Ok(json!({
+                "result": "Access denied: you can only read the requesting user's memory profile."
+            }));
+        }
 
-        let record = self
+        let record = ctx
             .db
             .run_blocking({
                 let user_id = user_id.clone();
@@ -56,7 +64,7 @@ impl Tool for GetUserMemoryTool {
         if let Some(expires_at) = record.expires_at.as_deref() {
             if let Some(expires_ts) = parse_sqlite_utc(expires_at) {
                 if Utc::now() >= expires_ts {
-                    let _ = self
+                    let _ = ctx
                         .db
                         .run_blocking(move |db| db.delete_user_memory(&user_id))
                         .await;
@@ -1,5 +1,5 @@
 use std::sync::Arc;
-use crate::tools::{ToolRegistry, Tool};
+use crate::tools::{ToolContext, ToolRegistry, Tool};
 use serde_json::Value;
 
 pub struct ToolExecutor {
@@ -11,10 +11,10 @@ impl ToolExecutor {
         Self { registry }
     }
 
-    pub async fn execute(&self, name: &str, params: Value) -> anyhow::Result<Value> {
+    pub async fn execute(&self, name: &str, ctx: &ToolContext, params: Value) -> anyhow::Result<Value> {
         let tool = self.registry.get(name)
             .ok_or_else(|| anyhow::anyhow!("Tool not found: {}", name))?;
             
-        tool.execute(params).await
+        tool.execute(ctx, params).await
     }
 }
@@ -1,9 +1,169 @@
+use crate::cache::MessageCache;
+use crate::config::Config;
+use crate::db::Database;
+use crate::llm::LlmClient;
+use crate::mcp::client::McpClientManager;
+use crate::Data;
 use async_trait::async_trait;
+use poise::serenity_prelude as serenity;
+use policy::ToolPolicies;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
 
 pub mod builtin;
+pub mod policy;
+
+/// Per-invocation information about where and for whom a tool is being run, plus the shared
+/// bot handles tools need.
+///
+/// Discord fields are optional so tools can still be executed outside of a conversation
+/// (e.g. background tasks); tools that need them should hide themselves via `is_available`.
+#[derive(Clone)]
+pub struct ToolContext {
+    pub guild_id: Option<serenity::GuildId>,
+    pub channel_id: Option<serenity::ChannelId>,
+    pub user_id: Option<serenity::UserId>,
+    /// Effective permissions of the invoking member in `channel_id`, when known.
+    pub member_permissions: Option<serenity::Permissions>,
+    /// Roles of the invoking member, when known (used by tool policies).
+    pub member_roles: Vec<serenity::RoleId>,
+    pub http: Option<Arc<serenity::Http>>,
+    pub serenity: Option<serenity::Context>,
+    pub config: Config,
+    pub db: Database,
+    pub cache: MessageCache,
+    pub llm: LlmClient,
+    pub http_client: reqwest::Client,
+    pub mcp_manager: Arc<McpClientManager>,
+    /// Tool policies of the invocation, set by the agent once they are loaded.
+    pub policies: ToolPolicies,
+}
+
+impl ToolContext {
+    /// Context without any Discord invocation attached.
+    pub fn new(data: &Data) -> Self {
+        Self {
+            guild_id: None,
+            channel_id: None,
+            user_id: None,
+            member_permissions: None,
+            member_roles: Vec::new(),
+            http: None,
+            serenity: None,
+            config: data.config.clone(),
+            db: data.db.clone(),
+            cache: data.cache.clone(),
+            llm: data.llm_client.clone(),
+            http_client: data.http_client.clone(),
+            mcp_manager: data.mcp_manager.clone(),
+            policies: ToolPolicies::default(),
+        }
+    }
+
+    /// Context for a message or command sent by `user_id` in `channel_id`.
+    ///
+    /// Member permissions are resolved from the serenity cache; use
+    /// `with_member_permissions` when the interaction already carries them.
+    pub fn for_invocation(
+        data: &Data,
+        serenity_ctx: &serenity::Context,
+        guild_id: Option<serenity::GuildId>,
+        channel_id: serenity::ChannelId,
+        user_id: serenity::UserId,
+    ) -> Self {
+        let member_permissions = guild_id
+            .and_then(|gid| cached_member_permissions(serenity_ctx, gid, channel_id, user_id));
+        let member_roles = guild_id
+            .and_then(|gid| {
+                let guild = serenity_ctx.cache.guild(gid)?;
+                guild.members.get(&user_id).map(|m| m.roles.clone())
+            })
+            .unwrap_or_default();
+        Self {
+            guild_id,
+            channel_id: Some(channel_id),
+            user_id: Some(user_id),
+            member_permissions,
+            member_roles,
+            http: Some(serenity_ctx.http.clone()),
+            serenity: Some(serenity_ctx.clone()),
+            ..Self::new(data)
+        }
+    }
+
+    pub fn with_member_permissions(mut self, permissions: Option<serenity::Permissions>) -> Self {
+        if permissions.is_some() {
+            self.member_permissions = permissions;
+        }
+        self
+    }
+
+    /// Use the roles carried by the message or interaction when the cache has no member.
+    pub fn with_member_roles(mut self, roles: Option<Vec<serenity::RoleId>>) -> Self {
+        if let Some(roles) = roles {
+            self.member_roles = roles;
+        }
+        self
+    }
+
+    pub fn with_policies(mut self, policies: ToolPolicies) -> Self {
+        self.policies = policies;
+        self
+    }
+
+    /// Whether the invoking member is known to hold all of `required`.
+    pub fn has_permissions(&self, required: serenity::Permissions) -> bool {
+        self.member_permissions.is_some_and(|p| {
+            p.contains(serenity::Permissions::ADMINISTRATOR) || p.contains(required)
+        })
+    }
+
+    pub fn is_owner(&self) -> bool {
+        matches!(
+            (self.config.owner_id, self.user_id),
+            (Some(owner), Some(user)) if owner == user.get()
+        )
+    }
+
+    /// Context without a Discord invocation, backed by an in-memory database.
+    #[cfg(test)]
+    pub(crate) fn for_tests(config: Config, mcp_manager: Arc<McpClientManager>) -> Self {
+        let db = Database::new(&config).unwrap();
+        db.execute_init().unwrap();
+        Self {
+            guild_id: None,
+            channel_id: None,
+            user_id: None,
+            member_permissions: None,
+            member_roles: Vec::new(),
+            http: None,
+            serenity: None,
+            db,
+            cache: MessageCache::new(10),
+            llm: LlmClient::new(&config),
+            http_client: reqwest::Client::new(),
+            mcp_manager,
+            policies: ToolPolicies::default(),
+            config,
+        }
+    }
+}
+
+fn cached_member_permissions(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    channel_id: serenity::ChannelId,
+    user_id: serenity::UserId,
+) -> Option<serenity::Permissions> {
+    let guild = ctx.cache.guild(guild_id)?;
+    let member = guild.members.get(&user_id)?;
+    // Threads are not part of `guild.channels`; fall back to guild-level permissions there.
+    Some(match guild.channels.get(&channel_id) {
+        Some(channel) => guild.user_permissions_in(channel, member),
+        None => guild.member_permissions(member),
+    })
+}
 
 #[async_trait]
 pub trait Tool: Send + Sync {
@@ -13,7 +173,15 @@ pub trait Tool: Send + Sync {
     fn requires_confirmation(&self) -> bool {
         false
     }
-    async fn execute(&self, params: Value) -> anyhow::Result<Value>;
+    /// The MCP server providing the tool; `None` for built-in tools.
+    fn server(&self) -> Option<&str> {
+        None
+    }
+    /// Whether the tool should be offered to the LLM for this invocation.
+    fn is_available(&self, _ctx: &ToolContext) -> bool {
+        true
+    }
+    async fn execute(&self, ctx: &ToolContext, params: Value) -> anyhow::Result<Value>;
 }
 
 pub struct ToolRegistry {
@@ -45,6 +213,15 @@ impl ToolRegistry {
         self.tools.values().cloned().collect()
     }
 
+    /// Tools that are available for the given invocation.
+    pub fn list_tools_for(&self, ctx: &ToolContext) -> Vec<Arc<dyn Tool>> {
+        self.tools
+            .values()
+            .filter(|tool| tool.is_available(ctx))
+            .cloned()
+            .collect()
+    }
+
     pub fn get_definitions(&self) -> Vec<Value> {
         self.tools
             .values()
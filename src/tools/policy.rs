@@ -0,0 +1,367 @@
+//! Per-guild tool policies (`/settings tools`): allow, deny or require confirmation for a
+//! tool or an MCP server, server-wide, in a channel or for a role.
+
+use super::{Tool, ToolContext};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Where a policy applies. Listed from least to most specific.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PolicyScope {
+    Guild,
+    Role,
+    Channel,
+}
+
+/// What a policy applies to. Listed from least to most specific.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PolicyTarget {
+    /// Every tool of an MCP server.
+    Server,
+    Tool,
+}
+
+/// Listed from least to most restrictive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PolicyAction {
+    /// Offer the tool and run it without confirmation.
+    Allow,
+    /// Offer the tool, but ask the user before running it.
+    Confirm,
+    /// Do not offer the tool.
+    Deny,
+}
+
+impl PolicyScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Guild => "guild",
+            Self::Role => "role",
+            Self::Channel => "channel",
+        }
+    }
+
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "guild" => Ok(Self::Guild),
+            "role" => Ok(Self::Role),
+            "channel" => Ok(Self::Channel),
+            other => anyhow::bail!("Unknown tool policy scope '{}'", other),
+        }
+    }
+}
+
+impl PolicyTarget {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Server => "server",
+            Self::Tool => "tool",
+        }
+    }
+
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "server" => Ok(Self::Server),
+            "tool" => Ok(Self::Tool),
+            other => anyhow::bail!("Unknown tool policy target '{}'", other),
+        }
+    }
+}
+
+impl PolicyAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Confirm => "confirm",
+            Self::Deny => "deny",
+        }
+    }
+
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "confirm" => Ok(Self::Confirm),
+            "deny" => Ok(Self::Deny),
+            other => anyhow::bail!("Unknown tool policy action '{}'", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolPolicy {
+    pub guild_id: String,
+    pub scope: PolicyScope,
+    /// Guild, channel or role id, depending on `scope`.
+    pub scope_id: String,
+    pub target: PolicyTarget,
+    /// Tool or MCP server name, depending on `target`.
+    pub target_name: String,
+    pub action: PolicyAction,
+}
+
+/// The policies of a guild, narrowed to one invocation: its channel (and the thread's parent
+/// channel) and the invoking member's roles.
+#[derive(Debug, Clone, Default)]
+pub struct ToolPolicies {
+    policies: Vec<ToolPolicy>,
+}
+
+impl ToolPolicies {
+    pub fn new(policies: Vec<ToolPolicy>, channel_ids: &[String], role_ids: &[String]) -> Self {
+        let policies = policies
+            .into_iter()
+            .filter(|p| match p.scope {
+                PolicyScope::Guild => p.scope_id == p.guild_id,
+                PolicyScope::Channel => channel_ids.contains(&p.scope_id),
+                PolicyScope::Role => role_ids.contains(&p.scope_id),
+            })
+            .collect();
+        Self { policies }
+    }
+
+    /// The policies that apply to an invocation: its guild's rules for the channel (or the
+    /// thread's parent channel) and the member's roles, including @everyone.
+    pub async fn load(ctx: &ToolContext) -> anyhow::Result<Self> {
+        let Some(guild_id) = ctx.guild_id else {
+            return Ok(Self::default());
+        };
+        let guild_id = guild_id.to_string();
+        let channel_id = ctx.channel_id.map(|c| c.to_string());
+        let mut role_ids: Vec<String> = ctx.member_roles.iter().map(|r| r.to_string()).collect();
+        // The @everyone role shares the guild's id.
+        role_ids.push(guild_id.clone());
+        ctx.db
+            .run_blocking(move |db| {
+                let policies = db.list_tool_policies(&guild_id)?;
+                if policies.is_empty() {
+                    return Ok(Self::default());
+                }
+                let mut channel_ids = Vec::new();
+                if let Some(channel_id) = channel_id {
+                    if let Some(thread) = db.get_channel_thread(&channel_id)? {
+                        channel_ids.push(thread.parent_channel_id);
+                    }
+                    channel_ids.push(channel_id);
+                }
+                Ok(Self::new(policies, &channel_ids, &role_ids))
+            })
+            .await
+    }
+
+    /// The action for a tool, or `None` when no policy matches. Tool rules beat server rules;
+    /// then channel rules beat role rules, which beat server-wide rules. Among equally
+    /// specific rules (e.g. for two of the member's roles) the most restrictive wins.
+    pub fn action_for(&self, tool: &str, server: Option<&str>) -> Option<PolicyAction> {
+        self.policies
+            .iter()
+            .filter(|p| match p.target {
+                PolicyTarget::Tool => p.target_name == tool,
+                PolicyTarget::Server => server == Some(p.target_name.as_str()),
+            })
+            .max_by_key(|p| (p.target, p.scope, p.action))
+            .map(|p| p.action)
+    }
+
+    /// Whether a server-level rule denies every tool and resource of an MCP server. Used by
+    /// built-in tools that reach MCP servers themselves, such as `read_mcp_resource`.
+    pub fn denies_server(&self, server: &str) -> bool {
+        self.policies
+            .iter()
+            .filter(|p| p.target == PolicyTarget::Server && p.target_name == server)
+            .max_by_key(|p| (p.scope, p.action))
+            .is_some_and(|p| p.action == PolicyAction::Deny)
+    }
+
+    /// Drop denied tools and override confirmation for allowed and confirm-only ones. Tools
+    /// without a matching policy are returned unchanged.
+    pub fn apply(&self, tools: Vec<Arc<dyn Tool>>) -> Vec<Arc<dyn Tool>> {
+        if self.policies.is_empty() {
+            return tools;
+        }
+        tools
+            .into_iter()
+            .filter_map(|tool| match self.action_for(tool.name(), tool.server()) {
+                None => Some(tool),
+                Some(PolicyAction::Deny) => None,
+                Some(action) => {
+                    let requires_confirmation = action == PolicyAction::Confirm;
+                    if tool.requires_confirmation() == requires_confirmation {
+                        Some(tool)
+                    } else {
+                        Some(Arc::new(ConfirmationOverride {
+                            inner: tool,
+                            requires_confirmation,
+                        }) as Arc<dyn Tool>)
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// A tool whose confirmation requirement was changed by a policy.
+struct ConfirmationOverride {
+    inner: Arc<dyn Tool>,
+    requires_confirmation: bool,
+}
+
+#[async_trait]
+impl Tool for ConfirmationOverride {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+    fn description(&self) -> &str {
+        self.inner.description()
+    }
+    fn parameters_schema(&self) -> Value {
+        self.inner.parameters_schema()
+    }
+    fn requires_confirmation(&self) -> bool {
+        self.requires_confirmation
+    }
+    fn server(&self) -> Option<&str> {
+        self.inner.server()
+    }
+    fn is_available(&self, ctx: &ToolContext) -> bool {
+        self.inner.is_available(ctx)
+    }
+    async fn execute(&self, ctx: &ToolContext, params: Value) -> anyhow::Result<Value> {
+        self.inner.execute(ctx, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NamedTool {
+        name: &'static str,
+        server: Option<&'static str>,
+        confirm: bool,
+    }
+
+    #[async_trait]
+    impl Tool for NamedTool {
+        fn name(&self) -> &str {
+            self.name
+        }
+        fn description(&self) -> &str {
+            ""
+        }
+        fn parameters_schema(&self) -> Value {
+            serde_json::json!({ "type": "object" })
+        }
+        fn requires_confirmation(&self) -> bool {
+            self.confirm
+        }
+        fn server(&self) -> Option<&str> {
+            self.server
+        }
+        async fn execute(&self, _ctx: &ToolContext, _params: Value) -> anyhow::Result<Value> {
+            Ok(Value::Null)
+        }
+    }
+
+    fn policy(
+        scope: PolicyScope,
+        scope_id: &str,
+        target: PolicyTarget,
+        target_name: &str,
+        action: PolicyAction,
+    ) -> ToolPolicy {
+        ToolPolicy {
+            guild_id: "g1".to_string(),
+            scope,
+            scope_id: scope_id.to_string(),
+            target,
+            target_name: target_name.to_string(),
+            action,
+        }
+    }
+
+    #[test]
+    fn test_policy_precedence() {
+        use PolicyAction::*;
+        use PolicyScope::*;
+        use PolicyTarget::*;
+        let policies = ToolPolicies::new(
+            vec![
+                policy(Guild, "g1", Server, "github", Deny),
+                policy(Channel, "dev", Server, "github", Allow),
+                policy(Guild, "g1", Tool, "delete_repo", Deny),
+                policy(Role, "mods", Tool, "play_music", Allow),
+                policy(Role, "muted", Tool, "play_music", Deny),
+                policy(Role, "other", Tool, "search_messages", Deny),
+                policy(Channel, "elsewhere", Tool, "search_messages", Deny),
+            ],
+            &["dev".to_string()],
+            &["mods".to_string(), "muted".to_string()],
+        );
+
+        // A channel rule beats a server-wide rule for the same target...
+        assert_eq!(
+            policies.action_for("list_issues", Some("github")),
+            Some(Allow)
+        );
+        // ...but a tool rule beats any server rule.
+        assert_eq!(
+            policies.action_for("delete_repo", Some("github")),
+            Some(Deny)
+        );
+        // Conflicting role rules: the most restrictive wins.
+        assert_eq!(policies.action_for("play_music", None), Some(Deny));
+        // Rules for other roles and channels do not apply.
+        assert_eq!(policies.action_for("search_messages", None), None);
+    }
+
+    #[test]
+    fn test_apply_filters_and_overrides_confirmation() {
+        use PolicyAction::*;
+        let policies = ToolPolicies::new(
+            vec![
+                policy(PolicyScope::Guild, "g1", PolicyTarget::Tool, "a", Deny),
+                policy(PolicyScope::Guild, "g1", PolicyTarget::Server, "fs", Allow),
+                policy(PolicyScope::Guild, "g1", PolicyTarget::Tool, "c", Confirm),
+            ],
+            &[],
+            &[],
+        );
+        let tools: Vec<Arc<dyn Tool>> = vec![
+            Arc::new(NamedTool {
+                name: "a",
+                server: None,
+                confirm: false,
+            }),
+            Arc::new(NamedTool {
+                name: "b",
+                server: Some("fs"),
+                confirm: true,
+            }),
+            Arc::new(NamedTool {
+                name: "c",
+                server: None,
+                confirm: false,
+            }),
+            Arc::new(NamedTool {
+                name: "d",
+                server: None,
+                confirm: true,
+            }),
+        ];
+
+        let result: Vec<(String, bool)> = policies
+            .apply(tools)
+            .iter()
+            .map(|t| (t.name().to_string(), t.requires_confirmation()))
+            .collect();
+        assert_eq!(
+            result,
+            vec![
+                ("b".to_string(), false),
+                ("c".to_string(), true),
+                ("d".to_string(), true)
+            ]
+        );
+    }
+}